@@ -0,0 +1,59 @@
+//! Bakes build information into the binary for `--version --verbose`.
+
+use std::{
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+/// Days since the unix epoch into a (year, month, day) date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn main() {
+
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_owned())
+        .unwrap_or_else(|| "unknown".to_owned());
+
+    // reproducible builds set this.
+    let seconds = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<i64>().ok())
+        .unwrap_or_else(|| SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or(0)
+        );
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(var, _)| var
+            .strip_prefix("CARGO_FEATURE_")
+            .map(|feature| feature.to_lowercase().replace('_', "-"))
+        )
+        .collect();
+    features.sort();
+
+    println!("cargo:rustc-env=XMVA_GIT_HASH={git_hash}");
+    println!("cargo:rustc-env=XMVA_BUILD_DATE={year:04}-{month:02}-{day:02}");
+    println!("cargo:rustc-env=XMVA_FEATURES={}", features.join(","));
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+}
@@ -1,19 +1,212 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::{diagnostic::MessageFormat, graph::GraphFormat, stage::Stage, testgen::Framework, trace::TraceFormat};
 
 /// Generator for a specific kind of macro im using that counts the amount
 /// of arguments and dispatches the apropriate x-macro with said arguments.
 #[derive(Parser, Debug)]
+#[command(subcommand_negates_reqs = true, disable_version_flag = true)]
 pub struct Arguments {
 
-    #[arg(short, long)]
-    pub input:  PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[arg(short, long, required_unless_present_any = ["version", "stdin_format"])]
+    pub input:  Option<PathBuf>,
+
+    /// Read the config from stdin in this format instead of from
+    /// `--input`, paths in it are relative to the current directory.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub stdin_format: Option<StdinFormat>,
 
     #[arg(short, long)]
     pub output: Option<PathBuf>,
 
     #[arg(short, long)]
-    pub logging: bool
+    pub logging: bool,
+
+    /// How config errors and lints are reported, `github` and `gitlab`
+    /// print CI annotations instead (see [crate::diagnostic]).
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t)]
+    pub message_format: MessageFormat,
+
+    /// Write a Makefile style dependency file listing every file that
+    /// contributed to the output.
+    #[arg(long)]
+    pub depfile: Option<PathBuf>,
+
+    /// Override `common.repeats` from the config.
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub repeats: Option<usize>,
+
+    /// Override `common.prefix` from the config.
+    #[arg(long, value_name = "STR")]
+    pub prefix: Option<String>,
+
+    /// Override `common.max_output_bytes` from the config, refuse to
+    /// write any output bigger than this.
+    #[arg(long, value_name = "BYTES")]
+    pub max_output_bytes: Option<usize>,
+
+    /// Don't write anything, only check if the outputs on disk are up to
+    /// date and exit with [crate::STALE_EXIT_CODE] if they aren't.
+    #[arg(long, visible_alias = "diff")]
+    pub check: bool,
+
+    /// Print the version, with `--verbose` the whole build information
+    /// as JSON.
+    #[arg(short = 'V', long)]
+    pub version: bool,
+
+    /// See `--version`.
+    #[arg(long, requires = "version")]
+    pub verbose: bool,
+
+    /// Write a report of every substitution made while generating next
+    /// to the output (`<output>.trace.txt` or `<output>.trace.json`).
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub trace_expansion: Option<TraceFormat>,
+
+    /// Overwrite outputs even if they weren't generated by us (they
+    /// are missing the `@generated` sentinel line).
+    #[arg(long)]
+    pub force: bool,
+
+    /// Write a JSON source map next to every output (`<output>.map`)
+    /// pointing each generated line range back to the config.
+    #[arg(long)]
+    pub source_map: bool,
+
+    /// Print how long every phase of generating took and how many bytes
+    /// it produced.
+    #[arg(long)]
+    pub timings: bool,
+
+    /// Print the config with the final value of every preprocessed
+    /// field below it instead of generating.
+    #[arg(long, conflicts_with_all = ["check", "stop_after"])]
+    pub explain_config: bool,
+
+    /// Write the signatures, arguments and docs of the macros meant to
+    /// be called next to the output (`<output name>.xmva-ide.json`) for
+    /// editor plugins.
+    #[arg(long)]
+    pub ide_metadata: bool,
+
+    /// Strip comments and optional whitespace from the outputs, the
+    /// `@generated` sentinel and config hash are kept.
+    #[arg(long, conflicts_with = "source_map")]
+    pub minify: bool,
+
+    /// Stop after this stage and dump what it left behind next to the
+    /// output (`<output>.<stage>.json`) instead of writing the output.
+    #[arg(long, value_enum, value_name = "STAGE", conflicts_with = "check")]
+    pub stop_after: Option<Stage>,
+
+    /// Continue after the stage of a `--stop-after` dump of the same
+    /// config instead of starting over.
+    #[arg(long, value_name = "DUMP")]
+    pub resume_from: Option<PathBuf>,
+
+    /// Print a summary of every generator instead of generating.
+    #[arg(long)]
+    pub list_generators: bool
+
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdinFormat {
+    Toml,
+    /// The same layout as the TOML, for tools that generate configs.
+    Json
+}
+
+/// Anything other than generating a header from a config.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+
+    /// Run a language server over stdio for `.xmva.toml` files.
+    Lsp,
+
+    /// Print a string field from the config (e.g. `generator.0.repeat`)
+    /// with each token colored by its kind.
+    Highlight {
+        field: String
+    },
+
+    /// Print the key reference graph of the config, pipe it into `dot`
+    /// or paste it into anything that renders Mermaid.
+    Graph {
+        #[arg(long, value_enum, default_value_t)]
+        format: GraphFormat
+    },
+
+    /// Tokenize a string with the default sigils and print the tokens
+    /// along side the string untokenized back, no config needed.
+    Tokens {
+        /// Tokenize with the compiler tokenizer (generator strings).
+        #[arg(long, value_name = "STR", required_unless_present = "preprocessor", conflicts_with = "preprocessor")]
+        compiler: Option<String>,
+
+        /// Tokenize with the preprocessor tokenizer (anything with keys).
+        #[arg(long, value_name = "STR")]
+        preprocessor: Option<String>
+    },
+
+    /// Check that the outputs were generated from the config (and the
+    /// configs, libraries, plugin and files it pulls in) as it is now,
+    /// exits with [crate::STALE_EXIT_CODE] if they weren't.
+    Verify,
+
+    /// Generate every member of a workspace, the generating flags
+    /// (`--check`, `--force`, ...) apply to every member.
+    Build {
+        /// Defaults to [crate::workspace::WORKSPACE_FILE] in the current
+        /// directory.
+        workspace: Option<PathBuf>,
+
+        /// How many members to generate at once, ignored when running
+        /// under a `make` jobserver (we take its tokens instead).
+        #[arg(short, long, value_name = "N", default_value_t = 1)]
+        jobs: usize,
+
+        /// Generate only this member, the jobs of a parallel build are
+        /// this same command with it.
+        #[arg(long, hide = true)]
+        member: Option<PathBuf>
+    },
+
+    /// Generate a built-in example config in a temporary directory and
+    /// expand a sample invocation of it, exits with 1 if it fails.
+    SelfTest,
+
+    /// Print a C test file running the `[example]` and `[[test]]`
+    /// invocations of the `--input` config against its output.
+    EmitTests {
+        #[arg(long, value_enum, default_value_t)]
+        framework: Framework
+    },
+
+    /// Print what one generator of the `--input` config expands into for
+    /// the first few argument groups, see [crate::preview].
+    Preview {
+        /// The name of the generator, or its index.
+        #[arg(long, value_name = "NAME")]
+        generator: String,
+
+        /// Up to how many argument groups to expand it with.
+        #[arg(long, value_name = "N", default_value_t = 2, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        groups: usize
+    },
+
+    /// Remove every output of the config given with `--input`, or of
+    /// every member of a workspace. Only files carrying the `@generated`
+    /// sentinel are removed.
+    Clean {
+        /// See `build`, ignored when a `--input` is given.
+        workspace: Option<PathBuf>
+    }
 
-}
\ No newline at end of file
+}
@@ -0,0 +1,144 @@
+//! Backends emit the assembled pieces of a output in a specific
+//! language, the assembler only decides what goes where.
+//!
+//! The backend is picked with `common.language`, C is the only one for
+//! now but C++ (or anything else with a preprocessor) should only need
+//! a new [Backend] instead of a fork of the assembler.
+//!
+//! Example
+//! -------
+//! ```toml
+//! [common]
+//! output   = "./ya_ecgen.h"
+//! prefix   = "YA_ECGEN_"
+//! repeats  = 30
+//! language = "c"
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compiler::{self, Error, Ladder},
+    config::{Common, Config, Core, Generator, Output},
+    sourcemap::SourceMapped
+};
+
+/// The language the output is generated in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    C,
+}
+
+impl Language {
+
+    pub fn backend(&self) -> &'static dyn Backend {
+        match self {
+            Self::C => &C
+        }
+    }
+
+}
+
+pub trait Backend {
+
+    /// Includes, the raw preamble and every definition.
+    fn assemble_preamble(&self, config: &Config) -> Result<SourceMapped, Error>;
+
+    /// The repeat ladder of `generator` with `suffix` in its names, one
+    /// step for every argument count up to [Common::repeats] (inside of
+    /// its [crate::config::RepeatRange]).
+    fn assemble_repeat(
+        &self,
+        generator:   &Generator,
+        common:      &Common,
+        core:        &Core,
+        output:      &Output,
+        suffix:      usize
+    ) -> Result<String, Error>;
+
+    /// The picker selecting a step of a ladder by the argument count,
+    /// shared by every ladder, along side the helpers it needs to tell
+    /// if there are any varadict arguments.
+    fn assemble_picker(&self, core: &Core, common: &Common) -> Result<String, Error>;
+
+    /// The main `xmva` dispatching into the generator macros,
+    /// `ladders` holds the ladder every generator uses.
+    fn assemble_dispatcher(
+        &self,
+        core:    &Core,
+        common:  &Common,
+        output:  &Output,
+        ladders: &[Ladder]
+    ) -> Result<String, Error>;
+
+}
+
+/// Plain C99, `__VA_OPT__` is used where the standard has it.
+pub struct C;
+
+impl Backend for C {
+
+    fn assemble_preamble(&self, config: &Config) -> Result<SourceMapped, Error> {
+        config.assemble_preamble()
+    }
+
+    fn assemble_repeat(
+        &self,
+        generator:   &Generator,
+        common:      &Common,
+        core:        &Core,
+        output:      &Output,
+        suffix:      usize
+    ) -> Result<String, Error> {
+        compiler::compile_and_assemble_repeat_string(generator, common, core, output, suffix)
+    }
+
+    fn assemble_picker(&self, core: &Core, common: &Common) -> Result<String, Error> {
+        compiler::assemble_picker_string(core, common)
+    }
+
+    fn assemble_dispatcher(
+        &self,
+        core:    &Core,
+        common:  &Common,
+        output:  &Output,
+        ladders: &[Ladder]
+    ) -> Result<String, Error> {
+        compiler::assemble_main_macro_string(core, common, output, ladders)
+    }
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn c_backend() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 3\nlanguage = \"c\"\n\n[core]\nxmva = \"A\"\n\
+            args = [{key = \"n\", name = \"n\"}, {varadict = 1}]\n\n[[generator]]\nrepeat = \"$(0)$[,]\"\n";
+        let config = Config::parse(contents, std::path::Path::new("backend.xmva.toml")).unwrap();
+        assert_eq!(config.common.language, Language::C);
+        config.preprocess().unwrap();
+
+        // the assembler only places what the backend emits.
+        let backend = config.common.language.backend();
+        let repeat = backend.assemble_repeat(&config.generator[0], &config.common, &config.core, &config.output, 0).unwrap();
+        let picker = backend.assemble_picker(&config.core, &config.common).unwrap();
+        let assembled = config.compile_and_assemble().unwrap().main.contents;
+        assert!(repeat.contains("#define A___ARGS__0_2(n, __0__, __1__) __0__,__1__"));
+        assert!(assembled.contains(&repeat));
+        assert!(assembled.contains(&picker));
+
+        let err = Config::parse(&contents.replace("\"c\"", "\"rust\""), std::path::Path::new("backend.xmva.toml"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("unknown variant `rust`"));
+
+    }
+
+}
@@ -1,14 +1,57 @@
-use std::{collections::HashMap, mem::discriminant, usize};
+use std::{borrow::Cow, collections::HashMap, mem::discriminant, path::{Path, PathBuf}, sync::{Arc, RwLock}};
 
 use colored::Colorize;
-use strum::{EnumIter, EnumProperty};
+use serde::{Deserialize, Serialize};
+use strum::EnumProperty;
 
 use crate::{
-    config::{Argument, Common, Config, Core, Generator}, preprocessor::{Preprocessable, PreprocessableString}, sigil::CompilerSigil
+    config::{Argument, Common, Config, Core, Emit, Generator, Group, Helper, Layout, MemberDocs, Output, RepeatRange, SortDefinitions}, metadata::{self, Metadata}, plugin, sourcemap::{SourceMapped, Stage}, preprocessor::{substitution_trace_key, tokenized_text, Preprocess, Preprocessable, PreprocessableString, SubstitutionTraces}, sigil::CompilerSigil
 };
 
-const REPEAT_SECTION_SUFFIX: &'static str = "__ARGS__";
-const GENERATOR_SUFFIX: &'static str = "__GENERATOR__";
+const REPEAT_SECTION_SUFFIX: &str = "__ARGS__";
+const GENERATOR_SUFFIX: &str = "__GENERATOR__";
+const LIST_SUFFIX: &str = "__LIST__";
+const CALL_SUFFIX: &str = "__CALL__";
+const COMMA_SUFFIX: &str = "__COMMA__";
+/// The public per arity macros, see [crate::config::Output::expose_arity_macros].
+const APPLY_SUFFIX: &str = "APPLY_";
+const VARIADIC_PARAMETER: &str = "...";
+const VARIADIC_ARGUMENTS: &str = "__VA_ARGS__";
+/// First line of every output, files without it are never overwritten
+/// unless `--force` is given.
+pub const SENTINEL: &str = "/* @generated by xmvagen */";
+/// Written in the banner right before the config hash, see `xmva verify`.
+pub const CONFIG_HASH_MARKER: &str = "xmva config hash: ";
+
+/// Everything generating the repeats produced for the generators, the
+/// assembly only puts it together (see `--stop-after`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Baked {
+    /// The ladder of every generator, [None] when it shares the ladder
+    /// of a earlier one.
+    pub repeats: Vec<Option<String>>,
+    /// The generator macro of every generator.
+    pub generators: Vec<String>,
+    pub ladders: Vec<Ladder>,
+}
+
+/// The assembled main output along side the files that generators
+/// with their own [Generator::output] were routed into.
+#[derive(Debug)]
+pub struct Assembled {
+    pub main: SourceMapped,
+    /// Paths are already relative to the main output.
+    pub routed: Vec<(PathBuf, SourceMapped)>
+}
+
+impl Assembled {
+
+    /// Bytes of every output together.
+    pub fn len(&self) -> usize {
+        self.main.contents.len() + self.routed.iter().map(|(_, routed)| routed.contents.len()).sum::<usize>()
+    }
+
+}
 
 
 #[derive(Debug, PartialEq, Eq)]
@@ -20,7 +63,10 @@ pub enum ErrorKind {
     InvalidToken,
     PoisonedLock,
     NotPreprocessed,
-    NonExistantArgument
+    NonExistantArgument,
+    InvalidDefinition,
+    NameCollision,
+    Plugin
 }
 
 #[derive(Debug)]
@@ -39,62 +85,188 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
-#[derive(Debug, PartialEq, Eq, EnumProperty, EnumIter)]
-enum CompilerToken {
+/// Tokens borrow from the tokenized string, the text ones (`Raw` and
+/// `SkipLast`) are only owned when they had embeds that were unescaped.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, EnumProperty)]
+pub(crate) enum CompilerToken<'a> {
     #[strum(props(surface = true))]
-    Raw(String),
+    Raw(Cow<'a, str>),
     #[strum(props(surface = true))]
-    NamedArgumentRef(String),
+    NamedArgumentRef(Cow<'a, str>),
     UnamedArgumentRef(usize),
+    /// A argument referenced by its [crate::config::Group] member name
+    /// instead of its index, resolved into a [CompilerToken::UnamedArgumentRef].
+    MemberRef(Cow<'a, str>),
     Position,
-    SkipLast(String)
+    SkipLast(Cow<'a, str>),
+    /// `$[N: text]` and `$[^N: text]`, [CompilerToken::SkipLast] that skips
+    /// the last (or first) `count` repetitions instead of only the last.
+    Skip {
+        count: usize,
+        edge:  SkipEdge,
+        text:  Cow<'a, str>
+    },
+    /// One of the arguments left dangling when the argument count isn't
+    /// a multiple of the group size, only means something inside of
+    /// [crate::config::Fallbacks::unparity].
+    LeftoverArgumentRef(usize),
+    /// `$(first.0)` and `$(last.0)`, a argument of the first or last group
+    /// instead of the current one. What lets the preamble and postamble
+    /// of a generator see the arguments, along side `$.` which is the
+    /// group count in there.
+    EdgeArgumentRef(SkipEdge, usize),
+    /// `$(first.name)`, resolved into a [CompilerToken::EdgeArgumentRef]
+    /// like a [CompilerToken::MemberRef].
+    EdgeMemberRef(SkipEdge, Cow<'a, str>)
+}
+
+/// Which repetitions a [CompilerToken::Skip] skips, or which group a
+/// [CompilerToken::EdgeArgumentRef] takes its argument from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SkipEdge {
+    First,
+    Last,
+}
+
+/// Marks a [SkipEdge::First] count, `$[^2:, ]`.
+const SKIP_FIRST_MARKER: char = '^';
+const SKIP_COUNT_SEPARATOR: char = ':';
+
+/// `$(first.0)` and `$(last.0)`, see [CompilerToken::EdgeArgumentRef].
+const FIRST_GROUP: &str = "first";
+const LAST_GROUP: &str = "last";
+const EDGE_SEPARATOR: char = '.';
+
+/// Split the edge off of a `$(first.0)` reference, [None] for the
+/// references to the current group.
+fn edge_reference(reference: &str) -> Option<(SkipEdge, &str)> {
+    let (edge, reference) = reference.split_once(EDGE_SEPARATOR)?;
+    match edge {
+        FIRST_GROUP => Some((SkipEdge::First, reference)),
+        LAST_GROUP => Some((SkipEdge::Last, reference)),
+        _ => None
+    }
+}
+
+/// Split the count off of the text of a `$[...]`, plain text stays a
+/// [CompilerToken::SkipLast].
+fn skip_token(text: Cow<'_, str>) -> CompilerToken<'_> {
+    let (edge, counted) = match text.strip_prefix(SKIP_FIRST_MARKER) {
+        Some(counted) => (SkipEdge::First, counted),
+        None => (SkipEdge::Last, text.as_ref())
+    };
+    let Some((count, rest)) = counted.split_once(SKIP_COUNT_SEPARATOR)
+        .filter(|(count, _)| !count.is_empty() && count.chars().all(|ch| ch.is_ascii_digit()))
+        .and_then(|(count, rest)| Some((count.parse().ok()?, rest.len()))) else {
+        return CompilerToken::SkipLast(text)
+    };
+    // the text is the tail of the original so it can stay borrowed.
+    let text = match text {
+        Cow::Borrowed(text) => Cow::Borrowed(&text[text.len() - rest..]),
+        Cow::Owned(text) => Cow::Owned(text[text.len() - rest..].to_owned())
+    };
+    CompilerToken::Skip { count, edge, text }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Byte offsets are into the string being tokenized, text is unescaped
+/// into the [String] once a embed shows up in it and borrowed otherwise.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum CompilerTokenizerState {
-    Copying(String),
-    CopyingNamedArgumentRef(String),
-    CopyingUnamedArgumentRef(String),
-    CopyingSkipLast(String),
+    Copying(usize, Option<String>),
+    CopyingNamedArgumentRef(usize),
+    CopyingUnamedArgumentRef(usize),
+    CopyingLeftoverArgumentRef(usize),
+    CopyingSkipLast(usize, Option<String>),
     CopyingSkipLastEmbed(String),
     SigilFound,
-    EmbedFound(String)
+    EmbedFound(String),
+    /// After a error while recovering, up to the next token start.
+    Skipping
 }
 
-impl CompilerToken {
+impl<'a> CompilerToken<'a> {
+
+    pub(crate) fn tokenize(
+        s: &'a str
+    ) -> Result<Vec<CompilerToken<'a>>, Error> {
+        Self::tokenize_with(s, false).map(|(tokens, _)| tokens)
+    }
+
+    /// [CompilerToken::tokenize] that doesn't stop at the first error,
+    /// it is recorded and everything up to the next token start is
+    /// skipped. Returns what did tokenize along side every error.
+    pub(crate) fn tokenize_recovering(
+        s: &'a str
+    ) -> (Vec<CompilerToken<'a>>, Vec<Error>) {
+        Self::tokenize_with(s, true).unwrap_or_else(|err| (vec![], vec![err]))
+    }
 
-    fn tokenize(
-        s: &str
-    ) -> Result<Vec<CompilerToken>, Error> {
+    fn tokenize_with(
+        s: &'a str,
+        recover: bool
+    ) -> Result<(Vec<CompilerToken<'a>>, Vec<Error>), Error> {
 
         let mut parts: Vec<CompilerToken> = vec![];
+        let mut diagnostics: Vec<Error> = vec![];
         let mut state: CompilerTokenizerState 
-            = CompilerTokenizerState::Copying(String::new());
-        let mut prev_state = state.clone();
+            = CompilerTokenizerState::Copying(0, None);
+        let mut prev_state = discriminant(&state);
+
+        // strict mode returns the error, recovering records it and
+        // skips to the next token start.
+        macro_rules! fail {
+            ($err:expr) => {{
+                let err = $err;
+                if !recover {
+                    return Err(err)
+                }
+                diagnostics.push(err);
+                state = CompilerTokenizerState::Skipping;
+                continue
+            }};
+        }
+        macro_rules! fail_at_end {
+            ($err:expr) => {{
+                let err = $err;
+                if !recover {
+                    return Err(err)
+                }
+                diagnostics.push(err);
+            }};
+        }
 
-        for ch in s.chars() {
+        for (sigil, range) in CompilerSigil::lex(s) {
 
-            if discriminant(&prev_state) != discriminant(&state) {
+            if discriminant(&state) != prev_state {
                 log::trace!(
                     "{}: {}",
-                    format!("[CompilerToken::tokenize]").bold(),
-                    format!("Curr state {:?}", prev_state).dimmed()
+                    "[CompilerToken::tokenize]".to_string().bold(),
+                    format!("Curr state {:?}", state).dimmed()
                 );
+                prev_state = discriminant(&state);
             }
-            prev_state = state.clone();
+            let ch = &s[range.clone()];
 
             match state {
 
-                CompilerTokenizerState::Copying(ref mut buffer) => {
-                    match CompilerSigil::from(ch) {
+                CompilerTokenizerState::Skipping => {
+                    if sigil == CompilerSigil::TokenStart {
+                        state = CompilerTokenizerState::SigilFound;
+                    }
+                }
+                CompilerTokenizerState::Copying(start, ref mut unescaped) => {
+                    match sigil {
                         CompilerSigil::TokenStart => {
-                            if !buffer.is_empty() {
-                                parts.push(CompilerToken::Raw(buffer.clone()));
+                            let text = tokenized_text(s, start, range.start, unescaped.take());
+                            if !text.is_empty() {
+                                parts.push(CompilerToken::Raw(text));
                             }
                             state = CompilerTokenizerState::SigilFound;
                         }
                         CompilerSigil::TokenEmbed => {
-                            state = CompilerTokenizerState::EmbedFound(buffer.clone());
+                            state = CompilerTokenizerState::EmbedFound(
+                                tokenized_text(s, start, range.start, unescaped.take()).into_owned()
+                            );
                         }
                         CompilerSigil::NamedArgumentRefOpen |
                         CompilerSigil::NamedArgumentRefClose |
@@ -102,35 +274,39 @@ impl CompilerToken {
                         CompilerSigil::UnamedArgumentRefClose |
                         CompilerSigil::SkipLastOpen |
                         CompilerSigil::SkipLastClose |
+                        CompilerSigil::LeftoverArgumentRefOpen |
+                        CompilerSigil::LeftoverArgumentRefClose |
                         CompilerSigil::PositionDot |
-                        CompilerSigil::Non(_) => buffer.push(ch)
+                        CompilerSigil::Non(_) => if let Some(unescaped) = unescaped {
+                            unescaped.push_str(ch)
+                        }
                     }
                 }
                 CompilerTokenizerState::EmbedFound(ref mut buffer) => {
-                    match CompilerSigil::from(ch) {
+                    match sigil {
                         CompilerSigil::TokenStart |
                         CompilerSigil::TokenEmbed => {
-                            buffer.push(ch);
+                            buffer.push_str(ch);
                         }
                         _ => {
-                            return Err(Error{
+                            fail!(Error{
                                 kind: ErrorKind::IllegalSymbol,
                                 message: format!(
                                     "Expected a {:?} symbol {:?} or {:?} symbol {:?} after '{ch}'",
                                         CompilerSigil::TokenStart,
-                                        CompilerSigil::TokenStart.get_str("ch"),
+                                        CompilerSigil::TokenStart.sequence(),
                                         CompilerSigil::TokenEmbed,
-                                        CompilerSigil::TokenEmbed.get_str("ch")
+                                        CompilerSigil::TokenEmbed.sequence()
                                 )
                             })
                         }
                     }
-                    state = CompilerTokenizerState::Copying(buffer.clone());
+                    state = CompilerTokenizerState::Copying(range.end, Some(std::mem::take(buffer)));
                 }
                 CompilerTokenizerState::SigilFound => {
-                    match CompilerSigil::from(ch) {  
+                    match sigil {  
                         CompilerSigil::TokenStart => {
-                            return Err(Error{
+                            fail!(Error{
                                 kind: ErrorKind::IllegalSymbol,
                                 message: format!(
                                     "Duplicate symbol '{}' in '{}' twice or more in a row", ch, s
@@ -139,188 +315,236 @@ impl CompilerToken {
                         }
                         CompilerSigil::PositionDot => {
                             parts.push(CompilerToken::Position);
-                            state = CompilerTokenizerState::Copying(String::new())
+                            state = CompilerTokenizerState::Copying(range.end, None)
                         }
                         CompilerSigil::NamedArgumentRefOpen => {
-                            state = CompilerTokenizerState::CopyingNamedArgumentRef(String::new())
+                            state = CompilerTokenizerState::CopyingNamedArgumentRef(range.end)
                         }
                         CompilerSigil::UnamedArgumentRefOpen => {
-                            state = CompilerTokenizerState::CopyingUnamedArgumentRef(String::new())
+                            state = CompilerTokenizerState::CopyingUnamedArgumentRef(range.end)
                         }
                         CompilerSigil::SkipLastOpen => {
-                            state = CompilerTokenizerState::CopyingSkipLast(String::new())
+                            state = CompilerTokenizerState::CopyingSkipLast(range.end, None)
+                        }
+                        CompilerSigil::LeftoverArgumentRefOpen => {
+                            state = CompilerTokenizerState::CopyingLeftoverArgumentRef(range.end)
                         }
                         CompilerSigil::NamedArgumentRefClose |
                         CompilerSigil::UnamedArgumentRefClose |
                         CompilerSigil::SkipLastClose | 
+                        CompilerSigil::LeftoverArgumentRefClose |
                         CompilerSigil::TokenEmbed |
                         CompilerSigil::Non(_)=> {
-                            return Err(Error {
+                            fail!(Error {
                                 kind: ErrorKind::IllegalSymbol,
                                 message: format!(
                                     "Illegal character '{}' in '{}' after '{:?}' symbol '{:?}' ", 
-                                    ch, s, CompilerSigil::TokenStart, CompilerSigil::TokenStart.get_str("ch")
+                                    ch, s, CompilerSigil::TokenStart, CompilerSigil::TokenStart.sequence()
                                 )
                             })
                         }
                     }
                 }
-                CompilerTokenizerState::CopyingNamedArgumentRef(ref mut buffer_key) => {
-                    match CompilerSigil::from(ch) {
+                CompilerTokenizerState::CopyingNamedArgumentRef(start) => {
+                    match sigil {
                         CompilerSigil::NamedArgumentRefClose => {
-                            if buffer_key.is_empty() {
-                                return Err(Error {
+                            if start == range.start {
+                                fail!(Error {
                                     kind: ErrorKind::EmptyReference,
                                     message: format!(
                                         "Empty named argument reference `{}{}{}` inside of a compilable name `{s}`",
-                                        CompilerSigil::TokenStart.get_str("ch").unwrap(),
-                                        CompilerSigil::NamedArgumentRefOpen.get_str("ch").unwrap(),
-                                        CompilerSigil::NamedArgumentRefClose.get_str("ch").unwrap(),
+                                        CompilerSigil::TokenStart.sequence(),
+                                        CompilerSigil::NamedArgumentRefOpen.sequence(),
+                                        CompilerSigil::NamedArgumentRefClose.sequence(),
                                     )
                                 })
                             }
-                            parts.push(CompilerToken::NamedArgumentRef(buffer_key.clone()));
-                            state = CompilerTokenizerState::Copying(String::new());
+                            parts.push(CompilerToken::NamedArgumentRef(Cow::Borrowed(&s[start..range.start])));
+                            state = CompilerTokenizerState::Copying(range.end, None);
                         }
                         CompilerSigil::PositionDot |
-                        CompilerSigil::Non(_) => buffer_key.push(ch),
+                        CompilerSigil::Non(_) => {}
                         _ => {
-                            return Err(Error {
+                            fail!(Error {
                                 kind: ErrorKind::IllegalSymbol,
                                 message: format!(
                                     "Illegal character '{}' in '{}', expected a '{:?}' symbol '{:?}'", 
-                                    ch, s, CompilerSigil::NamedArgumentRefClose, CompilerSigil::NamedArgumentRefClose.get_str("ch")
+                                    ch, s, CompilerSigil::NamedArgumentRefClose, CompilerSigil::NamedArgumentRefClose.sequence()
                                 )
                             })
                         }
                     }
                 }
-                CompilerTokenizerState::CopyingUnamedArgumentRef(ref mut buffer_key) => {
-                    match CompilerSigil::from(ch) {
+                CompilerTokenizerState::CopyingUnamedArgumentRef(start) => {
+                    match sigil {
                         CompilerSigil::UnamedArgumentRefClose => {
-                            if buffer_key.is_empty() {
-                                return Err(Error {
+                            let reference = &s[start..range.start];
+                            if reference.is_empty() {
+                                fail!(Error {
                                     kind: ErrorKind::EmptyReference,
                                     message: format!(
                                         "Empty unamed argument reference `{}{}{}` inside of a compilable name `{s}`",
-                                        CompilerSigil::TokenStart.get_str("ch").unwrap(),
-                                        CompilerSigil::UnamedArgumentRefOpen.get_str("ch").unwrap(),
-                                        CompilerSigil::UnamedArgumentRefClose.get_str("ch").unwrap(),
+                                        CompilerSigil::TokenStart.sequence(),
+                                        CompilerSigil::UnamedArgumentRefOpen.sequence(),
+                                        CompilerSigil::UnamedArgumentRefClose.sequence(),
                                     )
                                 })
                             }
-                            let Ok(value) = buffer_key.clone().parse::<usize>() else {
-                                return Err(Error {
+                            let edge = edge_reference(reference);
+                            match (edge, reference.parse::<usize>()) {
+                                (None, Ok(value)) => parts.push(CompilerToken::UnamedArgumentRef(value)),
+                                (None, Err(_)) if is_member_name(reference) => 
+                                    parts.push(CompilerToken::MemberRef(Cow::Borrowed(reference))),
+                                (Some((edge, reference)), _) if let Ok(value) = reference.parse::<usize>() =>
+                                    parts.push(CompilerToken::EdgeArgumentRef(edge, value)),
+                                (Some((edge, reference)), _) if is_member_name(reference) =>
+                                    parts.push(CompilerToken::EdgeMemberRef(edge, Cow::Borrowed(reference))),
+                                _ => {
+                                    fail!(Error {
+                                        kind: ErrorKind::InvalidReference,
+                                        message: format!(
+                                            "Couldn't convert `{}` into a number or a member name for token {:?}", 
+                                            reference, CompilerToken::UnamedArgumentRef(0)
+                                        )
+                                    })
+                                }
+                            }
+                            state = CompilerTokenizerState::Copying(range.end, None);
+                        }
+                        CompilerSigil::PositionDot |
+                        CompilerSigil::Non(_) => {}
+                        _ => {
+                            fail!(Error {
+                                kind: ErrorKind::IllegalSymbol,
+                                message: format!(
+                                    "Illegal character '{}' in '{}', expected a '{:?}' symbol '{:?}'", 
+                                    ch, s, CompilerSigil::UnamedArgumentRefClose, CompilerSigil::UnamedArgumentRefClose.sequence()
+                                )
+                            })
+                        }
+                    }
+                }
+                CompilerTokenizerState::CopyingLeftoverArgumentRef(start) => {
+                    match sigil {
+                        CompilerSigil::LeftoverArgumentRefClose => {
+                            let reference = &s[start..range.start];
+                            let Ok(value) = reference.parse::<usize>() else {
+                                fail!(Error {
                                     kind: ErrorKind::InvalidReference,
                                     message: format!(
                                         "Couldn't convert `{}` into a number for token {:?}", 
-                                        buffer_key, CompilerToken::UnamedArgumentRef(0)
+                                        reference, CompilerToken::LeftoverArgumentRef(0)
                                     )
                                 })
                             };
-                            parts.push(CompilerToken::UnamedArgumentRef(value));
-                            state = CompilerTokenizerState::Copying(String::new());
+                            parts.push(CompilerToken::LeftoverArgumentRef(value));
+                            state = CompilerTokenizerState::Copying(range.end, None);
                         }
-                        CompilerSigil::PositionDot |
-                        CompilerSigil::Non(_) => buffer_key.push(ch),
+                        CompilerSigil::Non(_) => {}
                         _ => {
-                            return Err(Error {
+                            fail!(Error {
                                 kind: ErrorKind::IllegalSymbol,
                                 message: format!(
                                     "Illegal character '{}' in '{}', expected a '{:?}' symbol '{:?}'", 
-                                    ch, s, CompilerSigil::UnamedArgumentRefClose, CompilerSigil::UnamedArgumentRefClose.get_str("ch")
+                                    ch, s, CompilerSigil::LeftoverArgumentRefClose, CompilerSigil::LeftoverArgumentRefClose.sequence()
                                 )
                             })
                         }
                     }
                 }
-                CompilerTokenizerState::CopyingSkipLast(ref mut buffer_key) => {
-                    // log::trace!("sl: {ch}");
-                    match CompilerSigil::from(ch) {
+                CompilerTokenizerState::CopyingSkipLast(start, ref mut unescaped) => {
+                    match sigil {
                         CompilerSigil::SkipLastClose => {
-                            if buffer_key.is_empty() {
-                                return Err(Error {
+                            let text = tokenized_text(s, start, range.start, unescaped.take());
+                            if text.is_empty() {
+                                fail!(Error {
                                     kind: ErrorKind::EmptyReference,
                                     message: format!(
                                         "Empty skip last token `{}{}{}` inside of a compilable name `{s}`",
-                                        CompilerSigil::TokenStart.get_str("ch").unwrap(),
-                                        CompilerSigil::SkipLastOpen.get_str("ch").unwrap(),
-                                        CompilerSigil::SkipLastClose.get_str("ch").unwrap(),
+                                        CompilerSigil::TokenStart.sequence(),
+                                        CompilerSigil::SkipLastOpen.sequence(),
+                                        CompilerSigil::SkipLastClose.sequence(),
                                     )
                                 })
                             }
-                            parts.push(CompilerToken::SkipLast(buffer_key.clone()));
-                            state = CompilerTokenizerState::Copying(String::new());
+                            parts.push(skip_token(text));
+                            state = CompilerTokenizerState::Copying(range.end, None);
                         }
                         CompilerSigil::TokenEmbed => {
-                            state = CompilerTokenizerState::CopyingSkipLastEmbed(buffer_key.to_owned())
+                            state = CompilerTokenizerState::CopyingSkipLastEmbed(
+                                tokenized_text(s, start, range.start, unescaped.take()).into_owned()
+                            )
+                        }
+                        _ => if let Some(unescaped) = unescaped {
+                            unescaped.push_str(ch)
                         }
-                        _ => buffer_key.push(ch)
                     }
                 }
-                CompilerTokenizerState::CopyingSkipLastEmbed(ref mut buffer_key) => {
-                    // log::trace!("sle: {ch}");
-                    match CompilerSigil::from(ch) {
+                CompilerTokenizerState::CopyingSkipLastEmbed(ref mut buffer) => {
+                    match sigil {
                         CompilerSigil::SkipLastClose |
                         CompilerSigil::TokenEmbed => {
-                            buffer_key.push(ch);
+                            buffer.push_str(ch);
                         }
                         _ => {
-                            return Err(Error{
+                            fail!(Error{
                                 kind: ErrorKind::IllegalSymbol,
                                 message: format!(
                                     "Expected a {:?} symbol {:?} or {:?} symbol {:?} after {ch}",
                                         CompilerSigil::SkipLastClose,
-                                        CompilerSigil::SkipLastClose.get_str("ch"),
+                                        CompilerSigil::SkipLastClose.sequence(),
                                         CompilerSigil::TokenEmbed,
-                                        CompilerSigil::TokenEmbed.get_str("ch")
+                                        CompilerSigil::TokenEmbed.sequence()
                                 )
                             })
                         }
                     }
-                    state = CompilerTokenizerState::CopyingSkipLast(buffer_key.to_owned());
+                    state = CompilerTokenizerState::CopyingSkipLast(range.end, Some(std::mem::take(buffer)));
                 }
             }
         }
 
         log::trace!(
             "{}: {}",
-            format!("[CompilerToken::tokenize]").bold(),
+            "[CompilerToken::tokenize]".to_string().bold(),
             format!("Last state {:?}", state).dimmed()
         );
         match state {
-            CompilerTokenizerState::Copying(buffer) => {
-                if !buffer.is_empty() {
-                    parts.push(CompilerToken::Raw(buffer))
+            CompilerTokenizerState::Skipping => {}
+            CompilerTokenizerState::Copying(start, unescaped) => {
+                let text = tokenized_text(s, start, s.len(), unescaped);
+                if !text.is_empty() {
+                    parts.push(CompilerToken::Raw(text))
                 }
             }
             CompilerTokenizerState::EmbedFound(_) => {
-                return Err(Error{
+                fail_at_end!(Error{
                     kind: ErrorKind::IllegalSymbol,
                     message: format!(
                         "Expected a {:?} symbol {:?} or {:?} symbol {:?} after {:?}",
                             CompilerSigil::TokenStart,
-                            CompilerSigil::TokenStart.get_str("ch"),
+                            CompilerSigil::TokenStart.sequence(),
                             CompilerSigil::TokenEmbed,
-                            CompilerSigil::TokenEmbed.get_str("ch"),
-                            CompilerSigil::TokenEmbed.get_str("ch")
+                            CompilerSigil::TokenEmbed.sequence(),
+                            CompilerSigil::TokenEmbed.sequence()
                     )
                 })
             }
             CompilerTokenizerState::SigilFound => {
-                return Err(Error {
+                fail_at_end!(Error {
                     kind: ErrorKind::InvalidToken,
                     message: format!(
                         "'{:?}' symbol '{:?}' found with no body to go along side it in '{}'", 
-                        CompilerSigil::TokenStart, CompilerSigil::TokenStart.get_str("ch"), s
+                        CompilerSigil::TokenStart, CompilerSigil::TokenStart.sequence(), s
                     )
                 })
             }
             CompilerTokenizerState::CopyingNamedArgumentRef(_) |
             CompilerTokenizerState::CopyingUnamedArgumentRef(_) |
+            CompilerTokenizerState::CopyingLeftoverArgumentRef(_) |
             CompilerTokenizerState::CopyingSkipLastEmbed(_) |
-            CompilerTokenizerState::CopyingSkipLast(_) => {
-                return Err(Error {
+            CompilerTokenizerState::CopyingSkipLast(..) => {
+                fail_at_end!(Error {
                     kind: ErrorKind::InvalidToken,
                     message: format!(
                         "Unfinished token at the end of a compilable '{}'", s)
@@ -328,27 +552,58 @@ impl CompilerToken {
             }
         }
 
-        Ok(parts)
+        Ok((parts, diagnostics))
+
+    }
+
+    /// Lenient [CompilerToken::tokenize] for strings that aren't compiled,
+    /// every token start is tokenized up to the next one on its own and
+    /// whatever doesn't tokenize is skipped. Only the tokens are returned.
+    pub(crate) fn scan(s: &'a str) -> Vec<CompilerToken<'a>> {
+
+        let start = CompilerSigil::TokenStart.sequence();
+        let embed = CompilerSigil::TokenEmbed.sequence();
+
+        let starts: Vec<usize> = s
+            .match_indices(start.as_str())
+            .map(|(i, _)| i)
+            .filter(|i| !s[..*i].ends_with(embed.as_str()))
+            .collect();
+
+        starts
+            .iter()
+            .enumerate()
+            .filter_map(|(i, begin)| {
+                let end = starts.get(i + 1).copied().unwrap_or(s.len());
+                Self::tokenize(&s[*begin..end]).ok()
+            })
+            .flatten()
+            .filter(|token| !matches!(token, CompilerToken::Raw(_)))
+            .collect()
 
     }
 
     fn tokenize_surface(
-        s: &str
-    ) -> Result<Vec<CompilerToken>, Error> {
+        s: &'a str
+    ) -> Result<Vec<CompilerToken<'a>>, Error> {
 
         let mut tokens = Self::tokenize(s)?;
 
         for token in tokens.iter_mut() {
 
             match token.get_bool("surface") {
+                // stays escaped, the surface compiled string is tokenized
+                // again when it's baked.
+                Some(true) if matches!(token, CompilerToken::Raw(_)) =>
+                    *token = CompilerToken::Raw(Cow::Owned(token.untokenize())),
                 Some(true) => (),
                 None | Some(false) => {
                     log::trace!(
                         "{}: {}",
-                        format!("[CompilerToken::tokenize_surface]").bold(),
+                        "[CompilerToken::tokenize_surface]".to_string().bold(),
                         format!("Untokenized token: {:?}", token).dimmed()
                     );
-                    *token = CompilerToken::Raw(token.untokenize())
+                    *token = CompilerToken::Raw(Cow::Owned(token.untokenize()))
                 }
             }
 
@@ -358,72 +613,159 @@ impl CompilerToken {
 
     }
 
-    fn untokenize(&self) -> String {
+    pub(crate) fn untokenize(&self) -> String {
         match self {
             Self::Raw(value) => value
                 .replace( // first.
-                    format!("{}", 
-                        CompilerSigil::TokenEmbed.get_str("ch").unwrap(),
-                    ).as_str(), 
+                    CompilerSigil::TokenEmbed.sequence().to_string().as_str(), 
                     format!("{}{}",
-                        CompilerSigil::TokenEmbed.get_str("ch").unwrap(),
-                        CompilerSigil::TokenEmbed.get_str("ch").unwrap(),
+                        CompilerSigil::TokenEmbed.sequence(),
+                        CompilerSigil::TokenEmbed.sequence(),
                     ).as_str()
                 )
                 .replace(
-                    format!("{}",
-                        CompilerSigil::TokenStart.get_str("ch").unwrap()
-                    ).as_str(), 
+                    CompilerSigil::TokenStart.sequence().to_string().as_str(), 
                     format!("{}{}",
-                        CompilerSigil::TokenEmbed.get_str("ch").unwrap(),
-                        CompilerSigil::TokenStart.get_str("ch").unwrap()
+                        CompilerSigil::TokenEmbed.sequence(),
+                        CompilerSigil::TokenStart.sequence()
                     ).as_str()
                 ).to_owned(),
             Self::Position => 
                 format!("{}{}", 
-                    CompilerSigil::TokenStart.get_str("ch").unwrap(),
-                    CompilerSigil::PositionDot.get_str("ch").unwrap()
+                    CompilerSigil::TokenStart.sequence(),
+                    CompilerSigil::PositionDot.sequence()
                 ),
             Self::NamedArgumentRef(value) => 
-                CompilerSigil::TokenStart.get_str("ch").unwrap().to_owned() +
-                CompilerSigil::NamedArgumentRefOpen.get_str("ch").unwrap() +
-                value.to_string().as_str() + 
-                CompilerSigil::NamedArgumentRefClose.get_str("ch").unwrap(),
+                CompilerSigil::TokenStart.sequence().to_owned() +
+                CompilerSigil::NamedArgumentRefOpen.sequence().as_str() +
+                value.to_string().as_str() +
+                CompilerSigil::NamedArgumentRefClose.sequence().as_str(),
             Self::UnamedArgumentRef(value) => 
-                CompilerSigil::TokenStart.get_str("ch").unwrap().to_owned() +
-                CompilerSigil::UnamedArgumentRefOpen.get_str("ch").unwrap() +
-                value.to_string().as_str() + 
-                CompilerSigil::UnamedArgumentRefClose.get_str("ch").unwrap(),
+                CompilerSigil::TokenStart.sequence().to_owned() +
+                CompilerSigil::UnamedArgumentRefOpen.sequence().as_str() +
+                value.to_string().as_str() +
+                CompilerSigil::UnamedArgumentRefClose.sequence().as_str(),
+            Self::MemberRef(value) => 
+                CompilerSigil::TokenStart.sequence().to_owned() +
+                CompilerSigil::UnamedArgumentRefOpen.sequence().as_str() +
+                value.as_ref() +
+                CompilerSigil::UnamedArgumentRefClose.sequence().as_str(),
+            Self::LeftoverArgumentRef(value) => 
+                CompilerSigil::TokenStart.sequence().to_owned() +
+                CompilerSigil::LeftoverArgumentRefOpen.sequence().as_str() +
+                value.to_string().as_str() +
+                CompilerSigil::LeftoverArgumentRefClose.sequence().as_str(),
+            Self::EdgeArgumentRef(edge, value) =>
+                CompilerSigil::TokenStart.sequence().to_owned() +
+                CompilerSigil::UnamedArgumentRefOpen.sequence().as_str() +
+                edge_name(*edge) +
+                EDGE_SEPARATOR.to_string().as_str() +
+                value.to_string().as_str() +
+                CompilerSigil::UnamedArgumentRefClose.sequence().as_str(),
+            Self::EdgeMemberRef(edge, value) =>
+                CompilerSigil::TokenStart.sequence().to_owned() +
+                CompilerSigil::UnamedArgumentRefOpen.sequence().as_str() +
+                edge_name(*edge) +
+                EDGE_SEPARATOR.to_string().as_str() +
+                value.as_ref() +
+                CompilerSigil::UnamedArgumentRefClose.sequence().as_str(),
             Self::SkipLast(value) => 
-                CompilerSigil::TokenStart.get_str("ch").unwrap().to_owned() +
-                CompilerSigil::SkipLastOpen.get_str("ch").unwrap() +
-                value
-                    .replace( // first.
-                        format!("{}", 
-                            CompilerSigil::TokenEmbed.get_str("ch").unwrap(),
-                        ).as_str(), 
-                        format!("{}{}",
-                            CompilerSigil::TokenEmbed.get_str("ch").unwrap(),
-                            CompilerSigil::TokenEmbed.get_str("ch").unwrap(),
-                        ).as_str()
-                    )
-                    .replace(
-                        format!("{}", 
-                            CompilerSigil::SkipLastClose.get_str("ch").unwrap(),
-                        ).as_str(), 
-                        format!("{}{}",
-                            CompilerSigil::TokenEmbed.get_str("ch").unwrap(),
-                            CompilerSigil::SkipLastClose.get_str("ch").unwrap(),
-                        ).as_str()
-                    )
-                    .as_str() + 
-                CompilerSigil::SkipLastClose.get_str("ch").unwrap()
+                CompilerSigil::TokenStart.sequence().to_owned() +
+                CompilerSigil::SkipLastOpen.sequence().as_str() +
+                escape_skip_text(value).as_str() + 
+                CompilerSigil::SkipLastClose.sequence().as_str(),
+            Self::Skip { count, edge, text } => 
+                CompilerSigil::TokenStart.sequence().to_owned() +
+                CompilerSigil::SkipLastOpen.sequence().as_str() +
+                match edge {
+                    SkipEdge::First => SKIP_FIRST_MARKER.to_string(),
+                    SkipEdge::Last => String::new()
+                }.as_str() +
+                count.to_string().as_str() +
+                SKIP_COUNT_SEPARATOR.to_string().as_str() +
+                escape_skip_text(text).as_str() + 
+                CompilerSigil::SkipLastClose.sequence().as_str()
             
         }
     }
 
 }
 
+fn edge_name(edge: SkipEdge) -> &'static str {
+    match edge {
+        SkipEdge::First => FIRST_GROUP,
+        SkipEdge::Last => LAST_GROUP
+    }
+}
+
+/// Embed the sigils inside of the text of a `$[...]`.
+fn escape_skip_text(text: &str) -> String {
+    text
+        .replace( // first.
+            CompilerSigil::TokenEmbed.sequence().to_string().as_str(), 
+            format!("{}{}",
+                CompilerSigil::TokenEmbed.sequence(),
+                CompilerSigil::TokenEmbed.sequence(),
+            ).as_str()
+        )
+        .replace(
+            CompilerSigil::SkipLastClose.sequence().to_string().as_str(), 
+            format!("{}{}",
+                CompilerSigil::TokenEmbed.sequence(),
+                CompilerSigil::SkipLastClose.sequence(),
+            ).as_str()
+        )
+}
+
+/// Member names are identifiers so that they never look like a index.
+pub(crate) fn is_member_name(s: &str) -> bool {
+    s.chars().next().is_some_and(|ch| ch.is_alphabetic() || ch == '_')
+        && s.chars().all(|ch| ch.is_alphanumeric() || ch == '_')
+}
+
+/// Turn every [CompilerToken::MemberRef] into the index of the member,
+/// with a [crate::config::Group] numeric references are checked
+/// against the group size aswell.
+fn resolve_members<'a>(
+    tokens: Vec<CompilerToken<'a>>,
+    core: &Core
+) -> Result<Vec<CompilerToken<'a>>, Error> {
+
+    let members = core.group.as_ref().map(Group::names);
+
+    tokens
+        .into_iter()
+        .map(|token| match (token, members.as_ref()) {
+            (CompilerToken::MemberRef(name), Some(members)) => members
+                .iter()
+                .position(|member| *member == name)
+                .map(CompilerToken::UnamedArgumentRef)
+                .ok_or_else(|| Error {
+                    kind: ErrorKind::NonExistantArgument,
+                    message: format!("Group member `{name}` does not exist, the members are {members:?}")
+                }),
+            (CompilerToken::EdgeMemberRef(edge, name), Some(members)) => members
+                .iter()
+                .position(|member| *member == name)
+                .map(|n| CompilerToken::EdgeArgumentRef(edge, n))
+                .ok_or_else(|| Error {
+                    kind: ErrorKind::NonExistantArgument,
+                    message: format!("Group member `{name}` does not exist, the members are {members:?}")
+                }),
+            (CompilerToken::MemberRef(name) | CompilerToken::EdgeMemberRef(_, name), None) => Err(Error {
+                kind: ErrorKind::NonExistantArgument,
+                message: format!("Group member `{name}` referenced without a [core.group] naming the members")
+            }),
+            (CompilerToken::UnamedArgumentRef(n) | CompilerToken::EdgeArgumentRef(_, n), Some(members)) if n >= members.len() => Err(Error {
+                kind: ErrorKind::InvalidReference,
+                message: format!("Argument {n} is out of range for a group of {} members {members:?}", members.len())
+            }),
+            (token, _) => Ok(token)
+        })
+        .collect()
+
+}
+
 fn compile_surface_string(
     compilable_string: PreprocessableString,
     named: &HashMap<String, PreprocessableString>
@@ -462,7 +804,15 @@ fn compile_surface_string(
         match token {
             CompilerToken::Raw(ref value) => compiled_surface_string.push_str(value),
             CompilerToken::NamedArgumentRef(ref value) => {
-                let Some(entry) = named.get(value) else {
+                let Some(entry) = named.get(value.as_ref()) else {
+                    // not a argument, maybe a token handler of the plugin script.
+                    if let Some(handled) = plugin::handle(value) {
+                        compiled_surface_string.push_str(&handled.map_err(|err| Error {
+                            kind: ErrorKind::Plugin,
+                            message: err.to_string()
+                        })?);
+                        continue
+                    }
                     return Err(Error { 
                         kind: ErrorKind::NonExistantArgument, 
                         message: format!("Argument with key '{}' does not exist, occured when trying to compile '{:?}'", value, inner)
@@ -486,7 +836,7 @@ fn compile_surface_string(
                     }
                     Preprocessable::Preprocessed(value) => value
                 };
-                compiled_surface_string.push_str(entry_inner);
+                compiled_surface_string.push_str(&CompilerToken::Raw(Cow::Borrowed(entry_inner)).untokenize());
             }
             _ => unreachable!()
         }
@@ -516,14 +866,40 @@ fn compile_surface_string(
 
 fn compile_surface_strings(
     compilable_strings: Vec<PreprocessableString>,
-    named: &HashMap<String, PreprocessableString>
+    named: &HashMap<String, PreprocessableString>,
+    substitutions: &SubstitutionTraces,
+    surface_compilations: &SubstitutionTraces,
+    metadata: &Metadata
 ) -> Result<(), Error> {
 
     for compilable in compilable_strings {
+        let trace_key = substitution_trace_key(&compilable);
+        let before = read_preprocessed(&compilable).ok();
         compile_surface_string(
-            compilable,
+            compilable.clone(),
             named
-        )?;
+        ).map_err(|mut err| {
+            // show where the string came from if keys were substituted in.
+            if let Ok(traces) = substitutions.read()
+                && let Some(trace) = traces.get(&trace_key) {
+                err.message.push_str("\nhelp: the string was produced by preprocessing:");
+                for step in trace {
+                    err.message.push_str(format!("\n    {step}").as_str());
+                }
+            }
+            err.message = metadata.annotate(err.message);
+            err
+        })?;
+        if let Some(before) = before
+            && let Ok(after) = read_preprocessed(&compilable)
+            && before != after {
+            surface_compilations.write()
+                .map_err(|err| Error {
+                    kind: ErrorKind::PoisonedLock,
+                    message: err.to_string()
+                })?
+                .insert(trace_key, vec![before, format!("after surface compiling: {after}")]);
+        }
     }
 
     Ok(())
@@ -546,24 +922,245 @@ fn generate_repeat_name(
 
 }
 
+/// Every ladder is picked with the same picker.
 fn generate_repeat_picker_macro_name(
-    common: &Common,
-    suffix: usize
+    common: &Common
 ) -> String {
 
     let mut name = String::new();
     name.push_str(&common.keyable.prefix);
     name.push_str(REPEAT_SECTION_SUFFIX);
-    name.push_str(suffix.to_string().as_str());
     name
 
 }
 
-fn compile_and_assemble_repeat_string(
+/// Step of the ladder `suffix` that argument counts outside of its
+/// [RepeatRange] end up in.
+fn generate_out_of_range_name(
+    common: &Common,
+    suffix: usize
+) -> String {
+    format!("{}{REPEAT_SECTION_SUFFIX}{suffix}_RANGE", common.keyable.prefix)
+}
+
+/// Step every ladder picks when there are more varadict arguments than
+/// the ladders have steps.
+fn generate_too_many_name(
+    common: &Common
+) -> String {
+    format!("{}{REPEAT_SECTION_SUFFIX}TOO_MANY", common.keyable.prefix)
+}
+
+/// `name` with the prefix, for the helpers of the picker.
+fn generate_helper_name(
+    common: &Common,
+    name: &str
+) -> String {
+    format!("{}{name}", common.keyable.prefix)
+}
+
+/// `callee(arguments)` through the call helper, so that the commas
+/// the comma helper expands into separate the arguments.
+fn call_with_varadict(
+    common: &Common,
+    callee: &str,
+    arguments: &str
+) -> String {
+    format!("{}({callee}, {arguments})", generate_helper_name(common, CALL_SUFFIX))
+}
+
+/// A comma only when there are varadict arguments, goes into
+/// [call_with_varadict].
+fn comma_if_varadict(
+    common: &Common
+) -> String {
+    format!("{}({VARIADIC_ARGUMENTS})", generate_helper_name(common, COMMA_SUFFIX))
+}
+
+/// If `generator` has a ladder step for `count` varadict arguments.
+fn handles(
     generator: &Generator,
-    common:    &Common,
-    core:      &Core,
-    suffix:    usize
+    count: usize
+) -> bool {
+    generator.repeats.is_none_or(|repeats| repeats.contains(count))
+}
+
+/// Replace [CompilerToken::LeftoverArgumentRef]s inside of a unparity
+/// fallback with the last `remainder` of the `count` arguments, references
+/// past the remainder expand to nothing so one fallback can handle every
+/// remainder. Everything else is left as it was written.
+fn compile_unparity_fallback(
+    fallback: &str,
+    count: usize,
+    remainder: usize
+) -> Result<String, Error> {
+
+    Ok(CompilerToken::tokenize(fallback)?
+        .iter()
+        .map(|token| match token {
+            CompilerToken::LeftoverArgumentRef(n) if *n < remainder => 
+                format!("__{}__", count - remainder + n),
+            CompilerToken::LeftoverArgumentRef(_) => String::new(),
+            CompilerToken::Raw(s) => s.to_string(),
+            _ => token.untokenize()
+        })
+        .collect())
+
+}
+
+/// Escape `s` so it can be put inside of a C string literal.
+fn escape_c_string(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// `_Pragma` that warns about `name` being deprecated wherever the macro
+/// it's in gets expanded, the message ends up inside of two string
+/// literals so it gets escaped twice.
+/// 
+/// `GCC warning` instead of `message` since GCC refuses a `message`
+/// pragma in the middle of a expression, clang understands both.
+fn deprecation_pragma(name: &str, reason: &str) -> String {
+    let message = escape_c_string(&format!("{name} is deprecated: {reason}"));
+    format!("_Pragma(\"{}\") ", escape_c_string(&format!("GCC warning \"{message}\"")))
+}
+
+/// Docs can't end the comment they are written into.
+fn comment_safe(doc: &str) -> String {
+    doc.replace("*/", "* /")
+}
+
+/// Comment naming the member every one of the `count` arguments is,
+/// [None] without a [Group] or when `count` isn't whole groups.
+fn member_docs_comment(
+    core: &Core,
+    count: usize
+) -> Option<String> {
+
+    let members = &core.group.as_ref()?.members;
+    if members.is_empty() || !count.is_multiple_of(members.len()) {
+        return None
+    }
+
+    let arguments: Vec<String> = (0..count)
+        .map(|i| {
+            let member = &members[i % members.len()];
+            match member.doc().map(comment_safe) {
+                Some(doc) => format!("__{i}__ {} ({doc})", member.name()),
+                None => format!("__{i}__ {}", member.name())
+            }
+        })
+        .collect();
+
+    Some(format!("/* {} */\n", arguments.join(", ")))
+
+}
+
+/// Doxygen block describing the [Group] members, written above the
+/// main `xmva` with [MemberDocs::Main].
+fn assemble_member_docs(
+    core: &Core
+) -> String {
+
+    let Some(group) = &core.group else {
+        return String::new()
+    };
+
+    let mut docs = String::new();
+    docs.push_str("/**\n");
+    docs.push_str(format!(" * @brief Takes the varadict arguments in groups of {}:\n", group.members.len()).as_str());
+    for member in group.members.iter() {
+        match member.doc().map(comment_safe) {
+            Some(doc) => docs.push_str(format!(" * - `{}`: {doc}\n", member.name()).as_str()),
+            None => docs.push_str(format!(" * - `{}`\n", member.name()).as_str())
+        }
+    }
+    docs.push_str(" */\n");
+    docs
+
+}
+
+/// How the main macro is called, the named parameters and the shape of
+/// the varadict groups, `(name, ..., groups of 2: value, string)`.
+pub(crate) fn main_macro_shape(
+    core: &Core
+) -> Result<String, Error> {
+
+    let mut parameters: Vec<String> = vec![];
+    for arg in core.args.iter() {
+        if let Argument::Named(named) = arg {
+            parameters.push(read_preprocessed(&named.name)?);
+        }
+    }
+    parameters.push(VARIADIC_PARAMETER.to_owned());
+
+    let mut shape = parameters.join(", ");
+    if let Some(varadict) = core.varadict().filter(|varadict| *varadict > 0) {
+        shape.push_str(&format!(", groups of {varadict}"));
+        if let Some(group) = &core.group {
+            shape.push_str(&format!(": {}", group.names().join(", ")));
+        }
+    }
+    Ok(format!("({shape})"))
+
+}
+
+/// The argument of a [CompilerToken::EdgeArgumentRef] in a step with
+/// `groups` groups of `group_size`.
+fn edge_argument(
+    edge: SkipEdge,
+    n: usize,
+    groups: usize,
+    group_size: usize
+) -> String {
+    match edge {
+        SkipEdge::First => format!("__{n}__"),
+        SkipEdge::Last => format!("__{}__", n + (groups - 1) * group_size)
+    }
+}
+
+/// The preamble or postamble of a generator for a step with `groups`
+/// groups, `$.` is the group count and `$(first.0)`/`$(last.0)` the
+/// arguments of the first and last group. The rest only makes sense for
+/// a single group.
+fn compile_amble(
+    tokens: &[CompilerToken],
+    groups: usize,
+    group_size: usize
+) -> Result<String, Error> {
+
+    let mut compiled = String::new();
+    for token in tokens {
+        match token {
+            CompilerToken::Raw(s) => compiled.push_str(s),
+            CompilerToken::Position => compiled.push_str(&groups.to_string()),
+            CompilerToken::EdgeArgumentRef(edge, n) => compiled.push_str(&edge_argument(*edge, *n, groups, group_size)),
+            CompilerToken::NamedArgumentRef(_) |
+            CompilerToken::MemberRef(_) |
+            CompilerToken::EdgeMemberRef(..) => unreachable!(),
+            CompilerToken::UnamedArgumentRef(_) |
+            CompilerToken::LeftoverArgumentRef(_) |
+            CompilerToken::SkipLast(_) |
+            CompilerToken::Skip { .. } => {
+                return Err(Error {
+                    kind: ErrorKind::InvalidToken,
+                    message: format!(
+                        "`{}` only means something in the repeat string, a preamble or postamble can use `$.` (the group count), `$(first.N)` and `$(last.N)`",
+                        token.untokenize()
+                    )
+                })
+            }
+        }
+    }
+    Ok(compiled)
+
+}
+
+pub(crate) fn compile_and_assemble_repeat_string(
+    generator:   &Generator,
+    common:      &Common,
+    core:        &Core,
+    output:      &Output,
+    suffix:      usize
 ) -> Result<String, Error> {
 
     let read_guard = generator.fallbacks.empty.read()
@@ -736,19 +1333,30 @@ fn compile_and_assemble_repeat_string(
         Preprocessable::Preprocessed(le_stranger) => le_stranger
     };
 
-    let le_tokens = CompilerToken::tokenize(le_stranger)?;
+    let le_tokens = resolve_members(CompilerToken::tokenize(le_stranger)?, core)?;
+    let preamble_tokens = resolve_members(CompilerToken::tokenize(&preamble)?, core)?;
+    let postamble_tokens = resolve_members(CompilerToken::tokenize(&postamble)?, core)?;
     let mut generated_repeats = String::new();
 
-    generated_repeats.push_str("#define ");
-    generated_repeats.push_str(generate_repeat_name(common, 0, suffix).as_str());
-    generated_repeats.push('(');
-    generated_repeats.push_str(named_args.join(", ").as_str());
-    generated_repeats.push(')');
-    generated_repeats.push(' ');
-    generated_repeats.push_str(fallback_empty.as_str());
-    generated_repeats.push('\n');
+    if handles(generator, 0) {
+        generated_repeats.push_str("#define ");
+        generated_repeats.push_str(generate_repeat_name(common, 0, suffix).as_str());
+        generated_repeats.push('(');
+        generated_repeats.push_str(named_args.join(", ").as_str());
+        generated_repeats.push(')');
+        generated_repeats.push(' ');
+        // compiled like a unparity fallback without arguments, so that
+        // escapes don't end up in the output as they were written.
+        generated_repeats.push_str(&compile_unparity_fallback(&fallback_empty, 0, 0)?);
+        generated_repeats.push('\n');
+    }
+
+    for current_repetiton in (1..common.repeats).filter(|count| handles(generator, *count)) {
 
-    for current_repetiton in 1..common.repeats {
+        if output.member_docs == MemberDocs::Repeats
+            && let Some(comment) = member_docs_comment(core, current_repetiton) {
+            generated_repeats.push_str(&comment);
+        }
         
         generated_repeats.push_str("#define ");
         generated_repeats.push_str(generate_repeat_name(common, current_repetiton, suffix).as_str());
@@ -768,18 +1376,45 @@ fn compile_and_assemble_repeat_string(
         
         if current_repetiton % va_args == 0 {
 
-            generated_repeats.push_str(preamble.as_str());
-
+            // wrapped every repeat gets its own line, inside of the
+            // preamble and postamble.
+            let repeat_indent = output.indent.level(match preamble.is_empty() {
+                true => 1,
+                false => 2
+            });
             let j = current_repetiton/va_args;
 
+            if output.wrap && !preamble.is_empty() {
+                push_continued_line(&mut generated_repeats, &output.indent.level(1));
+            }
+            generated_repeats.push_str(&compile_amble(&preamble_tokens, j, va_args)?);
+
             for i in 0..j {
+
+                if output.wrap {
+                    push_continued_line(&mut generated_repeats, &repeat_indent);
+                }
                 
                 for token in le_tokens.iter() {
 
                     match token {
-                        CompilerToken::NamedArgumentRef(_) => unreachable!(),
+                        CompilerToken::NamedArgumentRef(_) |
+                        CompilerToken::MemberRef(_) |
+                        CompilerToken::EdgeMemberRef(..) => unreachable!(),
+                        CompilerToken::EdgeArgumentRef(edge, n) => {
+                            generated_repeats.push_str(&edge_argument(*edge, *n, j, va_args))
+                        }
+                        CompilerToken::LeftoverArgumentRef(_) => {
+                            return Err(Error {
+                                kind: ErrorKind::InvalidToken,
+                                message: format!(
+                                    "{:?} can only be used inside of a unparity fallback, found one in `{le_stranger}`",
+                                    token
+                                )
+                            })
+                        }
                         CompilerToken::Raw(s) => {
-                            generated_repeats.push_str(s)
+                            generated_repeats.push_str(s.as_ref())
                         }
                         CompilerToken::Position => {
                             generated_repeats.push_str((i+1).to_string().as_str());
@@ -789,34 +1424,59 @@ fn compile_and_assemble_repeat_string(
                         }
                         CompilerToken::SkipLast(s) => {
                             if j-1 != i {
-                                generated_repeats.push_str(s);
+                                generated_repeats.push_str(s.as_ref());
+                            }
+                        }
+                        CompilerToken::Skip { count, edge, text } => {
+                            let skipped = match edge {
+                                SkipEdge::First => i < *count,
+                                SkipEdge::Last => i + count >= j
+                            };
+                            if !skipped {
+                                generated_repeats.push_str(text.as_ref());
                             }
                         }
                     }
                 }
             }
 
-            generated_repeats.push_str(postamble.as_str())
+            if output.wrap && !postamble.is_empty() {
+                push_continued_line(&mut generated_repeats, &output.indent.level(1));
+            }
+            generated_repeats.push_str(&compile_amble(&postamble_tokens, j, va_args)?)
 
         } else {
-            generated_repeats.push_str(fallback_unparity.as_str());
+            let remainder = current_repetiton % va_args;
+            let unparity = match generator.fallbacks.unparity_remainder.get(&remainder) {
+                Some(unparity) => read_preprocessed(unparity)?,
+                None => fallback_unparity.clone()
+            };
+            generated_repeats.push_str(&compile_unparity_fallback(&unparity, current_repetiton, remainder)?);
         }
 
         generated_repeats.push('\n');
 
     }
 
-    generated_repeats.push_str("#define ");
-    generated_repeats.push_str(generate_repeat_picker_macro_name(common, suffix).as_str());
-    generated_repeats.push('(');
-    generated_repeats.push_str(
-    (0..common.repeats)
-        .map(|i| format!("__{i}__"))
-        .collect::<Vec<String>>()
-        .join(", ")
-        .as_str()
-    );
-    generated_repeats.push_str(", __NAME__, ...) __NAME__");
+    if let Some(repeats) = generator.repeats
+        && (0..common.repeats).any(|count| !repeats.contains(count)) {
+        let max = repeats.last(common.repeats);
+        let message = escape_c_string(&format!(
+            "{}: a generator only handles {} to {max} varadict arguments",
+            read_preprocessed(&core.xmva)?,
+            repeats.min
+        ));
+        generated_repeats.push_str(format!(
+            "#define {}(...) _Pragma(\"{}\")",
+            generate_out_of_range_name(common, suffix),
+            escape_c_string(&format!("GCC error \"{message}\""))
+        ).as_str());
+    }
+
+    // the sections add the newlines between themselves.
+    if generated_repeats.ends_with('\n') {
+        generated_repeats.pop();
+    }
 
     Ok(generated_repeats)
 
@@ -882,19 +1542,101 @@ fn assemble_generator_string(
     generator_macro.push_str(", __GEN__, ...");
     generator_macro.push(')');
     generator_macro.push(' ');
-    generator_macro.push_str("__GEN__(");
-    generator_macro.push_str(named_args.join(", ").as_str());
-    generator_macro.push_str(", __VA_ARGS__)");
+    generator_macro.push_str(&call_with_varadict(
+        common,
+        "__GEN__",
+        &format!("{} {} {VARIADIC_ARGUMENTS}", named_args.join(", "), comma_if_varadict(common))
+    ));
     generator_macro.push('\n');
 
     Ok(generator_macro)
     
 }
 
-fn assemble_main_macro_string(
+/// The repeat ladder a generator picks from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Ladder {
+    /// Generators with identical ladders share one, so this isn't
+    /// always the index of the generator.
+    pub suffix:  usize,
+    /// Counts outside of it are picked into the out of range step.
+    pub repeats: Option<RepeatRange>,
+}
+
+/// The picker every ladder goes through, picks the `__NAME__` after
+/// the arguments. Called as `PICKER(__VA_ARGS__, TOO_MANY, N-1, ..., 0)`
+/// with the comma only there when there are varadict arguments, so no
+/// argument has to stand in for the empty `__VA_ARGS__`.
+/// 
+/// The comma comes from `__VA_OPT__` where it is available and from a
+/// empty argument probe otherwise (the one from Jens Gustedt, sized so
+/// it still works on the boundary), along side the helpers every
+/// ladder is called through and the step for too many arguments.
+pub(crate) fn assemble_picker_string(
+    core: &Core,
+    common: &Common
+) -> Result<String, Error> {
+
+    let helper = |name: &str| generate_helper_name(common, name);
+    let parameters = |count: usize| (0..count)
+        .map(|i| format!("__{i}__"))
+        .collect::<Vec<String>>()
+        .join(", ");
+    let message = escape_c_string(&format!(
+        "{}: takes at most {} varadict arguments, raise `common.repeats`",
+        read_preprocessed(&core.xmva)?,
+        common.repeats.saturating_sub(1)
+    ));
+    // the probe sees one more argument than there are when the first one
+    // starts with a parenthesis.
+    let probed = common.repeats + 2;
+
+    Ok([
+        format!("#define {}(__CALLEE__, ...) __CALLEE__(__VA_ARGS__)", helper(CALL_SUFFIX)),
+        "#if (defined(__cplusplus) && __cplusplus > 201703L) || (defined(__STDC_VERSION__) && __STDC_VERSION__ > 201710L)".to_owned(),
+        format!("#define {}(...) __VA_OPT__(,)", helper(COMMA_SUFFIX)),
+        "#else".to_owned(),
+        format!("#define {}({}, __NAME__, ...) __NAME__", helper("__HAS_COMMA_PICK__"), parameters(probed)),
+        format!("#define {}(...) {}(__VA_ARGS__, {}0, ~)",
+            helper("__HAS_COMMA__"),
+            helper("__HAS_COMMA_PICK__"),
+            "1, ".repeat(probed - 1)
+        ),
+        format!("#define {}(...) ,", helper("__TRIGGER__")),
+        format!("#define {}(_0, _1, _2, _3, _4) _0 ## _1 ## _2 ## _3 ## _4", helper("__PASTE__")),
+        format!("#define {}(_0, _1, _2, _3) {}({}({}, _0, _1, _2, _3))",
+            helper("__IS_EMPTY_CASE__"),
+            helper("__HAS_COMMA__"),
+            helper("__PASTE__"),
+            helper("__EMPTY_")
+        ),
+        format!("#define {}0001 ,", helper("__EMPTY_")),
+        format!("#define {0}(...) {1}({2}(__VA_ARGS__), {2}({3} __VA_ARGS__), {2}(__VA_ARGS__ ()), {2}({3} __VA_ARGS__ ()))",
+            helper("__IS_EMPTY__"),
+            helper("__IS_EMPTY_CASE__"),
+            helper("__HAS_COMMA__"),
+            helper("__TRIGGER__")
+        ),
+        format!("#define {}0 ,", helper("__COMMA_")),
+        format!("#define {}1", helper("__COMMA_")),
+        format!("#define {}(empty) {}({}, empty, , , )", helper("__COMMA_IF__"), helper("__PASTE__"), helper("__COMMA_")),
+        format!("#define {}(...) {}({}(__VA_ARGS__))", helper(COMMA_SUFFIX), helper("__COMMA_IF__"), helper("__IS_EMPTY__")),
+        "#endif".to_owned(),
+        format!("#define {}(...) _Pragma(\"{}\")",
+            generate_too_many_name(common),
+            escape_c_string(&format!("GCC error \"{message}\""))
+        ),
+        format!("#define {}({}, __NAME__, ...) __NAME__", generate_repeat_picker_macro_name(common), parameters(common.repeats)),
+    ].join("\n"))
+
+}
+
+/// `ladders` holds the repeat ladder every generator picks from.
+pub(crate) fn assemble_main_macro_string(
     core: &Core,
     common: &Common,
-    generator_count: usize
+    output: &Output,
+    ladders: &[Ladder]
 ) -> Result<String, Error> {
 
     let read_guard = core.xmva.read()
@@ -963,132 +1705,550 @@ fn assemble_main_macro_string(
     main_macro.push('(');
     main_macro.push_str(named_args.join(", ").as_str());
     main_macro.push_str(", ...) ");
+    if let Some(reason) = &core.deprecated {
+        if output.wrap {
+            push_continued_line(&mut main_macro, &output.indent.level(1));
+        }
+        main_macro.push_str(&deprecation_pragma(&xmva, reason));
+    }
     
-    for i in 0..generator_count {
+    for (i, ladder) in ladders.iter().enumerate() {
+        if output.wrap {
+            push_continued_line(&mut main_macro, &output.indent.level(1));
+        }
+        let steps = (0..common.repeats)
+            .map(|j| match ladder.repeats {
+                Some(repeats) if !repeats.contains(j) => generate_out_of_range_name(common, ladder.suffix),
+                _ => generate_repeat_name(common, j, ladder.suffix)
+            })
+            .rev()
+            .collect::<Vec<String>>()
+            .join(", ");
         main_macro.push_str(generate_generator_macro_name(common, i).as_str());
         main_macro.push('(');
         main_macro.push_str(named_args.join(", ").as_str());
         main_macro.push_str(", ");
-        main_macro.push_str(&generate_repeat_picker_macro_name(common, i).as_str());
-        main_macro.push_str("(\"empty\", ##__VA_ARGS__, ");
-        main_macro.push_str(
-        (0..common.repeats)
-            .map(|j| generate_repeat_name(common, j, i))
-            .rev()
-            .collect::<Vec<String>>()
-            .join(", ")
-            .as_str()
-        );
-        main_macro.push_str("), __VA_ARGS__) ");
+        main_macro.push_str(&call_with_varadict(
+            common,
+            &generate_repeat_picker_macro_name(common),
+            &format!("{VARIADIC_ARGUMENTS} {} {}, {steps}",
+                comma_if_varadict(common),
+                generate_too_many_name(common)
+            )
+        ));
+        main_macro.push_str(", __VA_ARGS__) ");
     }
 
     Ok(main_macro)
 
 }
 
+/// End the current line of `define` with a line continuation and start
+/// the next one at `indent`.
+fn push_continued_line(define: &mut String, indent: &str) {
+    define.truncate(define.trim_end().len());
+    define.push_str(" \\\n");
+    define.push_str(indent);
+}
 
-impl Config {
+/// Read a string that must already be preprocessed.
+pub(crate) fn read_preprocessed<T: Preprocess + std::fmt::Debug>(
+    s: &Arc<RwLock<Preprocessable<T>>>
+) -> Result<String, Error> {
 
-    fn load_named_arguments(
-        &self,
-    ) -> Result<HashMap<String, PreprocessableString>, Error> {
+    let read_guard = s.read()
+         .map_err(|err| Error {
+            kind: ErrorKind::PoisonedLock,
+            message: err.to_string()
+        })?;
 
-        let mut table: HashMap<String, PreprocessableString> = HashMap::new();
-        for arg in self.core.args.iter() { 
-            match arg {
-                &Argument::Named(ref named) => {
-                    let result = table.insert(named.key.clone(), named.name.clone());
-                    if result.is_some() {
-                        return Err(Error {
-                            kind: ErrorKind::DuplicateArgument,
-                            message: format!("duplicate argument for the main xmva: {:?}", result.unwrap())
-                        })
-                    }
-                }
-                &Argument::Varadict { varadict: _ } => ()
-            }
-        }
+    match &*read_guard {
+        Preprocessable::NotPreprocessed(_) => Err(Error { 
+            kind: ErrorKind::NotPreprocessed, 
+            message: 
+            format!(
+                "Recived a string that was not preprocessed during the compilation process: {:?}",
+                read_guard
+            )
+        }),
+        Preprocessable::Preprocessed(s) => Ok(s.clone())
+    }
 
-        log::trace!("{}",
-            format!("Named arguments: {:#?}", table)
-            .dimmed()
-        );
+}
 
-        Ok(table)
+/// A `#define` has to be on a single line, so every newline inside of
+/// a multi-line expansion gets a `\` line continuation (unless the user
+/// already wrote one) and trailing newlines are stripped.
+fn continue_lines(
+    expansion: &str
+) -> String {
+    expansion
+        .trim_end_matches(['\n', '\r'])
+        .lines()
+        .map(|line| line.trim_end_matches('\\').trim_end())
+        .collect::<Vec<&str>>()
+        .join(" \\\n")
+}
 
-    }
+/// Wrap `code` inside of `#if condition ... #endif`, with a optional
+/// `#else` branch.
+fn wrap_in_condition(
+    code: &str,
+    condition: &Option<PreprocessableString>,
+    otherwise: Option<&str>
+) -> Result<String, Error> {
 
-    fn load_surface_compilable_strings(
-        &self
-    ) -> Vec<PreprocessableString> {
+    let Some(condition) = condition else {
+        return Ok(code.to_owned())
+    };
 
-        let mut compilable_strings: Vec<PreprocessableString> = vec![];
+    let mut wrapped = format!("#if {}\n{code}", read_preprocessed(condition)?);
+    if !wrapped.ends_with('\n') {
+        wrapped.push('\n');
+    }
+    if let Some(otherwise) = otherwise {
+        wrapped.push_str("#else\n");
+        wrapped.push_str(otherwise);
+        wrapped.push('\n');
+    }
+    wrapped.push_str("#endif");
 
-        for generator in self.generator.iter() {
+    Ok(wrapped)
 
-            compilable_strings.push(generator.fallbacks.empty.clone());
-            compilable_strings.push(generator.fallbacks.unparity.clone());
-            compilable_strings.push(generator.postamble.clone());
-            compilable_strings.push(generator.preamble.clone());
-            compilable_strings.push(generator.repeat.clone());
+}
 
+/// Index of a generator along side its repeat ladder and generator macro,
+/// the ladder is [None] when the generator shares the one of another generator.
+/// Last are the steps of the ladder with the field they came from, see [step_fields].
+type Section = (usize, Option<String>, String, Vec<(String, String)>);
+
+/// Every step of the ladder of generator `i` with the field of the
+/// generator that produced it, so the source map can point a single
+/// `__ARGS__N` back to the `repeat` or the fallback it expands.
+fn step_fields(
+    common: &Common,
+    core: &Core,
+    generator: &Generator,
+    i: usize,
+    ladder: &Ladder
+) -> Vec<(String, String)> {
+
+    let group = core.varadict().unwrap_or(1).max(1);
+    let mut steps: Vec<(String, String)> = (0..common.repeats)
+        .filter(|n| ladder.repeats.is_none_or(|repeats| repeats.contains(*n)))
+        .map(|n| {
+            let remainder = n % group;
+            let field = match n {
+                0 => "fallbacks.empty".to_owned(),
+                _ if remainder == 0 => "repeat".to_owned(),
+                _ if generator.fallbacks.unparity_remainder.contains_key(&remainder) =>
+                    format!("fallbacks.unparity_remainder.{remainder}"),
+                _ => "fallbacks.unparity".to_owned()
+            };
+            (generate_repeat_name(common, n, ladder.suffix), format!("generator.{i}.{field}"))
+        })
+        .collect();
+    if ladder.repeats.is_some() {
+        steps.push((generate_out_of_range_name(common, ladder.suffix), format!("generator.{i}.repeats")));
+    }
+    steps
+
+}
+
+/// Join the repeat ladders and generator macros of multiple generators
+/// in the given [Layout].
+fn assemble_sections(
+    layout: Layout,
+    sections: &[Section],
+    metadata: &Metadata
+) -> SourceMapped {
+
+    let mut assembled = SourceMapped::default();
+    let field = |i: &usize| format!("generator.{i}");
+
+    match layout {
+        Layout::Grouped => {
+            let ladders = sections
+                .iter()
+                .filter_map(|(i, repeat, _, steps)| repeat.as_ref().map(|repeat| (i, repeat, steps)));
+            for (n, (i, repeat, steps)) in ladders.enumerate() {
+                if n != 0 {
+                    assembled.push("\n");
+                }
+                assembled.push_defines(repeat, metadata, &field(i), Stage::Repeat, *i, steps);
+            }
+            assembled.push("\n");
+            for (n, (i, _, generator, _)) in sections.iter().enumerate() {
+                if n != 0 {
+                    assembled.push("\n");
+                }
+                assembled.push_mapped(generator, metadata, &field(i), Stage::Generator);
+            }
+        }
+        Layout::Interleaved => {
+            for (n, (i, repeat, generator, steps)) in sections.iter().enumerate() {
+                if n != 0 {
+                    assembled.push("\n");
+                }
+                if let Some(repeat) = repeat {
+                    assembled.push_defines(repeat, metadata, &field(i), Stage::Repeat, *i, steps);
+                    assembled.push("\n");
+                }
+                assembled.push_mapped(generator, metadata, &field(i), Stage::Generator);
+            }
         }
+    }
+
+    assembled
+
+}
+
+/// `PREFIX_COUNT`, `PREFIX_MAX_ARGS` and `PREFIX_GROUP_SIZE`, see
+/// [crate::config::Output::size_macros].
+fn assemble_size_macros(
+    common: &Common,
+    core: &Core
+) -> String {
+
+    let prefix = &common.keyable.prefix;
+    format!(
+        "#define {prefix}COUNT {}\n#define {prefix}MAX_ARGS {}\n#define {prefix}GROUP_SIZE {}\n",
+        common.repeats,
+        common.repeats.saturating_sub(1),
+        core.varadict().unwrap_or(0)
+    )
+
+}
+
+/// `PREFIX_APPLY_N`, the public name of the `xmva` for exactly `n`
+/// varadict arguments.
+pub(crate) fn generate_apply_name(
+    common: &Common,
+    n: usize
+) -> String {
+    format!("{}{APPLY_SUFFIX}{n}", common.keyable.prefix)
+}
+
+/// The argument counts that get a `PREFIX_APPLY_N`, every count the
+/// ladders have a step for that is a whole number of groups.
+pub(crate) fn apply_counts(
+    common: &Common,
+    core: &Core
+) -> Vec<usize> {
+    let group = core.varadict().unwrap_or(0).max(1);
+    (0..common.repeats).filter(|n| n % group == 0).collect()
+}
+
+/// `PREFIX_APPLY_N(named..., __0__, ..., __N-1__)` for every count of
+/// [apply_counts], calls every generator with the step for `N` directly
+/// instead of picking it. See [crate::config::Output::expose_arity_macros].
+fn assemble_arity_macros(
+    common: &Common,
+    core: &Core,
+    ladders: &[Ladder],
+    taken: &[String]
+) -> Result<String, Error> {
+
+    let mut named_args: Vec<String> = vec![];
+    for arg in core.args.iter() {
+        if let Argument::Named(named) = arg {
+            named_args.push(read_preprocessed(&named.name)?);
+        }
+    }
+
+    let mut macros = String::new();
+    for n in apply_counts(common, core) {
+        let name = generate_apply_name(common, n);
+        if taken.contains(&name) {
+            return Err(Error {
+                kind: ErrorKind::NameCollision,
+                message: format!(
+                    "`output.expose_arity_macros` defines `{name}` which is already the name of a definition or the `xmva`, change `common.prefix` or rename it."
+                )
+            })
+        }
+        let parameters: Vec<String> = named_args
+            .iter()
+            .cloned()
+            .chain((0..n).map(|i| format!("__{i}__")))
+            .collect();
+        macros.push_str(&format!("#define {name}({}) ", parameters.join(", ")));
+        for (i, ladder) in ladders.iter().enumerate() {
+            let step = match ladder.repeats {
+                Some(repeats) if !repeats.contains(n) => generate_out_of_range_name(common, ladder.suffix),
+                _ => generate_repeat_name(common, n, ladder.suffix)
+            };
+            // the named arguments are written like the main macro does,
+            // the generator macro has a empty one when there are none.
+            macros.push_str(&format!("{}({}, {step}, {}) ",
+                generate_generator_macro_name(common, i),
+                named_args.join(", "),
+                parameters[named_args.len()..].join(", ")
+            ));
+        }
+        macros.truncate(macros.trim_end().len());
+        macros.push('\n');
+    }
+
+    Ok(macros)
+
+}
+
+/// The `static inline` functions of [GeneratorKind::Helpers] generators,
+/// computed from the final repeats so command line overrides count.
+fn assemble_helpers(
+    helpers: &[Helper],
+    common: &Common,
+    core: &Core
+) -> String {
+
+    let prefix = &common.keyable.prefix;
+    let group_size = core.varadict().unwrap_or(1).max(1);
+    // the ladder goes from 0 up to repeats - 1 arguments.
+    let max_arguments = common.repeats.saturating_sub(1);
+
+    let mut assembled = String::new();
+    for helper in helpers {
+        match helper {
+            Helper::Count => assembled.push_str(&format!(
+                "static inline int {prefix}group_count(int count) {{ return count / {group_size}; }}\n"
+            )),
+            Helper::Valid => assembled.push_str(&format!(
+                "static inline int {prefix}valid_argument_count(int count) \
+                {{ return count >= 0 && count <= {max_arguments} && count % {group_size} == 0; }}\n"
+            )),
+            Helper::Max => {
+                assembled.push_str(&format!(
+                    "static inline int {prefix}max_arguments(void) {{ return {max_arguments}; }}\n"
+                ));
+                assembled.push_str(&format!(
+                    "static inline int {prefix}max_groups(void) {{ return {}; }}\n",
+                    max_arguments / group_size
+                ));
+            }
+        }
+    }
+    assembled
+
+}
+
+fn generate_list_name(
+    common: &Common,
+    n: usize
+) -> String {
+
+    let mut name = String::new();
+    name.push_str(&common.keyable.prefix);
+    name.push_str(LIST_SUFFIX);
+    name.push_str(n.to_string().as_str());
+    name
+
+}
+
+/// Assemble a classic x-macro `LIST(X, ...)` that applies `X` to every
+/// varadict argument group, `X(__0__, __1__) X(__2__, __3__) ...`.
+/// 
+/// It gets its own ladder and picker (same as the generators) but no
+/// named arguments, only the callback `X`.
+fn assemble_list_macro_string(
+    list: &PreprocessableString,
+    core: &Core,
+    common: &Common
+) -> Result<String, Error> {
+
+    let list_name = read_preprocessed(list)?;
+
+    let Some(va_args) = core.args.iter().find_map(|arg| match arg {
+        Argument::Varadict { varadict } => Some(*varadict),
+        Argument::Named(_) => None
+    }) else {
+        return Err(Error { 
+            kind: ErrorKind::NonExistantArgument, 
+            message: "Missing varadict argument count argument".to_string()
+        })
+    };
+
+    let mut list_macro = String::new();
+
+    for n in 0..common.repeats {
+
+        list_macro.push_str("#define ");
+        list_macro.push_str(generate_list_name(common, n).as_str());
+        list_macro.push_str("(X");
+        for i in 0..n {
+            list_macro.push_str(format!(", __{i}__").as_str());
+        }
+        list_macro.push_str(") ");
+
+        if n % va_args == 0 {
+            for group in 0..n/va_args {
+                list_macro.push_str("X(");
+                list_macro.push_str(
+                    (0..va_args)
+                        .map(|i| format!("__{}__", group*va_args + i))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                        .as_str()
+                );
+                list_macro.push_str(") ");
+            }
+        } else {
+            list_macro.push_str(format!(
+                "_Static_assert(0, \"{list_name}: [Argument unparity] argument count is not a multiple of {va_args}.\")"
+            ).as_str());
+        }
+
+        list_macro.push('\n');
+
+    }
+
+    list_macro.push_str("#define ");
+    list_macro.push_str(&common.keyable.prefix);
+    list_macro.push_str(LIST_SUFFIX);
+    list_macro.push('(');
+    list_macro.push_str(
+        (0..common.repeats)
+            .map(|i| format!("__{i}__"))
+            .collect::<Vec<String>>()
+            .join(", ")
+            .as_str()
+    );
+    list_macro.push_str(", __NAME__, ...) __NAME__\n");
+
+    let steps = (0..common.repeats)
+        .map(|n| generate_list_name(common, n))
+        .rev()
+        .collect::<Vec<String>>()
+        .join(", ");
+    let picked = call_with_varadict(
+        common,
+        &format!("{}{LIST_SUFFIX}", common.keyable.prefix),
+        &format!("{VARIADIC_ARGUMENTS} {} {}, {steps}",
+            comma_if_varadict(common),
+            generate_too_many_name(common)
+        )
+    );
+    list_macro.push_str("#define ");
+    list_macro.push_str(list_name.as_str());
+    list_macro.push_str("(X, ...) ");
+    list_macro.push_str(&call_with_varadict(common, &picked, &format!("X {} {VARIADIC_ARGUMENTS}", comma_if_varadict(common))));
+
+    Ok(list_macro)
+
+}
+
+
+impl Generator {
+
+    pub(crate) fn load_surface_compilable_strings(
+        &self
+    ) -> Vec<PreprocessableString> {
+
+        let mut compilable_strings = vec![
+            self.fallbacks.empty.clone(),
+            self.fallbacks.unparity.clone(),
+            self.postamble.clone(),
+            self.preamble.clone(),
+            self.repeat.clone()
+        ];
+        compilable_strings.extend(self.fallbacks.unparity_remainder.values().cloned());
 
         log::trace!("{}",
             format!("Surface compilable string: {:#?}", compilable_strings)
             .dimmed()
         );
         
-        return compilable_strings
+        compilable_strings
 
     }
 
+}
 
+impl Config {
 
-    fn assemble_preamble(
-        &self
-    ) -> Result<String, Error> {
-
-        let mut assembled_preamble: String = String::new();
-
-        match &self.preamble {
-            Some(preamble) => { 
-                match &preamble.raw {
-                    Some(raw) => {
-                        let read_guard = raw.read()
-                            .map_err(|err| Error {
-                                kind: ErrorKind::PoisonedLock,
-                                message: err.to_string()
-                            })?;
-                        match &*read_guard {
-                            Preprocessable::NotPreprocessed(_) => {
-                                return Err(Error { 
-                                    kind: ErrorKind::NotPreprocessed, 
-                                    message: 
-                                    format!(
-                                        "Recived a string that was not preprocessed during the compilation process: {:?}",
-                                        read_guard
-                                    )
-                                })
-                            }
-                            Preprocessable::Preprocessed(string) => {
-                                assembled_preamble.push_str(string);
-                                assembled_preamble.push('\n');
-                            }
-                        }
+    fn load_named_arguments(
+        &self,
+    ) -> Result<HashMap<String, PreprocessableString>, Error> {
+
+        // both occurrences of a duplicate underlined, the first one and
+        // the one colliding with it.
+        let duplicate = |message: String, field: &str, first: usize, again: usize| {
+            let labels: String = [(first, "first here"), (again, "and again here")]
+                .into_iter()
+                .filter_map(|(i, label)| self.metadata.label(&format!("core.args.{i}.{field}"), label))
+                .map(|snippet| format!("\n{snippet}"))
+                .collect();
+            Error {
+                kind: ErrorKind::DuplicateArgument,
+                message: format!("{message}{labels}")
+            }
+        };
+
+        let mut table: HashMap<String, PreprocessableString> = HashMap::new();
+        // index and resolved name of every named argument so far.
+        let mut seen: Vec<(usize, &String, String)> = vec![];
+        for (i, arg) in self.core.args.iter().enumerate() { 
+            match arg {
+                Argument::Named(named) => {
+                    let name = read_preprocessed(&named.name)?;
+                    if let Some((first, _, _)) = seen.iter().find(|(_, key, _)| **key == named.key) {
+                        return Err(duplicate(
+                            format!("the main xmva has the named argument `{}` twice (`core.args.{first}` and `core.args.{i}`)", named.key),
+                            "key",
+                            *first,
+                            i
+                        ))
                     }
-                    None => ()
+                    if let Some((first, key, _)) = seen.iter().find(|(_, _, resolved)| *resolved == name) {
+                        return Err(duplicate(
+                            format!("the named arguments `{key}` and `{}` of the main xmva both resolve to the parameter `{name}`", named.key),
+                            "name",
+                            *first,
+                            i
+                        ))
+                    }
+                    seen.push((i, &named.key, name));
+                    table.insert(named.key.clone(), named.name.clone());
                 }
+                Argument::Varadict { varadict: _ } => ()
             }
-            None => ()
         }
 
-        if self.definition.is_some() {
-            for definition in self.definition.clone().unwrap().iter() {
+        log::trace!("{}",
+            format!("Named arguments: {:#?}", table)
+            .dimmed()
+        );
 
-                assembled_preamble.push_str("#define ");
+        Ok(table)
 
-                let read_guard = definition.name.read()
+    }
+
+    pub(crate) fn assemble_preamble(
+        &self
+    ) -> Result<SourceMapped, Error> {
+
+        let mut assembled_preamble = SourceMapped::default();
+
+        for (i, include) in self.includes.system.iter().enumerate() {
+            assembled_preamble.push_mapped(
+                &format!("#include <{}>\n", read_preprocessed(include)?),
+                &self.metadata,
+                &format!("includes.system.{i}"),
+                Stage::Include
+            );
+        }
+        for (i, include) in self.includes.local.iter().enumerate() {
+            assembled_preamble.push_mapped(
+                &format!("#include \"{}\"\n", read_preprocessed(include)?),
+                &self.metadata,
+                &format!("includes.local.{i}"),
+                Stage::Include
+            );
+        }
+
+        if let Some(preamble) = &self.preamble 
+            && let Some(raw) = &preamble.raw {
+                let read_guard = raw.read()
                     .map_err(|err| Error {
                         kind: ErrorKind::PoisonedLock,
                         message: err.to_string()
@@ -1104,15 +2264,65 @@ impl Config {
                             )
                         })
                     }
-                    Preprocessable::Preprocessed(name) => {
-                        assembled_preamble.push_str(name);
+                    Preprocessable::Preprocessed(string) => {
+                        assembled_preamble.push_mapped(
+                            &format!("{string}\n"),
+                            &self.metadata,
+                            "preamble.raw",
+                            Stage::Preamble
+                        );
                     }
                 }
+            }
+
+        let mut assembled_definitions = Vec::new();
+        if self.definition.is_some() {
+            for (i, definition) in self.definition.clone().unwrap().iter().enumerate() {
+
+                if definition.emit == Emit::Never {
+                    continue
+                }
+
+                let mut assembled_definition = String::new();
+                assembled_definition.push_str("#define ");
+
+                let read_guard = definition.name.read()
+                    .map_err(|err| Error {
+                        kind: ErrorKind::PoisonedLock,
+                        message: err.to_string()
+                    })?;
+                let name = match &*read_guard {
+                    Preprocessable::NotPreprocessed(_) => {
+                        return Err(Error { 
+                            kind: ErrorKind::NotPreprocessed, 
+                            message: 
+                            format!(
+                                "Recived a string that was not preprocessed during the compilation process: {:?}",
+                                read_guard
+                            )
+                        })
+                    }
+                    Preprocessable::Preprocessed(name) => name.clone()
+                };
                 drop(read_guard);
+                assembled_definition.push_str(&name);
 
+                let mut variadic = false;
                 if definition.parameters.is_some() {
                     let parameters = definition.parameters.clone().unwrap();
-                    assembled_preamble.push_str(
+                    if let Some(position) = parameters.iter().position(|p| p == VARIADIC_PARAMETER) {
+                        if position != parameters.len() - 1 {
+                            return Err(Error {
+                                kind: ErrorKind::InvalidDefinition,
+                                message: format!(
+                                    "Definition `{}` has `{VARIADIC_PARAMETER}` as a parameter that is not the last one.",
+                                    definition.key
+                                )
+                            })
+                        }
+                        variadic = true;
+                    }
+                    assembled_definition.push_str(
                         format!(
                             "({})",
                             parameters.join(", ")
@@ -1120,7 +2330,10 @@ impl Config {
                     );
                 }
 
-                assembled_preamble.push(' ');
+                assembled_definition.push(' ');
+                if let Some(reason) = &definition.deprecated {
+                    assembled_definition.push_str(&deprecation_pragma(&name, reason));
+                }
 
                 let read_guard = definition.expansion.read()
                     .map_err(|err| Error {
@@ -1139,82 +2352,336 @@ impl Config {
                         })
                     }
                     Preprocessable::Preprocessed(expansion) => {
-                        assembled_preamble.push_str(expansion);
-                        assembled_preamble.push('\n');
+                        if variadic && !expansion.contains(VARIADIC_ARGUMENTS) {
+                            return Err(Error {
+                                kind: ErrorKind::InvalidDefinition,
+                                message: format!(
+                                    "Definition `{}` is variadic but its expansion never uses `{VARIADIC_ARGUMENTS}`.",
+                                    definition.key
+                                )
+                            })
+                        }
+                        assembled_definition.push_str(&continue_lines(expansion));
                     }
                 }
                 drop(read_guard);
+
+                assembled_definitions.push((
+                    name,
+                    i,
+                    wrap_in_condition(&assembled_definition, &definition.condition, None)?
+                ));
             }
         }
 
-        log::trace!("{}", format!("Created preamble: \n{}", assembled_preamble).dimmed());
+        // stable, definitions with the same name keep their order.
+        if self.output.sort_definitions == SortDefinitions::Alpha {
+            assembled_definitions.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+        }
+        for (_, i, assembled_definition) in assembled_definitions {
+            assembled_preamble.push_mapped(
+                &format!("{assembled_definition}\n"),
+                &self.metadata,
+                &format!("definition.{i}"),
+                Stage::Definition
+            );
+        }
+
+        log::trace!("{}", format!("Created preamble: \n{}", assembled_preamble.contents).dimmed());
         Ok(assembled_preamble)
         
     }
 
 
 
+    /// Comment at the top of every output, only the config file name is
+    /// used so the banner doesn't depend on where we were called from.
+    fn assemble_banner(
+        &self
+    ) -> String {
+
+        let config = self.metadata.config
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let timestamp = match self.output.timestamp {
+            true => format!(" on {}", metadata::timestamp()),
+            false => String::new()
+        };
+
+        format!("{SENTINEL}\n/* Generated by xmva {} from {config}{timestamp}, do not edit. */\n/* {CONFIG_HASH_MARKER}{} */\n", 
+            self.metadata.version,
+            self.hash()
+        )
+
+    }
+
+    fn assemble_postamble(
+        &self
+    ) -> Result<SourceMapped, Error> {
+
+        let mut assembled_postamble = SourceMapped::default();
+        if let Some(postamble) = &self.postamble 
+            && let Some(raw) = &postamble.raw {
+            assembled_postamble.push("\n");
+            assembled_postamble.push_mapped(
+                &read_preprocessed(raw)?,
+                &self.metadata,
+                "postamble.raw",
+                Stage::Postamble
+            );
+        }
+        Ok(assembled_postamble)
+
+    }
+
     pub fn compile_and_assemble(
         &self
-    ) -> Result<String, Error> {
+    ) -> Result<Assembled, Error> {
+        self.surface_compile()?;
+        let baked = self.generate_repeats()?;
+        self.assemble(&baked)
+    }
+
+    /// Replace the named arguments inside of the generator strings.
+    pub fn surface_compile(
+        &self
+    ) -> Result<(), Error> {
 
         log::debug!("Starting to compile the config.");
 
         log::debug!("Loading named arguments...");
         let named = self.load_named_arguments()?;
-        log::debug!("Loading all surface compilable strings...");
-        let compilable_strings = self.load_surface_compilable_strings();
         log::debug!("Surface compiling...");
-        compile_surface_strings(
-            compilable_strings,
-            &named
-        )?;
+        for (i, generator) in self.generator.iter().enumerate() {
+            compile_surface_strings(
+                generator.load_surface_compilable_strings(),
+                &named,
+                &self.substitutions,
+                &self.surface_compilations,
+                &self.metadata.in_generator(i, generator.name.clone())
+            )?;
+        }
+        Ok(())
 
-        // surface compile and then start assembling the file
-        log::debug!("Assembling preamble...");
-        let preamble = self.assemble_preamble()?;
+    }
 
-        // during the compilation we compile and assemble the repeat part
+    /// Compile the repeat ladder and generator macro of every generator,
+    /// has to be surface compiled first.
+    pub fn generate_repeats(
+        &self
+    ) -> Result<Baked, Error> {
+        self.generate_repeats_with(|_, _| {})
+    }
 
-        let mut repeats: Vec<String> =  vec![];
-        let mut generators: Vec<String> = vec![];
+    /// [Config::generate_repeats] calling `generated` with the index of
+    /// every generator and how many bytes it baked once it is done.
+    pub fn generate_repeats_with(
+        &self,
+        mut generated: impl FnMut(usize, usize)
+    ) -> Result<Baked, Error> {
+
+        let backend = self.common.language.backend();
+
+        let mut baked = Baked {
+            repeats: vec![],
+            generators: vec![],
+            ladders: vec![]
+        };
+        // every ladder we emitted compiled with the same suffix so they compare.
+        let mut canonical_ladders: Vec<(String, usize)> = vec![];
         log::debug!("Compiling and assembling the repeat section, and assembling the generator macro...");
         for (i, generator) in self.generator.iter().enumerate() {
 
-            repeats.push(
-                compile_and_assemble_repeat_string(
-                    &generator, 
+            let compile_ladder = |suffix: usize| wrap_in_condition(
+                &backend.assemble_repeat(
+                    generator, 
                     &self.common, 
                     &self.core,
-                    i
-                )?
+                    &self.output,
+                    suffix
+                ).map_err(|err| Error {
+                    kind: err.kind,
+                    message: self.metadata.in_generator(i, generator.name.clone()).annotate(err.message)
+                })?,
+                &generator.condition,
+                None
             );
 
-            generators.push(
-                assemble_generator_string(
+            let canonical = compile_ladder(0)?;
+            let repeat = match canonical_ladders.iter().find(|(ladder, _)| *ladder == canonical) {
+                Some((_, shared)) => {
+                    log::debug!("Generator {i} shares the repeat ladder of generator {shared}.");
+                    baked.ladders.push(Ladder { suffix: *shared, repeats: generator.repeats });
+                    None
+                }
+                None => {
+                    canonical_ladders.push((canonical, i));
+                    baked.ladders.push(Ladder { suffix: i, repeats: generator.repeats });
+                    Some(compile_ladder(i)?)
+                }
+            };
+            baked.repeats.push(repeat);
+
+            // the main xmva calls every generator so it needs to exist
+            // even when the condition is false.
+            baked.generators.push(wrap_in_condition(
+                &assemble_generator_string(
                     &self.common, 
                     &self.core,
                     i
-                )?
-            );
+                )?,
+                &generator.condition,
+                Some(format!("#define {}(...)", generate_generator_macro_name(&self.common, i)).as_str())
+            )?);
+            generated(i, baked.repeats[i].as_ref().map_or(0, String::len) + baked.generators[i].len());
 
         }
 
+        Ok(baked)
+
+    }
+
+    /// Put the file together from what was `baked` for the generators.
+    pub fn assemble(
+        &self,
+        baked: &Baked
+    ) -> Result<Assembled, Error> {
+
+        let backend = self.common.language.backend();
+        let ladders = &baked.ladders;
+
+        log::debug!("Assembling preamble...");
+        let preamble = backend.assemble_preamble(self)?;
+
+        let sections: Vec<Section> = self.generator
+            .iter()
+            .zip(baked.repeats.iter().zip(baked.generators.iter()))
+            .enumerate()
+            .map(|(i, (generator, (repeat, generator_macro)))| {
+                let steps = step_fields(&self.common, &self.core, generator, i, &ladders[i]);
+                if !self.output.comments {
+                    return (i, repeat.clone(), generator_macro.clone(), steps)
+                }
+                let name = generator.name.as_ref().map(|name| format!(" `{name}`")).unwrap_or_default();
+                (
+                    i,
+                    repeat.as_ref().map(|ladder| format!("/* Repeat ladder {i}, a step for every argument count. */\n{ladder}")),
+                    format!("/* Generator {i}{name}, picks from repeat ladder {}. */\n{generator_macro}", ladders[i].suffix),
+                    steps
+                )
+            })
+            .collect();
+
         log::debug!("Assembling the main xmva macro...");
-        let xmva = assemble_main_macro_string(
+        let mut xmva = backend.assemble_dispatcher(
             &self.core, 
             &self.common, 
-            self.generator.len()
+            &self.output,
+            ladders
         )?;
+        if self.output.member_docs == MemberDocs::Main {
+            xmva.insert_str(0, &assemble_member_docs(&self.core));
+        }
+        let mut picker = backend.assemble_picker(&self.core, &self.common)?;
+        if self.output.comments {
+            picker.insert_str(0, "/* Picks the step of a ladder by the argument count. */\n");
+            xmva.insert_str(0, &format!(
+                "/* The main macro, calls every generator with its step.\n * {}{} */\n",
+                read_preprocessed(&self.core.xmva)?,
+                comment_safe(&main_macro_shape(&self.core)?)
+            ));
+        }
+
+        let list = match &self.core.list {
+            Some(list) => {
+                log::debug!("Assembling the list macro...");
+                format!("\n{}", assemble_list_macro_string(list, &self.core, &self.common)?)
+            }
+            None => String::new()
+        };
+
+        // generators with their own output file, in the order their
+        // files first appear.
+        log::debug!("Routing generators into their output files...");
+        let mut main_sections: Vec<Section> = vec![];
+        let mut routed_sections: Vec<(&PathBuf, Vec<Section>)> = vec![];
+        for (generator, section) in self.generator.iter().zip(sections) {
+            match &generator.output {
+                None => main_sections.push(section),
+                Some(output) => match routed_sections.iter_mut().find(|(path, _)| *path == output) {
+                    Some((_, routed)) => routed.push(section),
+                    None => routed_sections.push((output, vec![section]))
+                }
+            }
+        }
 
+        let includes: String = routed_sections
+            .iter()
+            .map(|(path, _)| format!("#include \"{}\"\n", path.display()))
+            .collect();
+
+        let main_output_dir = self.common.output
+            .as_deref()
+            .and_then(Path::parent)
+            .unwrap_or(Path::new(""));
+        let routed = routed_sections
+            .into_iter()
+            .map(|(path, sections)| {
+                let mut routed = SourceMapped::default();
+                routed.push(&self.assemble_banner());
+                routed.append(assemble_sections(self.output.layout, &sections, &self.metadata));
+                routed.push("\n");
+                (main_output_dir.join(path), routed)
+            })
+            .collect();
+
+        log::debug!("Assembling postamble...");
+        let postamble = self.assemble_postamble()?;
 
         log::debug!("Assembling file contents...");
-        let file = format!("{preamble}\n{}\n{}\n{xmva}",
-            repeats.join("\n"),
-            generators.join("\n")
-        );
+        let mut file = SourceMapped::default();
+        file.push(&self.assemble_banner());
+        file.append(preamble);
+        file.push("\n");
+        file.push(&includes);
+        file.append(assemble_sections(self.output.layout, &main_sections, &self.metadata));
+        file.push("\n");
+        file.push_mapped(&picker, &self.metadata, "core", Stage::Main);
+        file.push("\n");
+        file.push_mapped(&xmva, &self.metadata, "core", Stage::Main);
+        file.push_mapped(&list, &self.metadata, "core.list", Stage::List);
+        if self.output.size_macros {
+            file.push("\n\n");
+            file.push_mapped(&assemble_size_macros(&self.common, &self.core), &self.metadata, "output.size_macros", Stage::Main);
+        }
+        if self.output.expose_arity_macros {
+            log::debug!("Assembling the per arity macros...");
+            let mut taken = vec![read_preprocessed(&self.core.xmva)?];
+            if let Some(list) = &self.core.list {
+                taken.push(read_preprocessed(list)?);
+            }
+            for definition in self.definition.iter().flatten() {
+                taken.push(read_preprocessed(&definition.name)?);
+            }
+            let mut arity_macros = assemble_arity_macros(&self.common, &self.core, ladders, &taken)?;
+            if self.output.comments {
+                arity_macros.insert_str(0, "/* The main macro for a exact varadict argument count, skips the picking. */\n");
+            }
+            file.push("\n\n");
+            file.push_mapped(&arity_macros, &self.metadata, "output.expose_arity_macros", Stage::Main);
+        }
+        if !self.helpers.is_empty() {
+            file.push("\n\n");
+            file.push(&assemble_helpers(&self.helpers, &self.common, &self.core));
+        }
+        file.append(postamble);
+        // a define continued onto the last line needs the newline.
+        if self.output.wrap && !file.contents.ends_with('\n') {
+            file.push("\n");
+        }
 
-        Ok(file)
+        Ok(Assembled { main: file, routed })
 
     }
 
@@ -1230,6 +2697,20 @@ mod tests {
     #[allow(unused_imports)]
     use super::*;
 
+    /// The config `contents` after it was preprocessed.
+    #[allow(dead_code)]
+    fn preprocessed(contents: &str) -> Config {
+        let config = Config::parse(contents, Path::new("test.xmva.toml")).unwrap();
+        config.preprocess().unwrap();
+        config
+    }
+
+    /// The main output generated from the config `contents`.
+    #[allow(dead_code)]
+    fn assemble(contents: &str) -> Result<String, Error> {
+        preprocessed(contents).compile_and_assemble().map(|assembled| assembled.main.contents)
+    }
+
     /// The tokneizer is both the only thing that interacts with user strings
     /// and the most complex part of the preprocessor.
     /// Everything else is pretty simple and relies on enums to guide
@@ -1244,8 +2725,8 @@ mod tests {
                 "hello world${argument}"
             ).unwrap(),
             vec![
-                CompilerToken::Raw("hello world".to_owned()),
-                CompilerToken::NamedArgumentRef("argument".to_owned())
+                CompilerToken::Raw("hello world".into()),
+                CompilerToken::NamedArgumentRef("argument".into())
             ]
         );
 
@@ -1262,14 +2743,14 @@ mod tests {
             vec![
                 CompilerToken::Position,
                 CompilerToken::Position,
-                CompilerToken::Raw("[HELLO_ ## ".to_owned()),
-                CompilerToken::NamedArgumentRef("NAME".to_owned()),
-                CompilerToken::Raw(" ## _ ## ".to_owned()),
+                CompilerToken::Raw("[HELLO_ ## ".into()),
+                CompilerToken::NamedArgumentRef("NAME".into()),
+                CompilerToken::Raw(" ## _ ## ".into()),
                 CompilerToken::UnamedArgumentRef(0),
-                CompilerToken::Raw("] = \"$".to_owned()),
+                CompilerToken::Raw("] = \"$".into()),
                 CompilerToken::UnamedArgumentRef(1),
-                CompilerToken::SkipLast(",]".to_owned()),
-                CompilerToken::Raw("\"".to_owned())
+                CompilerToken::SkipLast(",]".into()),
+                CompilerToken::Raw("\"".into())
             ]
         );
     
@@ -1284,36 +2765,101 @@ mod tests {
                 "$.$.[HELLO_ ## ${NAME} ## _ ## $(000)] = \"\\$$(01)$[,\\]]\""
             ).unwrap(),
             vec![
-                CompilerToken::Raw("$.".to_owned()),
-                CompilerToken::Raw("$.".to_owned()),
-                CompilerToken::Raw("[HELLO_ ## ".to_owned()),
-                CompilerToken::NamedArgumentRef("NAME".to_owned()),
-                CompilerToken::Raw(" ## _ ## ".to_owned()),
-                CompilerToken::Raw("$(0)".to_owned()),
-                CompilerToken::Raw("] = \"$".to_owned()),
-                CompilerToken::Raw("$(1)".to_owned()),
-                CompilerToken::Raw("$[,\\]]".to_owned()),
-                CompilerToken::Raw("\"".to_owned())
+                CompilerToken::Raw("$.".into()),
+                CompilerToken::Raw("$.".into()),
+                CompilerToken::Raw("[HELLO_ ## ".into()),
+                CompilerToken::NamedArgumentRef("NAME".into()),
+                CompilerToken::Raw(" ## _ ## ".into()),
+                CompilerToken::Raw("$(0)".into()),
+                CompilerToken::Raw("] = \"\\$".into()),
+                CompilerToken::Raw("$(1)".into()),
+                CompilerToken::Raw("$[,\\]]".into()),
+                CompilerToken::Raw("\"".into())
             ]
         );
 
     }
 
+    #[test]
+    fn tokens_borrow() {
+
+        let tokens = CompilerToken::tokenize("a ${name} \\$b$[, ]").unwrap();
+        assert!(matches!(&tokens[0], CompilerToken::Raw(Cow::Borrowed("a "))));
+        assert!(matches!(&tokens[1], CompilerToken::NamedArgumentRef(Cow::Borrowed("name"))));
+        // unescaped, so it can't borrow.
+        assert!(matches!(&tokens[2], CompilerToken::Raw(Cow::Owned(raw)) if raw == " $b"));
+        assert!(matches!(&tokens[3], CompilerToken::SkipLast(Cow::Borrowed(", "))));
+
+    }
+
+    #[test]
+    fn lenient_scan() {
+        assert_eq!(
+            CompilerToken::scan("a $ b ${name} \\$(0) $(1 $[, ]"),
+            vec![
+                CompilerToken::NamedArgumentRef("name".into()),
+                CompilerToken::SkipLast(", ".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenizer_recovering() {
+
+        let (tokens, errors) = CompilerToken::tokenize_recovering("a $! b $(0) $(x-y) c ${}$[, ]");
+        assert_eq!(tokens, vec![
+            CompilerToken::Raw("a ".into()),
+            CompilerToken::UnamedArgumentRef(0),
+            CompilerToken::Raw(" ".into()),
+            CompilerToken::SkipLast(", ".into())
+        ]);
+        assert_eq!(
+            errors.iter().map(|err| &err.kind).collect::<Vec<_>>(),
+            vec![&ErrorKind::IllegalSymbol, &ErrorKind::InvalidReference, &ErrorKind::EmptyReference]
+        );
+        assert!(CompilerToken::tokenize("a $! b $(0)").is_err());
+
+    }
+
+    #[test]
+    fn tokens_json() {
+
+        let tokens = CompilerToken::tokenize("$.${NAME}$(1)$[, ]").unwrap();
+        let json = serde_json::to_string(&tokens).unwrap();
+        assert_eq!(
+            json,
+            r#"["Position",{"NamedArgumentRef":"NAME"},{"UnamedArgumentRef":1},{"SkipLast":", "}]"#
+        );
+        assert_eq!(serde_json::from_str::<Vec<CompilerToken>>(&json).unwrap(), tokens);
+
+    }
+
     #[test]
     fn untokenizer() {
-        let variants: Vec<CompilerToken> = CompilerToken::iter()
-            .collect();
+        // every variant, the tokens borrow so strum can't iterate them.
+        let variants: Vec<CompilerToken> = vec![
+            CompilerToken::Raw(Cow::default()),
+            CompilerToken::NamedArgumentRef(Cow::default()),
+            CompilerToken::UnamedArgumentRef(0),
+            CompilerToken::MemberRef(Cow::default()),
+            CompilerToken::Position,
+            CompilerToken::SkipLast(Cow::default()),
+            CompilerToken::Skip { count: 2, edge: SkipEdge::First, text: Cow::default() },
+            CompilerToken::LeftoverArgumentRef(0),
+            CompilerToken::EdgeArgumentRef(SkipEdge::Last, 0),
+            CompilerToken::EdgeMemberRef(SkipEdge::First, Cow::default())
+        ];
 
         for ref variant in variants {
             match variant {
                 CompilerToken::Raw(value) => {
-                    assert_eq!(value.to_owned(), variant.untokenize())
+                    assert_eq!(value.to_string(), variant.untokenize())
                 }
                 CompilerToken::Position => {
                     assert_eq!(
                         format!("{}{}",
-                            CompilerSigil::TokenStart.get_str("ch").unwrap(),
-                            CompilerSigil::PositionDot.get_str("ch").unwrap(),
+                            CompilerSigil::TokenStart.sequence(),
+                            CompilerSigil::PositionDot.sequence(),
                         ),
                         variant.untokenize()
                     )
@@ -1321,9 +2867,9 @@ mod tests {
                 CompilerToken::NamedArgumentRef(value) => {
                     assert_eq!(
                         format!("{}{}{value}{}",
-                            CompilerSigil::TokenStart.get_str("ch").unwrap(),
-                            CompilerSigil::NamedArgumentRefOpen.get_str("ch").unwrap(),
-                            CompilerSigil::NamedArgumentRefClose.get_str("ch").unwrap()
+                            CompilerSigil::TokenStart.sequence(),
+                            CompilerSigil::NamedArgumentRefOpen.sequence(),
+                            CompilerSigil::NamedArgumentRefClose.sequence()
                         ), 
                         variant.untokenize()
                     )
@@ -1331,9 +2877,19 @@ mod tests {
                 CompilerToken::UnamedArgumentRef(value) => {
                     assert_eq!(
                         format!("{}{}{value}{}",
-                            CompilerSigil::TokenStart.get_str("ch").unwrap(),
-                            CompilerSigil::UnamedArgumentRefOpen.get_str("ch").unwrap(),
-                            CompilerSigil::UnamedArgumentRefClose.get_str("ch").unwrap()
+                            CompilerSigil::TokenStart.sequence(),
+                            CompilerSigil::UnamedArgumentRefOpen.sequence(),
+                            CompilerSigil::UnamedArgumentRefClose.sequence()
+                        ), 
+                        variant.untokenize()
+                    )
+                }
+                CompilerToken::MemberRef(value) => {
+                    assert_eq!(
+                        format!("{}{}{value}{}",
+                            CompilerSigil::TokenStart.sequence(),
+                            CompilerSigil::UnamedArgumentRefOpen.sequence(),
+                            CompilerSigil::UnamedArgumentRefClose.sequence()
                         ), 
                         variant.untokenize()
                     )
@@ -1341,9 +2897,49 @@ mod tests {
                 CompilerToken::SkipLast(value) => {
                     assert_eq!(
                         format!("{}{}{value}{}",
-                            CompilerSigil::TokenStart.get_str("ch").unwrap(),
-                            CompilerSigil::SkipLastOpen.get_str("ch").unwrap(),
-                            CompilerSigil::SkipLastClose.get_str("ch").unwrap()
+                            CompilerSigil::TokenStart.sequence(),
+                            CompilerSigil::SkipLastOpen.sequence(),
+                            CompilerSigil::SkipLastClose.sequence()
+                        ), 
+                        variant.untokenize()
+                    )
+                }
+                CompilerToken::Skip { count, text, .. } => {
+                    assert_eq!(
+                        format!("{}{}^{count}:{text}{}",
+                            CompilerSigil::TokenStart.sequence(),
+                            CompilerSigil::SkipLastOpen.sequence(),
+                            CompilerSigil::SkipLastClose.sequence()
+                        ), 
+                        variant.untokenize()
+                    )
+                }
+                CompilerToken::LeftoverArgumentRef(value) => {
+                    assert_eq!(
+                        format!("{}{}{value}{}",
+                            CompilerSigil::TokenStart.sequence(),
+                            CompilerSigil::LeftoverArgumentRefOpen.sequence(),
+                            CompilerSigil::LeftoverArgumentRefClose.sequence()
+                        ), 
+                        variant.untokenize()
+                    )
+                }
+                CompilerToken::EdgeArgumentRef(_, value) => {
+                    assert_eq!(
+                        format!("{}{}last.{value}{}",
+                            CompilerSigil::TokenStart.sequence(),
+                            CompilerSigil::UnamedArgumentRefOpen.sequence(),
+                            CompilerSigil::UnamedArgumentRefClose.sequence()
+                        ), 
+                        variant.untokenize()
+                    )
+                }
+                CompilerToken::EdgeMemberRef(_, value) => {
+                    assert_eq!(
+                        format!("{}{}first.{value}{}",
+                            CompilerSigil::TokenStart.sequence(),
+                            CompilerSigil::UnamedArgumentRefOpen.sequence(),
+                            CompilerSigil::UnamedArgumentRefClose.sequence()
                         ), 
                         variant.untokenize()
                     )
@@ -1387,6 +2983,32 @@ mod tests {
 
     }
 
+    #[test]
+    fn tokenize_and_untokenize_custom_embed() {
+
+        crate::sigil::set_overrides(
+            HashMap::new(),
+            HashMap::from([(CompilerSigil::TokenEmbed, "^".to_owned())])
+        ).unwrap();
+
+        let s = "\"^$\\n$(1)\" $[, ^]^^\\]";
+
+        assert_eq!(
+            CompilerToken::tokenize(s).unwrap()[0], 
+            CompilerToken::Raw("\"$\\n".into())
+        );
+        assert_eq!(
+            s.to_owned(),
+            CompilerToken::tokenize(s)
+                .unwrap()
+                .into_iter()
+                .map(|x| x.untokenize())
+                .collect::<Vec<String>>()
+                .join("")
+        );
+
+    }
+
     #[test]
     fn tokenizer_errors() {
 
@@ -1451,27 +3073,598 @@ mod tests {
 
         assert_eq!(
             CompilerToken::tokenize("\\\\").unwrap(),
-            vec![CompilerToken::Raw("\\".to_owned())]
+            vec![CompilerToken::Raw("\\".into())]
         );
 
         assert_eq!(
             CompilerToken::tokenize("\\$").unwrap(),
-            vec![CompilerToken::Raw("$".to_owned())]
+            vec![CompilerToken::Raw("$".into())]
         );
 
         assert_eq!(
             CompilerToken::tokenize("\\\\n\\$\\\\\\\\%\\\\").unwrap(),
-            vec![CompilerToken::Raw("\\n$\\\\%\\".to_owned())]
+            vec![CompilerToken::Raw("\\n$\\\\%\\".into())]
         );
 
         assert_eq!(
             CompilerToken::tokenize("$[\\\\n\\\\$\\\\\\\\%\\]]").unwrap(),
-            vec![CompilerToken::SkipLast("\\n\\$\\\\%]".to_owned())]
+            vec![CompilerToken::SkipLast("\\n\\$\\\\%]".into())]
+        );
+
+
+    }
+
+    #[test]
+    fn unparity_leftovers() {
+
+        // 5 arguments in groups of 3, __3__ and __4__ are left over.
+        assert_eq!(
+            compile_unparity_fallback("missing($<0>, $<1>, $<2>) $.", 5, 2).unwrap(),
+            "missing(__3__, __4__, ) $."
+        );
+
+    }
+
+    #[test]
+    fn definition_line_continuation() {
+
+        assert_eq!(continue_lines("a + b"), "a + b");
+        assert_eq!(
+            continue_lines("do {\n    x();\n} while (0)\n"),
+            "do { \\\n    x(); \\\n} while (0)"
+        );
+        // already escaped by hand
+        assert_eq!(continue_lines("a \\\nb"), "a \\\nb");
+
+    }
+
+    #[test]
+    fn member_refs() {
+
+        assert_eq!(
+            CompilerToken::tokenize("$(key) $(1)").unwrap(),
+            vec![
+                CompilerToken::MemberRef("key".into()),
+                CompilerToken::Raw(" ".into()),
+                CompilerToken::UnamedArgumentRef(1)
+            ]
+        );
+        assert_eq!(
+            CompilerToken::tokenize("$(1key)").unwrap_err().kind,
+            ErrorKind::InvalidReference
+        );
+
+    }
+
+    #[test]
+    fn deprecation_escaping() {
+        assert_eq!(
+            deprecation_pragma("OLD", "use \"NEW\""),
+            "_Pragma(\"GCC warning \\\"OLD is deprecated: use \\\\\\\"NEW\\\\\\\"\\\"\") "
+        );
+    }
+
+    #[test]
+    fn shared_ladders() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 3\n\n[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n\n\
+            [[generator]]\nrepeat = \"$(0)\"\n\n[[generator]]\nrepeat = \"$(0)\"\n\n[[generator]]\nrepeat = \"-$(0)\"\n";
+        let config = Config::parse(contents, Path::new("shared.xmva.toml")).unwrap();
+        config.preprocess().unwrap();
+        let assembled = config.compile_and_assemble().unwrap().main.contents;
+
+        assert!(!assembled.contains("#define A___ARGS__1_1("));
+        assert!(assembled.contains("#define A___ARGS__2_1("));
+        assert!(assembled.contains("A___GENERATOR__1(, A___CALL__(A___ARGS__, __VA_ARGS__ A___COMMA__(__VA_ARGS__) A___ARGS__TOO_MANY, A___ARGS__0_2"));
+
+    }
+
+    #[test]
+    fn repeat_ranges() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 4\n\n[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n\n\
+            [[generator]]\nrepeat = \"$(0)\"\n\n[[generator]]\nrepeats = {min = 1, max = 2}\nrepeat = \"-$(0)\"\n";
+        let config = Config::parse(contents, Path::new("ranges.xmva.toml")).unwrap();
+        config.preprocess().unwrap();
+        let assembled = config.compile_and_assemble().unwrap().main.contents;
+
+        assert_eq!(assembled.matches("#define A___ARGS__(").count(), 1);
+        assert!(assembled.contains("#define A___ARGS__0_3("));
+        assert!(!assembled.contains("#define A___ARGS__1_0("));
+        assert!(!assembled.contains("#define A___ARGS__1_3("));
+        assert!(assembled.contains("#define A___ARGS__1_RANGE(...) _Pragma(\"GCC error \\\"A: a generator only handles 1 to 2 varadict arguments\\\"\")"));
+        assert!(assembled.contains("A___ARGS__TOO_MANY, A___ARGS__1_RANGE, A___ARGS__1_2, A___ARGS__1_1, A___ARGS__1_RANGE)"));
+
+        let backwards = contents.replace("min = 1", "min = 3");
+        assert!(matches!(Config::parse(&backwards, Path::new("ranges.xmva.toml")), Err(crate::config::Error::Invalid { .. })));
+
+    }
+
+    #[test]
+    fn output_styles() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 3\n\n[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n\n\
+            [[generator]]\npreamble = \"{\"\nrepeat = \"$(0)$[,]\"\npostamble = \"}\"\n\n[output]\nstyle = \"readable\"\n";
+        let readable = assemble(contents).unwrap();
+        assert!(readable.contains("#define A___ARGS__0_2(, __0__, __1__) \\\n    { \\\n        __0__, \\\n        __1__ \\\n    }\n"));
+        assert!(readable.contains("/* Repeat ladder 0, a step for every argument count. */\n"));
+        assert!(readable.contains("#define A(, ...) \\\n    A___GENERATOR__0("));
+        assert!(readable.contains("/* The main macro, calls every generator with its step.\n * A(..., groups of 1) */\n"));
+
+        // options given anyway win over the preset.
+        let unwrapped = assemble(&format!("{contents}wrap = false\n")).unwrap();
+        assert!(unwrapped.contains("#define A___ARGS__0_2(, __0__, __1__) {__0__,__1__}\n"));
+        assert!(unwrapped.contains("/* Repeat ladder 0"));
+        assert!(!assemble(&contents.replace("readable", "compact")).unwrap().contains("/* Repeat ladder 0"));
+
+        let tabs = assemble(&format!("{contents}indent = {{with = \"tabs\"}}\n")).unwrap();
+        assert!(tabs.contains("#define A___ARGS__0_1(, __0__) \\\n\t{ \\\n\t\t__0__ \\\n\t}\n"));
+        let two = assemble(&format!("{contents}indent = {{width = 2}}\n")).unwrap();
+        assert!(two.contains("#define A___ARGS__0_1(, __0__) \\\n  { \\\n    __0__ \\\n  }\n"));
+
+    }
+
+    #[test]
+    fn auto_repeats() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = \"auto\"\nround_repeats = 4\n\n[core]\nxmva = \"A\"\n\
+            args = [{key = \"n\", name = \"n\"}, {varadict = 1}]\n\n[[generator]]\nrepeat = \"$(0)\"\n\n\
+            [example]\nargs = [\"x\", \"1\"]\n\n[[test]]\nargs = [\"x\", \"1\", \"2\", \"3\", \"4\", \"5\"]\n";
+        assert_eq!(Config::parse(contents, Path::new("auto.xmva.toml")).unwrap().common.repeats, 9);
+
+        let untested = contents.split("\n\n[example]").next().unwrap();
+        assert!(matches!(Config::parse(untested, Path::new("auto.xmva.toml")), Err(crate::config::Error::Invalid { .. })));
+
+    }
+
+    #[test]
+    fn raw_literal_strings() {
+
+        let contents = |raw: bool| format!("[common]\nprefix = \"A_\"\nrepeats = 2\nraw_literal_strings = {raw}\n\n[core]\nxmva = \"A\"\n\
+            args = [{{key = \"n\", name = \"n\"}}, {{varadict = 1}}]\n\n\
+            [[generator]]\npreamble = 'printf(\"${{n}} @{{prefix}} \\n\"); '\nrepeat = \"@{{prefix}}$(0)\"\npostamble = ''' $. '''\n");
+        let assembled = assemble(&contents(true)).unwrap();
+        assert!(assembled.contains("#define A___ARGS__0_1(n, __0__) printf(\"${n} @{prefix} \\n\"); A___0__ $. \n"));
+        // the same strings are tokens without it.
+        let config = Config::parse(&contents(false), Path::new("literal.xmva.toml")).unwrap();
+        assert!(config.preprocess().is_err());
+
+        // the fallbacks are taken as written too.
+        let fallbacks = "[common]\nprefix = \"A_\"\nrepeats = 3\nraw_literal_strings = true\n\n[core]\nxmva = \"A\"\n\
+            args = [{key = \"n\", name = \"n\"}, {varadict = 2}]\n\n\
+            [[generator]]\nrepeat = \"$(0)\"\n\n[generator.fallbacks]\nempty = '$(0) @{x}'\nunparity = '$. \\ @{x}'\n";
+        let assembled = assemble(fallbacks).unwrap();
+        assert!(assembled.contains("#define A___ARGS__0_0(n) $(0) @{x}\n"));
+        assert!(assembled.contains("#define A___ARGS__0_1(n, __0__) $. \\ @{x}\n"));
+
+    }
+
+    #[test]
+    fn arity_macros() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 5\n\n[core]\nxmva = \"A\"\n\
+            args = [{key = \"n\", name = \"n\"}, {varadict = 2}]\n\n\
+            [[generator]]\nrepeat = \"$(0)\"\n\n[[generator]]\nrepeats = {min = 2, max = 2}\nrepeat = \"$(1)\"\n\n\
+            [output]\nexpose_arity_macros = true\n";
+        let assembled = assemble(contents).unwrap();
+        assert!(assembled.contains("#define A_APPLY_0(n) A___GENERATOR__0(n, A___ARGS__0_0, ) A___GENERATOR__1(n, A___ARGS__1_RANGE, )\n"));
+        assert!(assembled.contains("#define A_APPLY_2(n, __0__, __1__) A___GENERATOR__0(n, A___ARGS__0_2, __0__, __1__) A___GENERATOR__1(n, A___ARGS__1_2, __0__, __1__)\n"));
+        assert!(assembled.contains("#define A_APPLY_4("));
+        // only whole groups.
+        assert!(!assembled.contains("A_APPLY_1(") && !assembled.contains("A_APPLY_3("));
+
+        let collision = format!("{contents}\n[[definition]]\nkey = \"two\"\nname = \"APPLY_2\"\nexpansion = \"2\"\n");
+        assert!(matches!(assemble(&collision), Err(Error { kind: ErrorKind::NameCollision, .. })));
+
+    }
+
+    #[test]
+    fn amble_positions() {
+
+        let contents = |postamble: &str| format!("[common]\nprefix = \"A_\"\nrepeats = 5\n\n[core]\nxmva = \"A\"\n\
+            args = [{{key = \"n\", name = \"n\"}}, {{varadict = 2}}]\ngroup = {{members = [\"value\", \"string\"]}}\n\n\
+            [[generator]]\npreamble = '[$.] \\\\$. '\nrepeat = \"$(first.0)$(0)$[, ]\"\npostamble = \"{postamble}\"\n");
+        let assembled = assemble(&contents(" $(first.1) $(last.value)")).unwrap();
+        assert!(assembled.contains("#define A___ARGS__0_2(n, __0__, __1__) [1] $. __0____0__ __1__ __0__\n"));
+        assert!(assembled.contains("#define A___ARGS__0_4(n, __0__, __1__, __2__, __3__) [2] $. __0____0__, __0____2__ __1__ __2__\n"));
+
+        let repeat_only = assemble(&contents(" $(0)")).unwrap_err();
+        assert_eq!(repeat_only.kind, ErrorKind::InvalidToken);
+        // caught when the config is loaded.
+        assert!(Config::parse(&contents("$(last.2)"), Path::new("amble.xmva.toml")).is_err_and(|err| err.to_string().contains("is out of range")));
+
+    }
+
+    #[test]
+    fn sorted_definitions() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 3\n\n[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n\n\
+            [[generator]]\nrepeat = \"$(0)\"\n\n\
+            [[definition]]\nkey = \"z\"\nname = \"ZED\"\nexpansion = \"1\"\n\n\
+            [[definition]]\nkey = \"a\"\nname = \"ALPHA\"\nexpansion = \"2\"\n";
+        assert_eq!(preprocessed(contents).assemble_preamble().unwrap().contents, "#define A_ZED 1\n#define A_ALPHA 2\n");
+        let sorted = preprocessed(&format!("{contents}\n[output]\nsort_definitions = \"alpha\"\n"));
+        assert_eq!(sorted.assemble_preamble().unwrap().contents, "#define A_ALPHA 2\n#define A_ZED 1\n");
+
+    }
+
+    #[test]
+    fn emitted_definitions() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 3\n\n[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n\n\
+            [[generator]]\nrepeat = \"@{used}($(0))\"\n\n\
+            [[definition]]\nkey = \"used\"\nname = \"USED\"\nparameters = [\"x\"]\nexpansion = \"@{chained}(x)\"\nemit = \"if-used\"\n\n\
+            [[definition]]\nkey = \"chained\"\nname = \"CHAINED\"\nparameters = [\"x\"]\nexpansion = \"x\"\nemit = \"if-used\"\n\n\
+            [[definition]]\nkey = \"unused\"\nname = \"UNUSED\"\nexpansion = \"@{only}\"\nemit = \"if-used\"\n\n\
+            [[definition]]\nkey = \"only\"\nname = \"ONLY_BY_UNUSED\"\nexpansion = \"1\"\nemit = \"if-used\"\n\n\
+            [[definition]]\nkey = \"never\"\nname = \"NEVER\"\nexpansion = \"@{used}(1)\"\nemit = \"never\"\n\n\
+            [[definition]]\nkey = \"always\"\nname = \"ALWAYS\"\nexpansion = \"2\"\n";
+        let config = Config::parse(contents, Path::new("emit.xmva.toml")).unwrap();
+        let emits: Vec<Emit> = config.definition.iter().flatten().map(|definition| definition.emit).collect();
+        assert_eq!(emits, [Emit::Always, Emit::Always, Emit::Never, Emit::Never, Emit::Never, Emit::Always]);
+
+        config.preprocess().unwrap();
+        assert_eq!(config.assemble_preamble().unwrap().contents, "#define A_USED(x) A_CHAINED(x)\n#define A_CHAINED(x) x\n#define A_ALWAYS 2\n");
+
+    }
+
+    #[test]
+    fn counted_skips() {
+
+        let tokens = CompilerToken::tokenize("$[2:, ]$[^10:|]$[x:y]").unwrap();
+        assert_eq!(tokens, vec![
+            CompilerToken::Skip { count: 2, edge: SkipEdge::Last, text: Cow::Borrowed(", ") },
+            CompilerToken::Skip { count: 10, edge: SkipEdge::First, text: Cow::Borrowed("|") },
+            CompilerToken::SkipLast(Cow::Borrowed("x:y")),
+        ]);
+        assert!(matches!(&tokens[0], CompilerToken::Skip { text: Cow::Borrowed(_), .. }));
+        assert_eq!(tokens.iter().map(CompilerToken::untokenize).collect::<String>(), "$[2:, ]$[^10:|]$[x:y]");
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 4\n\n[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n\n\
+            [[generator]]\nrepeat = \"$[^1:+]$(0)$[2:,]\"\n";
+        let config = Config::parse(contents, Path::new("skip.xmva.toml")).unwrap();
+        config.preprocess().unwrap();
+        let assembled = config.compile_and_assemble().unwrap().main.contents;
+        assert!(assembled.contains("#define A___ARGS__0_3(, __0__, __1__, __2__) __0__,+__1__+__2__\n"));
+        assert!(assembled.contains("#define A___ARGS__0_1(, __0__) __0__\n"));
+
+    }
+
+    #[test]
+    fn joiners() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 4\n\n[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n\n\
+            [[generator]]\nrepeat = \"[$(0)]\"\njoin = \"2:] \\\\ @{x} \"\n";
+        let config = Config::parse(contents, Path::new("join.xmva.toml")).unwrap();
+        config.preprocess().unwrap();
+        let assembled = config.compile_and_assemble().unwrap().main.contents;
+        assert!(assembled.contains("#define A___ARGS__0_3(, __0__, __1__, __2__) [__0__]2:] \\ @{x} [__1__]2:] \\ @{x} [__2__]\n"));
+
+        let table = contents.replace("[[generator]]\n", "[[generator]]\nkind = \"table\"\ntype = \"int\"\narray = \"a\"\n");
+        assert!(Config::parse(&table, Path::new("join.xmva.toml")).is_err());
+
+    }
+
+    #[test]
+    fn empty_arguments() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 3\n\n[core]\nxmva = \"A\"\nlist = \"L\"\n\
+            args = [{key = \"n\", name = \"n\"}, {varadict = 1}]\n\n[[generator]]\npreamble = \"${n}:\"\nrepeat = \"$(0)$[,]\"\n";
+        let config = Config::parse(contents, Path::new("empty.xmva.toml")).unwrap();
+        config.preprocess().unwrap();
+        let assembled = config.compile_and_assemble().unwrap().main.contents;
+        assert!(!assembled.contains("\"empty\""));
+
+        // the simulator takes the probe since it skips the conditionals.
+        let macros = crate::expand::Macros::from_header(&assembled);
+        assert_eq!(macros.expand("A(x)").unwrap(), "");
+        assert_eq!(macros.expand("A(x, (int)1, b)").unwrap(), "x:(int)1,b");
+        assert_eq!(macros.expand("L(X)").unwrap(), "");
+        assert_eq!(macros.expand("L(X, 1, 2)").unwrap(), "X(1) X(2)");
+        assert!(macros.expand("A(x, a, b, c)").unwrap().starts_with("_Pragma(\"GCC error"));
+
+    }
+
+    #[test]
+    fn substitution_traces() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 3\n\n[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n\n\
+            [preamble]\nkeys = [{key = \"bad\", name = {tags = [\"NO_PREFIX\"], string = \"$!\"}}]\n\n\
+            [[generator]]\npreamble = \"x @{bad}\"\nrepeat = \"$(0)\"\n";
+        let config = Config::parse(contents, Path::new("trace.xmva.toml")).unwrap();
+        config.preprocess().unwrap();
+        let err = config.compile_and_assemble().unwrap_err();
+
+        assert!(err.message.contains("\nhelp: the string was produced by preprocessing:\n    x @{bad}\n    after @{bad}: x $!"));
+
+        // nothing to trace when no key was substituted in.
+        let contents = contents.replace("x @{bad}", "x $!");
+        let config = Config::parse(&contents, Path::new("trace.xmva.toml")).unwrap();
+        config.preprocess().unwrap();
+        assert!(!config.compile_and_assemble().unwrap_err().message.contains("help:"));
+
+    }
+
+    #[test]
+    fn routed_outputs() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 3\noutput = \"include/a.h\"\n\n[core]\nxmva = \"A\"\nargs = [{key = \"n\", name = \"n\"}, {varadict = 1}]\n\n\
+            [[generator]]\nrepeat = \"main $(0)\"\n\n\
+            [[generator]]\nrepeat = \"first $(0)\"\noutput = \"ladders.h\"\n\n\
+            [[generator]]\nrepeat = \"second $(0)\"\noutput = \"ladders.h\"\n";
+        let assembled = preprocessed(contents).compile_and_assemble().unwrap();
+
+        // both routed generators share one file next to the main output,
+        // which includes it once.
+        assert_eq!(assembled.main.contents.matches("#include \"ladders.h\"\n").count(), 1);
+        assert!(assembled.main.contents.contains("#define A___ARGS__0_1(n, __0__) main __0__\n"));
+        assert!(!assembled.main.contents.contains("#define A___ARGS__1_"));
+        assert_eq!(assembled.routed.len(), 1);
+        let (path, routed) = &assembled.routed[0];
+        assert_eq!(path, Path::new("include/ladders.h"));
+        assert!(routed.contents.starts_with(SENTINEL));
+        assert!(routed.contents.contains("#define A___ARGS__1_1(n, __0__) first __0__\n"));
+        assert!(routed.contents.contains("#define A___ARGS__2_1(n, __0__) second __0__\n"));
+        assert!(routed.contents.contains("#define A___GENERATOR__2(n, __GEN__, ...)"));
+
+    }
+
+    #[test]
+    fn remainder_fallbacks() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 8\n\n[core]\nxmva = \"A\"\nargs = [{key = \"n\", name = \"n\"}, {varadict = 4}]\n\n\
+            [[generator]]\nrepeat = \"$(0)\"\n\n\
+            [generator.fallbacks]\nunparity = \"other $<0>\"\n\n\
+            [generator.fallbacks.unparity_remainder]\n1 = \"one $<0>\"\n2 = \"two $<1>\"\n";
+        let assembled = assemble(contents).unwrap();
+
+        // remainders 1 and 2 have their own fallback, 3 doesn't.
+        assert!(assembled.contains("#define A___ARGS__0_1(n, __0__) one __0__\n"));
+        assert!(assembled.contains("#define A___ARGS__0_2(n, __0__, __1__) two __1__\n"));
+        assert!(assembled.contains("#define A___ARGS__0_3(n, __0__, __1__, __2__) other __0__\n"));
+        assert!(assembled.contains("#define A___ARGS__0_4(n, __0__, __1__, __2__, __3__) __0__\n"));
+        // the same after a whole group.
+        assert!(assembled.contains("#define A___ARGS__0_5(n, __0__, __1__, __2__, __3__, __4__) one __4__\n"));
+        assert!(assembled.contains("#define A___ARGS__0_6(n, __0__, __1__, __2__, __3__, __4__, __5__) two __5__\n"));
+        assert!(assembled.contains("#define A___ARGS__0_7(n, __0__, __1__, __2__, __3__, __4__, __5__, __6__) other __4__\n"));
+
+    }
+
+    #[test]
+    fn xmacro_list() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 5\n\n[core]\nxmva = \"A\"\nlist = \"A_LIST\"\n\
+            args = [{key = \"n\", name = \"n\"}, {varadict = 2}]\n\n[[generator]]\nrepeat = \"$(0)\"\n";
+        let assembled = assemble(contents).unwrap();
+
+        // `X` once for every group, the same unparity as the xmva.
+        assert!(assembled.contains("#define A___LIST__0(X) \n"));
+        assert!(assembled.contains("#define A___LIST__2(X, __0__, __1__) X(__0__, __1__) \n"));
+        assert!(assembled.contains("#define A___LIST__4(X, __0__, __1__, __2__, __3__) X(__0__, __1__) X(__2__, __3__) \n"));
+        assert!(assembled.contains("#define A___LIST__3(X, __0__, __1__, __2__) _Static_assert(0, \"A_LIST: [Argument unparity] argument count is not a multiple of 2.\")\n"));
+        assert!(assembled.contains("#define A_LIST(X, ...) A___CALL__(A___CALL__(A___LIST__, __VA_ARGS__ A___COMMA__(__VA_ARGS__) A___ARGS__TOO_MANY, A___LIST__4, A___LIST__3, A___LIST__2, A___LIST__1, A___LIST__0), X A___COMMA__(__VA_ARGS__) __VA_ARGS__)"));
+
+        // and nothing without a `core.list`.
+        let without = assemble(&contents.replace("list = \"A_LIST\"\n", "")).unwrap();
+        assert!(!without.contains("__LIST__"));
+
+    }
+
+    #[test]
+    fn condition_wrapping() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 3\n\n[core]\nxmva = \"A\"\nargs = [{key = \"n\", name = \"n\"}, {varadict = 1}]\n\n\
+            [[generator]]\ncondition = \"defined(@{prefix}FOO) && !defined(BAR)\"\nrepeat = \"$(0)\"\n\n\
+            [[definition]]\nkey = \"d\"\nname = \"D\"\nexpansion = \"1\"\ncondition = \"defined(FOO)\"\n";
+        let assembled = assemble(contents).unwrap();
+
+        assert!(assembled.contains("#if defined(FOO)\n#define A_D 1\n#endif\n"));
+        assert!(assembled.contains("#if defined(A_FOO) && !defined(BAR)\n#define A___ARGS__0_0(n) \n"));
+        assert!(assembled.contains("#define A___ARGS__0_2(n, __0__, __1__) __0____1__\n#endif\n"));
+        // the xmva still calls the generator macro when the condition is false.
+        assert!(assembled.contains(concat!(
+            "#if defined(A_FOO) && !defined(BAR)\n",
+            "#define A___GENERATOR__0(n, __GEN__, ...) A___CALL__(__GEN__, n A___COMMA__(__VA_ARGS__) __VA_ARGS__)\n",
+            "#else\n",
+            "#define A___GENERATOR__0(...)\n",
+            "#endif\n"
+        )));
+
+    }
+
+    #[test]
+    fn section_layouts() {
+
+        let contents = |layout: &str| format!("[common]\nprefix = \"A_\"\nrepeats = 2\n\n[core]\nxmva = \"A\"\nargs = [{{key = \"n\", name = \"n\"}}, {{varadict = 1}}]\n\n\
+            [[generator]]\nrepeat = \"a$(0)\"\n\n[[generator]]\nrepeat = \"b$(0)\"\n\n[output]\nlayout = \"{layout}\"\n");
+        let generator = |i: usize| format!("#define A___GENERATOR__{i}(n, __GEN__, ...) A___CALL__(__GEN__, n A___COMMA__(__VA_ARGS__) __VA_ARGS__)\n");
+
+        // every ladder and then every generator macro, the default.
+        let grouped = format!(
+            "#define A___ARGS__0_1(n, __0__) a__0__\n#define A___ARGS__1_0(n) \n#define A___ARGS__1_1(n, __0__) b__0__\n{}\n{}",
+            generator(0),
+            generator(1)
         );
+        assert!(assemble(&contents("grouped")).unwrap().contains(&grouped));
+        assert!(assemble(&contents("grouped").replace("[output]\nlayout = \"grouped\"\n", "")).unwrap().contains(&grouped));
+
+        // each ladder right before its generator macro.
+        let interleaved = assemble(&contents("interleaved")).unwrap();
+        assert!(interleaved.contains(&format!(
+            "#define A___ARGS__0_1(n, __0__) a__0__\n{}\n#define A___ARGS__1_0(n) \n#define A___ARGS__1_1(n, __0__) b__0__\n{}",
+            generator(0),
+            generator(1)
+        )));
+
+    }
+
+    #[test]
+    fn file_postamble() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 2\n\n[core]\nxmva = \"A\"\nargs = [{key = \"n\", name = \"n\"}, {varadict = 1}]\n\n\
+            [[generator]]\nrepeat = \"$(0)\"\n\n\
+            [postamble]\nraw = \"#define @{wrap}(...) A(0, __VA_ARGS__)\\n#endif\"\nkeys = [{key = \"wrap\", name = \"WRAP\"}]\n";
+        let assembled = assemble(contents).unwrap();
+
+        // preprocessed with its own keys and after the main macro.
+        let postamble = assembled.find("#define A_WRAP(...) A(0, __VA_ARGS__)\n#endif").unwrap();
+        assert!(assembled.find("#define A(n, ...)").unwrap() < postamble);
+        assert_eq!(assembled[postamble..].trim_end(), "#define A_WRAP(...) A(0, __VA_ARGS__)\n#endif");
+
+    }
+
+    #[test]
+    fn variadic_definitions() {
+
+        let contents = |parameters: &str, expansion: &str| format!("[common]\nprefix = \"A_\"\nrepeats = 2\n\n[core]\nxmva = \"A\"\nargs = [{{varadict = 1}}]\n\n\
+            [[generator]]\nrepeat = \"$(0)\"\n\n\
+            [[definition]]\nkey = \"log\"\nname = \"LOG\"\nparameters = [{parameters}]\nexpansion = \"{expansion}\"\n");
+
+        let variadic = preprocessed(&contents("\"fmt\", \"...\"", "printf(fmt, __VA_ARGS__)"));
+        assert_eq!(variadic.assemble_preamble().unwrap().contents, "#define A_LOG(fmt, ...) printf(fmt, __VA_ARGS__)\n");
+
+        let unused = preprocessed(&contents("\"fmt\", \"...\"", "printf(fmt)")).assemble_preamble().unwrap_err();
+        assert_eq!(unused.kind, ErrorKind::InvalidDefinition);
+        assert!(unused.message.contains("never uses `__VA_ARGS__`"));
+
+        let not_last = preprocessed(&contents("\"...\", \"fmt\"", "printf(fmt, __VA_ARGS__)")).assemble_preamble().unwrap_err();
+        assert_eq!(not_last.kind, ErrorKind::InvalidDefinition);
+        assert!(not_last.message.contains("not the last one"));
+
+    }
+
+    #[test]
+    fn structured_includes() {
+
+        let contents = "[common]\nprefix = \"my_\"\nrepeats = 2\n\n[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n\n\
+            [[generator]]\nrepeat = \"$(0)\"\n\n\
+            [includes]\nlocal = [\"@{prefix}types.h\", \"a.h\"]\nsystem = [\"stdio.h\", \"stdint.h\"]\n\n\
+            [preamble]\nraw = \"/* raw */\"\n";
+        let preamble = preprocessed(contents).assemble_preamble().unwrap().contents;
+
+        // system first, each in the order they were written and before the raw preamble.
+        assert!(preamble.starts_with("#include <stdio.h>\n#include <stdint.h>\n#include \"my_types.h\"\n#include \"a.h\"\n"));
+        assert!(preamble.contains("/* raw */"));
+
+    }
+
+    #[test]
+    fn fallback_defaults() {
+
+        let contents = |fallbacks: &str| format!("[common]\nprefix = \"A_\"\nrepeats = 4\n\n[core]\nxmva = \"A\"\n\
+            args = [{{key = \"n\", name = \"n\"}}, {{varadict = 3}}]\n\n[[generator]]\nrepeat = \"$(0)\"\n{fallbacks}");
+
+        // nothing for no arguments and a failed assertion naming the group size.
+        let defaults = assemble(&contents("")).unwrap();
+        assert!(defaults.contains("#define A___ARGS__0_0(n) \n"));
+        assert!(defaults.contains("#define A___ARGS__0_1(n, __0__) _Static_assert(0, \"A_: argument count not a multiple of 3\")\n"));
+        assert!(defaults.contains("#define A___ARGS__0_2(n, __0__, __1__) _Static_assert(0, \"A_: argument count not a multiple of 3\")\n"));
+
+        // setting one of them keeps the default of the other.
+        let empty_only = assemble(&contents("\n[generator.fallbacks]\nempty = \"none\"\n")).unwrap();
+        assert!(empty_only.contains("#define A___ARGS__0_0(n) none\n"));
+        assert!(empty_only.contains("#define A___ARGS__0_1(n, __0__) _Static_assert(0, \"A_: argument count not a multiple of 3\")\n"));
+        let unparity_only = assemble(&contents("\n[generator.fallbacks]\nunparity = \"odd\"\n")).unwrap();
+        assert!(unparity_only.contains("#define A___ARGS__0_0(n) \n"));
+        assert!(unparity_only.contains("#define A___ARGS__0_1(n, __0__) odd\n"));
+
+    }
+
+    #[test]
+    fn member_docs() {
 
+        let contents = |member_docs: &str| format!("[common]\nprefix = \"A_\"\nrepeats = 5\n\n[core]\nxmva = \"A\"\n\
+            args = [{{key = \"n\", name = \"n\"}}, {{varadict = 2}}]\ngroup = {{members = [\"key\", {{name = \"value\", doc = \"What */ maps.\"}}]}}\n\n\
+            [[generator]]\nrepeat = \"$(key)\"\n\n[output]\nmember_docs = \"{member_docs}\"\n");
+
+        // above the steps that bake whole groups, the docs can't close the comment.
+        let repeats = assemble(&contents("repeats")).unwrap();
+        assert!(repeats.contains("/* __0__ key, __1__ value (What * / maps.) */\n#define A___ARGS__0_2(n, __0__, __1__) __0__\n"));
+        assert!(repeats.contains("/* __0__ key, __1__ value (What * / maps.), __2__ key, __3__ value (What * / maps.) */\n#define A___ARGS__0_4("));
+        // not above the unparity steps.
+        assert!(repeats.contains("__0__\n#define A___ARGS__0_3("));
+        assert!(!repeats.contains("@brief"));
+
+        let main = assemble(&contents("main")).unwrap();
+        assert!(main.contains("/**\n * @brief Takes the varadict arguments in groups of 2:\n * - `key`\n * - `value`: What * / maps.\n */\n#define A(n, ...)"));
+        assert!(!main.contains("/* __0__"));
+
+        let off = assemble(&contents("off")).unwrap();
+        assert!(!off.contains("What"));
 
     }
 
+    #[test]
+    fn size_macros() {
+
+        let contents = |output: &str| format!("[common]\nprefix = \"A_\"\nrepeats = 7\n\n[core]\nxmva = \"A\"\n\
+            args = [{{key = \"n\", name = \"n\"}}, {{varadict = 3}}]\n\n[[generator]]\nrepeat = \"$(0)\"\n{output}");
+
+        let assembled = assemble(&contents("\n[output]\nsize_macros = true\n")).unwrap();
+        assert!(assembled.contains("\n\n#define A_COUNT 7\n#define A_MAX_ARGS 6\n#define A_GROUP_SIZE 3\n"));
+        assert!(assembled.find("#define A(n, ...)").unwrap() < assembled.find("#define A_COUNT").unwrap());
 
+        assert!(!assemble(&contents("")).unwrap().contains("A_COUNT"));
+
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn main_macro_shapes() {
+
+        let contents = |args: &str| format!("[common]\nprefix = \"A_\"\nrepeats = 5\n\n[core]\nxmva = \"A\"\n{args}\n\n\
+            [[generator]]\nrepeat = \"$(0)\"\n\n[output]\ncomments = true\n");
+
+        let grouped = preprocessed(&contents(
+            "args = [{key = \"lower\", name = \"lower\"}, {key = \"upper\", name = \"UPPER\"}, {varadict = 2}]\ngroup = {members = [\"code\", \"message\"]}"
+        ));
+        assert_eq!(main_macro_shape(&grouped.core).unwrap(), "(lower, UPPER, ..., groups of 2: code, message)");
+        assert!(grouped.compile_and_assemble().unwrap().main.contents.contains(
+            "/* The main macro, calls every generator with its step.\n * A(lower, UPPER, ..., groups of 2: code, message) */\n#define A(lower, UPPER, ...)"
+        ));
+
+        // no member names without a group.
+        let ungrouped = preprocessed(&contents("args = [{key = \"n\", name = \"n\"}, {varadict = 3}]"));
+        assert_eq!(main_macro_shape(&ungrouped.core).unwrap(), "(n, ..., groups of 3)");
+
+    }
+
+    #[test]
+    fn ladder_provenance() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 8\n\n[core]\nxmva = \"A\"\nargs = [{key = \"n\", name = \"n\"}, {varadict = 4}]\n\n\
+            [[generator]]\nrepeat = \"$(0)\"\n\n\
+            [generator.fallbacks]\nempty = \"none\"\nunparity = \"other $<0>\"\n\n\
+            [generator.fallbacks.unparity_remainder]\n1 = \"one $<0>\"\n";
+        let config = preprocessed(contents);
+        let main = config.compile_and_assemble().unwrap().main;
+
+        let step = |name: &str| main.mappings.iter()
+            .find(|mapping| mapping.macro_name.as_deref() == Some(name))
+            .unwrap_or_else(|| panic!("no mapping for {name}"));
+        let line = |mapping: &crate::sourcemap::Mapping| main.contents.lines().nth(mapping.lines.0 - 1).unwrap().to_owned();
+
+        for (name, field) in [
+            ("A___ARGS__0_0", "generator.0.fallbacks.empty"),
+            ("A___ARGS__0_1", "generator.0.fallbacks.unparity_remainder.1"),
+            ("A___ARGS__0_3", "generator.0.fallbacks.unparity"),
+            ("A___ARGS__0_4", "generator.0.repeat"),
+        ] {
+            let mapping = step(name);
+            assert_eq!(mapping.generator, Some(0));
+            assert_eq!(mapping.field, field);
+            assert_eq!(mapping.span, config.metadata.span(field));
+            assert!(mapping.span.is_some());
+            assert_eq!(mapping.lines.0, mapping.lines.1);
+            assert!(line(mapping).starts_with(&format!("#define {name}(")));
+        }
+
+        // the whole section keeps its own mapping, without provenance.
+        assert!(main.mappings.iter().any(|mapping| mapping.field == "generator.0" && mapping.macro_name.is_none()));
+
+        let json = main.to_json();
+        assert!(json.contains("\"macro\": \"A___ARGS__0_4\""));
+        assert!(json.contains("\"generator\": 0"));
+
+    }
+
+}
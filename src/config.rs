@@ -1,32 +1,169 @@
-use std::{path::{Path, PathBuf}, sync::{Arc, RwLock}};
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, hash::Hash, ops::Range, path::{Path, PathBuf}, sync::{Arc, RwLock}};
 
 use colored::Colorize;
 use lazy_static::lazy_static;
 use strum::{IntoEnumIterator, EnumProperty, EnumIter};
 use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::preprocessor::{Preprocessable, PreprocessableName, PreprocessableString};
+use crate::preprocessor::{BUILTIN_GROUP_SIZE, Preprocessable, PreprocessableName, PreprocessableString, PreprocessorToken, SubstitutionTraces};
+use crate::compiler::CompilerToken;
+use crate::metadata::Metadata;
+use crate::diagnostic::Location;
+use crate::lint::{self, Level, Lint};
+use crate::plugin;
+use crate::transform::{self, Chains};
+use crate::sigil::{self, CompilerSigil, PreprocessorSigil};
+use crate::backend::Language;
 
 const MAX_REPEATS: usize = 10000; // so i dont accidentaly eat my entire ssd
+/// What `repeats = "auto"` deserializes into until [Config::infer_repeats]
+/// replaces it, TOML integers can't get this big.
+const AUTO_REPEATS: usize = usize::MAX;
+
+/// Versions of the `.xmva.toml` layout this build understands.
+pub const CONFIG_SCHEMA_VERSIONS: &[u32] = &[1];
+
+/// Seperates the generator name from the key in `@{name.key}`.
+pub const KEY_SCOPE_SEPARATOR: char = '.';
+/// Separates a key reference from the [Tag]s applied to its value,
+/// `@{key|NO_PREFIX|LOWER}`.
+pub const KEY_TAG_SEPARATOR: char = '|';
+
+/// What we were doing when a [Error::IO] happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    ReadConfig,
+    ReadStdin,
+    ResolvePath,
+    WriteOutput,
+    CreateDir,
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ReadConfig => write!(f, "read config"),
+            Self::ReadStdin => write!(f, "read the config from stdin for"),
+            Self::ResolvePath => write!(f, "resolve the path of"),
+            Self::WriteOutput => write!(f, "write output"),
+            Self::CreateDir => write!(f, "create directory")
+        }
+    }
+}
+
+/// The config field a path came from, see [Error::labeled].
+#[derive(Debug)]
+pub struct Field {
+    pub location: Location,
+    /// The line of the field with its value underlined, [Metadata::label].
+    pub snippet:  Option<String>,
+}
 
 #[derive(Debug)]
 pub enum Error {
-    IO   {file: PathBuf, message: String},
-    TOML {file: PathBuf, message: String, line: Option<(usize, usize)>},
+    IO   {file: PathBuf, operation: Operation, code: Option<i32>, message: String, field: Option<Box<Field>>},
+    Toml {file: PathBuf, message: String, line: Option<(usize, usize)>, span: Option<Range<usize>>},
+    Invalid {file: PathBuf, message: String},
     // KeySerialization {message: String},
     // KeyCompilation {key: String, name: String, message: String},
     // KeyPreprocessing {key: String, name: String, message: String},
     // KeyMutualReferencing {key_names: Vec<(String, PreprocessableName)>}
 }
 
+impl Error {
+
+    /// A [Error::IO] from a failed `operation` on `file`.
+    pub fn io(operation: Operation, file: &Path, err: std::io::Error) -> Self {
+        Self::IO {
+            file: file.to_owned(),
+            operation,
+            code: err.raw_os_error(),
+            message: err.to_string(),
+            field: None
+        }
+    }
+
+    /// Point a [Error::IO] at the config `field` its path came from.
+    pub fn labeled(self, metadata: &Metadata, field: &str) -> Self {
+        match self {
+            Self::IO { file, operation, code, message, .. } => Self::IO {
+                file,
+                operation,
+                code,
+                message,
+                field: Some(Box::new(Field {
+                    location: metadata.location(field),
+                    snippet: metadata.label(field, &format!("`{field}` is set here"))
+                }))
+            },
+            other => other
+        }
+    }
+
+    /// A [Error::Toml] for a value that parsed fine but doesn't make
+    /// sense, with the value of `field` underlined and labeled with `label`.
+    fn at(metadata: &Metadata, field: &str, message: String, label: &str) -> Self {
+        Self::Toml {
+            file: metadata.config.clone(),
+            message: match metadata.label(field, label) {
+                Some(snippet) => format!("{message}\n{snippet}"),
+                None => message
+            },
+            // the snippet already says where.
+            line: None,
+            span: metadata.span(field)
+        }
+    }
+
+    /// The same as [Error::at] but only `text` inside of the value of
+    /// `field` is underlined, or all of it if `text` isn't written as is.
+    fn at_text(metadata: &Metadata, field: &str, text: &str, message: String, label: &str) -> Self {
+        let Some(span) = metadata.find(field, text) else {
+            return Self::at(metadata, field, message, label)
+        };
+        Self::Toml {
+            file: metadata.config.clone(),
+            message: match metadata.label_span(span.clone(), label) {
+                Some(snippet) => format!("{message}\n{snippet}"),
+                None => message
+            },
+            line: None,
+            span: Some(span)
+        }
+    }
+
+    /// Where the error is in the config, for [crate::diagnostic] annotations.
+    pub fn location(&self) -> Location {
+        match self {
+            Self::IO { field: Some(field), .. } => field.location.clone(),
+            Self::Toml { file, span: Some(span), .. } if let Ok(contents) = std::fs::read_to_string(file) =>
+                Metadata::new(file, strip_bom(&contents)).location_of(span.start),
+            Self::Toml { file, line, .. } => Location {
+                file: file.clone(),
+                line: line.map(|(start, _)| start.max(1)),
+                column: None
+            },
+            Self::IO { file, .. } | Self::Invalid { file, .. } => Location::file(file)
+        }
+    }
+
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         _ = write!(f, "[config.rs] ");
         match self {
-            Self::IO { file, message } => {
+            Self::IO { file, operation, message, field, .. } => {
+                write!(f, "failed to {operation} {:?}: {message}", file)?;
+                match field.as_ref().and_then(|field| field.snippet.as_ref()) {
+                    Some(snippet) => write!(f, "\n{snippet}"),
+                    None => Ok(())
+                }
+            }
+            Self::Invalid { file, message } => {
                 write!(f, "in config file {:?}: {message}", file)
             }
-            Self::TOML { file, message, line } => {
+            Self::Toml { file, message, line, .. } => {
                 write!(f, "in config file {:?}: {message}{}",
                     file, 
                     if line.is_some() {
@@ -63,7 +200,39 @@ impl std::error::Error for Error {}
 /// being the key.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CommonKeyable {
-    pub prefix: String
+    pub prefix:    String,
+    /// Appended to every `name` (unless tagged with [Tag::NoSuffix]).
+    #[serde(default)]
+    pub suffix:    String,
+    /// Prepended to every `name` before the prefix and joined with
+    /// the [CommonKeyable::separator] (unless tagged with [Tag::NoNamespace]).
+    #[serde(default)]
+    pub namespace: String,
+    /// Joins the [CommonKeyable::namespace] and the rest of the `name`.
+    #[serde(default)]
+    pub separator: String,
+    /// Any other keys, nested tables end up as dotted keys so big sets
+    /// of keys can be kept organized.
+    /// 
+    /// Example
+    /// -------
+    /// ```toml
+    /// [common.keys]
+    /// license = "MIT"
+    /// 
+    /// [common.keys.paths]
+    /// include_dir = "include" # @{paths.include_dir}
+    /// ```
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub keys: BTreeMap<String, CommonKey>,
+}
+
+/// A entry of [CommonKeyable::keys].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum CommonKey {
+    Name(String),
+    Table(BTreeMap<String, CommonKey>),
 }
 
 /// Common configuration values shared across the entire process of
@@ -81,7 +250,45 @@ pub struct Common {
 
     /// No. of times the repeat pattern in the [Generator] is
     /// repeated.
-    pub repeats: usize
+    /// 
+    /// `"auto"` takes the most varadict arguments of the [Config::example]
+    /// and [Config::test] invocations (rounded up to [Common::round_repeats])
+    /// so it doesn't have to be kept up to date by hand.
+    #[serde(deserialize_with = "repeats_deserializer")]
+    pub repeats: usize,
+
+    /// `repeats = "auto"` rounds the most varadict arguments up to a
+    /// multiple of this, leaving room for the next few invocations.
+    #[serde(default = "default_round_repeats")]
+    pub round_repeats: usize,
+
+    /// Refuse to write a output bigger than this many bytes, a typo in
+    /// [Common::repeats] can otherwise end up as a 50 MB header in the
+    /// next commit. Checked before anything is written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_bytes: Option<usize>,
+
+    /// Picks the [crate::backend::Backend] the output is emitted with.
+    #[serde(default)]
+    pub language: Language,
+
+    /// Take strings written as TOML literal strings (`'...'`) as is,
+    /// neither `@{...}` nor `$(...)` and friends are tokens inside of them
+    /// and `\` is just a backslash. Plain C snippets can be pasted in
+    /// without escaping anything, double quoted strings work as before.
+    ///
+    /// Example
+    /// -------
+    /// ```toml
+    /// [common]
+    /// raw_literal_strings = true
+    ///
+    /// [[generator]]
+    /// preamble = 'printf("$(0) costs $5\n");' # written out as is.
+    /// repeat = "$(0)"
+    /// ```
+    #[serde(default)]
+    pub raw_literal_strings: bool
 }
 
 /// [Tag]s that the user adds along side a `name` string, these 
@@ -91,8 +298,70 @@ pub struct Common {
 /// [Tag]s can either remove preset [Todo]s or add new [Todo]s.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[allow(clippy::enum_variant_names)]
 pub enum Tag {
-    NoPrefix
+    NoPrefix,
+    NoSuffix,
+    NoNamespace,
+    Upper,
+    Lower,
+    /// The name of a chain in [Config::transforms], see [crate::transform].
+    #[serde(untagged)]
+    Chain(String)
+}
+
+impl Tag {
+
+    /// A tag written as text, anything that isn't a built in tag is
+    /// a chain.
+    pub fn parse(tag: &str) -> Self {
+        toml::Value::String(tag.to_owned())
+            .try_into()
+            .unwrap_or_else(|_| Self::Chain(tag.to_owned()))
+    }
+
+    /// Apply the tags of a key reference to the `value` of the key,
+    /// the value is already tagged so the `NO_*` tags strip what the
+    /// presets added (if it's still there).
+    pub fn apply_to_reference(
+        tags: &[Tag],
+        value: String,
+        common_keys: &CommonKeyable
+    ) -> Result<String, transform::Error> {
+
+        let namespace = format!("{}{}", common_keys.namespace, common_keys.separator);
+        let mut value = value;
+        for tag in tags {
+            value = match tag {
+                Tag::NoNamespace if !common_keys.namespace.is_empty() => value
+                    .strip_prefix(&namespace)
+                    .map(str::to_owned)
+                    .unwrap_or(value),
+                Tag::NoPrefix if !common_keys.prefix.is_empty() => {
+                    // the namespace goes in front of the prefix.
+                    let (namespaced, rest) = match value.strip_prefix(&namespace) {
+                        Some(rest) if !common_keys.namespace.is_empty() => (namespace.as_str(), rest),
+                        _ => ("", value.as_str())
+                    };
+                    match rest.strip_prefix(&common_keys.prefix) {
+                        Some(rest) => format!("{namespaced}{rest}"),
+                        None => value
+                    }
+                }
+                Tag::NoSuffix if !common_keys.suffix.is_empty() => value
+                    .strip_suffix(&common_keys.suffix)
+                    .map(str::to_owned)
+                    .unwrap_or(value),
+                Tag::NoPrefix | Tag::NoSuffix | Tag::NoNamespace => value,
+                Tag::Upper => value.to_uppercase(),
+                Tag::Lower => value.to_lowercase(),
+                Tag::Chain(chain) => transform::apply(chain, value)?
+            };
+        }
+        Ok(value)
+
+    }
+
 }
 
 /// A list of things to do to a `name`.
@@ -116,9 +385,18 @@ pub enum Tag {
 /// will automatically apply this [Todo] to all `name`s unless removed
 /// by a [Tag].
 #[derive(EnumIter, EnumProperty, Clone, Copy, PartialEq, Eq)]
-pub(self) enum Todo {
+#[allow(clippy::enum_variant_names)]
+enum Todo {
+    #[strum(props(preset = true))]
+    ApplyPrefix,
+    // after the prefix so that the namespace ends up in front of it.
+    #[strum(props(preset = true))]
+    ApplyNamespace,
     #[strum(props(preset = true))]
-    ApplyPrefix
+    ApplySuffix,
+    // after the presets so the prefix and suffix change case aswell.
+    ApplyUppercase,
+    ApplyLowercase
 }
 
 lazy_static! {
@@ -127,7 +405,7 @@ lazy_static! {
     static ref PRESET_TODO: Vec<Todo> = {
         let mut preset_todo_vec: Vec<Todo> = vec![];
         for todo in Todo::iter() {
-            if todo.get_bool("preset").is_some_and(|preset| preset == true) {
+            if todo.get_bool("preset").is_some_and(|preset| preset) {
                 preset_todo_vec.push(todo);
             }
         }
@@ -152,6 +430,24 @@ impl Todo {
                         );
                     }
                 }
+                Tag::NoSuffix => {
+                    if todo_vec.contains(&Todo::ApplySuffix) {
+                        todo_vec.retain(|todo| 
+                            *todo != Todo::ApplySuffix
+                        );
+                    }
+                }
+                Tag::NoNamespace => {
+                    if todo_vec.contains(&Todo::ApplyNamespace) {
+                        todo_vec.retain(|todo| 
+                            *todo != Todo::ApplyNamespace
+                        );
+                    }
+                }
+                Tag::Upper => todo_vec.push(Todo::ApplyUppercase),
+                Tag::Lower => todo_vec.push(Todo::ApplyLowercase),
+                // applied after every todo in [StringWithTags::apply_tags].
+                Tag::Chain(_) => ()
             }
 
         }
@@ -165,17 +461,22 @@ impl Todo {
 pub struct StringWithTags {
     pub tags: Vec<Tag>,
     pub string: String,
+    /// Functions of the [Plugin] script the name is run through after
+    /// the tags were applied, see [crate::plugin].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transforms: Vec<String>,
 }
 
 impl StringWithTags {
 
     // Translates [Tag]s into [Todo]s and applies
-    // the [Todo]s to the string.
+    // the [Todo]s to the string, then runs it through
+    // the transform chains it was tagged with.
     // `common_keys` is needed for certaint [Tag]s.
     pub fn apply_tags(
         &self,
         common_keys: &CommonKeyable
-    ) -> String {
+    ) -> Result<String, transform::Error> {
 
         let mut tagged_string = self.string.clone();
 
@@ -185,13 +486,35 @@ impl StringWithTags {
 
             match todo {
                 Todo::ApplyPrefix => {
-                    tagged_string = common_keys.prefix.to_owned() + &tagged_string
+                    tagged_string = common_keys.prefix.to_owned() + tagged_string.as_str()
+                }
+                Todo::ApplyNamespace => {
+                    if !common_keys.namespace.is_empty() {
+                        tagged_string = common_keys.namespace.to_owned() 
+                            + common_keys.separator.as_str()
+                            + tagged_string.as_str()
+                    }
+                }
+                Todo::ApplySuffix => {
+                    tagged_string.push_str(&common_keys.suffix)
+                }
+                Todo::ApplyUppercase => {
+                    tagged_string = tagged_string.to_uppercase()
+                }
+                Todo::ApplyLowercase => {
+                    tagged_string = tagged_string.to_lowercase()
                 }
             }
 
         }
 
-        tagged_string
+        for tag in &self.tags {
+            if let Tag::Chain(chain) = tag {
+                tagged_string = transform::apply(chain, tagged_string)?;
+            }
+        }
+
+        Ok(tagged_string)
 
     }
 
@@ -229,6 +552,32 @@ where
     Ok(Arc::new(RwLock::new(Preprocessable::NotPreprocessed(unprocessed_name))))
 }
 
+/// A count or `"auto"`, which becomes [AUTO_REPEATS].
+fn repeats_deserializer<'de, D>(
+    deserializer: D
+) -> Result<usize, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repeats {
+        Count(usize),
+        Auto(String)
+    }
+    match Repeats::deserialize(deserializer)? {
+        Repeats::Count(count) => Ok(count),
+        Repeats::Auto(auto) if auto == "auto" => Ok(AUTO_REPEATS),
+        Repeats::Auto(other) => Err(serde::de::Error::custom(
+            format!("repeats must be a number or \"auto\", found {other:?}")
+        ))
+    }
+}
+
+fn default_round_repeats() -> usize {
+    1
+}
+
 /// This deserializer flattens [PreprocessableString] 
 /// and automatically stores the [String] inside of
 /// [Preprocessable::NotPreprocessed].
@@ -256,6 +605,60 @@ where
     }
 }
 
+/// Same as [preprocessable_string_deserializer] but with a [Vec].
+fn preprocessable_vec_string_deserializer<'de, D>(
+    deserializer: D
+) -> Result<Vec<PreprocessableString>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let unprocessed_strings = Vec::<String>::deserialize(deserializer)?;
+    Ok(unprocessed_strings
+        .into_iter()
+        .map(|string| Arc::new(RwLock::new(Preprocessable::NotPreprocessed(string))))
+        .collect())
+}
+
+/// Same as [preprocessable_string_deserializer] but with a table keyed
+/// by numbers, TOML keys are always strings so we parse them ourselves.
+fn preprocessable_numbered_map_deserializer<'de, D>(
+    deserializer: D
+) -> Result<BTreeMap<usize, PreprocessableString>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let unprocessed_strings = BTreeMap::<String, String>::deserialize(deserializer)?;
+    unprocessed_strings
+        .into_iter()
+        .map(|(key, string)| match key.parse::<usize>() {
+            Ok(n) => Ok((n, Arc::new(RwLock::new(Preprocessable::NotPreprocessed(string))))),
+            Err(_) => Err(serde::de::Error::custom(format!("`{key}` is not a number")))
+        })
+        .collect()
+}
+
+/// `#include`s emitted at the very top of the output, system includes
+/// first and then local ones, both in the order they were written.
+/// 
+/// Example
+/// -------
+/// ```toml
+/// [includes]
+/// system = ["stdio.h"]
+/// local = ["@{prefix}types.h"]
+/// ```
+/// ```C
+/// #include <stdio.h>
+/// #include "my_types.h"
+/// ```
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Includes {
+    #[serde(default, deserialize_with = "preprocessable_vec_string_deserializer")]
+    pub system: Vec<PreprocessableString>,
+    #[serde(default, deserialize_with = "preprocessable_vec_string_deserializer")]
+    pub local:  Vec<PreprocessableString>,
+}
+
 /// A `#define` from C.
 /// 
 /// [Definition::key] is a reference to [Definition::name].
@@ -281,6 +684,9 @@ where
 /// /* With Definition::parameters being None */
 /// #define name expansion
 /// ```
+/// 
+/// The last parameter can be `...` which makes the definition variadic,
+/// the expansion then has to use `__VA_ARGS__`.
 #[derive(Deserialize, Debug, Clone)]
 pub struct Definition {
     pub key:        String,
@@ -289,6 +695,46 @@ pub struct Definition {
     pub parameters: Option<Vec<String>>,
     #[serde(deserialize_with = "preprocessable_string_deserializer")]
     pub expansion:  PreprocessableString,
+    /// Wraps the definition inside of `#if condition ... #endif`.
+    #[serde(default, deserialize_with = "preprocessable_option_string_deserializer")]
+    pub condition:  Option<PreprocessableString>,
+    /// Every use of the definition prints a warning with the reason
+    /// through `_Pragma`, the definition itself still works.
+    /// 
+    /// Example
+    /// -------
+    /// ```toml
+    /// deprecated = "use @{prefix}ERROR_V2 instead"
+    /// ```
+    pub deprecated: Option<String>,
+    /// If the `#define` is written at all, see [Emit].
+    #[serde(default)]
+    pub emit:       Emit,
+}
+
+/// When a [Definition] is written into the header.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Emit {
+    #[default]
+    Always,
+    /// Only when a generator or the main macro references its key,
+    /// directly or through other keys and emitted definitions. Turned
+    /// into [Emit::Always] or [Emit::Never] once the config is loaded.
+    /// 
+    /// Example
+    /// -------
+    /// ```toml
+    /// # shared.xmvadefs, only the ones this config uses end up in the header.
+    /// [[definition]]
+    /// key = "stringify"
+    /// name = "STRINGIFY"
+    /// parameters = ["x"]
+    /// expansion = "#x"
+    /// emit = "if-used"
+    /// ```
+    IfUsed,
+    Never,
 }
 
 /// Keys that might reference anything from another C file or the
@@ -303,23 +749,152 @@ pub struct Key {
 /// Custom preamble that is inserted as is (first preprocessed tho).
 #[derive(Deserialize, Debug, Clone)]
 pub struct Preamble {
-    #[serde(deserialize_with = "preprocessable_option_string_deserializer")]
+    #[serde(default, deserialize_with = "preprocessable_option_string_deserializer")]
     pub raw:  Option<PreprocessableString>,
     pub keys: Option<Vec<Key>>,
 }
 
+/// Same as the [Preamble] but appended after the main `xmva` macro.
+pub type Postamble = Preamble;
+
+/// Fails the compilation with the prefix and group size in the message,
+/// [BUILTIN_GROUP_SIZE] makes it work without knowing the [Core].
+fn default_unparity() -> PreprocessableString {
+    Arc::new(RwLock::new(Preprocessable::NotPreprocessed(format!(
+        "_Static_assert(0, \"@{{prefix}}: argument count not a multiple of @{{{BUILTIN_GROUP_SIZE}}}\")"
+    ))))
+}
+
+/// Expands to nothing.
+fn default_empty() -> PreprocessableString {
+    Arc::new(RwLock::new(Preprocessable::NotPreprocessed(String::new())))
+}
+
+/// Varadict argument counts a [Generator] handles, both inclusive,
+/// `max` defaults to [Common::repeats].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepeatRange {
+    #[serde(default)]
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl RepeatRange {
+
+    pub fn contains(&self, count: usize) -> bool {
+        self.min <= count && self.max.is_none_or(|max| count <= max)
+    }
+
+    /// [RepeatRange::max] clamped to the most varadict arguments the
+    /// ladders of `repeats` handle.
+    pub fn last(&self, repeats: usize) -> usize {
+        self.max.unwrap_or(usize::MAX).min(repeats.saturating_sub(1))
+    }
+
+}
+
 /// Fallbacks the [Generator] uses when encountering strange varadict
-/// argument counts.
+/// argument counts, both can be left out (or the whole table).
 #[derive(Deserialize, Debug, Clone)]
 pub struct Fallbacks {
-    #[serde(deserialize_with = "preprocessable_string_deserializer")]
+    #[serde(default = "default_unparity", deserialize_with = "preprocessable_string_deserializer")]
     /// What to do when the varadict argument count is not a multiple
     /// of [Paramaters::Varadict] in [Core::args].
+    /// 
+    /// The arguments left dangling can be used with `$<0>`, `$<1>`...,
+    /// references past the remainder expand to nothing.
+    /// 
+    /// Example
+    /// -------
+    /// ```toml
+    /// unparity = "_Static_assert(0, \"error code \" #$<0> \" has no message\")"
+    /// ```
     pub unparity: PreprocessableString,
 
-    #[serde(deserialize_with = "preprocessable_string_deserializer")]
+    #[serde(default = "default_empty", deserialize_with = "preprocessable_string_deserializer")]
     /// What to do when the varadict argument count is 0?
     pub empty: PreprocessableString,
+
+    /// [Fallbacks::unparity] for a specific remainder, so every off by N
+    /// case can say what exactly is missing.
+    /// 
+    /// Example
+    /// -------
+    /// ```toml
+    /// [generator.fallbacks.unparity_remainder]
+    /// 1 = "_Static_assert(0, \"@{prefix}: the last error code is missing its message.\")"
+    /// ```
+    #[serde(default, deserialize_with = "preprocessable_numbered_map_deserializer")]
+    pub unparity_remainder: BTreeMap<usize, PreprocessableString>,
+}
+
+impl Default for Fallbacks {
+    fn default() -> Self {
+        Self {
+            unparity: default_unparity(),
+            empty: default_empty(),
+            unparity_remainder: BTreeMap::new()
+        }
+    }
+}
+
+/// What kind of code a [Generator] generates.
+/// 
+/// Every kind other than [GeneratorKind::Repeat] is lowered into one or
+/// more [GeneratorKind::Repeat] generators when the config is loaded
+/// (see [crate::lower]), after that the kinds only exist for the user.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GeneratorKind {
+    /// The preamble, repeat and postamble are used as written.
+    #[default]
+    Repeat,
+    /// [Generator::repeat] is the name of a flag, every flag gets
+    /// assigned `1 << position` inside of a enum named [Generator::type_name]
+    /// along side a `_ALL` mask and a `_to_string` function.
+    Bitflags,
+    /// [Generator::repeat] is the element expression of a `static const`
+    /// array named [Generator::array] with elements of type
+    /// [Generator::type_name], every element is placed at its
+    /// position (starting from 0) with a designated initializer.
+    Table,
+    /// A `static inline` function with the [Generator::signature] that
+    /// `switch`es on [Generator::switch_on], every repeat is a
+    /// `case` [Generator::case_label] with [Generator::repeat] as its body
+    /// and [Generator::default_case] is the body of the `default` case.
+    Switch,
+    /// `static inline` functions computed from the repeats and group
+    /// size when generating, emitted below the `xmva`, see [Helper].
+    /// Doesn't generate anything inside of the `xmva` itself so it's
+    /// removed when lowering instead.
+    Helpers,
+}
+
+/// A `static inline` function of a [GeneratorKind::Helpers] generator,
+/// named after the prefix.
+/// 
+/// Example
+/// -------
+/// ```toml
+/// [[generator]]
+/// kind    = "helpers"
+/// helpers = ["max", "valid"] # all of them when left out.
+/// ```
+/// ```C
+/// static inline int YA_max_arguments(void) { return 29; }
+/// static inline int YA_max_groups(void) { return 14; }
+/// static inline int YA_valid_argument_count(int count) { ... }
+/// ```
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+#[serde(rename_all = "lowercase")]
+pub enum Helper {
+    /// `group_count(count)`, how many groups `count` arguments are.
+    Count,
+    /// `valid_argument_count(count)`, if the `xmva` accepts `count`
+    /// varadict arguments without hitting a fallback.
+    Valid,
+    /// `max_arguments()` and `max_groups()`.
+    Max,
 }
 
 /// In this XMVA macro i've invisioned there is but one catch,
@@ -332,12 +907,139 @@ pub struct Fallbacks {
 /// varadict arguments.
 #[derive(Deserialize, Debug, Clone)]
 pub struct Generator {
+    /// See [GeneratorKind].
+    #[serde(default)]
+    pub kind: GeneratorKind,
+
+    /// Name of the C type that kinds like [GeneratorKind::Bitflags]
+    /// generate, unused by [GeneratorKind::Repeat].
+    #[serde(rename = "type")]
+    pub type_name: Option<String>,
+
+    /// Name of the array a [GeneratorKind::Table] generates.
+    pub array: Option<String>,
+
+    /// Function signature of a [GeneratorKind::Switch], without the
+    /// `static inline`.
+    pub signature: Option<String>,
+
+    /// Expression a [GeneratorKind::Switch] switches on.
+    #[serde(rename = "switch")]
+    pub switch_on: Option<String>,
+
+    /// Label of every `case` in a [GeneratorKind::Switch].
+    #[serde(rename = "case")]
+    pub case_label: Option<String>,
+
+    /// Body of the `default` case in a [GeneratorKind::Switch],
+    /// `break;` if not given.
+    #[serde(rename = "default")]
+    pub default_case: Option<String>,
+
+    /// Wraps everything the generator emits inside of
+    /// `#if condition ... #endif`, when the condition is false the
+    /// generator macro is defined as empty so the `xmva` still works,
+    /// it just doesn't generate anything for this generator.
+    #[serde(default, deserialize_with = "preprocessable_option_string_deserializer")]
+    pub condition: Option<PreprocessableString>,
+
+    /// Name of the generator, its [Generator::keys] can be referenced
+    /// from anywhere as `@{name.key}`.
+    pub name: Option<String>,
+
+    /// Keys only visible inside of this generator's strings, they
+    /// shadow global keys with the same name.
+    pub keys: Option<Vec<Key>>,
+
+    /// Write the generator into its own file (relative to the main
+    /// output) instead of the main output, the main output then
+    /// `#include`s it.
+    pub output: Option<PathBuf>,
+
+    /// Only materialize the ladder for these varadict argument counts,
+    /// anything outside of them fails with a `#pragma GCC error`. Every
+    /// ladder is picked by the same picker sized to [Common::repeats],
+    /// so a `max` past it is cut down to it.
+    /// 
+    /// Example
+    /// -------
+    /// ```toml
+    /// # small generator next to one needing the full 64 repeats.
+    /// repeats = {min = 1, max = 8}
+    /// ```
+    pub repeats: Option<RepeatRange>,
+
+    /// Name of a callback macro that every repeat calls with the group
+    /// of varadict arguments and the position instead of a [Generator::repeat],
+    /// the classic x-macro style. Keeps the ladder small when the body is long.
+    /// 
+    /// Example
+    /// -------
+    /// ```toml
+    /// # with args -> {varadict = 2} every repeat is `ERROR_CODE($(0), $(1), $.)`.
+    /// apply = "ERROR_CODE"
+    /// ```
+    pub apply: Option<String>,
+
+    /// Written in between every two repeats, the same as ending the
+    /// [Generator::repeat] with a `$[...]` but without having to escape
+    /// anything. Only works with [GeneratorKind::Repeat].
+    /// 
+    /// Example
+    /// -------
+    /// ```toml
+    /// repeat = "$(0) = $."
+    /// join   = ", "
+    /// ```
+    pub join: Option<String>,
+
+    /// Take the generator from a library file (relative to the config),
+    /// a library is a `[[generator]]` table on its own plus the `params`
+    /// it expects through [Generator::with]. Only the `name`, `output`,
+    /// `repeats` and `keys` (appended to the library ones) are taken from here.
+    /// 
+    /// Example
+    /// -------
+    /// ```toml
+    /// # lib/enum_to_string.xmvagen
+    /// params = ["function"]
+    /// preamble = "static const char *@{function}(int value) { switch(value) {"
+    /// repeat = "case $(0): return #$(0);"
+    /// postamble = "default: return \"?\"; } }"
+    /// ```
+    /// ```toml
+    /// [[generator]]
+    /// from = "lib/enum_to_string.xmvagen"
+    /// with = {function = "error_to_string"}
+    /// ```
+    pub from: Option<PathBuf>,
+
+    /// Parameters of the [Generator::from] library, referenced inside of
+    /// it like keys but taken as is.
+    #[serde(default)]
+    pub with: BTreeMap<String, String>,
+
+    /// Which helpers a [GeneratorKind::Helpers] generator emits, all of
+    /// them if left out.
+    pub helpers: Option<Vec<Helper>>,
+
     /// On strange varadict argument counts, set what the generated
     /// x-macro will write out.
+    #[serde(default)]
     pub fallbacks: Fallbacks,
     
     /// What to write before the repeat part.
-    #[serde(deserialize_with = "preprocessable_string_deserializer")]
+    ///
+    /// Besides `${...}` it can use `$.`, how many groups the step has, and
+    /// `$(first.N)`/`$(last.N)` (or a member name, `$(last.value)`) for the
+    /// `N`th argument of the first or last group.
+    ///
+    /// Example
+    /// -------
+    /// ```toml
+    /// preamble = "enum { ${name}_count = $., ${name}_first = $(first.0) };"
+    /// ```
+    #[serde(default, deserialize_with = "preprocessable_string_deserializer")]
     pub preamble: PreprocessableString,
     
     /// Repeat represents a string that can contain arguments passed into
@@ -358,16 +1060,26 @@ pub struct Generator {
     /// special sigils from [crate::sigil::CompilerSigil]:
     /// 
     /// - `${...}`
-    ///     tells us where to place a named argument:
-    ///     `... ${lowercase_name} ... ${UPPERCASE_NAME}`
+    ///   tells us where to place a named argument:
+    ///   `... ${lowercase_name} ... ${UPPERCASE_NAME}`
     /// 
     /// - `$(...)`
-    ///     tells us where to place a varadict argument: 
-    ///     `... $(0) ... $(1) ...`
+    ///   tells us where to place a varadict argument: 
+    ///   `... $(0) ... $(1) ...`
     /// 
     /// - `$[...]`
-    ///     tells us to repeat this character except on the last repeat:
-    ///     `... $[,] ... $[peepee poopoo] ...`
+    ///   tells us to repeat this character except on the last repeat:
+    ///   `... $[,] ... $[peepee poopoo] ...`
+    ///
+    /// - `$(first.N)` and `$(last.N)`
+    ///   the `N`th argument of the first or last group, a member name
+    ///   works too: `... $(first.0) ... $(last.value) ...`
+    ///
+    /// - `$[N:...]` and `$[^N:...]`
+    ///   the same but skipped on the last (or first) `N` repeats, the
+    ///   text starts right after the `:`. `$[1:2:...]` writes a `$[...]`
+    ///   whose text starts with `2:`.
+    ///   `... $[2:,] ... $[^1: | ] ...`
     /// 
     /// Example
     /// -------
@@ -392,11 +1104,12 @@ pub struct Generator {
     /// # ...
     /// repeat = "[@{prefix} ## $(0)] = $(1)$[,]"
     /// ```
-    #[serde(deserialize_with = "preprocessable_string_deserializer")]
+    #[serde(default, deserialize_with = "preprocessable_string_deserializer")]
     pub repeat: PreprocessableString,
 
-    // What to write after the repeat part.
-    #[serde(deserialize_with = "preprocessable_string_deserializer")]
+    // What to write after the repeat part, takes the same tokens as the
+    // [Generator::preamble].
+    #[serde(default, deserialize_with = "preprocessable_string_deserializer")]
     pub postamble: PreprocessableString
 }
 
@@ -423,7 +1136,10 @@ pub enum Argument {
 }
 
 /// The [Core] which holds the main XMVA name and arguments.
-#[derive(Deserialize, Debug, Clone)]
+/// 
+/// Defaults to a empty [Core] so that high level sections like [Enum]
+/// can fill it in instead.
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct Core {
     /// The name of the `xmva` we want to create.
     #[serde(deserialize_with = "preprocessable_string_deserializer")]
@@ -432,69 +1148,1583 @@ pub struct Core {
     /// including named parameters and the number of
     /// varadict arguments.
     pub args: Vec<Argument>,
+    /// Name of a optional classic x-macro `list(X, ...)` emitted along
+    /// side the `xmva`, it calls `X` once for every varadict
+    /// argument group.
+    #[serde(default, deserialize_with = "preprocessable_option_string_deserializer")]
+    pub list: Option<PreprocessableString>,
+    /// See [Group].
+    pub group: Option<Group>,
+    /// Same as [Definition::deprecated] but for the `xmva`.
+    pub deprecated: Option<String>,
 }
 
-/// The main config structure.
-/// Each part of the [Config] and what they do are explained in their own docs.
+/// Names for the members of every varadict argument group, so that
+/// repeats can write `$(key)` instead of `$(0)`. There has to be one
+/// name for every member of the group.
 /// 
-/// Init
-/// ----
-/// This structure can be initialized from a `.xmva.toml` file.
-/// ```
-/// let config: Config = Config::load(&Path::new("example.xmva.toml"));
+/// Members can be documented, see [Output::member_docs] for where the
+/// docs end up.
+/// 
+/// Example
+/// -------
+/// ```toml
+/// [core]
+/// args = [{varadict = 3}]
+/// group = {members = ["key", {name = "value", doc = "What the key maps to."}, "doc"]}
+/// 
+/// [[generator]]
+/// repeat = "[$(key)] = $(value)$[,]"
 /// ```
-#[derive(Deserialize, Debug, Clone)]
-pub struct Config {
-    pub common:     Common, 
-    pub preamble:   Option<Preamble>,
-    pub definition: Option<Vec<Definition>>,
-    pub core:       Core,
-    pub generator:  Vec<Generator>,
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Group {
+    pub members: Vec<Member>,
 }
 
-impl Config { 
+/// A [Group] member, either just its name or its name and doc.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Member {
+    Name(String),
+    Documented {name: String, doc: String}
+}
 
-    pub fn load(path: &Path) -> Result<Self, Error>{
-        
-        log::debug!("Starting to load config.");
+impl Member {
 
-        let file_contents = std::fs::read_to_string(path)
-            .map_err(|fs_err| Error::IO { 
-                file: path.to_owned(),
-                message: fs_err.to_string() 
-            })?;
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Name(name) | Self::Documented { name, .. } => name
+        }
+    }
 
-        log::debug!("Loaded file into memory.");
+    pub fn doc(&self) -> Option<&str> {
+        match self {
+            Self::Name(_) => None,
+            Self::Documented { doc, .. } => Some(doc)
+        }
+    }
 
-        let mut config: Self = toml::from_str(&file_contents)
-            .map_err(|toml_err| Error::TOML { 
-                file: path.to_owned(),
-                message: toml_err.message().to_owned(), 
-                line: if toml_err.span().is_some() {
-                    let offset_start = toml_err.span().unwrap().start;
-                    let offset_end   = toml_err.span().unwrap().end;
-                    let line_start   = file_contents[..offset_start].lines().count();
-                    let line_end     = file_contents[..offset_end].lines().count();
-                    Some((line_start, line_end))
-                } else {
-                    None
-                }
-            })?;
+}
 
-        // limit repeats
-        config.common.repeats = std::cmp::min(MAX_REPEATS, config.common.repeats);
+impl Group {
 
-        if config.common.output.is_none() {
-            config.common.output = Some(path.to_owned());
-        }
-        
-        log::trace!("{}",
-            format!("Config loaded: {:#?}", config)
-            .dimmed()
+    /// Names of the members in order.
+    pub fn names(&self) -> Vec<&str> {
+        self.members.iter().map(Member::name).collect()
+    }
+
+}
+
+impl Core {
+
+    /// The group size of the varadict arguments, [None] if [Core::args]
+    /// doesn't have a [Argument::Varadict].
+    pub fn varadict(&self) -> Option<usize> {
+        self.args.iter().find_map(|arg| match arg {
+            Argument::Varadict { varadict } => Some(*varadict),
+            Argument::Named(_) => None
+        })
+    }
+
+}
+
+/// A member of each varadict argument group passed to a [Enum] `xmva`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EnumMember {
+    /// The enumerator itself.
+    Value,
+    /// String representation of the enumerator, if it isn't a member
+    /// the enumerator is stringified instead.
+    String,
+    /// Documentation string for the enumerator.
+    Doc
+}
+
+impl EnumMember {
+
+    /// The name it's written as, also its [Group] member name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Value => "value",
+            Self::String => "string",
+            Self::Doc => "doc"
+        }
+    }
+
+}
+
+fn default_enum_members() -> Vec<EnumMember> {
+    vec![EnumMember::Value, EnumMember::String]
+}
+
+/// High level section for the most common use case, a `xmva` that
+/// creates a enum.
+/// 
+/// Instead of writing the [Core] and the [Generator]s by hand, the
+/// [Enum] gets lowered into them (see [crate::lower]) generating
+/// the enum definition, a name lookup table, a doc lookup table
+/// (if [EnumMember::Doc] is a member) and a count.
+/// 
+/// Example
+/// -------
+/// ```TOML
+/// [enum]
+/// xmva    = "@{prefix}ENUM"
+/// entries = ["value", "string", "doc"]
+/// ```
+/// ```C
+/// YA_ENUM(color, RED, "red", "The color red.", GREEN, "green", "Grass.")
+/// /* enum color {RED, GREEN};
+///  * static const char *const color_names[] = {[RED] = "red", ...};
+///  * static const char *const color_docs[] = {[RED] = "The color red.", ...};
+///  * enum {color_count = 0 + 1 + 1}; */
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+pub struct Enum {
+    /// The name of the `xmva` we want to create.
+    pub xmva: String,
+    /// Members of every varadict argument group in order.
+    #[serde(default = "default_enum_members")]
+    pub entries: Vec<EnumMember>,
+}
+
+/// How the assembler orders the sections of the generated file.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// All repeat ladders first and then all generator macros.
+    #[default]
+    Grouped,
+    /// Every generator's ladder is directly followed by its
+    /// generator macro.
+    Interleaved,
+}
+
+/// Where the docs of the [Group] members are written as comments.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MemberDocs {
+    /// Nowhere.
+    #[default]
+    Off,
+    /// Above every ladder step that bakes whole groups, naming the
+    /// member every argument is.
+    Repeats,
+    /// In a Doxygen block above the main `xmva`.
+    Main,
+}
+
+/// The order the `[[definition]]`s are written in.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDefinitions {
+    /// The order they were declared in the config.
+    #[default]
+    Declaration,
+    /// By their name, reorganizing the config then doesn't show up in
+    /// a diff of the output.
+    Alpha,
+}
+
+/// Preset for the formatting options of [Output], the options that
+/// are given anyway win over the preset.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Style {
+    /// Every define on a single line, grouped layout and no comments.
+    #[default]
+    Compact,
+    /// Interleaved layout, wrapped and indented defines, comments above
+    /// every section and the member docs above the main `xmva`.
+    Readable,
+}
+
+/// What wrapped lines are indented with.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IndentWith {
+    #[default]
+    Spaces,
+    Tabs,
+}
+
+/// Indentation of the lines [Output::wrap] continues a define onto,
+/// the `width` defaults to 4 spaces or a single tab.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Indent {
+    #[serde(default)]
+    pub with:  IndentWith,
+    pub width: Option<usize>,
+}
+
+impl Indent {
+
+    /// The indentation `level` levels deep.
+    pub fn level(&self, level: usize) -> String {
+        let (unit, width) = match self.with {
+            IndentWith::Spaces => (" ", self.width.unwrap_or(4)),
+            IndentWith::Tabs => ("\t", self.width.unwrap_or(1))
+        };
+        unit.repeat(width * level)
+    }
+
+}
+
+/// Controls the shape of the generated file.
+/// 
+/// Example
+/// -------
+/// ```toml
+/// [output]
+/// style     = "readable"
+/// layout    = "interleaved"
+/// # write when the file was generated into the banner, honors
+/// # SOURCE_DATE_EPOCH so reproducible builds stay reproducible.
+/// timestamp = true
+/// member_docs = "main"
+/// # `#define YA_COUNT 30`, `YA_MAX_ARGS 29` and `YA_GROUP_SIZE 2`.
+/// size_macros = true
+/// # `YA_APPLY_0(...)`, `YA_APPLY_2(...)`... the `xmva` for exactly that
+/// # many varadict arguments, a arity the ladders don't handle picks
+/// # the fallback like the `xmva` would.
+/// expose_arity_macros = true
+/// # continue the body of ladder steps and the main `xmva` on their own
+/// # indented lines.
+/// wrap      = true
+/// # a comment above every ladder, generator macro and the main `xmva`.
+/// comments  = true
+/// # what the wrapped lines are indented with, tables and switch bodies
+/// # of the generator kinds end up one level deeper.
+/// indent    = {with = "spaces", width = 2}
+/// sort_definitions = "alpha"
+/// ```
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(from = "OutputOptions")]
+pub struct Output {
+    pub layout: Layout,
+    pub timestamp: bool,
+    pub member_docs: MemberDocs,
+    /// Emit the [Common::repeats], the most varadict arguments the
+    /// `xmva` takes and the group size as object-like macros.
+    pub size_macros: bool,
+    /// Give every step of the ladders a public name, `PREFIX_APPLY_N`
+    /// calls the generators with the steps for exactly `N` varadict
+    /// arguments. Only counts that are a whole number of groups get one.
+    pub expose_arity_macros: bool,
+    pub wrap: bool,
+    pub comments: bool,
+    pub indent: Indent,
+    pub sort_definitions: SortDefinitions,
+}
+
+/// [Output] as it is written, the [Style] fills in what was left out.
+#[derive(Deserialize)]
+struct OutputOptions {
+    #[serde(default)]
+    style: Style,
+    layout: Option<Layout>,
+    #[serde(default)]
+    timestamp: bool,
+    member_docs: Option<MemberDocs>,
+    #[serde(default)]
+    size_macros: bool,
+    #[serde(default)]
+    expose_arity_macros: bool,
+    wrap: Option<bool>,
+    comments: Option<bool>,
+    #[serde(default)]
+    indent: Indent,
+    #[serde(default)]
+    sort_definitions: SortDefinitions,
+}
+
+impl From<OutputOptions> for Output {
+
+    fn from(options: OutputOptions) -> Self {
+        let readable = options.style == Style::Readable;
+        Self {
+            layout: options.layout.unwrap_or(match readable {
+                true => Layout::Interleaved,
+                false => Layout::Grouped
+            }),
+            timestamp: options.timestamp,
+            member_docs: options.member_docs.unwrap_or(match readable {
+                true => MemberDocs::Main,
+                false => MemberDocs::Off
+            }),
+            size_macros: options.size_macros,
+            expose_arity_macros: options.expose_arity_macros,
+            wrap: options.wrap.unwrap_or(readable),
+            comments: options.comments.unwrap_or(readable),
+            indent: options.indent,
+            sort_definitions: options.sort_definitions,
+        }
+    }
+
+}
+
+/// A script with extension hooks, see [crate::plugin].
+/// 
+/// Example
+/// -------
+/// ```toml
+/// [plugin]
+/// script = "transforms.rhai"
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+pub struct Plugin {
+    /// Relative to the config.
+    pub script: PathBuf,
+}
+
+/// Sigils of a single tokenizer that are written differently than the
+/// default, for when the generated code needs the default characters.
+/// The `embed` is a good candidate since `\\` is all over C strings.
+/// 
+/// Example
+/// -------
+/// ```toml
+/// [sigils.preprocessor]
+/// start = "%"
+/// embed = "^"
+/// [sigils.compiler]
+/// start = "$$"
+/// embed = "^"
+/// ```
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct TokenizerSigils {
+    pub start: Option<toml::Spanned<String>>,
+    pub embed: Option<toml::Spanned<String>>,
+}
+
+impl TokenizerSigils {
+
+    /// Remapped sigils by the name of the sigil they replace.
+    fn remapped(&self) -> Vec<(&'static str, &toml::Spanned<String>)> {
+        [("TokenStart", &self.start), ("TokenEmbed", &self.embed)]
+            .into_iter()
+            .filter_map(|(sigil, sequence)| sequence.as_ref().map(|sequence| (sigil, sequence)))
+            .collect()
+    }
+
+    fn overrides<T: std::str::FromStr + Hash + Eq>(&self) -> HashMap<T, String> {
+        self.remapped()
+            .into_iter()
+            .filter_map(|(sigil, sequence)| Some((sigil.parse().ok()?, sequence.get_ref().clone())))
+            .collect()
+    }
+
+    /// Where in the config `sigil` was remapped.
+    fn span(&self, sigil: &str) -> Option<Range<usize>> {
+        self.remapped()
+            .into_iter()
+            .find(|(remapped, _)| *remapped == sigil)
+            .map(|(_, sequence)| sequence.span())
+    }
+
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Sigils {
+    #[serde(default)]
+    pub preprocessor: TokenizerSigils,
+    #[serde(default)]
+    pub compiler: TokenizerSigils,
+}
+
+impl Sigils {
+
+    /// Validate the remapped sigils and use them for all tokenizing.
+    fn apply(&self, file_contents: &str, path: &Path) -> Result<(), Error> {
+
+        let Err(collision) = sigil::set_overrides(
+            self.preprocessor.overrides::<PreprocessorSigil>(), 
+            self.compiler.overrides::<CompilerSigil>()
+        ) else {
+            return Ok(())
+        };
+
+        let span = match collision.sigil() {
+            ("preprocessor", sigil) => self.preprocessor.span(sigil),
+            (_, sigil) => self.compiler.span(sigil)
+        }.or_else(|| self.compiler.remapped().first().map(|(_, sequence)| sequence.span()))
+         .or_else(|| self.preprocessor.remapped().first().map(|(_, sequence)| sequence.span()));
+
+        Err(Error::Toml {
+            file: path.to_owned(),
+            message: format!("invalid [sigils]: {collision}"),
+            line: span.as_ref().map(|span| (
+                file_contents[..span.start].lines().count(),
+                file_contents[..span.end].lines().count()
+            )),
+            span
+        })
+
+    }
+
+}
+
+/// A config pulled in through [Config::uses], parsed and preprocessed
+/// along side the config that uses it.
+#[derive(Debug, Clone)]
+pub struct Used {
+    /// Scope of the builtin keys, the file name up to the first `.`.
+    pub alias:  String,
+    /// Both paths are absolute since the generator changes directories.
+    pub config: PathBuf,
+    /// The main output of the used config.
+    pub output: PathBuf,
+    pub xmva:   String,
+    pub prefix: String,
+}
+
+/// A generator library, see [Generator::from].
+#[derive(Deserialize, Debug, Clone)]
+struct Library {
+    #[serde(default)]
+    params: Vec<String>,
+    #[serde(flatten)]
+    generator: Generator,
+}
+
+/// A TOML error of `file_contents` at `path`.
+pub(crate) fn toml_error(toml_err: toml::de::Error, file_contents: &str, path: &Path) -> Error {
+    Error::Toml { 
+        file: path.to_owned(),
+        message: toml_err.message().to_owned(), 
+        line: if toml_err.span().is_some() {
+            let offset_start = toml_err.span().unwrap().start;
+            let offset_end   = toml_err.span().unwrap().end;
+            let line_start   = file_contents[..offset_start].lines().count();
+            let line_end     = file_contents[..offset_end].lines().count();
+            Some((line_start, line_end))
+        } else {
+            None
+        },
+        span: toml_err.span()
+    }
+}
+
+/// Where the main output of the config at `path` ends up.
+fn main_output(path: &Path, output: Option<&PathBuf>) -> PathBuf {
+    let directory = path.parent().unwrap_or(Path::new(""));
+    std::path::absolute(directory.join(output.map(PathBuf::as_path).unwrap_or(path)))
+        .unwrap_or_else(|_| directory.join(output.map(PathBuf::as_path).unwrap_or(path)))
+}
+
+/// A UTF-8 byte order mark, some Windows editors start every file with one.
+const BOM: char = '\u{feff}';
+
+/// `contents` without a leading [BOM], TOML doesn't allow one.
+pub fn strip_bom(contents: &str) -> &str {
+    contents.strip_prefix(BOM).unwrap_or(contents)
+}
+
+/// What a config that isn't UTF-8 most likely is instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Utf16Le => write!(f, "UTF-16 LE"),
+            Self::Utf16Be => write!(f, "UTF-16 BE"),
+            Self::Latin1 => write!(f, "Latin-1 (or Windows-1252)")
+        }
+    }
+}
+
+impl Encoding {
+
+    /// Guess from the byte order mark or, without one, from where the
+    /// zero bytes are. UTF-16 text that is mostly ASCII has every other
+    /// byte zeroed.
+    pub fn guess(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&[0xff, 0xfe]) {
+            return Self::Utf16Le
+        }
+        if bytes.starts_with(&[0xfe, 0xff]) {
+            return Self::Utf16Be
+        }
+        let sample = &bytes[..bytes.len().min(1024)];
+        let zeros = |parity: usize| sample
+            .iter()
+            .skip(parity)
+            .step_by(2)
+            .filter(|byte| **byte == 0)
+            .count();
+        match (zeros(0), zeros(1)) {
+            (even, odd) if odd > sample.len() / 4 && odd > even => Self::Utf16Le,
+            (even, _) if even > sample.len() / 4 => Self::Utf16Be,
+            _ => Self::Latin1
+        }
+    }
+
+}
+
+/// `bytes` of the config at `path` as a string, with where the first
+/// invalid sequence is and a guess at the [Encoding] when they aren't UTF-8.
+///
+/// UTF-16 without a byte order mark is mostly valid UTF-8 full of zero
+/// bytes, so those are caught too.
+pub fn decode(bytes: Vec<u8>, path: &Path) -> Result<String, Error> {
+
+    let invalid = |bytes: &[u8], offset: usize, problem: &str| {
+        let line_start = bytes[..offset].iter().rposition(|byte| *byte == b'\n').map_or(0, |newline| newline + 1);
+        Error::Invalid {
+            file: path.to_owned(),
+            message: format!(
+                "isn't valid UTF-8, {problem} (0x{:02x}) is at offset {offset} (line {}, column {}). \
+                It looks like {}, save it as UTF-8 instead.",
+                bytes[offset],
+                bytes[..offset].iter().filter(|byte| **byte == b'\n').count() + 1,
+                offset - line_start + 1,
+                Encoding::guess(bytes)
+            )
+        }
+    };
+
+    let contents = String::from_utf8(bytes)
+        .map_err(|err| invalid(err.as_bytes(), err.utf8_error().valid_up_to(), "the first invalid byte"))?;
+    if let Some(offset) = contents.find('\0')
+        && Encoding::guess(contents.as_bytes()) != Encoding::Latin1 {
+        return Err(invalid(contents.as_bytes(), offset, "the first zero byte"))
+    }
+    Ok(contents)
+
+}
+
+/// Read the config (or anything else in TOML) at `path`, see [decode].
+pub fn read(path: &Path) -> Result<String, Error> {
+    let bytes = std::fs::read(path).map_err(|fs_err| Error::io(Operation::ReadConfig, path, fs_err))?;
+    decode(bytes, path)
+}
+
+/// `path` with every `\\` turned into a `/`, so a config written on
+/// Windows works everywhere. Windows takes `/` just as well.
+pub fn normalize_separators(path: &Path) -> PathBuf {
+    match path.to_str() {
+        Some(text) if text.contains('\\') => PathBuf::from(text.replace('\\', "/")),
+        _ => path.to_owned()
+    }
+}
+
+/// A invocation of the `xmva` written in the config, every argument
+/// as it would be written in C with the named ones first.
+/// 
+/// Example
+/// -------
+/// ```toml
+/// [example]
+/// args = ["vector", "VECTOR", "EMPTY", "\"Vector was empty.\""]
+/// 
+/// [[test]]
+/// name = "full"
+/// args = ["vector", "VECTOR", "EMPTY", "\"Empty.\"", "FULL", "\"Full.\""]
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+pub struct Invocation {
+    pub name: Option<String>,
+    pub args: Vec<String>,
+}
+
+/// The main config structure.
+/// Each part of the [Config] and what they do are explained in their own docs.
+/// 
+/// Init
+/// ----
+/// This structure can be initialized from a `.xmva.toml` file.
+/// ```
+/// let config: Config = Config::load(&Path::new("example.xmva.toml"));
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    pub common:     Common, 
+    #[serde(default)]
+    pub includes:   Includes,
+    pub preamble:   Option<Preamble>,
+    pub postamble:  Option<Postamble>,
+    pub definition: Option<Vec<Definition>>,
+    #[serde(default)]
+    pub core:       Core,
+    #[serde(default)]
+    pub generator:  Vec<Generator>,
+    #[serde(rename = "enum")]
+    pub enumeration: Option<Enum>,
+    #[serde(default)]
+    pub output:     Output,
+    #[serde(default)]
+    pub sigils:     Sigils,
+    /// Level of every lint that shouldn't just warn, see [crate::lint].
+    #[serde(default)]
+    pub lints:      BTreeMap<Lint, Level>,
+    pub plugin:     Option<Plugin>,
+    /// Named transform chains that tags can reference, see [crate::transform].
+    #[serde(default)]
+    pub transforms: Chains,
+    /// The invocation shown in the docs, see [Invocation].
+    pub example:    Option<Invocation>,
+    /// Invocations the `xmva` has to handle, see [Invocation].
+    #[serde(default)]
+    pub test:       Vec<Invocation>,
+    /// Other configs (relative to this one) whose main macro and prefix
+    /// can be referenced with `@{name.__XMVA__}` and `@{name.__PREFIX__}`,
+    /// where `name` is the file name of the other config up to the first `.`.
+    /// 
+    /// Has to come before any table in the config.
+    /// 
+    /// Example
+    /// -------
+    /// ```toml
+    /// uses = ["errors.xmva.toml"]
+    /// 
+    /// [[generator]]
+    /// repeat = "@{errors.__XMVA__}(...)"
+    /// ```
+    #[serde(default)]
+    pub uses:       Vec<PathBuf>,
+    /// Filled in when parsing, see [Used].
+    #[serde(skip)]
+    pub used:       Vec<Used>,
+    /// Filled in when lowering [GeneratorKind::Helpers] generators.
+    #[serde(skip)]
+    pub helpers:    Vec<Helper>,
+    /// Filled in during preprocessing, see [SubstitutionTraces].
+    #[serde(skip)]
+    pub substitutions: SubstitutionTraces,
+    /// Filled in during surface compiling, the string before and after.
+    #[serde(skip)]
+    pub surface_compilations: SubstitutionTraces,
+    /// Filled in when parsing, see [Metadata].
+    #[serde(skip)]
+    pub metadata: Metadata,
+    /// `[common]` fields set after parsing along side where their value
+    /// came from, the config doesn't show it. See [Config::set_repeats].
+    #[serde(skip)]
+    pub overridden: Vec<(&'static str, String)>,
+}
+
+impl Config { 
+
+    /// Every invocation written in the config, the [Config::example] first.
+    pub fn invocations(&self) -> impl Iterator<Item = &Invocation> {
+        self.example.iter().chain(self.test.iter())
+    }
+
+    /// `repeats = "auto"`, enough repeats for the invocation with the
+    /// most varadict arguments.
+    fn infer_repeats(&self) -> Result<usize, String> {
+
+        let named = self.core.args
+            .iter()
+            .filter(|arg| matches!(arg, Argument::Named(_)))
+            .count();
+        let mut most: Option<usize> = None;
+        for (i, invocation) in self.invocations().enumerate() {
+            let Some(varadict) = invocation.args.len().checked_sub(named) else {
+                return Err(format!(
+                    "invocation {} has {} arguments but the `xmva` takes {named} named ones first",
+                    invocation.name.clone().unwrap_or(i.to_string()),
+                    invocation.args.len()
+                ))
+            };
+            most = Some(most.unwrap_or(0).max(varadict));
+        }
+
+        let Some(most) = most else {
+            return Err("repeats = \"auto\" needs the invocations of a [example] or [[test]] to count".to_owned())
+        };
+        let round = self.common.round_repeats.max(1);
+        // one more step than arguments, the empty invocation is one too.
+        Ok(most.div_ceil(round) * round + 1)
+
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error>{
+        
+        log::debug!("Starting to load config.");
+
+        let file_contents = read(path)?;
+
+        log::debug!("Loaded file into memory.");
+
+        Self::parse(&file_contents, path)
+
+    }
+
+    /// Overrides given from the command line, applied after the config
+    /// is loaded (and the workspace overrides) and before it gets
+    /// preprocessed. Every overridden value goes through the same checks
+    /// as the config values.
+    pub fn apply_overrides(
+        &mut self,
+        repeats: Option<usize>,
+        prefix: Option<String>
+    ) -> Result<(), Error> {
+        if let Some(repeats) = repeats {
+            self.set_repeats(repeats, "`--repeats`".to_owned());
+        }
+        if let Some(prefix) = prefix {
+            self.set_prefix(prefix, "`--prefix`".to_owned());
+        }
+        self.validate_overridden()
+    }
+
+    /// [Config::parse] for a JSON config, it's turned into TOML first so
+    /// that everything after (spans, the hash...) works the same way.
+    pub fn parse_json(file_contents: &str, path: &Path) -> Result<Self, Error> {
+
+        let value: toml::Value = serde_json::from_str(strip_bom(file_contents))
+            .map_err(|err| Error::Invalid {
+                file: path.to_owned(),
+                message: format!("invalid JSON config: {err}")
+            })?;
+        let toml = toml::to_string(&value)
+            .map_err(|err| Error::Invalid {
+                file: path.to_owned(),
+                message: format!("JSON config can't be expressed in TOML: {err}")
+            })?;
+
+        Self::parse(&toml, path)
+
+    }
+
+    /// Same as [Config::load] but for contents that are already in memory,
+    /// `path` is only used for error messages and as the default output.
+    pub fn parse(file_contents: &str, path: &Path) -> Result<Self, Error> {
+        crate::preprocessor::reset_included();
+        Self::parse_used_by(file_contents, path, &[])
+    }
+
+    /// Every file other than the config itself that its outputs are
+    /// generated from, absolute: the used configs, `from` libraries, the
+    /// plugin script and the files included with `@{file:...}` so far.
+    pub fn inputs(&self) -> Vec<PathBuf> {
+        let mut inputs: Vec<PathBuf> = self.used.iter().map(|used| used.config.clone()).collect();
+        inputs.extend(self.generator
+            .iter()
+            .filter_map(|generator| generator.from.as_ref())
+            .map(|from| self.metadata.directory.join(from))
+        );
+        inputs.extend(plugin::script());
+        inputs.extend(crate::preprocessor::included_files());
+        inputs
+    }
+
+    /// The hash in the banner of every output, [Metadata::hash] folded
+    /// together with the contents of every one of [Config::inputs] so
+    /// editing any of them makes the outputs stale aswell.
+    pub fn hash(&self) -> String {
+        self.inputs().iter().fold(self.metadata.hash.clone(), |hash, input| {
+            let contents = std::fs::read_to_string(input).unwrap_or_default();
+            crate::metadata::hash(&format!("{hash}{contents}"))
+        })
+    }
+
+    /// Parse and preprocess every config in [Config::uses], `chain` holds
+    /// the configs that (indirectly) use this one so cycles get caught.
+    fn resolve_uses(&self, path: &Path, chain: &[PathBuf]) -> Result<Vec<Used>, Error> {
+
+        let invalid = |message: String| Error::Invalid {
+            file: path.to_owned(),
+            message
+        };
+
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let output = main_output(path, self.common.output.as_ref());
+        let mut chain = chain.to_vec();
+        chain.push(main_output(path, None));
+
+        let mut used: Vec<Used> = vec![];
+        for uses in self.uses.iter() {
+
+            let used_path = directory.join(uses);
+            if chain.contains(&main_output(&used_path, None)) {
+                return Err(invalid(format!(
+                    "{} and {} use each other",
+                    path.display(),
+                    used_path.display()
+                )))
+            }
+
+            let contents = read(&used_path)?;
+            let config = Self::parse_used_by(&contents, &used_path, &chain)?;
+            config.preprocess()
+                .map_err(|err| Error::Invalid {
+                    file: used_path.clone(),
+                    message: err.to_string()
+                })?;
+
+            let alias = uses.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.split('.').next())
+                .unwrap_or_default()
+                .to_owned();
+            if alias.is_empty() || used.iter().any(|used| used.alias == alias) {
+                return Err(invalid(format!("{} must have a unique name before the first `.`", uses.display())))
+            }
+
+            let xmva = match &*config.core.xmva.read().map_err(|err| invalid(err.to_string()))? {
+                Preprocessable::Preprocessed(xmva) => xmva.clone(),
+                Preprocessable::NotPreprocessed(_) => 
+                    return Err(invalid(format!("the main macro of {} didn't get preprocessed", uses.display())))
+            };
+
+            // a used config is generated on its own, writing both into the
+            // same file would leave only one of them.
+            let used_output = main_output(&used_path, config.common.output.as_ref());
+            if used_output == output {
+                return Err(invalid(format!(
+                    "{} writes to the same output {} as this config", 
+                    uses.display(),
+                    output.display()
+                )))
+            }
+
+            used.push(Used {
+                alias,
+                config: std::path::absolute(&used_path).unwrap_or(used_path),
+                output: used_output,
+                xmva,
+                prefix: config.common.keyable.prefix.clone()
+            });
+
+        }
+
+        Ok(used)
+
+    }
+
+    /// Replace every generator with a [Generator::from] by the library
+    /// generator it names.
+    fn resolve_libraries(&mut self, path: &Path) -> Result<(), Error> {
+
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        for (i, generator) in self.generator.iter_mut().enumerate() {
+
+            let Some(from) = generator.from.clone() else {
+                continue
+            };
+            let invalid = |message: String| Error::Invalid {
+                file: path.to_owned(),
+                message: format!("generator {i} from {}: {message}", from.display())
+            };
+
+            if !crate::lower::raw(&generator.repeat).map_err(invalid)?.is_empty() {
+                return Err(invalid("a library generator can't have a `repeat` aswell".to_owned()))
+            }
+
+            let library_path = directory.join(&from);
+            let contents = read(&library_path)?;
+            let contents = strip_bom(&contents);
+            let Library { params, generator: mut library } = toml::from_str(contents)
+                .map_err(|toml_err| toml_error(toml_err, contents, &library_path))?;
+
+            if library.from.is_some() {
+                return Err(invalid("libraries can't take their generator from another library".to_owned()))
+            }
+            if let Some(missing) = params.iter().find(|param| !generator.with.contains_key(*param)) {
+                return Err(invalid(format!("missing the `{missing}` parameter")))
+            }
+            if let Some(unknown) = generator.with.keys().find(|param| !params.contains(param)) {
+                return Err(invalid(format!("unknown parameter `{unknown}`, expected one of {params:?}")))
+            }
+
+            library.name = generator.name.take().or(library.name);
+            library.output = generator.output.take().or(library.output);
+            library.repeats = generator.repeats.take().or(library.repeats);
+            if let Some(keys) = generator.keys.take() {
+                library.keys.get_or_insert_default().extend(keys);
+            }
+            library.with = std::mem::take(&mut generator.with);
+            library.from = Some(from);
+            *generator = library;
+
+        }
+
+        Ok(())
+
+    }
+
+    /// [Core::args] needs exactly one [Argument::Varadict] of at least 1,
+    /// it is what the ladders count.
+    fn validate_args(&self) -> Result<(), Error> {
+
+        let varadicts: Vec<(usize, usize)> = self.core.args
+            .iter()
+            .enumerate()
+            .filter_map(|(i, arg)| match arg {
+                Argument::Varadict { varadict } => Some((i, *varadict)),
+                Argument::Named(_) => None
+            })
+            .collect();
+
+        match varadicts.as_slice() {
+            [] => Err(Error::at(&self.metadata, "core.args",
+                "`core.args` has no varadict argument, add a `{varadict = N}` with the group size".to_owned(),
+                "has no `{varadict = N}`"
+            )),
+            [(i, 0)] => Err(Error::at(&self.metadata, &format!("core.args.{i}.varadict"),
+                "the varadict groups of `core.args` can't be 0 arguments big".to_owned(),
+                "must be at least 1"
+            )),
+            [_] => Ok(()),
+            [(first, _), (again, _), ..] => {
+                let mut err = Error::at(&self.metadata, &format!("core.args.{again}.varadict"),
+                    format!("`core.args` has more than one varadict argument (`core.args.{first}` and `core.args.{again}`), keep one"),
+                    "second varadict argument"
+                );
+                if let Error::Toml { message, .. } = &mut err
+                    && let Some(snippet) = self.metadata.label(&format!("core.args.{first}.varadict"), "first one") {
+                    message.push('\n');
+                    message.push_str(&snippet);
+                }
+                Err(err)
+            }
+        }
+
+    }
+
+    /// With a [Core::group], every argument a generator references has to
+    /// be one of its members, the rest would expand into a placeholder the
+    /// ladder step doesn't have.
+    fn validate_group_references(&self) -> Result<(), Error> {
+
+        let Some(group) = &self.core.group else {
+            return Ok(())
+        };
+        let members = group.names();
+        let invalid = |message: String| Error::Invalid {
+            file: self.metadata.config.clone(),
+            message
+        };
+
+        for (i, generator) in self.generator.iter().enumerate() {
+            for (name, string) in [("preamble", &generator.preamble), ("repeat", &generator.repeat), ("postamble", &generator.postamble)] {
+                let field = format!("generator.{i}.{name}");
+                let raw = crate::lower::raw(string).map_err(invalid)?;
+                for token in CompilerToken::scan(&raw) {
+                    let (message, label) = match &token {
+                        CompilerToken::UnamedArgumentRef(n) | CompilerToken::EdgeArgumentRef(_, n) if *n >= members.len() => (
+                            format!(
+                                "`{}` in `{field}` is out of range, [core.group] has {} members ({}) so it goes up to {}",
+                                token.untokenize(),
+                                members.len(),
+                                members.join(", "),
+                                members.len() - 1
+                            ),
+                            format!("out of range for a group of {}", members.len())
+                        ),
+                        CompilerToken::MemberRef(member) | CompilerToken::EdgeMemberRef(_, member) if !members.contains(&member.as_ref()) => (
+                            format!(
+                                "`{}` in `{field}` isn't a member of [core.group], the members are {}",
+                                token.untokenize(),
+                                members.join(", ")
+                            ),
+                            "not a member".to_owned()
+                        ),
+                        _ => continue
+                    };
+                    return Err(Error::at_text(&self.metadata, &field, &token.untokenize(), message, &label))
+                }
+            }
+        }
+
+        Ok(())
+
+    }
+
+    /// The first `[common]` value that would generate a header that can't
+    /// be used, as its field along side the message and label. The ladders
+    /// need the step for no arguments and room for at least one whole
+    /// group after it.
+    fn common_problem(&self) -> Option<(&'static str, String, String)> {
+
+        let repeats = self.common.repeats;
+        if repeats == 0 {
+            return Some(("common.repeats",
+                "`common.repeats` is 0, every ladder needs at least the step for no arguments".to_owned(),
+                "must be at least 1".to_owned()
+            ))
+        }
+        if let Some(group) = self.core.varadict()
+            && group > 0
+            && repeats <= group {
+            return Some(("common.repeats",
+                format!(
+                    "`common.repeats = {repeats}` takes at most {} varadict arguments, not even one group of {group}",
+                    repeats - 1
+                ),
+                format!("must be more than {group}")
+            ))
+        }
+        if self.common.keyable.prefix.is_empty() {
+            return Some(("common.prefix",
+                "`common.prefix` is empty, the ladders and helpers would be called `__ARGS__0_1`, `__CALL__`... in every header".to_owned(),
+                "can't be empty".to_owned()
+            ))
+        }
+
+        None
+
+    }
+
+    /// Catch `[common]` values that would generate a header that can't be
+    /// used, see [Config::common_problem].
+    fn validate_common(&self) -> Result<(), Error> {
+        match self.common_problem() {
+            Some((field, message, label)) => Err(Error::at(&self.metadata, field, message, &label)),
+            None => Ok(())
+        }
+    }
+
+    /// [Config::validate_common] for the final config, after every
+    /// override. A bad overridden value is reported with where it came
+    /// from instead of the config value it replaced.
+    fn validate_overridden(&self) -> Result<(), Error> {
+        match self.common_problem() {
+            Some((field, message, _)) if let Some((_, origin)) = self.overridden.iter().rev().find(|(overridden, _)| *overridden == field) =>
+                Err(Error::Invalid {
+                    file: self.metadata.config.clone(),
+                    message: format!("{message}, set by {origin}")
+                }),
+            Some((field, message, label)) => Err(Error::at(&self.metadata, field, message, &label)),
+            None => Ok(())
+        }
+    }
+
+    /// Override [Common::repeats] with a value from `origin` (`--repeats`),
+    /// checked by the next [Config::apply_overrides].
+    pub fn set_repeats(&mut self, repeats: usize, origin: String) {
+        self.common.repeats = std::cmp::min(MAX_REPEATS, repeats);
+        self.overridden.push(("common.repeats", origin));
+    }
+
+    /// Override the [CommonKeyable::prefix], see [Config::set_repeats].
+    pub fn set_prefix(&mut self, prefix: String, origin: String) {
+        self.common.keyable.prefix = prefix;
+        self.overridden.push(("common.prefix", origin));
+    }
+
+    /// With [Common::raw_literal_strings], escape every string written as
+    /// a TOML literal string so both tokenizers leave it as it was. Has to
+    /// run before the libraries and lowering add strings that aren't in
+    /// the config.
+    fn escape_literal_strings(&self) -> Result<(), Error> {
+
+        if !self.common.raw_literal_strings {
+            return Ok(())
+        }
+
+        let invalid = |message: String| Error::Invalid {
+            file: self.metadata.config.clone(),
+            message
+        };
+        let compiled: Vec<PreprocessableString> = self.generator
+            .iter()
+            .flat_map(|generator| generator.load_surface_compilable_strings())
+            .collect();
+
+        for (field, string) in crate::trace::named_strings(self) {
+            if !self.metadata.literal(&field) {
+                continue
+            }
+            let mut escaped = crate::lower::raw(&string).map_err(invalid)?;
+            if compiled.iter().any(|compilable| Arc::ptr_eq(compilable, &string)) {
+                escaped = CompilerToken::Raw(escaped.into()).untokenize();
+            }
+            escaped = PreprocessorToken::Raw(escaped.into()).untokenize();
+            *string.write().map_err(|err| invalid(err.to_string()))? = Preprocessable::NotPreprocessed(escaped);
+        }
+
+        Ok(())
+
+    }
+
+    /// Decide every [Emit::IfUsed] definition, a definition is used when
+    /// its key ends up in a generator, the main macro or the raw
+    /// preamble/postamble, or in a definition that is emitted itself.
+    fn resolve_emits(&mut self) {
+
+        let Some(definitions) = &self.definition else {
+            return
+        };
+        if definitions.iter().all(|definition| definition.emit != Emit::IfUsed) {
+            return
+        }
+
+        let graph = crate::graph::Graph::new(self);
+        let reachable: Vec<BTreeSet<&str>> = (0..definitions.len())
+            .map(|i| graph.reachable(&format!("definition.{i}")))
+            .collect();
+        let mut emitted: Vec<bool> = definitions.iter().map(|definition| definition.emit == Emit::Always).collect();
+
+        // emitting one can make another one used, until nothing changes.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, definition) in definitions.iter().enumerate() {
+                if definition.emit != Emit::IfUsed || emitted[i] {
+                    continue
+                }
+                let used = reachable[i].iter().any(|node| match node.strip_prefix("definition.") {
+                    Some(field) => field
+                        .split('.')
+                        .next()
+                        .and_then(|j| j.parse::<usize>().ok())
+                        .is_some_and(|j| emitted[j]),
+                    None => graph.nodes.get(*node).is_some_and(|(_, kind)| *kind == crate::graph::NodeKind::Sink)
+                });
+                if used {
+                    emitted[i] = true;
+                    changed = true;
+                }
+            }
+        }
+
+        for (definition, emitted) in self.definition.iter_mut().flatten().zip(emitted) {
+            if definition.emit == Emit::IfUsed {
+                log::debug!("Definition `{}` is {}.", definition.key, if emitted { "used" } else { "unused, not emitting it" });
+                definition.emit = if emitted { Emit::Always } else { Emit::Never };
+            }
+        }
+
+    }
+
+    /// See [normalize_separators], every path of the config goes
+    /// through it before anything uses them.
+    fn normalize_paths(&mut self) {
+        let normalize = |path: &mut PathBuf| *path = normalize_separators(path);
+        self.common.output.iter_mut().for_each(normalize);
+        self.uses.iter_mut().for_each(normalize);
+        self.plugin.iter_mut().map(|plugin| &mut plugin.script).for_each(normalize);
+        for generator in self.generator.iter_mut() {
+            generator.output.iter_mut().for_each(normalize);
+            generator.from.iter_mut().for_each(normalize);
+        }
+    }
+
+    fn parse_used_by(file_contents: &str, path: &Path, chain: &[PathBuf]) -> Result<Self, Error> {
+
+        let file_contents = strip_bom(file_contents);
+        let mut config: Self = toml::from_str(file_contents)
+            .map_err(|toml_err| toml_error(toml_err, file_contents, path))?;
+        config.normalize_paths();
+
+        config.metadata = Metadata::new(path, file_contents);
+        log::debug!("Config {:?} has hash {} (xmva {}).", 
+            config.metadata.config, 
+            config.metadata.hash, 
+            config.metadata.version
+        );
+        // the used configs set their own sigils, so ours come after them.
+        config.used = config.resolve_uses(path, chain)?;
+        config.sigils.apply(file_contents, path)?;
+        config.escape_literal_strings()?;
+        lint::set_levels(config.lints.clone());
+        let script = config.plugin.as_ref().map(|plugin| config.metadata.directory.join(&plugin.script));
+        plugin::load(script.as_deref()).map_err(|err| Error::Invalid {
+            file: path.to_owned(),
+            message: err.to_string()
+        })?;
+        for chain in config.transforms.keys() {
+            if !matches!(Tag::parse(chain), Tag::Chain(_)) {
+                return Err(Error::Invalid {
+                    file: path.to_owned(),
+                    message: format!("transform chain `{chain}` has the same name as a built in tag")
+                })
+            }
+        }
+        transform::set_chains(config.transforms.clone());
+        config.resolve_libraries(path)?;
+
+        if let Some(enumeration) = config.enumeration.clone() {
+            if !config.core.args.is_empty() {
+                return Err(Error::Invalid {
+                    file: path.to_owned(),
+                    message: "a [enum] section can't be combined with a [core] section".to_owned()
+                })
+            }
+            enumeration.lower(&mut config)
+                .map_err(|message| Error::Invalid {
+                    file: path.to_owned(),
+                    message
+                })?;
+        } else if config.core.args.is_empty() {
+            return Err(Error::Invalid {
+                file: path.to_owned(),
+                message: "missing a [core] section with arguments (or a [enum] section)".to_owned()
+            })
+        }
+
+        let mut names: Vec<&String> = vec![];
+        for name in config.generator.iter().filter_map(|generator| generator.name.as_ref()) {
+            if name.contains(KEY_SCOPE_SEPARATOR) || names.contains(&name) {
+                return Err(Error::Invalid {
+                    file: path.to_owned(),
+                    message: format!(
+                        "generator name `{name}` must be unique and can't contain a `{KEY_SCOPE_SEPARATOR}`"
+                    )
+                })
+            }
+            names.push(name);
+        }
+
+        config.lower_generators()
+            .map_err(|message| Error::Invalid {
+                file: path.to_owned(),
+                message
+            })?;
+
+        config.validate_args()?;
+        let varadict = config.core.varadict();
+        if let Some(group) = &config.core.group {
+            if Some(group.members.len()) != varadict {
+                return Err(Error::Invalid {
+                    file: path.to_owned(),
+                    message: format!(
+                        "[core.group] names {} members but the varadict groups have {}",
+                        group.members.len(),
+                        varadict.unwrap_or(0)
+                    )
+                })
+            }
+            let names = group.names();
+            for (i, member) in names.iter().enumerate() {
+                if !crate::compiler::is_member_name(member) || names[..i].contains(member) {
+                    return Err(Error::Invalid {
+                        file: path.to_owned(),
+                        message: format!("[core.group] member `{member}` must be a unique identifier")
+                    })
+                }
+            }
+        }
+        config.validate_group_references()?;
+        for (i, generator) in config.generator.iter().enumerate() {
+            if let Some(RepeatRange { min, max: Some(max) }) = generator.repeats
+                && min > max {
+                return Err(Error::Invalid {
+                    file: path.to_owned(),
+                    message: format!("generator {i} has repeats from {min} to {max}, the min can't be past the max")
+                })
+            }
+            if let Some(varadict) = varadict
+                && let Some(remainder) = generator.fallbacks.unparity_remainder
+                    .keys()
+                    .find(|remainder| **remainder == 0 || **remainder >= varadict) {
+                return Err(Error::Invalid {
+                    file: path.to_owned(),
+                    message: format!(
+                        "generator {i} has a unparity fallback for remainder {remainder}, but remainders go from 1 to {}",
+                        varadict.saturating_sub(1)
+                    )
+                })
+            }
+        }
+
+        if config.common.repeats == AUTO_REPEATS {
+            config.common.repeats = config.infer_repeats()
+                .map_err(|message| Error::Invalid {
+                    file: path.to_owned(),
+                    message
+                })?;
+            log::info!("Inferred repeats = {} from the invocations.", config.common.repeats);
+        }
+
+        // limit repeats
+        config.common.repeats = std::cmp::min(MAX_REPEATS, config.common.repeats);
+        config.validate_common()?;
+        config.resolve_emits();
+
+        if config.common.output.is_none() {
+            config.common.output = Some(path.to_owned());
+        }
+        
+        log::trace!("{}",
+            format!("Config loaded: {:#?}", config)
+            .dimmed()
         );
 
         Ok(config)
 
     }
 
-}
\ No newline at end of file
+}
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn checked_overrides() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 5\n\n[core]\nxmva = \"A\"\n\
+            args = [{varadict = 2}]\n\n[[generator]]\nrepeat = \"$(0)\"\n";
+        let config = || Config::parse(contents, Path::new("overrides.xmva.toml")).unwrap();
+
+        let message = |repeats: Option<usize>, prefix: Option<&str>| match config().apply_overrides(repeats, prefix.map(str::to_owned)) {
+            Err(Error::Invalid { message, .. }) => message,
+            other => panic!("expected a invalid override, got {other:?}")
+        };
+        assert_eq!(message(Some(2), None), "`common.repeats = 2` takes at most 1 varadict arguments, not even one group of 2, set by `--repeats`");
+        assert!(message(None, Some("")).starts_with("`common.prefix` is empty") && message(None, Some("")).ends_with("set by `--prefix`"));
+
+        let mut overridden = config();
+        overridden.apply_overrides(Some(9), Some("B_".to_owned())).unwrap();
+        assert_eq!((overridden.common.repeats, overridden.common.keyable.prefix.as_str()), (9, "B_"));
+
+    }
+
+    #[test]
+    fn common_validation() {
+
+        let contents = |prefix: &str, repeats: usize| format!(
+            "[common]\nprefix = \"{prefix}\"\nrepeats = {repeats}\n\n[core]\nxmva = \"A\"\n\
+            args = [{{varadict = 2}}]\n\n[[generator]]\nrepeat = \"$(0)\"\n"
+        );
+        let message = |contents: String| match Config::parse(&contents, Path::new("common.xmva.toml")) {
+            Err(Error::Toml { message, span: Some(span), .. }) => (message, span),
+            other => panic!("expected a spanned error, got {other:?}")
+        };
+
+        let (zero, span) = message(contents("A_", 0));
+        assert!(zero.starts_with("`common.repeats` is 0"));
+        assert!(zero.ends_with("3 | repeats = 0\n  |           ^ must be at least 1"));
+        assert_eq!(span, 33..34);
+
+        let (small, _) = message(contents("A_", 2));
+        assert!(small.starts_with("`common.repeats = 2` takes at most 1 varadict arguments, not even one group of 2"));
+        assert!(small.ends_with("^ must be more than 2"));
+
+        let (empty, span) = message(contents("", 5));
+        assert!(empty.ends_with("2 | prefix = \"\"\n  |          ^^ can't be empty"));
+        assert_eq!(span, 18..20);
+
+        assert!(Config::parse(&contents("A_", 3), Path::new("common.xmva.toml")).is_ok());
+
+    }
+
+    #[test]
+    fn windows_configs() {
+
+        let contents = "\u{feff}[common]\nprefix = \"A_\"\nrepeats = 3\noutput = \"out\\\\a.h\"\n\n[core]\nxmva = \"A\"\n\
+            args = [{varadict = 1}]\n\n[[generator]]\nrepeat = \"$(0)\"\noutput = \"ladders\\\\a.h\"\n";
+        let config = Config::parse(contents, Path::new("bom.xmva.toml")).unwrap();
+        assert_eq!(config.common.output.as_deref(), Some(Path::new("out/a.h")));
+        assert_eq!(config.generator[0].output.as_deref(), Some(Path::new("ladders/a.h")));
+        assert_eq!(config.metadata.hash, crate::metadata::hash(strip_bom(contents)));
+        assert_eq!(config.metadata.span("common.prefix"), Some(18..22));
+
+    }
+
+    #[test]
+    fn encodings() {
+
+        let message = |bytes: Vec<u8>| match decode(bytes, Path::new("a.xmva.toml")) {
+            Err(Error::Invalid { message, .. }) => message,
+            other => panic!("expected a encoding error, got {other:?}")
+        };
+        let utf16 = |text: &str, little: bool| -> Vec<u8> {
+            text.encode_utf16().flat_map(|unit| match little {
+                true => unit.to_le_bytes(),
+                false => unit.to_be_bytes()
+            }).collect()
+        };
+
+        assert_eq!(decode(b"[common]\n".to_vec(), Path::new("a.xmva.toml")).unwrap(), "[common]\n");
+
+        let mut bom = vec![0xff, 0xfe];
+        bom.extend(utf16("[common]\n", true));
+        assert_eq!(Encoding::guess(&bom), Encoding::Utf16Le);
+        assert!(message(bom).contains("(0xff) is at offset 0 (line 1, column 1). It looks like UTF-16 LE"));
+
+        assert_eq!(Encoding::guess(&utf16("[common]\n", false)), Encoding::Utf16Be);
+        assert!(message(utf16("[common]\n", true)).contains("the first zero byte (0x00) is at offset 1"));
+
+        let latin1 = b"[common]\nprefix = \"caf\xe9\"\n".to_vec();
+        assert!(message(latin1).contains("(0xe9) is at offset 22 (line 2, column 14). It looks like Latin-1"));
+
+    }
+
+    #[test]
+    fn io_errors() {
+
+        let contents = "[common]\nprefix = \"A_\"\noutput = \"missing/a.h\"\n";
+        let metadata = Metadata::new(Path::new("io.xmva.toml"), contents);
+        let err = Error::io(Operation::WriteOutput, Path::new("missing/a.h"), std::io::Error::from_raw_os_error(2))
+            .labeled(&metadata, "common.output");
+
+        assert!(matches!(err, Error::IO { operation: Operation::WriteOutput, code: Some(2), .. }));
+        assert_eq!(err.location().line, Some(3));
+        let rendered = err.to_string();
+        assert!(rendered.starts_with("[config.rs] failed to write output \"missing/a.h\": "));
+        assert!(rendered.ends_with(" --> io.xmva.toml:3:10\n  |\n3 | output = \"missing/a.h\"\n  |          ^^^^^^^^^^^^^ `common.output` is set here"));
+
+        let unlabeled = Error::io(Operation::ReadConfig, Path::new("a.xmva.toml"), std::io::Error::from_raw_os_error(2));
+        assert_eq!(unlabeled.location(), Location::file("a.xmva.toml"));
+
+    }
+
+    #[test]
+    fn duplicate_arguments() {
+
+        let message = |args: &str| {
+            let contents = format!("[common]\nprefix = \"A_\"\nrepeats = 3\n\n[core]\nxmva = \"A\"\n\
+                args = [{args}, {{varadict = 1}}]\n\n[[generator]]\nrepeat = \"$(0)\"\n");
+            let config = Config::parse(&contents, Path::new("args.xmva.toml")).unwrap();
+            config.preprocess().unwrap();
+            match config.surface_compile() {
+                Err(crate::compiler::Error { kind: crate::compiler::ErrorKind::DuplicateArgument, message }) => message,
+                other => panic!("expected a duplicate argument, got {other:?}")
+            }
+        };
+
+        let keys = message("{key = \"n\", name = \"a\"}, {key = \"n\", name = \"b\"}");
+        assert!(keys.starts_with("the main xmva has the named argument `n` twice (`core.args.0` and `core.args.1`)\n"));
+        assert!(keys.contains("7 | args = [{key = \"n\", name = \"a\"}, {key = \"n\", name = \"b\"}, {varadict = 1}]\n  |                ^^^ first here"));
+        assert!(keys.ends_with("^^^ and again here"));
+
+        let names = message("{key = \"n\", name = \"x\"}, {key = \"m\", name = \"x\"}");
+        assert!(names.starts_with("the named arguments `n` and `m` of the main xmva both resolve to the parameter `x`\n"));
+        assert_eq!(names.matches(" --> args.xmva.toml:7:").count(), 2);
+
+    }
+
+    #[test]
+    fn varadict_validation() {
+
+        let message = |args: &str| {
+            let contents = format!("[common]\nprefix = \"A_\"\nrepeats = 3\n\n[core]\nxmva = \"A\"\n\
+                args = [{args}]\n\n[[generator]]\nrepeat = \"$(0)\"\n");
+            match Config::parse(&contents, Path::new("varadict.xmva.toml")) {
+                Err(Error::Toml { message, span: Some(_), .. }) => message,
+                other => panic!("expected a spanned error, got {other:?}")
+            }
+        };
+
+        let missing = message("{key = \"n\", name = \"n\"}");
+        assert!(missing.starts_with("`core.args` has no varadict argument"));
+        assert!(missing.ends_with("has no `{varadict = N}`"));
+
+        assert!(message("{varadict = 0}").ends_with("7 | args = [{varadict = 0}]\n  |                     ^ must be at least 1"));
+
+        let twice = message("{varadict = 1}, {varadict = 2}");
+        assert!(twice.starts_with("`core.args` has more than one varadict argument (`core.args.0` and `core.args.1`)"));
+        assert!(twice.contains("^ second varadict argument\n") && twice.ends_with("^ first one"));
+
+    }
+
+    #[test]
+    fn group_references() {
+
+        let message = |repeat: &str| {
+            let contents = format!("[common]\nprefix = \"A_\"\nrepeats = 5\n\n[core]\nxmva = \"A\"\n\
+                args = [{{varadict = 2}}]\ngroup = {{members = [\"value\", \"string\"]}}\n\n[[generator]]\nrepeat = \"{repeat}\"\n");
+            match Config::parse(&contents, Path::new("group.xmva.toml")) {
+                Err(Error::Toml { message, span: Some(span), .. }) => (message, span.len()),
+                other => panic!("expected a spanned error, got {other:?}")
+            }
+        };
+
+        let (index, width) = message("$(0) = $(2)$[, ]");
+        assert!(index.starts_with("`$(2)` in `generator.0.repeat` is out of range, [core.group] has 2 members (value, string) so it goes up to 1\n"));
+        assert!(index.ends_with("11 | repeat = \"$(0) = $(2)$[, ]\"\n   |                  ^^^^ out of range for a group of 2"));
+        assert_eq!(width, 4);
+
+        let (edge, _) = message("$(value)$(last.7)");
+        assert!(edge.starts_with("`$(last.7)` in `generator.0.repeat` is out of range"));
+        let (member, _) = message("$(colour)");
+        assert!(member.starts_with("`$(colour)` in `generator.0.repeat` isn't a member of [core.group], the members are value, string\n"));
+        assert!(member.ends_with("^^^^^^^^^ not a member"));
+
+    }
+
+    #[test]
+    fn repeat_range_last() {
+
+        // `repeats = 8` ladders go up to 7 varadict arguments.
+        assert_eq!(RepeatRange { min: 1, max: Some(3) }.last(8), 3);
+        assert_eq!(RepeatRange { min: 1, max: Some(64) }.last(8), 7);
+        assert_eq!(RepeatRange { min: 0, max: None }.last(8), 7);
+
+    }
+
+    #[test]
+    fn denied_in_used_configs() {
+
+        let directory = std::env::temp_dir().join(format!("xmva-denied-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let core = "[common]\nprefix = \"A_\"\nrepeats = 3\n\n[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n\n[[generator]]\nrepeat = \"$(0)\"\n";
+        std::fs::write(
+            directory.join("used.xmva.toml"),
+            format!("[lints]\nunused_key = \"deny\"\n\n[preamble]\nkeys = [{{key = \"unused\", name = \"UNUSED\"}}]\n\n{core}")
+        ).unwrap();
+        std::fs::write(
+            directory.join("main.xmva.toml"),
+            format!("uses = [\"used.xmva.toml\"]\n\n{}", core.replace("A", "B"))
+        ).unwrap();
+
+        // the main config doesn't deny anything, the used one still did.
+        lint::reset_denied();
+        Config::load(&directory.join("main.xmva.toml")).unwrap();
+        assert_eq!(lint::level(Lint::UnusedKey), Level::Warn);
+        assert_eq!(lint::denied(), 1);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+    }
+
+    #[test]
+    fn used_configs() {
+
+        let directory = std::env::temp_dir().join(format!("xmva-uses-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let config = |uses: &str, xmva: &str, output: &str, repeat: &str| format!(
+            "uses = [{uses}]\n\n[common]\nprefix = \"{xmva}_\"\nrepeats = 3\noutput = \"{output}\"\n\n\
+            [core]\nxmva = \"{xmva}\"\nargs = [{{varadict = 1}}]\n\n[[generator]]\nrepeat = \"{repeat}\"\n"
+        );
+        std::fs::write(directory.join("errors.xmva.toml"), config("", "ERR", "errors.h", "$(0)")).unwrap();
+        std::fs::write(
+            directory.join("main.xmva.toml"),
+            config("\"errors.xmva.toml\"", "MAIN", "main.h", "@{errors.__XMVA__}($(0)) @{errors.__PREFIX__}")
+        ).unwrap();
+
+        let main = Config::load(&directory.join("main.xmva.toml")).unwrap();
+        main.preprocess().unwrap();
+        assert_eq!(
+            crate::preprocessor::AnyPreprocessable::String(main.generator[0].repeat.clone()).preprocessed().unwrap(),
+            "ERR($(0)) ERR_"
+        );
+        assert_eq!(main.used[0].alias, "errors");
+        assert!(main.used[0].config.is_absolute());
+        assert!(main.used[0].output.ends_with("errors.h"));
+
+        // both outputs have to exist on their own.
+        std::fs::write(
+            directory.join("same.xmva.toml"),
+            config("\"errors.xmva.toml\"", "SAME", "errors.h", "$(0)")
+        ).unwrap();
+        let err = Config::load(&directory.join("same.xmva.toml")).unwrap_err().to_string();
+        assert!(err.contains("writes to the same output"));
+
+        std::fs::write(directory.join("a.xmva.toml"), config("\"b.xmva.toml\"", "A", "a.h", "$(0)")).unwrap();
+        std::fs::write(directory.join("b.xmva.toml"), config("\"a.xmva.toml\"", "B", "b.h", "$(0)")).unwrap();
+        let err = Config::load(&directory.join("a.xmva.toml")).unwrap_err();
+        assert!(matches!(err, Error::Invalid { .. }));
+        assert!(err.to_string().contains(&format!(
+            "{} and {} use each other",
+            directory.join("b.xmva.toml").display(),
+            directory.join("a.xmva.toml").display()
+        )));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+    }
+
+}
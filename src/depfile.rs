@@ -0,0 +1,99 @@
+//! Makefile style dependency files (the kind `gcc -MD` spits out).
+//! 
+//! make and ninja both read these so the generated header gets rebuilt
+//! whenever the config (or anything the config pulled in) changes.
+
+use std::path::{Path, PathBuf};
+
+/// Escape a path so make doesn't split it on spaces or treat
+/// `#` as a comment and `$` as a variable.
+fn escape(path: &Path) -> String {
+
+    let mut escaped = String::new();
+
+    for ch in path.to_string_lossy().chars() {
+        match ch {
+            ' ' | '#' => {
+                escaped.push('\\');
+                escaped.push(ch);
+            }
+            '$' => escaped.push_str("$$"),
+            _ => escaped.push(ch)
+        }
+    }
+
+    escaped
+
+}
+
+/// Assemble the contents of a dependency file where every one of
+/// `targets` depends on every path inside of `dependencies`.
+pub fn assemble(
+    targets: &[PathBuf],
+    dependencies: &[PathBuf]
+) -> String {
+
+    let mut depfile = String::new();
+
+    let targets: Vec<String> = targets.iter().map(|target| escape(target)).collect();
+    depfile.push_str(&targets.join(" "));
+    depfile.push(':');
+    for dependency in dependencies {
+        depfile.push_str(" \\\n  ");
+        depfile.push_str(&escape(dependency));
+    }
+    depfile.push('\n');
+
+    depfile
+
+}
+
+/// Write a dependency file to `path`, see [assemble].
+pub fn write(
+    path: &Path,
+    targets: &[PathBuf],
+    dependencies: &[PathBuf]
+) -> std::io::Result<()> {
+
+    std::fs::write(path, assemble(targets, dependencies))
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn assemble_escapes() {
+
+        assert_eq!(
+            assemble(
+                &[PathBuf::from("out dir/ya_ecgen.h")],
+                &[
+                    PathBuf::from("/home/me/#cfg/ex.xmva.toml"),
+                    PathBuf::from("$money.txt")
+                ]
+            ),
+            "out\\ dir/ya_ecgen.h: \\\n  /home/me/\\#cfg/ex.xmva.toml \\\n  $$money.txt\n"
+        );
+
+    }
+
+    #[test]
+    fn routed_targets() {
+
+        let path = Path::new("gen/routed.xmva.toml");
+        let config = crate::config::Config::parse(
+            "[common]\nprefix = \"A_\"\nrepeats = 3\noutput = \"include/a.h\"\n\n[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n\n[[generator]]\nrepeat = \"$(0)\"\n\n[[generator]]\nrepeat = \"$(0)\"\noutput = \"ladders/a.h\"\n",
+            path
+        ).unwrap();
+
+        assert_eq!(
+            assemble(&crate::verify::outputs(path, &config), &[path.to_owned()]),
+            "gen/include/a.h gen/include/ladders/a.h: \\\n  gen/routed.xmva.toml\n"
+        );
+
+    }
+
+}
@@ -0,0 +1,165 @@
+//! `--message-format`, how config errors and lints are reported. Besides
+//! the human readable default they can be printed as annotations that a
+//! CI service shows inline on the config in a pull/merge request:
+//!
+//! - `github`, GitHub Actions workflow commands
+//!   (`::error file=a.xmva.toml,line=2,col=10::message`).
+//! - `gitlab`, one GitLab Code Quality issue (JSON) per line, collect
+//!   them into the report with `jq -s`.
+//!
+//! Example
+//! -------
+//! ```yaml
+//! - run: xmva --message-format github -i ya_ecgen.xmva.toml --check
+//! ```
+
+use std::{cell::RefCell, path::PathBuf};
+
+use clap::ValueEnum;
+
+use crate::metadata;
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MessageFormat {
+    #[default]
+    Human,
+    Github,
+    Gitlab
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+/// Where in a config a diagnostic points to, lines and columns are 1 based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file:   PathBuf,
+    pub line:   Option<usize>,
+    pub column: Option<usize>
+}
+
+impl Location {
+
+    /// The whole `file`.
+    pub fn file(file: impl Into<PathBuf>) -> Self {
+        Self { file: file.into(), line: None, column: None }
+    }
+
+}
+
+thread_local! {
+    /// Set once from the arguments, per thread like the rest of our state.
+    static FORMAT: RefCell<MessageFormat> = const { RefCell::new(MessageFormat::Human) };
+}
+
+pub fn set_format(format: MessageFormat) {
+    FORMAT.with(|current| *current.borrow_mut() = format);
+}
+
+/// Escape the message of a workflow command, properties also escape `:` and `,`.
+fn escape_github(text: &str, property: bool) -> String {
+    let escaped = text
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+    match property {
+        true => escaped.replace(':', "%3A").replace(',', "%2C"),
+        false => escaped
+    }
+}
+
+fn github(severity: Severity, title: &str, message: &str, location: Option<&Location>) -> String {
+    let mut properties = vec![format!("title={}", escape_github(title, true))];
+    if let Some(location) = location {
+        properties.push(format!("file={}", escape_github(&location.file.display().to_string(), true)));
+        properties.extend(location.line.map(|line| format!("line={line}")));
+        properties.extend(location.column.map(|column| format!("col={column}")));
+    }
+    format!("::{} {}::{}",
+        match severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning"
+        },
+        properties.join(","),
+        escape_github(message, false)
+    )
+}
+
+fn gitlab(severity: Severity, title: &str, message: &str, location: Option<&Location>) -> String {
+    let path = location.map(|location| location.file.display().to_string()).unwrap_or_default();
+    let line = location.and_then(|location| location.line).unwrap_or(1);
+    serde_json::json!({
+        "description": message,
+        "check_name": title,
+        "fingerprint": metadata::hash(&format!("{title}\n{path}\n{line}\n{message}")),
+        "severity": match severity {
+            Severity::Error => "major",
+            Severity::Warning => "minor"
+        },
+        "location": {"path": path, "lines": {"begin": line}}
+    }).to_string()
+}
+
+/// The annotation of a diagnostic in the current [MessageFormat], [None]
+/// for [MessageFormat::Human] where the caller prints it like it always did.
+pub fn annotation(severity: Severity, title: &str, message: &str, location: Option<&Location>) -> Option<String> {
+    match FORMAT.with(|format| *format.borrow()) {
+        MessageFormat::Human => None,
+        MessageFormat::Github => Some(github(severity, title, message, location)),
+        MessageFormat::Gitlab => Some(gitlab(severity, title, message, location))
+    }
+}
+
+/// Print `message` as a annotation, returns if it did so the caller
+/// knows to skip the human readable version.
+pub fn annotate(severity: Severity, title: &str, message: &str, location: Option<&Location>) -> bool {
+    match annotation(severity, title, message, location) {
+        Some(annotation) => {
+            eprintln!("{annotation}");
+            true
+        }
+        None => false
+    }
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn annotations() {
+
+        let location = Location { file: PathBuf::from("a,b.xmva.toml"), line: Some(2), column: Some(10) };
+
+        set_format(MessageFormat::Human);
+        assert_eq!(annotation(Severity::Error, "xmva", "bad", Some(&location)), None);
+
+        set_format(MessageFormat::Github);
+        assert_eq!(
+            annotation(Severity::Error, "xmva: config", "50% bad\nvery", Some(&location)).unwrap(),
+            "::error title=xmva%3A config,file=a%2Cb.xmva.toml,line=2,col=10::50%25 bad%0Avery"
+        );
+        assert_eq!(
+            annotation(Severity::Warning, "unused_key", "unused", Some(&Location::file("a.xmva.toml"))).unwrap(),
+            "::warning title=unused_key,file=a.xmva.toml::unused"
+        );
+
+        set_format(MessageFormat::Gitlab);
+        let issue: serde_json::Value = serde_json::from_str(
+            &annotation(Severity::Warning, "unused_key", "unused", Some(&location)).unwrap()
+        ).unwrap();
+        assert_eq!(issue["severity"], "minor");
+        assert_eq!(issue["check_name"], "unused_key");
+        assert_eq!(issue["location"]["path"], "a,b.xmva.toml");
+        assert_eq!(issue["location"]["lines"]["begin"], 2);
+        assert_eq!(issue["fingerprint"].as_str().unwrap().len(), 16);
+
+        set_format(MessageFormat::Human);
+
+    }
+
+}
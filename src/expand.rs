@@ -0,0 +1,508 @@
+//! A small simulator of the C preprocessors macro expansion, just enough
+//! of it to expand a invocation against a generated header without
+//! needing a C compiler around.
+//!
+//! It knows object and function like macros, `#` stringizing, `##`
+//! pasting, `__VA_ARGS__` with the GNU `, ## __VA_ARGS__` comma
+//! swallowing and the rescanning rules (a macro isn't expanded again
+//! inside of its own expansion, tracked with hide sets like in Prossers
+//! algorithm).
+//!
+//! Conditionals aren't evaluated, every `#define` and `#undef` of the
+//! header is taken in order. Good enough for our headers which only put
+//! the `condition` of a definition around it.
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnterminatedInvocation,
+    ArgumentCount,
+    InvalidPaste,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub(crate) message: String
+}
+
+impl std::fmt::Display for Error {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Expansion simulator encountered a error, [{:?}]: {}", self.kind, self.message)
+    }
+
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token {
+    text: String,
+    /// If there was whitespace in front of the token.
+    space: bool,
+    /// Macros that can't be expanded from this token anymore.
+    hide: BTreeSet<String>
+}
+
+impl Token {
+
+    fn new(text: &str, space: bool) -> Self {
+        Self { text: text.to_owned(), space, hide: BTreeSet::new() }
+    }
+
+    /// Stands in for a empty argument next to a `##`.
+    fn placemarker() -> Self {
+        Self::new("", false)
+    }
+
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    /// `None` for object like macros, `__VA_ARGS__` is the last one
+    /// of a varadict macro.
+    parameters: Option<Vec<String>>,
+    body: Vec<Token>
+}
+
+impl Macro {
+
+    fn varadict(&self) -> bool {
+        self.parameters
+            .as_ref()
+            .is_some_and(|parameters| parameters.last().is_some_and(|last| last == "__VA_ARGS__"))
+    }
+
+}
+
+/// Replace comments with a space and join continued lines.
+fn strip(s: &str) -> String {
+
+    let s = s.replace("\\\r\n", "").replace("\\\n", "");
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    let mut quote: Option<char> = None;
+
+    while let Some(ch) = chars.next() {
+        match quote {
+            Some(q) => {
+                stripped.push(ch);
+                if ch == '\\' {
+                    stripped.extend(chars.next());
+                } else if ch == q {
+                    quote = None;
+                }
+            }
+            None if ch == '"' || ch == '\'' => {
+                quote = Some(ch);
+                stripped.push(ch);
+            }
+            None if ch == '/' && chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|&next| next != '\n') {
+                    chars.next();
+                }
+                stripped.push(' ');
+            }
+            None if ch == '/' && chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = '\0';
+                for next in chars.by_ref() {
+                    if last == '*' && next == '/' {
+                        break
+                    }
+                    last = next;
+                }
+                stripped.push(' ');
+            }
+            None => stripped.push(ch)
+        }
+    }
+
+    stripped
+
+}
+
+fn is_identifier_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_' || !ch.is_ascii()
+}
+
+/// Tokenize already [strip]ped text.
+fn tokenize(s: &str) -> Vec<Token> {
+
+    let mut tokens = vec![];
+    let mut space = false;
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((start, ch)) = chars.next() {
+
+        if ch.is_whitespace() {
+            space = true;
+            continue
+        }
+
+        let mut end = start + ch.len_utf8();
+        if is_identifier_char(ch) {
+            // identifiers and pp-numbers, `1.5e+3` and the likes don't
+            // show up in our headers so a `+` ends it.
+            while let Some(&(i, next)) = chars.peek()
+                && (is_identifier_char(next) || (ch.is_ascii_digit() && next == '.')) {
+                end = i + next.len_utf8();
+                chars.next();
+            }
+        } else if ch == '"' || ch == '\'' {
+            while let Some((i, next)) = chars.next() {
+                end = i + next.len_utf8();
+                if next == '\\' {
+                    if let Some((i, escaped)) = chars.next() {
+                        end = i + escaped.len_utf8();
+                    }
+                } else if next == ch {
+                    break
+                }
+            }
+        } else if ch == '#' && chars.peek().is_some_and(|&(_, next)| next == '#') {
+            chars.next();
+            end += 1;
+        }
+
+        tokens.push(Token::new(&s[start..end], space));
+        space = false;
+
+    }
+
+    tokens
+
+}
+
+fn render(tokens: &[Token]) -> String {
+    let mut rendered = String::new();
+    for token in tokens.iter().filter(|token| !token.text.is_empty()) {
+        if token.space && !rendered.is_empty() {
+            rendered.push(' ');
+        }
+        rendered.push_str(&token.text);
+    }
+    rendered
+}
+
+fn stringize(argument: &[Token]) -> String {
+    let mut stringized = String::from("\"");
+    for (i, token) in argument.iter().enumerate() {
+        if token.space && i != 0 {
+            stringized.push(' ');
+        }
+        if token.text.starts_with(['"', '\'']) {
+            stringized.push_str(&token.text.replace('\\', "\\\\").replace('"', "\\\""));
+        } else {
+            stringized.push_str(&token.text);
+        }
+    }
+    stringized.push('"');
+    stringized
+}
+
+fn paste(left: Token, right: Token) -> Result<Token, Error> {
+    if left.text.is_empty() {
+        return Ok(Token { space: left.space, ..right })
+    }
+    if right.text.is_empty() {
+        return Ok(left)
+    }
+    let text = format!("{}{}", left.text, right.text);
+    if tokenize(&text).len() != 1 {
+        return Err(Error {
+            kind: ErrorKind::InvalidPaste,
+            message: format!("pasting `{}` and `{}` doesn't give a valid token.", left.text, right.text)
+        })
+    }
+    Ok(Token { text, ..left })
+}
+
+/// The macros a header defines.
+#[derive(Debug, Default)]
+pub struct Macros {
+    macros: HashMap<String, Macro>
+}
+
+impl Macros {
+
+    /// Collect every `#define` (minus the `#undef`ed ones) of `header`.
+    pub fn from_header(header: &str) -> Self {
+
+        let mut macros = Self::default();
+
+        for line in strip(header).lines() {
+            let Some(directive) = line.trim_start().strip_prefix('#') else {
+                continue
+            };
+            let directive = directive.trim_start();
+            if let Some(name) = directive.strip_prefix("undef") {
+                macros.macros.remove(name.trim());
+                continue
+            }
+            let Some(definition) = directive.strip_prefix("define")
+                .filter(|definition| definition.starts_with(char::is_whitespace)) else {
+                continue
+            };
+
+            let definition = definition.trim_start();
+            let name_end = definition
+                .find(|ch: char| !is_identifier_char(ch))
+                .unwrap_or(definition.len());
+            let (name, rest) = definition.split_at(name_end);
+
+            // only a `(` right after the name makes it function like.
+            let (parameters, body) = match rest.strip_prefix('(') {
+                Some(rest) => {
+                    let Some((parameters, body)) = rest.split_once(')') else {
+                        continue
+                    };
+                    let parameters = parameters
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|parameter| !parameter.is_empty())
+                        .map(|parameter| match parameter {
+                            "..." => "__VA_ARGS__".to_owned(),
+                            parameter => parameter.to_owned()
+                        })
+                        .collect();
+                    (Some(parameters), body)
+                }
+                None => (None, rest)
+            };
+
+            let mut body = tokenize(body);
+            if let Some(first) = body.first_mut() {
+                first.space = false;
+            }
+            macros.macros.insert(name.to_owned(), Macro { parameters, body });
+        }
+
+        macros
+
+    }
+
+    /// Fully expand `invocation` and render it back into text, tokens
+    /// are separated by a single space where they had any whitespace
+    /// between them.
+    pub fn expand(&self, invocation: &str) -> Result<String, Error> {
+        let expanded = self.expand_tokens(tokenize(&strip(invocation)).into())?;
+        Ok(render(&expanded))
+    }
+
+    fn expand_tokens(&self, mut input: VecDeque<Token>) -> Result<Vec<Token>, Error> {
+
+        let mut output = vec![];
+
+        while let Some(token) = input.pop_front() {
+
+            let Some(definition) = self.macros.get(&token.text)
+                .filter(|_| !token.hide.contains(&token.text)) else {
+                output.push(token);
+                continue
+            };
+
+            let (arguments, mut hide) = match &definition.parameters {
+                None => (vec![], token.hide.clone()),
+                Some(_) if input.front().is_none_or(|next| next.text != "(") => {
+                    // a function like macro without arguments is just a name.
+                    output.push(token);
+                    continue
+                }
+                Some(_) => {
+                    let (arguments, closing) = Self::arguments(&token.text, &mut input)?;
+                    (arguments, token.hide.intersection(&closing.hide).cloned().collect())
+                }
+            };
+            hide.insert(token.text.clone());
+
+            let mut expansion = self.substitute(&token.text, definition, arguments, &hide)?;
+            if let Some(first) = expansion.first_mut() {
+                first.space = token.space;
+            }
+            for expanded in expansion.into_iter().rev() {
+                input.push_front(expanded);
+            }
+
+        }
+
+        Ok(output)
+
+    }
+
+    /// Take the arguments of a invocation of `name` from the front of
+    /// `input`, along side the closing parenthesis.
+    fn arguments(name: &str, input: &mut VecDeque<Token>) -> Result<(Vec<Vec<Token>>, Token), Error> {
+
+        input.pop_front();
+        let mut arguments = vec![vec![]];
+        let mut depth = 0usize;
+
+        while let Some(token) = input.pop_front() {
+            match token.text.as_str() {
+                ")" if depth == 0 => return Ok((arguments, token)),
+                "," if depth == 0 => {
+                    arguments.push(vec![]);
+                    continue
+                }
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                _ => {}
+            }
+            arguments.last_mut().unwrap().push(token);
+        }
+
+        Err(Error {
+            kind: ErrorKind::UnterminatedInvocation,
+            message: format!("the arguments of `{name}` are missing their closing `)`.")
+        })
+
+    }
+
+    fn substitute(
+        &self,
+        name: &str,
+        definition: &Macro,
+        mut arguments: Vec<Vec<Token>>,
+        hide: &BTreeSet<String>
+    ) -> Result<Vec<Token>, Error> {
+
+        let parameters = definition.parameters.clone().unwrap_or_default();
+
+        // `F()` is one empty argument, or none for a macro without parameters.
+        if parameters.is_empty() && arguments.len() == 1 && arguments[0].is_empty() {
+            arguments.clear();
+        }
+        if definition.varadict() && arguments.len() >= parameters.len() {
+            let rest: Vec<Vec<Token>> = arguments.split_off(parameters.len() - 1);
+            let mut varadict = vec![];
+            for (i, argument) in rest.into_iter().enumerate() {
+                if i != 0 {
+                    varadict.push(Token::new(",", false));
+                }
+                varadict.extend(argument);
+            }
+            arguments.push(varadict);
+        } else if definition.varadict() && arguments.len() + 1 == parameters.len() {
+            arguments.push(vec![]);
+        }
+        if arguments.len() != parameters.len() {
+            return Err(Error {
+                kind: ErrorKind::ArgumentCount,
+                message: format!("`{name}` takes {} argument(s) but was given {}.",
+                    parameters.len(),
+                    arguments.len()
+                )
+            })
+        }
+
+        let parameter = |token: &Token| parameters.iter().position(|parameter| *parameter == token.text);
+        let body = &definition.body;
+        let mut output: Vec<Token> = vec![];
+        let mut i = 0;
+
+        while i < body.len() {
+
+            let token = &body[i];
+
+            if token.text == "#" && definition.parameters.is_some()
+                && let Some(p) = body.get(i + 1).and_then(parameter) {
+                output.push(Token::new(&stringize(&arguments[p]), token.space));
+                i += 2;
+                continue
+            }
+
+            if token.text == "##" && let Some(right) = body.get(i + 1) {
+                let operand = match parameter(right) {
+                    Some(p) => arguments[p].clone(),
+                    None => vec![right.clone()]
+                };
+                // GNU, `, ## __VA_ARGS__` drops the comma when there are no varadict arguments.
+                if right.text == "__VA_ARGS__" && definition.varadict()
+                    && output.last().is_some_and(|last| last.text == ",") {
+                    if operand.is_empty() {
+                        output.pop();
+                    }
+                    output.extend(operand);
+                    i += 2;
+                    continue
+                }
+                let mut operand = operand.into_iter();
+                let left = output.pop().unwrap_or_else(Token::placemarker);
+                let right = operand.next().unwrap_or_else(Token::placemarker);
+                output.push(paste(left, right)?);
+                output.extend(operand);
+                i += 2;
+                continue
+            }
+
+            if let Some(p) = parameter(token) {
+                let pasted = body.get(i + 1).is_some_and(|next| next.text == "##");
+                let mut argument = if pasted {
+                    arguments[p].clone()
+                } else {
+                    self.expand_tokens(arguments[p].clone().into())?
+                };
+                match argument.first_mut() {
+                    Some(first) => first.space = token.space,
+                    None if pasted => argument.push(Token::placemarker()),
+                    None => {}
+                }
+                output.extend(argument);
+                i += 1;
+                continue
+            }
+
+            output.push(token.clone());
+            i += 1;
+
+        }
+
+        output.retain(|token| !token.text.is_empty());
+        for token in output.iter_mut() {
+            token.hide.extend(hide.iter().cloned());
+        }
+        Ok(output)
+
+    }
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn expansion() {
+
+        let macros = Macros::from_header(concat!(
+            "#define ONE 1 /* a comment */\n",
+            "#define ADD(a, b) ((a) + (b))\n",
+            "#define NAME(a) ya_ ## a ## _codes\n",
+            "#define STR(a) #a\n",
+            "#define COUNT(...) PICK(\"empty\", ##__VA_ARGS__, two, one, zero)\n",
+            "#define PICK(_0, _1, _2, N, ...) N\n",
+            "#define SELF SELF + ONE\n",
+            "#define LONG(a) \\\n    a a\n",
+            "#define GONE 0\n",
+            "#undef GONE\n"
+        ));
+
+        assert_eq!(macros.expand("ADD(ONE, 2)").unwrap(), "((1) + (2))");
+        assert_eq!(macros.expand("NAME(vector)").unwrap(), "ya_vector_codes");
+        assert_eq!(macros.expand("STR(\"hi\" there)").unwrap(), "\"\\\"hi\\\" there\"");
+        assert_eq!(macros.expand("COUNT()").unwrap(), "zero");
+        assert_eq!(macros.expand("COUNT(a, b)").unwrap(), "two");
+        assert_eq!(macros.expand("SELF").unwrap(), "SELF + 1");
+        assert_eq!(macros.expand("LONG(x)").unwrap(), "x x");
+        assert_eq!(macros.expand("GONE ADD").unwrap(), "GONE ADD");
+
+        assert_eq!(macros.expand("ADD(1").unwrap_err().kind, ErrorKind::UnterminatedInvocation);
+        assert_eq!(macros.expand("ADD(1)").unwrap_err().kind, ErrorKind::ArgumentCount);
+
+    }
+
+}
@@ -0,0 +1,146 @@
+//! `--explain-config`, prints the config as it was written with the
+//! final value of every preprocessed field right below it, so what the
+//! tags and keys did can be read off without going through a trace.
+//!
+//! Example
+//! -------
+//! ```toml
+//! [[definition]]
+//! key       = "emsg_unparity"
+//! name      = "ERROR_MESSAGE_UNPARITY"
+//! #=> definition.1.name = "YA_ECGEN_ERROR_MESSAGE_UNPARITY"
+//! ```
+//!
+//! Fields that aren't written in the config (defaults, from a `[enum]`
+//! or a generator library) are listed at the end.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    config::{Config, Key},
+    preprocessor::AnyPreprocessable,
+    stage
+};
+
+/// Starts every annotation, a comment so the output stays valid TOML.
+const ANNOTATION: &str = "#=> ";
+
+/// Every resolved name of `keys`, under `field`.
+fn key_names(field: &str, keys: Option<&Vec<Key>>) -> Vec<(String, AnyPreprocessable)> {
+    keys.into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, key)| (format!("{field}.{i}.name"), AnyPreprocessable::Name(key.name.clone())))
+        .collect()
+}
+
+/// A annotation line (or lines for a multi-line value).
+fn annotation(field: &str, value: &str) -> String {
+    let quoted = toml::Value::String(value.to_owned()).to_string();
+    let mut annotation = String::new();
+    for (i, line) in format!("{field} = {quoted}").lines().enumerate() {
+        annotation.push_str(match i {
+            0 => ANNOTATION,
+            _ => "#   "
+        });
+        annotation.push_str(line);
+        annotation.push('\n');
+    }
+    annotation
+}
+
+/// The config source with every preprocessed field of `config` annotated
+/// below the line its value ends on, `config` has to be preprocessed.
+pub fn explain(config: &Config) -> String {
+
+    let mut fields = stage::strings(config);
+    fields.extend(key_names("preamble.keys", config.preamble.as_ref().and_then(|preamble| preamble.keys.as_ref())));
+    fields.extend(key_names("postamble.keys", config.postamble.as_ref().and_then(|postamble| postamble.keys.as_ref())));
+    for (i, generator) in config.generator.iter().enumerate() {
+        fields.extend(key_names(&format!("generator.{i}.keys"), generator.keys.as_ref()));
+    }
+
+    let source = &config.metadata.source;
+    // annotations by the line (0 based) they go under.
+    let mut annotations: BTreeMap<usize, String> = BTreeMap::new();
+    let mut unwritten = String::new();
+    for (field, string) in fields {
+        let Some(value) = string.preprocessed() else {
+            continue
+        };
+        match config.metadata.span(&field) {
+            Some(span) => {
+                let last_line = config.metadata.line(span.end.max(span.start + 1) - 1) - 1;
+                annotations.entry(last_line).or_default().push_str(&annotation(&field, &value));
+            }
+            None if !value.is_empty() => unwritten.push_str(&annotation(&field, &value)),
+            None => {}
+        }
+    }
+
+    let mut explained = String::with_capacity(source.len() * 2);
+    for (i, line) in source.lines().enumerate() {
+        explained.push_str(line);
+        explained.push('\n');
+        if let Some(annotations) = annotations.get(&i) {
+            explained.push_str(annotations);
+        }
+    }
+    if !unwritten.is_empty() {
+        explained.push_str("\n# Not written in the config:\n");
+        explained.push_str(&unwritten);
+    }
+    explained
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn explained() {
+
+        let contents = concat!(
+            "[common]\n",
+            "prefix = \"A_\"\n",
+            "repeats = 3\n",
+            "\n",
+            "[preamble]\n",
+            "raw = \"\"\"\n",
+            "#define @{low}\n",
+            "\"\"\"\n",
+            "keys = [{key = \"low\", name = {tags = [\"LOWER\"], string = \"LOW\"}}]\n",
+            "\n",
+            "[core]\n",
+            "xmva = \"@{prefix}X\"\n",
+            "args = [{varadict = 1}]\n",
+            "\n",
+            "[[generator]]\n",
+            "repeat = \"$(0)\"\n",
+        );
+        let config = Config::parse(contents, std::path::Path::new("explain.xmva.toml")).unwrap();
+        config.preprocess().unwrap();
+        let explained = explain(&config);
+
+        assert!(explained.starts_with("[common]\nprefix = \"A_\"\nrepeats = 3\n"));
+        assert!(explained.contains(concat!(
+            "\"\"\"\n",
+            "#=> preamble.raw = \"\"\"\n",
+            "#   #define a_low\n",
+            "#   \"\"\"\n",
+            "keys = [{key = \"low\", name = {tags = [\"LOWER\"], string = \"LOW\"}}]\n",
+            "#=> preamble.keys.0.name = \"a_low\"\n",
+        )));
+        assert!(explained.contains("xmva = \"@{prefix}X\"\n#=> core.xmva = \"A_X\"\nargs"));
+        assert!(explained.contains("repeat = \"$(0)\"\n#=> generator.0.repeat = \"$(0)\"\n"));
+        // the default fallback isn't written, the empty preamble isn't worth listing.
+        assert!(explained.ends_with(concat!(
+            "\n# Not written in the config:\n",
+            "#=> generator.0.fallbacks.unparity = '_Static_assert(0, \"A_: argument count not a multiple of 1\")'\n"
+        )));
+
+    }
+
+}
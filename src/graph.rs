@@ -0,0 +1,328 @@
+//! `xmva graph`, the key reference graph of a config as GraphViz DOT or
+//! a Mermaid flowchart.
+//!
+//! Every key (common values, preamble/postamble keys, definitions, the
+//! keys of used configs and of generators) is a node with a edge to
+//! everything that references it. The strings of the generators and of
+//! the main macro, the raw preamble/postamble and the definition
+//! expansions and conditions are sinks, nothing can reference them.
+//!
+//! ```sh
+//! xmva -i ya_ecgen.xmva.toml graph | dot -Tsvg > keys.svg
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use clap::ValueEnum;
+
+use crate::{
+    config::{Argument, Config, Key},
+    preprocessor::{
+        common_key_values, split_reference, Preprocess, FILE_REFERENCE, Preprocessable, PreprocessorToken, BUILTIN_GENERATOR_INDEX, BUILTIN_GENERATOR_NAME,
+        BUILTIN_GROUP_SIZE, BUILTIN_REPEATS, BUILTIN_USED_PREFIX, BUILTIN_USED_XMVA
+    },
+    config::KEY_SCOPE_SEPARATOR
+};
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GraphFormat {
+    #[default]
+    Dot,
+    Mermaid
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Common,
+    Key,
+    Definition,
+    /// `alias.__XMVA__` and `alias.__PREFIX__` of a used config.
+    Used,
+    GeneratorKey,
+    /// The keys every generator gets, like `__GEN_INDEX__`.
+    Builtin,
+    Sink,
+    /// Referenced but never defined.
+    Unresolved
+}
+
+#[derive(Debug, Default)]
+pub struct Graph {
+    /// Ids along side the label and kind, ids are the dotted path of
+    /// the field the node comes from.
+    pub nodes: BTreeMap<String, (String, NodeKind)>,
+    /// From the referenced node to the node referencing it.
+    pub edges: BTreeSet<(String, String)>
+}
+
+/// Keys referenced by `value`, a [crate::config::Name] or a string that
+/// wasn't preprocessed yet.
+fn references<T: Preprocess>(value: &Preprocessable<T>, config: &Config) -> Vec<String> {
+    let Preprocessable::NotPreprocessed(value) = value else {
+        return vec![]
+    };
+    value
+        .to_preprocessor_tokens(&config.common.keyable)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|token| match token {
+            // included files aren't keys.
+            PreprocessorToken::Key(key) if key.starts_with(FILE_REFERENCE) => None,
+            PreprocessorToken::Key(key) => Some(split_reference(&key).0.to_owned()),
+            PreprocessorToken::Raw(_) => None
+        })
+        .collect()
+}
+
+impl Graph {
+
+    /// Build the graph of a parsed config that wasn't preprocessed.
+    pub fn new(config: &Config) -> Self {
+
+        let mut graph = Self::default();
+        // key names visible everywhere along side their node.
+        let mut global: BTreeMap<String, String> = BTreeMap::new();
+        // nodes along side the references they make and the generator
+        // whose keys they see.
+        let mut users: Vec<(String, Vec<String>, Option<usize>)> = vec![];
+
+        for (key, _) in common_key_values(&config.common.keyable).unwrap_or_default() {
+            let id = format!("common.{key}");
+            graph.nodes.insert(id.clone(), (key.clone(), NodeKind::Common));
+            global.insert(key, id);
+        }
+
+        let mut add_keys = |section: &str, keys: &[Key], kind: NodeKind, graph: &mut Self| {
+            for (i, key) in keys.iter().enumerate() {
+                let id = format!("{section}.keys.{i}");
+                graph.nodes.insert(id.clone(), (key.key.clone(), kind));
+                global.insert(key.key.clone(), id.clone());
+                let referenced = key.name.read().map(|name| references(&name, config)).unwrap_or_default();
+                users.push((id, referenced, None));
+            }
+        };
+        if let Some(keys) = config.preamble.as_ref().and_then(|preamble| preamble.keys.as_ref()) {
+            add_keys("preamble", keys, NodeKind::Key, &mut graph);
+        }
+        if let Some(keys) = config.postamble.as_ref().and_then(|postamble| postamble.keys.as_ref()) {
+            add_keys("postamble", keys, NodeKind::Key, &mut graph);
+        }
+
+        for (i, definition) in config.definition.iter().flatten().enumerate() {
+            let id = format!("definition.{i}");
+            graph.nodes.insert(id.clone(), (definition.key.clone(), NodeKind::Definition));
+            global.insert(definition.key.clone(), id.clone());
+            let referenced = definition.name.read().map(|name| references(&name, config)).unwrap_or_default();
+            users.push((id, referenced, None));
+        }
+
+        for (i, used) in config.used.iter().enumerate() {
+            for builtin in [BUILTIN_USED_XMVA, BUILTIN_USED_PREFIX] {
+                let key = format!("{}{KEY_SCOPE_SEPARATOR}{builtin}", used.alias);
+                let id = format!("uses.{i}.{builtin}");
+                graph.nodes.insert(id.clone(), (key.clone(), NodeKind::Used));
+                global.insert(key, id);
+            }
+        }
+
+        // generator keys see the other keys of their generator, named
+        // generators make theirs visible everywhere when qualified.
+        let mut local: Vec<BTreeMap<String, String>> = vec![];
+        for (i, generator) in config.generator.iter().enumerate() {
+            let mut keys = BTreeMap::new();
+            for (j, key) in generator.keys.iter().flatten().enumerate() {
+                let id = format!("generator.{i}.keys.{j}");
+                graph.nodes.insert(id.clone(), (key.key.clone(), NodeKind::GeneratorKey));
+                if let Some(scope) = &generator.name {
+                    global.insert(format!("{scope}{KEY_SCOPE_SEPARATOR}{}", key.key), id.clone());
+                }
+                keys.insert(key.key.clone(), id.clone());
+                let referenced = key.name.read().map(|name| references(&name, config)).unwrap_or_default();
+                users.push((id, referenced, Some(i)));
+            }
+            for (param, _) in generator.with.iter() {
+                let id = format!("generator.{i}.with.{param}");
+                graph.nodes.insert(id.clone(), (param.clone(), NodeKind::Builtin));
+                keys.insert(param.clone(), id);
+            }
+            local.push(keys);
+        }
+
+        let mut sink = |id: String, value: &crate::preprocessor::PreprocessableString, generator: Option<usize>, graph: &mut Self| {
+            let referenced = value.read().map(|value| references(&value, config)).unwrap_or_default();
+            graph.nodes.insert(id.clone(), (id.clone(), NodeKind::Sink));
+            users.push((id, referenced, generator));
+        };
+        if let Some(raw) = config.preamble.as_ref().and_then(|preamble| preamble.raw.as_ref()) {
+            sink("preamble.raw".to_owned(), raw, None, &mut graph);
+        }
+        if let Some(raw) = config.postamble.as_ref().and_then(|postamble| postamble.raw.as_ref()) {
+            sink("postamble.raw".to_owned(), raw, None, &mut graph);
+        }
+        for (i, definition) in config.definition.iter().flatten().enumerate() {
+            sink(format!("definition.{i}.expansion"), &definition.expansion, None, &mut graph);
+            if let Some(condition) = &definition.condition {
+                sink(format!("definition.{i}.condition"), condition, None, &mut graph);
+            }
+        }
+        sink("core.xmva".to_owned(), &config.core.xmva, None, &mut graph);
+        if let Some(list) = &config.core.list {
+            sink("core.list".to_owned(), list, None, &mut graph);
+        }
+        for (i, arg) in config.core.args.iter().enumerate() {
+            if let Argument::Named(named) = arg {
+                sink(format!("core.args.{i}.name"), &named.name, None, &mut graph);
+            }
+        }
+        for (i, generator) in config.generator.iter().enumerate() {
+            let mut strings = vec![
+                ("preamble", &generator.preamble),
+                ("repeat", &generator.repeat),
+                ("postamble", &generator.postamble),
+                ("fallbacks.unparity", &generator.fallbacks.unparity),
+                ("fallbacks.empty", &generator.fallbacks.empty)
+            ];
+            if let Some(condition) = &generator.condition {
+                strings.push(("condition", condition));
+            }
+            for (field, value) in strings {
+                sink(format!("generator.{i}.{field}"), value, Some(i), &mut graph);
+            }
+        }
+
+        let builtins = [BUILTIN_GENERATOR_NAME, BUILTIN_GENERATOR_INDEX, BUILTIN_REPEATS, BUILTIN_GROUP_SIZE];
+        for (user, referenced, generator) in users {
+            for key in referenced {
+                let id = match generator.and_then(|i| local[i].get(&key)).or_else(|| global.get(&key)) {
+                    Some(id) => id.clone(),
+                    None if generator.is_some() && builtins.contains(&key.as_str()) => {
+                        let id = format!("builtin.{key}");
+                        graph.nodes.insert(id.clone(), (key, NodeKind::Builtin));
+                        id
+                    }
+                    None => {
+                        let id = format!("unresolved.{key}");
+                        graph.nodes.insert(id.clone(), (key, NodeKind::Unresolved));
+                        id
+                    }
+                };
+                graph.edges.insert((id, user.clone()));
+            }
+        }
+
+        graph
+
+    }
+
+    /// Every node `id` ends up in, following the references through
+    /// any keys in between.
+    pub fn reachable(&self, id: &str) -> BTreeSet<&str> {
+
+        let mut reached: BTreeSet<&str> = BTreeSet::new();
+        let mut next = vec![id];
+        while let Some(from) = next.pop() {
+            for (_, to) in self.edges.iter().filter(|(edge, _)| edge == from) {
+                if reached.insert(to) {
+                    next.push(to);
+                }
+            }
+        }
+        reached
+
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.dot(),
+            GraphFormat::Mermaid => self.mermaid()
+        }
+    }
+
+    fn dot(&self) -> String {
+
+        let mut dot = String::from("digraph xmva {\n    rankdir=LR;\n");
+        for (id, (label, kind)) in self.nodes.iter() {
+            let style = match kind {
+                NodeKind::Common => "shape=box, style=filled, fillcolor=lightgray",
+                NodeKind::Key => "shape=ellipse",
+                NodeKind::Definition => "shape=box",
+                NodeKind::Used => "shape=box, style=dashed",
+                NodeKind::GeneratorKey => "shape=ellipse, style=filled, fillcolor=lightblue",
+                NodeKind::Builtin => "shape=box, style=filled, fillcolor=lightyellow",
+                NodeKind::Sink => "shape=note",
+                NodeKind::Unresolved => "shape=ellipse, style=dashed, color=red"
+            };
+            dot.push_str(&format!("    \"{id}\" [label=\"{}\", {style}];\n", label.replace('"', "\\\"")));
+        }
+        for (from, to) in self.edges.iter() {
+            dot.push_str(&format!("    \"{from}\" -> \"{to}\";\n"));
+        }
+        dot.push_str("}\n");
+        dot
+
+    }
+
+    fn mermaid(&self) -> String {
+
+        // mermaid ids can't have dots in them.
+        let ids: BTreeMap<&String, String> = self.nodes
+            .keys()
+            .enumerate()
+            .map(|(i, id)| (id, format!("n{i}")))
+            .collect();
+
+        let mut mermaid = String::from("flowchart LR\n");
+        for (id, (label, kind)) in self.nodes.iter() {
+            let label = label.replace('"', "#quot;");
+            let node = match kind {
+                NodeKind::Common | NodeKind::Definition | NodeKind::Used | NodeKind::Builtin => format!("[\"{label}\"]"),
+                NodeKind::Key | NodeKind::GeneratorKey => format!("([\"{label}\"])"),
+                NodeKind::Sink => format!("[/\"{label}\"/]"),
+                NodeKind::Unresolved => format!("((\"{label}?\"))")
+            };
+            mermaid.push_str(&format!("    {}{node}\n", ids[id]));
+        }
+        for (from, to) in self.edges.iter() {
+            mermaid.push_str(&format!("    {} --> {}\n", ids[from], ids[to]));
+        }
+        mermaid
+
+    }
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn reference_graph() {
+
+        let config = Config::parse(concat!(
+            "[common]\nprefix = \"A_\"\nrepeats = 4\n",
+            "[preamble]\nkeys = [{key = \"err\", name = \"@{prefix}ERR\"}]\n",
+            "[[definition]]\nkey = \"msg\"\nname = \"MSG_@{err}\"\nexpansion = \"@{missing}\"\n",
+            "[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n",
+            "[[generator]]\nkeys = [{key = \"err\", name = \"LOCAL\"}]\nrepeat = \"@{err} @{msg} @{__GEN_INDEX__}\"\n"
+        ), std::path::Path::new("a.xmva.toml")).unwrap();
+        let graph = Graph::new(&config);
+
+        let edge = |from: &str, to: &str| graph.edges.contains(&(from.to_owned(), to.to_owned()));
+        assert!(edge("common.prefix", "preamble.keys.0"));
+        assert!(edge("preamble.keys.0", "definition.0"));
+        assert!(edge("unresolved.missing", "definition.0.expansion"));
+        // the generator key shadows the global one.
+        assert!(edge("generator.0.keys.0", "generator.0.repeat"));
+        assert!(!edge("preamble.keys.0", "generator.0.repeat"));
+        assert!(edge("definition.0", "generator.0.repeat"));
+        assert!(edge("builtin.__GEN_INDEX__", "generator.0.repeat"));
+        assert!(graph.reachable("common.prefix").contains("generator.0.repeat"));
+        assert!(!graph.reachable("generator.0.keys.0").contains("definition.0"));
+
+        assert!(graph.render(GraphFormat::Dot).contains("\"common.prefix\" -> \"preamble.keys.0\";"));
+        assert!(graph.render(GraphFormat::Mermaid).starts_with("flowchart LR\n"));
+
+    }
+
+}
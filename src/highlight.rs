@@ -0,0 +1,166 @@
+//! Print a single string field from the config with every token colored
+//! by its kind, so that escaping mistakes are visible at a glance.
+//!
+//! Every field goes through the preprocessor tokenizer, fields under
+//! `generator` are compiled aswell so their raw parts also go through
+//! the compiler tokenizer.
+
+use std::path::Path;
+
+use colored::{ColoredString, Colorize};
+
+use crate::{
+    compiler::CompilerToken,
+    config,
+    preprocessor::{preprocessor_string_tokenizer, PreprocessorToken}
+};
+
+/// Top level table whose strings are compiled and not only preprocessed.
+const COMPILED_TABLE: &str = "generator";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    IO,
+    Toml,
+    NonExistantField,
+    NotAString,
+    Tokenizer
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub(crate) message: String
+}
+
+impl std::fmt::Display for Error {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Highlighter encountered a error, [{:?}]: {}", self.kind, self.message)
+    }
+
+}
+
+impl std::error::Error for Error {}
+
+/// Walk `field` (something like `generator.0.repeat`) through the TOML
+/// tree and return the string at the end.
+fn lookup_field(
+    root: &toml::Value,
+    field: &str
+) -> Result<String, Error> {
+
+    let mut current = root;
+
+    for part in field.split('.') {
+        let next = match current {
+            toml::Value::Table(table) => table.get(part),
+            toml::Value::Array(array) => part
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| array.get(i)),
+            _ => None
+        };
+        let Some(next) = next else {
+            return Err(Error {
+                kind: ErrorKind::NonExistantField,
+                message: format!("Field `{field}` does not exist, failed at `{part}`")
+            })
+        };
+        current = next;
+    }
+
+    match current {
+        toml::Value::String(s) => Ok(s.clone()),
+        // tagged names.
+        toml::Value::Table(table) if table.get("string").is_some_and(|s| s.is_str()) => {
+            Ok(table["string"].as_str().unwrap().to_owned())
+        }
+        _ => Err(Error {
+            kind: ErrorKind::NotAString,
+            message: format!("Field `{field}` is a {} and not a string", current.type_str())
+        })
+    }
+
+}
+
+fn color_preprocessor_token(token: &PreprocessorToken) -> ColoredString {
+    match token {
+        PreprocessorToken::Raw(_) => token.untokenize().normal(),
+        PreprocessorToken::Key(_) => token.untokenize().cyan().bold()
+    }
+}
+
+fn color_compiler_token(token: &CompilerToken) -> ColoredString {
+    match token {
+        CompilerToken::Raw(_) => token.untokenize().normal(),
+        CompilerToken::NamedArgumentRef(_) |
+        CompilerToken::UnamedArgumentRef(_) |
+        CompilerToken::MemberRef(_) |
+        CompilerToken::EdgeArgumentRef(..) |
+        CompilerToken::EdgeMemberRef(..) |
+        CompilerToken::LeftoverArgumentRef(_) => token.untokenize().green().bold(),
+        CompilerToken::SkipLast(_) |
+        CompilerToken::Skip { .. } => token.untokenize().magenta().bold(),
+        CompilerToken::Position => token.untokenize().blue().bold()
+    }
+}
+
+/// Tokenize `s` and color every token, `compiled` also tokenizes raw
+/// preprocessor tokens with the compiler tokenizer.
+pub fn highlight_string(
+    s: &str,
+    compiled: bool
+) -> Result<String, Error> {
+
+    let tokens = preprocessor_string_tokenizer(s)
+        .map_err(|err| Error {
+            kind: ErrorKind::Tokenizer,
+            message: err.to_string()
+        })?;
+
+    let mut highlighted = String::new();
+
+    for token in tokens.iter() {
+        match token {
+            PreprocessorToken::Raw(raw) if compiled => {
+                let compiler_tokens = CompilerToken::tokenize(raw)
+                    .map_err(|err| Error {
+                        kind: ErrorKind::Tokenizer,
+                        message: err.to_string()
+                    })?;
+                for compiler_token in compiler_tokens.iter() {
+                    highlighted.push_str(&color_compiler_token(compiler_token).to_string());
+                }
+            }
+            _ => highlighted.push_str(&color_preprocessor_token(token).to_string())
+        }
+    }
+
+    Ok(highlighted)
+
+}
+
+/// Load the config at `path` and highlight the string at `field`.
+pub fn highlight(
+    path: &Path,
+    field: &str
+) -> Result<String, Error> {
+
+    let file_contents = std::fs::read_to_string(path)
+        .map_err(|err| Error {
+            kind: ErrorKind::IO,
+            message: format!("in config file {:?}: {err}", path)
+        })?;
+
+    let root: toml::Value = toml::from_str(config::strip_bom(&file_contents))
+        .map_err(|err| Error {
+            kind: ErrorKind::Toml,
+            message: format!("in config file {:?}: {}", path, err.message())
+        })?;
+
+    let s = lookup_field(&root, field)?;
+
+    highlight_string(&s, field.split('.').next() == Some(COMPILED_TABLE))
+
+}
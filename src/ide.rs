@@ -0,0 +1,269 @@
+//! `--ide-metadata`, writes `<output name>.xmva-ide.json` next to the
+//! output describing every macro a user of the header is meant to call,
+//! for editor plugins (VSCode, clangd wrappers...) to show hovers and
+//! signatures that make sense instead of the expanded ladders.
+//!
+//! The ladders and generator macros are left out, they are an
+//! implementation detail of the `xmva`.
+//!
+//! Example
+//! -------
+//! ```json
+//! {
+//!   "version": 1,
+//!   "config": "ya_ecgen.xmva.toml",
+//!   "macros": [
+//!     {
+//!       "name": "YA_ECGEN",
+//!       "kind": "xmva",
+//!       "signature": "YA_ECGEN(lowercase_name, UPPERCASE_NAME, ...)",
+//!       "parameters": ["lowercase_name", "UPPERCASE_NAME", "..."],
+//!       "members": [{"name": "code", "doc": "The enumerator."}],
+//!       "shape": "(lowercase_name, UPPERCASE_NAME, ..., groups of 2: code, message)",
+//!       "doc": "Takes the varadict arguments in groups of 2."
+//!     }
+//!   ]
+//! }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{
+    compiler::{apply_counts, generate_apply_name, main_macro_shape, read_preprocessed, Error},
+    config::{Argument, Config, Emit}
+};
+
+/// Bumped whenever a field changes meaning or goes away.
+pub const FORMAT_VERSION: usize = 1;
+
+const EXTENSION: &str = "xmva-ide.json";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MacroKind {
+    Xmva,
+    List,
+    Definition,
+    Size,
+    Arity,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc:  Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Macro {
+    pub name: String,
+    pub kind: MacroKind,
+    /// How it is written in a `#define`, `NAME(a, b)` or just `NAME`.
+    pub signature: String,
+    /// [None] for object-like macros.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Vec<String>>,
+    /// The names of the members of every varadict argument group.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<Member>,
+    /// The named parameters and the shape of the varadict groups, only
+    /// for the `xmva`. `(name, ..., groups of 2: value, string)`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shape: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expansion: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<String>,
+}
+
+impl Macro {
+
+    fn new(name: String, kind: MacroKind, parameters: Option<Vec<String>>) -> Self {
+        let signature = match &parameters {
+            Some(parameters) => format!("{name}({})", parameters.join(", ")),
+            None => name.clone()
+        };
+        Self {
+            name,
+            kind,
+            signature,
+            parameters,
+            members: vec![],
+            shape: None,
+            doc: None,
+            expansion: None,
+            deprecated: None
+        }
+    }
+
+}
+
+#[derive(Serialize, Debug)]
+pub struct Ide {
+    pub version: usize,
+    pub config:  PathBuf,
+    pub macros:  Vec<Macro>,
+}
+
+/// Where the metadata of `output` is written.
+pub fn path(output: &Path) -> PathBuf {
+    output.with_extension(EXTENSION)
+}
+
+/// The macros of `config`, which has to be compiled already.
+pub fn export(config: &Config) -> Result<Ide, Error> {
+
+    let mut macros = vec![];
+
+    let mut parameters: Vec<String> = vec![];
+    for arg in config.core.args.iter() {
+        if let Argument::Named(named) = arg {
+            parameters.push(read_preprocessed(&named.name)?);
+        }
+    }
+    parameters.push("...".to_owned());
+    let members: Vec<Member> = config.core.group
+        .iter()
+        .flat_map(|group| group.members.iter())
+        .map(|member| Member {
+            name: member.name().to_owned(),
+            doc: member.doc().map(str::to_owned)
+        })
+        .collect();
+    let varadict = config.core.varadict().unwrap_or(0);
+
+    let mut xmva = Macro::new(read_preprocessed(&config.core.xmva)?, MacroKind::Xmva, Some(parameters));
+    xmva.members = members;
+    xmva.shape = Some(main_macro_shape(&config.core)?);
+    xmva.doc = Some(format!("Takes the varadict arguments in groups of {varadict}."));
+    xmva.deprecated = config.core.deprecated.clone();
+    macros.push(xmva);
+
+    if let Some(list) = &config.core.list {
+        let mut list = Macro::new(read_preprocessed(list)?, MacroKind::List, Some(vec!["X".to_owned(), "...".to_owned()]));
+        list.members = macros[0].members.iter().map(|member| Member {
+            name: member.name.clone(),
+            doc: member.doc.clone()
+        }).collect();
+        list.doc = Some(format!("Calls `X` once for every group of {varadict} varadict arguments."));
+        macros.push(list);
+    }
+
+    for definition in config.definition.iter().flatten().filter(|definition| definition.emit != Emit::Never) {
+        let mut defined = Macro::new(read_preprocessed(&definition.name)?, MacroKind::Definition, definition.parameters.clone());
+        defined.expansion = Some(read_preprocessed(&definition.expansion)?);
+        defined.deprecated = definition.deprecated.clone();
+        macros.push(defined);
+    }
+
+    if config.output.size_macros {
+        let prefix = &config.common.keyable.prefix;
+        let sizes = [
+            ("COUNT", config.common.repeats, "How many steps every ladder has."),
+            ("MAX_ARGS", config.common.repeats.saturating_sub(1), "The most varadict arguments the `xmva` takes."),
+            ("GROUP_SIZE", varadict, "How many varadict arguments make up a group.")
+        ];
+        for (name, value, doc) in sizes {
+            let mut size = Macro::new(format!("{prefix}{name}"), MacroKind::Size, None);
+            size.expansion = Some(value.to_string());
+            size.doc = Some(doc.to_owned());
+            macros.push(size);
+        }
+    }
+
+    if config.output.expose_arity_macros {
+        let named = macros[0].parameters.iter().flatten().filter(|parameter| *parameter != "...").cloned().collect::<Vec<String>>();
+        for n in apply_counts(&config.common, &config.core) {
+            let parameters = named.iter().cloned().chain((0..n).map(|i| format!("__{i}__"))).collect();
+            let mut arity = Macro::new(generate_apply_name(&config.common, n), MacroKind::Arity, Some(parameters));
+            arity.doc = Some(format!("`{}` with exactly {n} varadict arguments.", macros[0].name));
+            macros.push(arity);
+        }
+    }
+
+    Ok(Ide {
+        version: FORMAT_VERSION,
+        config: config.metadata.config.clone(),
+        macros
+    })
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn exported() {
+
+        let config = Config::parse(concat!(
+            "[common]\n",
+            "prefix = \"C_\"\n",
+            "repeats = 8\n",
+            "[output]\n",
+            "size_macros = true\n",
+            "expose_arity_macros = true\n",
+            "[core]\n",
+            "xmva = \"COLOR\"\n",
+            "list = \"COLOR_LIST\"\n",
+            "args = [{key = \"name\", name = \"name\"}, {varadict = 2}]\n",
+            "group = {members = [\"value\", {name = \"string\", doc = \"Shown to users.\"}]}\n",
+            "[[definition]]\n",
+            "key = \"ok\"\n",
+            "name = \"OK\"\n",
+            "parameters = [\"x\"]\n",
+            "expansion = \"(x)\"\n",
+            "deprecated = \"gone soon\"\n",
+            "[[generator]]\n",
+            "repeat = \"$(0) = $(1)$[, ]\"\n",
+            "fallbacks = {unparity = \"\", empty = \"\"}\n",
+        ), Path::new("color.xmva.toml")).unwrap();
+        config.preprocess().unwrap();
+
+        let ide = export(&config).unwrap();
+        assert_eq!(ide.version, FORMAT_VERSION);
+        let names: Vec<(&str, MacroKind)> = ide.macros.iter().map(|defined| (defined.name.as_str(), defined.kind)).collect();
+        assert_eq!(names, [
+            ("COLOR", MacroKind::Xmva),
+            ("COLOR_LIST", MacroKind::List),
+            ("C_OK", MacroKind::Definition),
+            ("C_COUNT", MacroKind::Size),
+            ("C_MAX_ARGS", MacroKind::Size),
+            ("C_GROUP_SIZE", MacroKind::Size),
+            ("C_APPLY_0", MacroKind::Arity),
+            ("C_APPLY_2", MacroKind::Arity),
+            ("C_APPLY_4", MacroKind::Arity),
+            ("C_APPLY_6", MacroKind::Arity),
+        ]);
+
+        let xmva = &ide.macros[0];
+        assert_eq!(xmva.signature, "COLOR(name, ...)");
+        assert_eq!(xmva.shape.as_deref(), Some("(name, ..., groups of 2: value, string)"));
+        assert_eq!(xmva.members, [
+            Member { name: "value".to_owned(), doc: None },
+            Member { name: "string".to_owned(), doc: Some("Shown to users.".to_owned()) }
+        ]);
+        assert_eq!(ide.macros[1].signature, "COLOR_LIST(X, ...)");
+        assert_eq!(ide.macros[2].signature, "C_OK(x)");
+        assert_eq!(ide.macros[2].expansion.as_deref(), Some("(x)"));
+        assert_eq!(ide.macros[2].deprecated.as_deref(), Some("gone soon"));
+        assert_eq!(ide.macros[3].signature, "C_COUNT");
+        assert_eq!(ide.macros[4].expansion.as_deref(), Some("7"));
+        assert_eq!(ide.macros[7].signature, "C_APPLY_2(name, __0__, __1__)");
+
+        let json: serde_json::Value = serde_json::to_value(&ide).unwrap();
+        assert!(json["macros"][0].get("expansion").is_none());
+        assert!(json["macros"][1].get("shape").is_none());
+        assert_eq!(json["macros"][0]["kind"], "xmva");
+
+        assert_eq!(path(Path::new("out/color.h")), Path::new("out/color.xmva-ide.json"));
+
+    }
+
+}
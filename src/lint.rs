@@ -0,0 +1,355 @@
+//! Lints, warnings about configs that generate fine but smell, each one
+//! with a code that the `[lints]` table of the config can set to `allow`,
+//! `warn` or `deny`.
+//!
+//! Example
+//! -------
+//! ```toml
+//! [lints]
+//! unused_key   = "allow"
+//! large_output = "deny"
+//! ```
+//!
+//! Everything that warns goes through [emit] which looks up the level
+//! the config set, a denied lint is reported as a error and stops the
+//! outputs from being written (see [denied]).
+//!
+//! The naming lints ([Lint::PrefixUnderscore], [Lint::ScreamingSnakeCase]
+//! and [Lint::LibcShadowing]) are opt-in, plenty of projects have their
+//! own conventions. So is [Lint::UnevenRepeats], a step or two that only
+//! ever picks the unparity fallback costs next to nothing.
+
+use std::{cell::RefCell, collections::BTreeMap, sync::{Arc, RwLock}};
+
+use colored::Colorize;
+use serde::Deserialize;
+use strum::Display;
+
+use crate::{
+    compiler::CompilerToken,
+    config::{Argument, Config},
+    diagnostic::{self, Location, Severity},
+    metadata::Metadata,
+    preprocessor::{Preprocess, Preprocessable}
+};
+
+/// Outputs bigger than this many bytes trigger [Lint::LargeOutput].
+pub const LARGE_OUTPUT_BYTES: usize = 1024 * 1024;
+
+#[derive(Deserialize, Display, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Lint {
+    /// A generator key with the same name as a global key.
+    ShadowedKey,
+    /// A key of the preamble, postamble or a generator that nothing references.
+    UnusedKey,
+    /// A used config whose output wasn't generated yet.
+    MissingUsedOutput,
+    /// A output bigger than [LARGE_OUTPUT_BYTES].
+    LargeOutput,
+    /// A `common.prefix` that doesn't end with a `_`.
+    PrefixUnderscore,
+    /// A generated macro name that isn't SCREAMING_SNAKE_CASE.
+    ScreamingSnakeCase,
+    /// A generated macro name that is also a common libc macro, see [LIBC_MACROS].
+    LibcShadowing,
+    /// What looks like a compiler token in a field that is only preprocessed.
+    StrayCompilerToken,
+    /// A `common.repeats` whose last steps can only get a partial group.
+    UnevenRepeats,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Lint {
+
+    /// The level when the config doesn't set one.
+    pub fn default_level(&self) -> Level {
+        match self {
+            Self::PrefixUnderscore | Self::ScreamingSnakeCase | Self::LibcShadowing | Self::UnevenRepeats => Level::Allow,
+            _ => Level::Warn
+        }
+    }
+
+}
+
+thread_local! {
+    /// Levels the config set, anything not in here uses its default.
+    /// Per thread for the same reason as the sigil overrides.
+    static LEVELS: RefCell<BTreeMap<Lint, Level>> = const { RefCell::new(BTreeMap::new()) };
+    /// How many denied lints were emitted since [reset_denied].
+    static DENIED: RefCell<usize> = const { RefCell::new(0) };
+    /// Lints emitted inside of [collect], [None] when they get printed.
+    static COLLECTED: RefCell<Option<Vec<Emitted>>> = const { RefCell::new(None) };
+}
+
+/// A lint emitted inside of [collect].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Emitted {
+    pub lint: Lint,
+    pub severity: Severity,
+    /// Without the snippet of the config, [Emitted::location] says where.
+    pub message: String,
+    pub location: Option<Location>
+}
+
+/// Run `f` with every lint it emits collected instead of printed, for
+/// the language server which shows them in the editor.
+pub fn collect<T>(f: impl FnOnce() -> T) -> (T, Vec<Emitted>) {
+    let previous = COLLECTED.with(|collected| collected.borrow_mut().replace(vec![]));
+    let result = f();
+    let emitted = COLLECTED.with(|collected| std::mem::replace(&mut *collected.borrow_mut(), previous));
+    (result, emitted.unwrap_or_default())
+}
+
+/// Use the given levels for all lints from now on, lints that were
+/// already denied stay counted (a used config sets its own levels).
+pub fn set_levels(levels: BTreeMap<Lint, Level>) {
+    LEVELS.with(|current| *current.borrow_mut() = levels);
+}
+
+/// Start counting denied lints from zero, once at the start of a run.
+pub fn reset_denied() {
+    DENIED.with(|denied| *denied.borrow_mut() = 0);
+}
+
+pub fn level(lint: Lint) -> Level {
+    LEVELS
+        .with(|levels| levels.borrow().get(&lint).copied())
+        .unwrap_or_else(|| lint.default_level())
+}
+
+/// Report `message` at the level set for `lint`.
+pub fn emit(lint: Lint, message: &str) {
+    emit_at(lint, message, message, None)
+}
+
+/// [emit] pointing to `location`, annotations (see [crate::diagnostic])
+/// get the `plain` message since they show it next to the config already.
+fn emit_at(lint: Lint, message: &str, plain: &str, location: Option<&Location>) {
+    let severity = match level(lint) {
+        Level::Allow => return,
+        Level::Warn => Severity::Warning,
+        Level::Deny => {
+            DENIED.with(|denied| *denied.borrow_mut() += 1);
+            Severity::Error
+        }
+    };
+    let collecting = COLLECTED.with(|collected| match collected.borrow_mut().as_mut() {
+        Some(collected) => {
+            collected.push(Emitted { lint, severity, message: plain.to_owned(), location: location.cloned() });
+            true
+        }
+        None => false
+    });
+    if collecting || diagnostic::annotate(severity, &lint.to_string(), plain, location) {
+        return
+    }
+    match severity {
+        Severity::Warning => eprintln!("{} {message}", format!("warning[{lint}]:").yellow().bold()),
+        Severity::Error => eprintln!("{} {message}", format!("error[{lint}]:").red().bold())
+    }
+}
+
+/// [emit] with the value of `field` in the config underlined and
+/// labeled with `label`, see [Metadata::label].
+pub fn emit_spanned(lint: Lint, message: &str, metadata: &Metadata, field: &str, label: &str) {
+    if level(lint) == Level::Allow {
+        return
+    }
+    let location = metadata.location(field);
+    match metadata.label(field, label) {
+        Some(snippet) => emit_at(lint, &format!("{message}\n{snippet}"), message, Some(&location)),
+        None => emit_at(lint, &metadata.annotate(message.to_owned()), message, Some(&location))
+    }
+}
+
+/// Common libc macros (and the C23 keywords that used to be macros)
+/// that a generated name shouldn't take over.
+pub const LIBC_MACROS: &[&str] = &[
+    "MIN", "MAX", "assert", "static_assert", "offsetof", "NULL", "EOF",
+    "errno", "bool", "true", "false", "alignof", "alignas", "noreturn",
+    "va_arg", "va_start", "va_end", "va_copy", "setjmp", "BUFSIZ",
+    "EXIT_SUCCESS", "EXIT_FAILURE", "RAND_MAX", "SIZE_MAX", "CHAR_BIT",
+];
+
+fn is_screaming_snake_case(name: &str) -> bool {
+    !name.starts_with(|ch: char| ch.is_ascii_digit())
+        && name.chars().all(|ch| ch.is_ascii_uppercase() || ch.is_ascii_digit() || ch == '_')
+}
+
+fn preprocessed<T: Preprocess>(preprocessable: &Arc<RwLock<Preprocessable<T>>>) -> Option<String> {
+    match &*preprocessable.read().ok()? {
+        Preprocessable::Preprocessed(s) => Some(s.clone()),
+        Preprocessable::NotPreprocessed(_) => None
+    }
+}
+
+/// Emit the naming lints for a preprocessed `config`.
+pub fn names(config: &Config) {
+
+    let prefix = &config.common.keyable.prefix;
+    if !prefix.is_empty() && !prefix.ends_with('_') {
+        emit_spanned(Lint::PrefixUnderscore,
+            &format!("prefix `{prefix}` doesn't end with a `_`."),
+            &config.metadata,
+            "common.prefix",
+            "add a trailing `_`"
+        );
+    }
+
+    let mut names: Vec<(String, Option<String>)> = vec![
+        ("core.xmva".to_owned(), preprocessed(&config.core.xmva)),
+        ("core.list".to_owned(), config.core.list.as_ref().and_then(preprocessed))
+    ];
+    for (i, definition) in config.definition.iter().flatten().enumerate() {
+        names.push((format!("definition.{i}.name"), preprocessed(&definition.name)));
+    }
+
+    for (field, name) in names {
+        let Some(name) = name else {
+            continue
+        };
+        if !is_screaming_snake_case(&name) {
+            emit_spanned(Lint::ScreamingSnakeCase,
+                &format!("generated name `{name}` isn't SCREAMING_SNAKE_CASE."),
+                &config.metadata,
+                &field,
+                &format!("generates `{name}`")
+            );
+        }
+        if LIBC_MACROS.contains(&name.as_str()) {
+            emit_spanned(Lint::LibcShadowing,
+                &format!("generated name `{name}` shadows the libc macro with the same name."),
+                &config.metadata,
+                &field,
+                &format!("generates `{name}`")
+            );
+        }
+    }
+
+}
+
+/// Emit [Lint::UnevenRepeats] when the most varadict arguments the
+/// ladders take isn't a whole number of groups.
+pub fn repeats(config: &Config) {
+
+    let Some(group) = config.core.varadict().filter(|group| *group > 1) else {
+        return
+    };
+    let max_args = config.common.repeats.saturating_sub(1);
+    let partial = max_args % group;
+    if partial != 0 {
+        emit_spanned(Lint::UnevenRepeats,
+            &format!(
+                "`common.repeats = {}` takes at most {max_args} varadict arguments, the last {partial} step(s) of every ladder can only pick the unparity fallback.",
+                config.common.repeats
+            ),
+            &config.metadata,
+            "common.repeats",
+            &format!("`{}` or `{}` end on a whole group", max_args - partial + 1, max_args - partial + group + 1)
+        );
+    }
+
+}
+
+/// Emit [Lint::StrayCompilerToken] for every field of a preprocessed
+/// `config` that isn't compiled but has compiler tokens in it, they end
+/// up in the output as they are. Keys are checked through the fields
+/// they were substituted into, a key used in a generator is compiled.
+pub fn compiler_tokens(config: &Config) {
+
+    let mut fields: Vec<(String, Option<String>)> = vec![];
+    for (i, include) in config.includes.system.iter().enumerate() {
+        fields.push((format!("includes.system.{i}"), preprocessed(include)));
+    }
+    for (i, include) in config.includes.local.iter().enumerate() {
+        fields.push((format!("includes.local.{i}"), preprocessed(include)));
+    }
+    if let Some(raw) = config.preamble.as_ref().and_then(|preamble| preamble.raw.as_ref()) {
+        fields.push(("preamble.raw".to_owned(), preprocessed(raw)));
+    }
+    if let Some(raw) = config.postamble.as_ref().and_then(|postamble| postamble.raw.as_ref()) {
+        fields.push(("postamble.raw".to_owned(), preprocessed(raw)));
+    }
+    for (i, definition) in config.definition.iter().flatten().enumerate() {
+        fields.push((format!("definition.{i}.name"), preprocessed(&definition.name)));
+        fields.push((format!("definition.{i}.expansion"), preprocessed(&definition.expansion)));
+        if let Some(condition) = &definition.condition {
+            fields.push((format!("definition.{i}.condition"), preprocessed(condition)));
+        }
+    }
+    fields.push(("core.xmva".to_owned(), preprocessed(&config.core.xmva)));
+    if let Some(list) = &config.core.list {
+        fields.push(("core.list".to_owned(), preprocessed(list)));
+    }
+    for (i, arg) in config.core.args.iter().enumerate() {
+        if let Argument::Named(named) = arg {
+            fields.push((format!("core.args.{i}.name"), preprocessed(&named.name)));
+        }
+    }
+
+    for (field, value) in fields {
+        let Some(token) = value
+            .as_deref()
+            .map(CompilerToken::scan)
+            .and_then(|tokens| tokens.into_iter().find(|token| *token != CompilerToken::Position)) else {
+            continue
+        };
+        let token = token.untokenize();
+        emit_spanned(Lint::StrayCompilerToken,
+            &format!("`{token}` in `{field}` is left as it is, only generator strings are compiled."),
+            &config.metadata,
+            &field,
+            "isn't compiled"
+        );
+    }
+
+}
+
+/// How many denied lints were emitted since [reset_denied].
+pub fn denied() -> usize {
+    DENIED.with(|denied| *denied.borrow())
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn levels() {
+
+        let levels: BTreeMap<Lint, Level> = toml::from_str(
+            "unused_key = \"allow\"\nlarge_output = \"deny\"\n"
+        ).unwrap();
+        set_levels(levels);
+
+        assert_eq!(level(Lint::UnusedKey), Level::Allow);
+        assert_eq!(level(Lint::ShadowedKey), Level::Warn);
+
+        emit(Lint::UnusedKey, "not reported");
+        assert_eq!(denied(), 0);
+        emit(Lint::LargeOutput, "reported");
+        assert_eq!(denied(), 1);
+
+        assert!(toml::from_str::<BTreeMap<Lint, Level>>("no_such_lint = \"allow\"").is_err());
+
+    }
+
+    #[test]
+    fn naming() {
+        assert!(is_screaming_snake_case("YA_ECGEN_2"));
+        assert!(!is_screaming_snake_case("ya_ecgen"));
+        assert!(!is_screaming_snake_case("2_ECGEN"));
+
+    }
+
+}
@@ -0,0 +1,590 @@
+//! Lowering of high level config sections into the [Core] and
+//! [Generator]s that the rest of the pipeline actually understands.
+//!
+//! Everything here happens right after the config is loaded and before
+//! preprocessing, so the strings created here are just as preprocessable
+//! and compilable as anything the user wrote by hand.
+
+use std::sync::{Arc, RwLock};
+
+use strum::IntoEnumIterator;
+
+use crate::{
+    compiler::{CompilerToken, SkipEdge},
+    config::{
+        Argument, Config, Core, Definition, Emit, Enum, EnumMember, Fallbacks,
+        Generator, GeneratorKind, Group, Helper, Member, Name, NamedArgument
+    },
+    preprocessor::{Preprocessable, PreprocessableString, PreprocessorToken}
+};
+
+/// Key and name of the named argument holding the enum name.
+const ENUM_NAME_ARGUMENT: &str = "name";
+
+/// Key of the helper definition that stringifies its argument after
+/// it was expanded (pasted), the name gets the prefix like any other.
+const STRINGIFY_KEY: &str = "__stringify__";
+const STRINGIFY_NAME: &str = "__STRINGIFY__";
+
+/// Wrap a [String] into a not yet preprocessed [PreprocessableString].
+pub fn preprocessable(s: impl Into<String>) -> PreprocessableString {
+    Arc::new(RwLock::new(Preprocessable::NotPreprocessed(s.into())))
+}
+
+/// The user written string of a [PreprocessableString], before lowering
+/// nothing is preprocessed so this only fails on a poisoned lock.
+pub(crate) fn raw(s: &PreprocessableString) -> Result<String, String> {
+    match &*s.read().map_err(|err| err.to_string())? {
+        Preprocessable::NotPreprocessed(raw) => Ok(raw.clone()),
+        Preprocessable::Preprocessed(_) => Err(
+            "tried to lower a string that was already preprocessed".to_owned()
+        )
+    }
+}
+
+/// Since [PreprocessableString]s are written into during preprocessing and
+/// compiling, generators created from the same user generator can't share
+/// them and need their own copy.
+fn deep_clone(s: &PreprocessableString) -> Result<PreprocessableString, String> {
+    Ok(preprocessable(raw(s)?))
+}
+
+/// Create a [Generator] with the given fallbacks.
+fn generator_with_fallbacks(
+    preamble: String,
+    repeat: String,
+    postamble: String,
+    fallbacks: Fallbacks
+) -> Generator {
+    Generator {
+        kind: GeneratorKind::Repeat,
+        type_name: None,
+        array: None,
+        signature: None,
+        switch_on: None,
+        case_label: None,
+        default_case: None,
+        condition: None,
+        name: None,
+        keys: None,
+        output: None,
+        repeats: None,
+        apply: None,
+        join: None,
+        from: None,
+        with: Default::default(),
+        helpers: None,
+        fallbacks,
+        preamble: preprocessable(preamble),
+        repeat: preprocessable(repeat),
+        postamble: preprocessable(postamble),
+    }
+}
+
+/// Create a [Generator] that generates a `static inline` function
+/// containing a single `switch`, see [GeneratorKind::Switch].
+fn switch_generator(
+    signature: &str,
+    switch_on: &str,
+    case_label: &str,
+    body: &str,
+    default_case: &str,
+    fallbacks: Fallbacks
+) -> Generator {
+    generator_with_fallbacks(
+        format!("static inline {signature} {{ switch ({switch_on}) {{ "),
+        format!("case {case_label}: {body} "),
+        format!("default: {default_case} }} }}"),
+        fallbacks
+    )
+}
+
+/// Create a [Generator] where the fallbacks fail the compilation
+/// with a `_Static_assert`.
+fn generator(
+    preamble: String,
+    repeat: String,
+    postamble: String,
+    group_size: usize
+) -> Generator {
+    generator_with_fallbacks(
+        preamble,
+        repeat,
+        postamble,
+        Fallbacks {
+            unparity: preprocessable(format!(
+                "_Static_assert(0, \"@{{prefix}}: [Argument unparity] argument count is not a multiple of {group_size}.\")"
+            )),
+            unparity_remainder: Default::default(),
+            empty: preprocessable(
+                "_Static_assert(0, \"@{prefix}: [No members] No member was specified for this enum type.\")"
+            ),
+        }
+    )
+}
+
+impl Enum {
+
+    /// Fill in the [Core] and prepend the enum [Generator]s to the
+    /// [Config], errors are returned as a message.
+    pub fn lower(&self, config: &mut Config) -> Result<(), String> {
+
+        let position = |member: EnumMember| self.entries
+            .iter()
+            .position(|m| *m == member);
+
+        for (i, member) in self.entries.iter().enumerate() {
+            if self.entries[..i].contains(member) {
+                return Err(format!("[enum] entries contain {:?} more than once", member))
+            }
+        }
+
+        let Some(value) = position(EnumMember::Value) else {
+            return Err("[enum] entries must contain \"value\"".to_owned())
+        };
+        let group_size = self.entries.len();
+
+        config.core = Core {
+            xmva: preprocessable(self.xmva.clone()),
+            args: vec![
+                Argument::Named(NamedArgument {
+                    key: ENUM_NAME_ARGUMENT.to_owned(),
+                    name: preprocessable(ENUM_NAME_ARGUMENT)
+                }),
+                Argument::Varadict { varadict: group_size }
+            ],
+            list: config.core.list.take(),
+            deprecated: config.core.deprecated.take(),
+            group: Some(Group {
+                members: self.entries.iter().map(|member| Member::Name(member.name().to_owned())).collect()
+            })
+        };
+
+        let name = format!("${{{ENUM_NAME_ARGUMENT}}}");
+        let mut generators = vec![];
+
+        generators.push(generator(
+            format!("enum {name} {{"),
+            format!("$({value})$[, ]"),
+            "};".to_owned(),
+            group_size
+        ));
+
+        // stringify the enumerator when no string is given.
+        let string = match position(EnumMember::String) {
+            Some(string) => format!("$({string})"),
+            None => format!("#$({value})")
+        };
+        generators.push(generator(
+            format!("static const char *const {name} ## _names[] = {{"),
+            format!("[$({value})] = {string}$[, ]"),
+            "};".to_owned(),
+            group_size
+        ));
+
+        if let Some(doc) = position(EnumMember::Doc) {
+            generators.push(generator(
+                format!("static const char *const {name} ## _docs[] = {{"),
+                format!("[$({value})] = $({doc})$[, ]"),
+                "};".to_owned(),
+                group_size
+            ));
+        }
+
+        generators.push(generator(
+            format!("enum {{{name} ## _count = 0"),
+            " + 1".to_owned(),
+            "};".to_owned(),
+            group_size
+        ));
+
+        generators.append(&mut config.generator);
+        config.generator = generators;
+
+        Ok(())
+
+    }
+
+}
+
+impl Generator {
+
+    fn fallbacks_deep_clone(&self) -> Result<Fallbacks, String> {
+        Ok(Fallbacks {
+            unparity: deep_clone(&self.fallbacks.unparity)?,
+            empty: deep_clone(&self.fallbacks.empty)?,
+            unparity_remainder: self.fallbacks.unparity_remainder
+                .iter()
+                .map(|(remainder, unparity)| Ok((*remainder, deep_clone(unparity)?)))
+                .collect::<Result<_, String>>()?
+        })
+    }
+
+    /// Every generator lowered from this one keeps its condition, name,
+    /// keys, output file and repeat range.
+    fn inheriting(mut lowered: Generator, from: &Generator) -> Result<Generator, String> {
+        lowered.condition = from.condition.as_ref().map(deep_clone).transpose()?;
+        lowered.name = from.name.clone();
+        lowered.repeats = from.repeats;
+        lowered.keys = from.keys.clone();
+        lowered.output = from.output.clone();
+        lowered.from = from.from.clone();
+        lowered.with = from.with.clone();
+        Ok(lowered)
+    }
+
+    /// Lower [GeneratorKind::Bitflags] into the flag enum, the `_ALL` mask
+    /// and the `_to_string` function.
+    fn lower_bitflags(&self) -> Result<Vec<Generator>, String> {
+
+        let Some(type_name) = &self.type_name else {
+            return Err("a generator with kind = \"bitflags\" needs a `type`".to_owned())
+        };
+        let flag = raw(&self.repeat)?;
+        let stringify = format!("@{{{STRINGIFY_KEY}}}");
+
+        Ok(vec![
+            generator_with_fallbacks(
+                format!("enum {type_name} {{"),
+                // the position starts from 1.
+                format!("{flag} = 1 << ($. - 1)$[, ]"),
+                "};".to_owned(),
+                self.fallbacks_deep_clone()?
+            ),
+            generator_with_fallbacks(
+                format!("enum {{{type_name} ## _ALL = 0"),
+                format!(" | {flag}"),
+                "};".to_owned(),
+                self.fallbacks_deep_clone()?
+            ),
+            switch_generator(
+                &format!("const char *{type_name} ## _to_string(enum {type_name} flag)"),
+                "flag",
+                &flag,
+                &format!("return {stringify}({flag});"),
+                "return 0;",
+                self.fallbacks_deep_clone()?
+            ),
+        ])
+
+    }
+
+    /// Lower [Generator::apply] into a repeat that calls the callback
+    /// with every argument of the group and the position.
+    fn lower_apply(mut self, group_size: usize) -> Result<Generator, String> {
+
+        let Some(apply) = self.apply.take() else {
+            return Ok(self)
+        };
+        if !raw(&self.repeat)?.is_empty() {
+            return Err(format!("a generator with `apply = \"{apply}\"` can't have a `repeat` aswell"))
+        }
+
+        let arguments: Vec<String> = (0..group_size)
+            .map(|i| format!("$({i})"))
+            .chain(std::iter::once("$.".to_owned()))
+            .collect();
+        self.repeat = preprocessable(format!("{apply}({})", arguments.join(", ")));
+
+        Ok(self)
+
+    }
+
+    /// Lower [Generator::join] into a [CompilerToken::Skip] at the end
+    /// of the repeat, the join is taken as is so it never references keys.
+    fn lower_join(mut self) -> Result<Generator, String> {
+
+        let Some(join) = self.join.take() else {
+            return Ok(self)
+        };
+        let joiner = CompilerToken::Skip {
+            count: 1,
+            edge: SkipEdge::Last,
+            text: join.into()
+        };
+        // escaped for the preprocessor aswell since the repeat goes
+        // through it first.
+        let joiner = PreprocessorToken::Raw(joiner.untokenize().into()).untokenize();
+        self.repeat = preprocessable(raw(&self.repeat)? + joiner.as_str());
+
+        Ok(self)
+
+    }
+
+    /// Lower [GeneratorKind::Table] into a array with designated
+    /// initializers.
+    fn lower_table(&self) -> Result<Generator, String> {
+
+        let (Some(type_name), Some(array)) = (&self.type_name, &self.array) else {
+            return Err("a generator with kind = \"table\" needs a `type` and a `array`".to_owned())
+        };
+        let element = raw(&self.repeat)?;
+
+        Ok(generator_with_fallbacks(
+            format!("static const {type_name} {array}[] = {{"),
+            // the position starts from 1.
+            format!("[$. - 1] = {element}$[, ]"),
+            "};".to_owned(),
+            self.fallbacks_deep_clone()?
+        ))
+
+    }
+
+    /// Lower [GeneratorKind::Switch] into a function with a `switch`.
+    fn lower_switch(&self) -> Result<Generator, String> {
+
+        let (Some(signature), Some(switch_on), Some(case_label)) = 
+            (&self.signature, &self.switch_on, &self.case_label) else {
+            return Err(
+                "a generator with kind = \"switch\" needs a `signature`, `switch` and `case`".to_owned()
+            )
+        };
+
+        Ok(switch_generator(
+            signature,
+            switch_on,
+            case_label,
+            &raw(&self.repeat)?,
+            self.default_case.as_deref().unwrap_or("break;"),
+            self.fallbacks_deep_clone()?
+        ))
+
+    }
+
+}
+
+impl Config {
+
+    /// Add a [Definition] unless one with the same key already exists.
+    fn add_definition(
+        &mut self,
+        key: &str,
+        name: &str,
+        parameters: Vec<String>,
+        expansion: &str
+    ) {
+        let definitions = self.definition.get_or_insert_with(Vec::new);
+        if definitions.iter().any(|definition| definition.key == key) {
+            return
+        }
+        definitions.push(Definition {
+            key: key.to_owned(),
+            name: Arc::new(RwLock::new(Preprocessable::NotPreprocessed(Name::Raw(name.to_owned())))),
+            parameters: Some(parameters),
+            expansion: preprocessable(expansion),
+            condition: None,
+            deprecated: None,
+            emit: Emit::Always
+        });
+    }
+
+    /// Lower every [Generator] that isn't a [GeneratorKind::Repeat].
+    pub fn lower_generators(&mut self) -> Result<(), String> {
+
+        let mut lowered = vec![];
+
+        for generator in std::mem::take(&mut self.generator) {
+            if generator.apply.is_some() && generator.kind != GeneratorKind::Repeat {
+                return Err(format!("`apply` only works with kind = \"repeat\", not {:?}", generator.kind))
+            }
+            if generator.join.is_some() && generator.kind != GeneratorKind::Repeat {
+                return Err(format!("`join` only works with kind = \"repeat\", not {:?}", generator.kind))
+            }
+            match generator.kind {
+                GeneratorKind::Repeat => lowered.push(
+                    generator.lower_apply(self.core.varadict().unwrap_or(0))?.lower_join()?
+                ),
+                GeneratorKind::Bitflags => {
+                    self.add_definition(STRINGIFY_KEY, STRINGIFY_NAME, vec!["x".to_owned()], "#x");
+                    for (i, flags) in generator.lower_bitflags()?.into_iter().enumerate() {
+                        let mut flags = Generator::inheriting(flags, &generator)?;
+                        // the name has to stay unique.
+                        if i != 0 {
+                            flags.name = None;
+                        }
+                        lowered.push(flags);
+                    }
+                }
+                GeneratorKind::Table => lowered.push(
+                    Generator::inheriting(generator.lower_table()?, &generator)?
+                ),
+                GeneratorKind::Switch => lowered.push(
+                    Generator::inheriting(generator.lower_switch()?, &generator)?
+                ),
+                GeneratorKind::Helpers => {
+                    let helpers = generator.helpers.unwrap_or_else(|| Helper::iter().collect());
+                    for helper in helpers {
+                        if !self.helpers.contains(&helper) {
+                            self.helpers.push(helper);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.generator = lowered;
+
+        Ok(())
+
+    }
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(dead_code)]
+    const CORE: &str = "[common]\nprefix = \"T_\"\nrepeats = 8\n\n[core]\nxmva = \"T\"\nargs = [{key = \"n\", name = \"n\"}, {varadict = 1}]\n\n";
+
+    /// The header generated from `contents`.
+    #[allow(dead_code)]
+    fn assemble(contents: &str) -> String {
+        let config = Config::parse(contents, std::path::Path::new("lower.xmva.toml")).unwrap();
+        config.preprocess().unwrap();
+        config.compile_and_assemble().unwrap().main.contents
+    }
+
+    /// The message of the error loading `contents` fails with.
+    #[allow(dead_code)]
+    fn load_error(contents: &str) -> String {
+        Config::parse(contents, std::path::Path::new("lower.xmva.toml")).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn inherited_repeats() {
+
+        let assembled = assemble(&format!("{CORE}[[generator]]\nkind = \"table\"\ntype = \"int\"\narray = \"values\"\n\
+            repeats = {{min = 2, max = 4}}\nrepeat = \"$(0)\"\n"));
+
+        assert!(assembled.contains("#define T___ARGS__0_2(n, __0__, __1__) static const int values[] = {[1 - 1] = __0__, [2 - 1] = __1__};\n"));
+        assert!(assembled.contains("#define T___ARGS__0_4("));
+        assert!(assembled.contains("#define T___ARGS__0_RANGE(...)"));
+        assert!(!assembled.contains("T___ARGS__0_1(") && !assembled.contains("T___ARGS__0_5("));
+
+    }
+
+    #[test]
+    fn enum_section() {
+
+        let common = "[common]\nprefix = \"T_\"\nrepeats = 4\n\n";
+        let assembled = assemble(&format!("{common}[enum]\nxmva = \"COLOR\"\nentries = [\"value\", \"string\", \"doc\"]\n"));
+
+        assert!(assembled.contains("#define T___ARGS__0_3(name, __0__, __1__, __2__) enum name {__0__};\n"));
+        assert!(assembled.contains("#define T___ARGS__1_3(name, __0__, __1__, __2__) static const char *const name ## _names[] = {[__0__] = __1__};\n"));
+        assert!(assembled.contains("#define T___ARGS__2_3(name, __0__, __1__, __2__) static const char *const name ## _docs[] = {[__0__] = __2__};\n"));
+        assert!(assembled.contains("#define T___ARGS__3_3(name, __0__, __1__, __2__) enum {name ## _count = 0 + 1};\n"));
+        assert!(assembled.contains("#define T___ARGS__0_2(name, __0__, __1__) _Static_assert(0, \"T_: [Argument unparity] argument count is not a multiple of 3.\")\n"));
+        assert!(assembled.contains("#define T___ARGS__0_0(name) _Static_assert(0, \"T_: [No members] No member was specified for this enum type.\")\n"));
+
+        // without a string the value is stringified and there are no docs.
+        let assembled = assemble(&format!("{common}[enum]\nxmva = \"COLOR\"\nentries = [\"value\"]\n"));
+        assert!(assembled.contains("#define T___ARGS__1_1(name, __0__) static const char *const name ## _names[] = {[__0__] = #__0__};\n"));
+        assert!(!assembled.contains("_docs"));
+
+        assert!(load_error(&format!("{common}[enum]\nxmva = \"COLOR\"\nentries = [\"string\"]\n"))
+            .contains("[enum] entries must contain \"value\""));
+        assert!(load_error(&format!("{common}[enum]\nxmva = \"COLOR\"\nentries = [\"value\", \"value\"]\n"))
+            .contains("[enum] entries contain Value more than once"));
+        assert!(load_error(&format!("{CORE}[enum]\nxmva = \"COLOR\"\n"))
+            .contains("a [enum] section can't be combined with a [core] section"));
+
+    }
+
+    #[test]
+    fn bitflags_generator() {
+
+        let assembled = assemble(&format!("{CORE}[[generator]]\nkind = \"bitflags\"\ntype = \"perm\"\nrepeat = \"$(0)\"\n"));
+
+        assert!(assembled.contains("#define T___STRINGIFY__(x) #x\n"));
+        assert!(assembled.contains("#define T___ARGS__0_2(n, __0__, __1__) enum perm {__0__ = 1 << (1 - 1), __1__ = 1 << (2 - 1)};\n"));
+        assert!(assembled.contains("#define T___ARGS__1_2(n, __0__, __1__) enum {perm ## _ALL = 0 | __0__ | __1__};\n"));
+        assert!(assembled.contains(concat!(
+            "#define T___ARGS__2_1(n, __0__) static inline const char *perm ## _to_string(enum perm flag) ",
+            "{ switch (flag) { case __0__: return T___STRINGIFY__(__0__); default: return 0; } }\n"
+        )));
+
+        assert!(load_error(&format!("{CORE}[[generator]]\nkind = \"bitflags\"\nrepeat = \"$(0)\"\n"))
+            .contains("a generator with kind = \"bitflags\" needs a `type`"));
+        assert!(load_error(&format!("{CORE}[[generator]]\nkind = \"bitflags\"\ntype = \"perm\"\nrepeat = \"$(0)\"\njoin = \", \"\n"))
+            .contains("`join` only works with kind = \"repeat\", not Bitflags"));
+
+    }
+
+    #[test]
+    fn table_generator() {
+
+        let assembled = assemble(&format!("{CORE}[[generator]]\nkind = \"table\"\ntype = \"char *\"\narray = \"names\"\nrepeat = \"#$(0)\"\n"));
+
+        assert!(assembled.contains("#define T___ARGS__0_0(n) \n"));
+        assert!(assembled.contains("#define T___ARGS__0_2(n, __0__, __1__) static const char * names[] = {[1 - 1] = #__0__, [2 - 1] = #__1__};\n"));
+
+        assert!(load_error(&format!("{CORE}[[generator]]\nkind = \"table\"\ntype = \"int\"\nrepeat = \"$(0)\"\n"))
+            .contains("a generator with kind = \"table\" needs a `type` and a `array`"));
+        assert!(load_error(&format!("{CORE}[[generator]]\nkind = \"table\"\ntype = \"int\"\narray = \"a\"\napply = \"F\"\n"))
+            .contains("`apply` only works with kind = \"repeat\", not Table"));
+
+    }
+
+    #[test]
+    fn switch_function() {
+
+        let switch = "[[generator]]\nkind = \"switch\"\nsignature = \"int f(int x)\"\nswitch = \"x\"\ncase = \"$(0)\"\nrepeat = \"return $.;\"\n";
+
+        let assembled = assemble(&format!("{CORE}{switch}"));
+        assert!(assembled.contains(
+            "#define T___ARGS__0_2(n, __0__, __1__) static inline int f(int x) { switch (x) { case __0__: return 1; case __1__: return 2; default: break; } }\n"
+        ));
+
+        let assembled = assemble(&format!("{CORE}{switch}default = \"return 0;\"\n"));
+        assert!(assembled.contains(
+            "#define T___ARGS__0_1(n, __0__) static inline int f(int x) { switch (x) { case __0__: return 1; default: return 0; } }\n"
+        ));
+
+        assert!(load_error(&format!("{CORE}[[generator]]\nkind = \"switch\"\nsignature = \"int f(int x)\"\ncase = \"$(0)\"\n"))
+            .contains("a generator with kind = \"switch\" needs a `signature`, `switch` and `case`"));
+
+    }
+
+    #[test]
+    fn apply_callback() {
+
+        let core = CORE.replace("varadict = 1", "varadict = 2").replace("repeats = 8", "repeats = 5");
+        let assembled = assemble(&format!("{core}[[generator]]\napply = \"ERROR_CODE\"\n"));
+
+        // every argument of the group and then the position.
+        assert!(assembled.contains("#define T___ARGS__0_2(n, __0__, __1__) ERROR_CODE(__0__, __1__, 1)\n"));
+        assert!(assembled.contains("#define T___ARGS__0_4(n, __0__, __1__, __2__, __3__) ERROR_CODE(__0__, __1__, 1)ERROR_CODE(__2__, __3__, 2)\n"));
+
+        assert!(load_error(&format!("{core}[[generator]]\napply = \"ERROR_CODE\"\nrepeat = \"$(0)\"\n")).contains("can't have a `repeat` aswell"));
+        assert!(load_error(&format!("{core}[[generator]]\nkind = \"table\"\ntype = \"int\"\narray = \"a\"\napply = \"ERROR_CODE\"\n")).contains("`apply` only works with kind = \"repeat\""));
+
+    }
+
+    #[test]
+    fn helper_functions() {
+
+        let core = CORE.replace("varadict = 1", "varadict = 2");
+        let assembled = assemble(&format!("{core}[[generator]]\nrepeat = \"$(0)\"\n\n[[generator]]\nkind = \"helpers\"\nhelpers = [\"max\", \"valid\"]\n"));
+
+        assert!(assembled.contains(concat!(
+            "static inline int T_max_arguments(void) { return 7; }\n",
+            "static inline int T_max_groups(void) { return 3; }\n",
+            "static inline int T_valid_argument_count(int count) { return count >= 0 && count <= 7 && count % 2 == 0; }\n"
+        )));
+        assert!(!assembled.contains("T_group_count"));
+        // nothing inside of the xmva.
+        assert!(!assembled.contains("T___GENERATOR__1"));
+
+        // all of them when left out, with the repeats from the command line.
+        let mut config = Config::parse(&format!("{core}[[generator]]\nkind = \"helpers\"\n"), std::path::Path::new("lower.xmva.toml")).unwrap();
+        config.apply_overrides(Some(5), None).unwrap();
+        config.preprocess().unwrap();
+        let assembled = config.compile_and_assemble().unwrap().main.contents;
+        assert!(assembled.contains("static inline int T_group_count(int count) { return count / 2; }\n"));
+        assert!(assembled.contains("static inline int T_max_arguments(void) { return 4; }\n"));
+
+    }
+
+}
@@ -0,0 +1,624 @@
+//! A small language server for `.xmva.toml` files.
+//!
+//! Speaks just enough LSP over stdio to be useful:
+//! - diagnostics (lints included) whenever a document is opened or changed,
+//! - key name completion inside of `@{...}`,
+//! - hovering a key reference shows what the key resolved to.
+//!
+//! The protocol is plain JSON-RPC with a `Content-Length` header so
+//! instead of pulling in a whole LSP framework we do it by hand with
+//! [serde_json].
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    ops::Range,
+    path::{Path, PathBuf}
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    compiler::CompilerToken,
+    config::{self, Config},
+    diagnostic::{Location, Severity},
+    lint,
+    metadata,
+    preprocessor::{preprocessor_string_tokenizer_recovering, Preprocessable, PreprocessableString, PreprocessorToken},
+    sigil::PreprocessorSigil
+};
+
+/// LSP error code for a method we don't know about.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Key names along side their resolved value (if they got resolved).
+type ResolvedKeys = Vec<(String, Option<String>)>;
+
+/// Everything we know about a opened document.
+struct Document {
+    text: String,
+    /// Resolved key values from the last time the document was valid
+    /// enough to preprocess the keys, kept around so completions still
+    /// work while the user is halfway through typing something.
+    keys: ResolvedKeys
+}
+
+/// Read a single JSON-RPC message, [None] on EOF.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+
+    let mut content_length: Option<usize> = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None)
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length") {
+            content_length = value.trim().parse().ok();
+        }
+    }
+
+    let Some(content_length) = content_length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Message without a Content-Length header"
+        ))
+    };
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+
+}
+
+/// Convert a byte offset into a LSP position (line, UTF-16 column).
+fn position_from_offset(text: &str, offset: usize) -> Value {
+
+    let offset = offset.min(text.len());
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+
+    json!({"line": line, "character": character})
+
+}
+
+/// Convert a LSP position (line, UTF-16 column) into a byte offset.
+fn offset_from_position(text: &str, position: &Value) -> Option<usize> {
+
+    let line = position.get("line")?.as_u64()? as usize;
+    let character = position.get("character")?.as_u64()? as usize;
+
+    let mut line_start = 0;
+    for _ in 0..line {
+        line_start += text[line_start..].find('\n')? + 1;
+    }
+
+    let mut utf16 = 0;
+    for (i, ch) in text[line_start..].char_indices() {
+        if utf16 >= character || ch == '\n' {
+            return Some(line_start + i)
+        }
+        utf16 += ch.len_utf16();
+    }
+
+    Some(text.len())
+
+}
+
+/// If `offset` is inside of a key reference `@{...}` return the
+/// byte range of the key name (without the sigils).
+fn key_reference_at(text: &str, offset: usize) -> Option<(usize, usize)> {
+
+    let start_sigil = PreprocessorSigil::TokenStart.sequence();
+    let open_sigil = PreprocessorSigil::KeyRefOpen.sequence();
+    let close_sigil = PreprocessorSigil::KeyRefClose.sequence();
+    let opening = format!("{start_sigil}{open_sigil}");
+
+    let line_start = text[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = text[offset..].find('\n').map(|i| offset + i).unwrap_or(text.len());
+
+    let key_start = text[line_start..offset].rfind(&opening)? + line_start + opening.len();
+    if text[key_start..offset].contains(&close_sigil) {
+        return None
+    }
+
+    let key_end = text[key_start..line_end]
+        .find(&close_sigil)
+        .map(|i| key_start + i)
+        .unwrap_or(offset);
+
+    Some((key_start, key_end))
+
+}
+
+/// Every tokenizer error in the generator strings along side the field
+/// it is in. Uses the recovering tokenizers so one bad token doesn't
+/// hide the others, the pipeline only ever reports the first one.
+fn token_errors(config: &Config) -> Vec<(String, String)> {
+
+    let mut fields: Vec<(String, PreprocessableString)> = vec![];
+    for (i, generator) in config.generator.iter().enumerate() {
+        fields.push((format!("generator.{i}.preamble"), generator.preamble.clone()));
+        fields.push((format!("generator.{i}.repeat"), generator.repeat.clone()));
+        fields.push((format!("generator.{i}.postamble"), generator.postamble.clone()));
+        fields.push((format!("generator.{i}.fallbacks.empty"), generator.fallbacks.empty.clone()));
+        fields.push((format!("generator.{i}.fallbacks.unparity"), generator.fallbacks.unparity.clone()));
+        for (remainder, unparity) in generator.fallbacks.unparity_remainder.iter() {
+            fields.push((format!("generator.{i}.fallbacks.unparity_remainder.{remainder}"), unparity.clone()));
+        }
+    }
+
+    let mut errors = vec![];
+    for (field, value) in fields {
+        let Ok(guard) = value.read() else {
+            continue
+        };
+        let Preprocessable::NotPreprocessed(raw) = &*guard else {
+            continue
+        };
+        let (tokens, preprocessor_errors) = preprocessor_string_tokenizer_recovering(raw);
+        errors.extend(preprocessor_errors.into_iter().map(|err| (field.clone(), err.to_string())));
+        // keys are stood in for by a `0` so the compiler tokens around
+        // them still tokenize.
+        let unescaped: String = tokens
+            .iter()
+            .map(|token| match token {
+                PreprocessorToken::Raw(text) => text.as_ref(),
+                PreprocessorToken::Key(_) => "0"
+            })
+            .collect();
+        let (_, compiler_errors) = CompilerToken::tokenize_recovering(&unescaped);
+        errors.extend(compiler_errors.into_iter().map(|err| (field.clone(), err.to_string())));
+    }
+    errors
+
+}
+
+/// The LSP range of the byte `span` of `text`.
+fn range_from_span(text: &str, span: Range<usize>) -> Value {
+    json!({
+        "start": position_from_offset(text, span.start),
+        "end": position_from_offset(text, span.end)
+    })
+}
+
+/// A diagnostic at `range` of the document.
+fn diagnostic(range: Value, severity: Severity, message: String) -> Value {
+    json!({
+        "range": range,
+        "severity": match severity {
+            Severity::Error => 1,
+            Severity::Warning => 2
+        },
+        "source": "xmva",
+        "message": message
+    })
+}
+
+/// The LSP range of `location`, from where it points to until the end
+/// of its line. Lints without a line of their own sit at the start of
+/// the document.
+fn range_from_location(text: &str, location: Option<&Location>) -> Value {
+
+    let Some(line) = location.and_then(|location| location.line) else {
+        return range_from_span(text, 0..0)
+    };
+    let line_start = match line {
+        0 | 1 => 0,
+        line => text
+            .match_indices('\n')
+            .nth(line - 2)
+            .map(|(i, _)| i + 1)
+            .unwrap_or(text.len())
+    };
+    let line_end = text[line_start..].find('\n').map(|i| line_start + i).unwrap_or(text.len());
+    let column = location.and_then(|location| location.column).unwrap_or(1);
+    let start = text[line_start..line_end]
+        .char_indices()
+        .nth(column - 1)
+        .map(|(i, _)| line_start + i)
+        .unwrap_or(line_start);
+
+    range_from_span(text, start..line_end)
+
+}
+
+/// The range of a preprocessor or compiler error, the generator it
+/// happened in if it says so (see [metadata::annotate]) and the
+/// whole document otherwise.
+fn error_range(text: &str, config: &Config, message: &str) -> Value {
+    metadata::annotated_generator(message)
+        .and_then(|i| config.metadata.span(&format!("generator.{i}")))
+        .map(|span| range_from_span(text, span))
+        .unwrap_or_else(|| range_from_span(text, 0..text.len()))
+}
+
+/// Parse and run the whole pipeline on `text` for diagnostics, lints
+/// included, returns the diagnostics and the keys (if we got far enough).
+///
+/// Relative paths are resolved from the directory of `path` like they
+/// are when generating, not from wherever the server was started.
+fn analyze(
+    path: &Path,
+    text: &str
+) -> (Vec<Value>, Option<ResolvedKeys>) {
+
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_owned());
+    let ((mut diagnostics, keys), emitted) = lint::collect(|| analyze_pipeline(&path, text));
+
+    diagnostics.extend(emitted.into_iter().map(|emitted| diagnostic(
+        range_from_location(text, emitted.location.as_ref()),
+        emitted.severity,
+        format!("[{}] {}", emitted.lint, emitted.message)
+    )));
+
+    (diagnostics, keys)
+
+}
+
+/// The errors of [analyze].
+fn analyze_pipeline(
+    path: &Path,
+    text: &str
+) -> (Vec<Value>, Option<ResolvedKeys>) {
+
+    let config = match Config::parse(text, path) {
+        Ok(config) => config,
+        Err(err) => {
+            let range = match &err {
+                config::Error::Toml { span: Some(span), .. } => range_from_span(text, span.clone()),
+                _ => range_from_span(text, 0..text.len())
+            };
+            return (vec![diagnostic(range, Severity::Error, err.to_string())], None)
+        }
+    };
+
+    let mut diagnostics = vec![];
+
+    for (field, message) in token_errors(&config) {
+        let range = match config.metadata.span(&field) {
+            Some(span) => range_from_span(text, span),
+            None => range_from_span(text, 0..text.len())
+        };
+        diagnostics.push(diagnostic(range, Severity::Error, message));
+    }
+
+    // `@{file:...}` is relative to the config, see [Metadata::directory].
+    let preprocessed = config.preprocess();
+    if let Err(err) = &preprocessed {
+        diagnostics.push(diagnostic(error_range(text, &config, &err.message), Severity::Error, err.to_string()));
+    }
+
+    let keys = config.load_preprocessable_key_name_pairs()
+        .ok()
+        .map(|keys| {
+            let mut keys: ResolvedKeys = keys
+                .into_iter()
+                .map(|(key, value)| (key, value.preprocessed()))
+                .collect();
+            keys.sort();
+            keys
+        });
+
+    if preprocessed.is_ok()
+        && let Err(err) = config.compile_and_assemble() {
+        diagnostics.push(diagnostic(error_range(text, &config, &err.message), Severity::Error, err.to_string()));
+    }
+
+    (diagnostics, keys)
+
+}
+
+/// The path of a `file://` uri, which has anything outside of plain
+/// ASCII percent-encoded as UTF-8 (`%20` for a space and so on).
+fn path_from_uri(uri: &str) -> PathBuf {
+
+    let encoded = uri.strip_prefix("file://").unwrap_or(uri);
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.bytes().enumerate();
+
+    while let Some((i, byte)) = bytes.next() {
+        match encoded.get(i + 1..i + 3).filter(|_| byte == b'%').and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(byte) => {
+                decoded.push(byte);
+                bytes.nth(1);
+            }
+            None => decoded.push(byte)
+        }
+    }
+
+    let decoded = String::from_utf8_lossy(&decoded).into_owned();
+    // `file:///C:/...` on windows.
+    match decoded.strip_prefix('/') {
+        Some(path) if cfg!(windows) && path.as_bytes().get(1) == Some(&b':') => PathBuf::from(path),
+        _ => PathBuf::from(decoded)
+    }
+
+}
+
+fn publish_diagnostics(
+    writer: &mut impl Write,
+    documents: &mut HashMap<String, Document>,
+    uri: &str,
+    text: String
+) -> io::Result<()> {
+
+    let (diagnostics, keys) = analyze(&path_from_uri(uri), &text);
+
+    let previous_keys = documents.remove(uri).map(|document| document.keys);
+    documents.insert(uri.to_owned(), Document {
+        text,
+        keys: keys.or(previous_keys).unwrap_or_default()
+    });
+
+    write_message(writer, &json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics}
+    }))
+
+}
+
+fn completion(documents: &HashMap<String, Document>, params: &Value) -> Option<Value> {
+
+    let document = documents.get(params["textDocument"]["uri"].as_str()?)?;
+    let offset = offset_from_position(&document.text, &params["position"])?;
+    key_reference_at(&document.text, offset)?;
+
+    let items: Vec<Value> = document.keys
+        .iter()
+        .map(|(key, value)| json!({
+            "label": key,
+            // Variable
+            "kind": 6,
+            "detail": value.clone().unwrap_or_default()
+        }))
+        .collect();
+
+    Some(json!(items))
+
+}
+
+fn hover(documents: &HashMap<String, Document>, params: &Value) -> Option<Value> {
+
+    let document = documents.get(params["textDocument"]["uri"].as_str()?)?;
+    let offset = offset_from_position(&document.text, &params["position"])?;
+    let (start, end) = key_reference_at(&document.text, offset)?;
+    let key = &document.text[start..end];
+
+    let (_, value) = document.keys.iter().find(|(k, _)| k == key)?;
+    let contents = match value {
+        Some(value) => format!("`{key}` → `{value}`"),
+        None => format!("`{key}` could not be resolved")
+    };
+
+    Some(json!({
+        "contents": {"kind": "markdown", "value": contents},
+        "range": {
+            "start": position_from_offset(&document.text, start),
+            "end": position_from_offset(&document.text, end)
+        }
+    }))
+
+}
+
+/// Run the language server until the client tells us to exit.
+pub fn run() -> io::Result<()> {
+
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+    let stdout = io::stdout();
+    let mut writer = stdout.lock();
+
+    let mut documents: HashMap<String, Document> = HashMap::new();
+
+    while let Some(message) = read_message(&mut reader)? {
+
+        let method = message["method"].as_str().unwrap_or_default();
+        let id = message.get("id").cloned();
+        let params = &message["params"];
+
+        log::debug!("LSP message `{method}`.");
+
+        let result = match method {
+            "initialize" => Some(json!({
+                "capabilities": {
+                    // Full document sync.
+                    "textDocumentSync": 1,
+                    "completionProvider": {
+                        "triggerCharacters": [PreprocessorSigil::KeyRefOpen.sequence()]
+                    },
+                    "hoverProvider": true
+                },
+                "serverInfo": {"name": "xmva", "version": env!("CARGO_PKG_VERSION")}
+            })),
+            "shutdown" => Some(Value::Null),
+            "exit" => return Ok(()),
+            "textDocument/didOpen" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                publish_diagnostics(&mut writer, &mut documents, uri, text.to_owned())?;
+                None
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                // Full sync means the last change holds the whole document.
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                    .unwrap_or_default();
+                publish_diagnostics(&mut writer, &mut documents, uri, text.to_owned())?;
+                None
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = params["textDocument"]["uri"].as_str() {
+                    documents.remove(uri);
+                }
+                None
+            }
+            "textDocument/completion" => Some(completion(&documents, params).unwrap_or(Value::Null)),
+            "textDocument/hover" => Some(hover(&documents, params).unwrap_or(Value::Null)),
+            _ => {
+                if let Some(id) = id {
+                    write_message(&mut writer, &json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {"code": METHOD_NOT_FOUND, "message": format!("Unknown method `{method}`")}
+                    }))?;
+                }
+                continue;
+            }
+        };
+
+        // Notifications don't get a response.
+        if let (Some(id), Some(result)) = (id, result) {
+            write_message(&mut writer, &json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": result
+            }))?;
+        }
+
+    }
+
+    Ok(())
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn key_reference_lookup() {
+
+        let text = "name = \"@{prefix}ERROR\"\nexpansion = \"@{emsg_pr";
+
+        // cursor in the middle of `prefix`
+        assert_eq!(key_reference_at(text, 12), Some((10, 16)));
+        // cursor after a finished reference
+        assert_eq!(key_reference_at(text, 19), None);
+        // unfinished reference at the end of the document
+        assert_eq!(key_reference_at(text, text.len()), Some((text.len() - 7, text.len())));
+
+    }
+
+    #[test]
+    fn position_round_trip() {
+
+        let text = "a = \"😳\"\nb = \"@{c}\"";
+        let position = position_from_offset(text, text.find("@").unwrap());
+
+        assert_eq!(position, json!({"line": 1, "character": 5}));
+        assert_eq!(offset_from_position(text, &position), text.find("@"));
+
+    }
+
+    #[test]
+    fn every_token_error() {
+
+        let text = concat!(
+            "[common]\nprefix = \"A_\"\nrepeats = 4\n",
+            "[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n",
+            "[[generator]]\nrepeat = \"$(0) $! \\\\@{prefix} @! $[,]\"\n"
+        );
+        let config = Config::parse(text, Path::new("a.xmva.toml")).unwrap();
+        let errors = token_errors(&config);
+
+        // `@!` and `$!`, the escaped `@` is fine.
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().all(|(field, _)| field == "generator.0.repeat"));
+        assert!(config.metadata.span("generator.0.repeat").is_some());
+
+        // fallbacks for a specific remainder are checked aswell.
+        let config = Config::parse(
+            &format!("{}[generator.fallbacks.unparity_remainder]\n1 = \"$<0>\"\n2 = \"$<0> $!\"\n", text.replace("varadict = 1", "varadict = 3")),
+            Path::new("a.xmva.toml")
+        ).unwrap();
+        let errors = token_errors(&config);
+        let field = "generator.0.fallbacks.unparity_remainder.2";
+        assert_eq!(errors.iter().filter(|(f, _)| f == field).count(), 1);
+        assert!(config.metadata.span(field).is_some());
+
+    }
+
+    #[test]
+    fn uri_decoding() {
+
+        assert_eq!(path_from_uri("file:///home/me/My%20Project/a.xmva.toml"), PathBuf::from("/home/me/My Project/a.xmva.toml"));
+        assert_eq!(path_from_uri("file:///tmp/%C5%A1ta.xmva.toml"), PathBuf::from("/tmp/šta.xmva.toml"));
+        // not a escape, kept as is.
+        assert_eq!(path_from_uri("file:///tmp/100%.xmva.toml"), PathBuf::from("/tmp/100%.xmva.toml"));
+        assert_eq!(path_from_uri("file:///tmp/%2"), PathBuf::from("/tmp/%2"));
+
+    }
+
+    #[test]
+    fn diagnostic_ranges() {
+
+        let directory = std::env::temp_dir().join(format!("xmva-lsp-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("license.h"), "/* MIT */").unwrap();
+        let path = directory.join("a.xmva.toml");
+
+        let core = "[common]\nprefix = \"A_\"\nrepeats = 4\n[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n";
+
+        // token errors point at the value they are in.
+        let text = format!("{core}[[generator]]\nrepeat = \"$(0) $!\"\n");
+        let (diagnostics, _) = analyze(&path, &text);
+        let start = text.find("\"$(0)").unwrap();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0]["range"], range_from_span(&text, start..text.len() - 1));
+        // compiling fails on it too, which points to the whole generator.
+        let generator = text.find("[[generator]]").unwrap()..text.len() - 1;
+        assert_eq!(diagnostics[1]["range"], range_from_span(&text, generator));
+
+        // `@{file:}` is relative to the document and not the server.
+        let text = format!("{core}[[generator]]\npreamble = \"@{{file:license.h}}\"\nrepeat = \"$(0)\"\n");
+        let (diagnostics, _) = analyze(&path, &text);
+        assert_eq!(diagnostics, Vec::<Value>::new());
+
+        // errors without a place of their own cover the whole document.
+        let text = format!("[preamble]\nraw = \"@{{file:missing.h}}\"\n{core}[[generator]]\nrepeat = \"$(0)\"\n");
+        let (diagnostics, _) = analyze(&path, &text);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["severity"], 1);
+        assert_eq!(diagnostics[0]["range"], json!({
+            "start": {"line": 0, "character": 0},
+            "end": {"line": 10, "character": 0}
+        }));
+
+        // lints are warnings from where they point to until the end of the line.
+        let text = format!("[lints]\nprefix_underscore = \"warn\"\n{}[[generator]]\nrepeat = \"$(0)\"\n", core.replace("\"A_\"", "\"A\""));
+        let (diagnostics, _) = analyze(&path, &text);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["severity"], 2);
+        assert!(diagnostics[0]["message"].as_str().unwrap().starts_with("[prefix_underscore] "));
+        let prefix = text.find("\"A\"").unwrap();
+        assert_eq!(diagnostics[0]["range"], range_from_span(&text, prefix..prefix + 3));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+    }
+
+}
@@ -129,20 +129,55 @@
 
 mod args;
 mod config;
+mod lower;
 mod sigil;
 
 mod preprocessor;
 mod compiler;
+mod backend;
+mod depfile;
+mod lsp;
+mod highlight;
+mod version;
+mod trace;
+mod metadata;
+mod sourcemap;
+mod verify;
+mod workspace;
+mod lint;
+mod plugin;
+mod transform;
+mod expand;
+mod graph;
+mod selftest;
+mod minify;
+mod stage;
+mod diagnostic;
+mod ide;
+mod explain;
+mod timings;
+mod testgen;
+mod preview;
 
-use std::{env, fs};
+use std::{
+    env, fs, process, thread,
+    io::{self, Read},
+    path::{self, Path, PathBuf},
+    sync::mpsc,
+    time::Duration
+};
 
-use clap::Parser;
-use args::Arguments;
-use config::Config;
+use clap::{CommandFactory, Parser};
+use args::{Arguments, Command, StdinFormat};
+use stage::Stage;
+use diagnostic::Location;
+use config::{Config, RepeatRange};
+use workspace::{CommonOverrides, Workspace};
 
 fn main() {
 
     let args = Arguments::parse();
+    diagnostic::set_format(args.message_format);
     if args.logging {
         env_logger::builder()
             .filter_level(log::LevelFilter::Trace)
@@ -150,58 +185,1006 @@ fn main() {
         log::info!("Logs are enabled.");
     }
 
-    log::info!("Loaded arguments, input file is {:?}", args.input);
-    if args.output.is_some() {
-        log::info!("Specified a external output file {:?}", args.output.unwrap())
+    match args.command {
+        Some(Command::Lsp) => {
+            if let Err(err) = lsp::run() {
+                eprintln!("Language server stopped: {err}");
+                panic!()
+            }
+        }
+        Some(Command::Highlight { ref field }) => {
+            match highlight::highlight(&required_input(&args), field) {
+                Ok(highlighted) => println!("{highlighted}"),
+                Err(err) => {
+                    eprintln!("{err}");
+                    panic!()
+                }
+            }
+        }
+        Some(Command::Graph { format }) => match Config::load(&required_input(&args)) {
+            Ok(config) => print!("{}", graph::Graph::new(&config).render(format)),
+            Err(err) => {
+                eprintln!("{err}");
+                panic!()
+            }
+        },
+        Some(Command::Tokens { ref compiler, ref preprocessor }) => {
+            if !tokens(compiler.as_deref(), preprocessor.as_deref()) {
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Verify) => verify(&required_input(&args)),
+        Some(Command::Build { ref workspace, jobs, ref member }) => 
+            build(&args, workspace.as_deref(), jobs, member.as_deref()),
+        Some(Command::EmitTests { framework }) => emit_tests(&args, framework),
+        Some(Command::Clean { ref workspace }) => clean(&args, workspace.as_deref()),
+        Some(Command::Preview { ref generator, groups }) => preview(&args, generator, groups),
+        Some(Command::SelfTest) => if !selftest::run() {
+            std::process::exit(1);
+        },
+        None if args.version => println!("{}", version::report(args.verbose)),
+        None if args.list_generators => list_generators(&args),
+        None => {
+            // clap makes sure we have a input when there is no subcommand.
+            let input = match args.stdin_format {
+                Some(_) => PathBuf::from(STDIN_CONFIG),
+                None => args.input.clone().expect("Missing input file.")
+            };
+            if generate(&args, &input, None) {
+                std::process::exit(STALE_EXIT_CODE);
+            }
+        }
+    }
+
+}
+
+/// Subcommands don't require a input file on their own (the lsp doesn't
+/// need one), the ones that do go through here.
+fn required_input(args: &Arguments) -> PathBuf {
+    match &args.input {
+        Some(input) => input.clone(),
+        None => Arguments::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "this subcommand requires a config file given with --input"
+            )
+            .exit()
+    }
+}
+
+/// Print the tokens of the `compiler` or `preprocessor` string (clap
+/// makes sure there is exactly one) one per line and the string
+/// untokenized from them. Every error is printed, not just the first.
+/// 
+/// Returns if it tokenized without errors.
+fn tokens(compiler: Option<&str>, preprocessor: Option<&str>) -> bool {
+
+    let (tokens, untokenized, errors): (Vec<String>, String, Vec<String>) = match (compiler, preprocessor) {
+        (Some(s), _) => {
+            let (tokens, errors) = compiler::CompilerToken::tokenize_recovering(s);
+            (
+                tokens.iter().map(|token| format!("{token:?}")).collect(),
+                tokens.iter().map(compiler::CompilerToken::untokenize).collect(),
+                errors.iter().map(ToString::to_string).collect()
+            )
+        }
+        (None, Some(s)) => {
+            let (tokens, errors) = preprocessor::preprocessor_string_tokenizer_recovering(s);
+            (
+                tokens.iter().map(|token| format!("{token:?}")).collect(),
+                tokens.iter().map(preprocessor::PreprocessorToken::untokenize).collect(),
+                errors.iter().map(ToString::to_string).collect()
+            )
+        }
+        (None, None) => unreachable!()
+    };
+
+    for token in tokens {
+        println!("{token}");
+    }
+    println!("untokenized: {untokenized}");
+    for error in errors.iter() {
+        eprintln!("{error}");
+    }
+    errors.is_empty()
+
+}
+
+/// Print the freshness of every output of the config at `input`.
+fn verify(input: &Path) {
+
+    let outputs = match verify::verify(input) {
+        Ok(outputs) => outputs,
+        Err(err) => {
+            eprintln!("{err}");
+            panic!()
+        }
+    };
+
+    let mut stale = false;
+    for (output, freshness) in outputs {
+        stale |= freshness != verify::Freshness::Fresh;
+        println!("{}: {freshness}", output.display());
+    }
+    if stale {
+        std::process::exit(STALE_EXIT_CODE);
+    }
+
+}
+
+/// Print the test file of the `--input` config, including its output
+/// (or `--output`) by file name.
+fn emit_tests(args: &Arguments, framework: testgen::Framework) {
+
+    let mut config = match Config::load(&required_input(args)) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            panic!()
+        }
+    };
+    if let Err(err) = config.apply_overrides(args.repeats, args.prefix.clone()) {
+        fail(&err, err.location())
+    }
+
+    let output = args.output.clone().or(config.common.output.clone()).unwrap_or_default();
+    let header = output.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+    match config.preprocess().map_err(|err| err.to_string()).and_then(|_|
+        testgen::emit(&config, &header, framework).map_err(|err| err.to_string())
+    ) {
+        Ok(tests) => print!("{tests}"),
+        Err(err) => {
+            eprintln!("{err}");
+            panic!()
+        }
+    }
+
+}
+
+fn preview(args: &Arguments, generator: &str, groups: usize) {
+
+    // the repeats are picked to fit the groups.
+    if args.repeats.is_some() {
+        Arguments::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--repeats can't be used with preview, it generates just enough repeats for --groups"
+            )
+            .exit()
+    }
+
+    let mut config = match Config::load(&required_input(args)) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            panic!()
+        }
+    };
+    if let Err(err) = config.apply_overrides(None, args.prefix.clone()) {
+        fail(&err, err.location())
+    }
+
+    match preview::preview(config, generator, groups) {
+        Ok(preview) => print!("{preview}"),
+        Err(err) => {
+            eprintln!("{err}");
+            panic!()
+        }
+    }
+
+}
+
+/// Remove the outputs of the `--input` config or of every member of the
+/// workspace at `path`.
+fn clean(args: &Arguments, path: Option<&Path>) {
+
+    let configs = match &args.input {
+        Some(input) => vec![input.clone()],
+        None => match Workspace::load(path.unwrap_or(Path::new(workspace::WORKSPACE_FILE))) {
+            Ok(workspace) => workspace.member_paths(),
+            Err(err) => {
+                eprintln!("{err}");
+                panic!()
+            }
+        }
+    };
+
+    for config_path in configs {
+
+        let config = match Config::load(&config_path) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("{err}");
+                panic!()
+            }
+        };
+
+        if let Err(err) = clean_outputs(&config_path, &config) {
+            eprintln!("{err}");
+            panic!()
+        }
+
+    }
+
+}
+
+/// Remove every output of the config at `path` that carries the
+/// [compiler::SENTINEL], returns the removed ones.
+fn clean_outputs(path: &Path, config: &Config) -> Result<Vec<PathBuf>, String> {
+
+    let mut removed = vec![];
+    for output in verify::outputs(path, config) {
+        let Ok(contents) = fs::read_to_string(&output) else {
+            continue
+        };
+        if !contents.lines().any(|line| line == compiler::SENTINEL) {
+            println!("{}: kept, it wasn't generated by xmva", output.display());
+            continue
+        }
+        fs::remove_file(&output).map_err(|e| format!("Failed to remove {}: {e}", output.display()))?;
+        println!("{}: removed", output.display());
+        removed.push(output);
+    }
+
+    Ok(removed)
+
+}
+
+/// Generate every member of the workspace at `path`, or only `member`.
+fn build(args: &Arguments, path: Option<&Path>, jobs: usize, member: Option<&Path>) {
+
+    // SAFETY: nothing has been opened yet, so the descriptors named in
+    // MAKEFLAGS are still the ones make gave us.
+    let jobserver = unsafe { jobserver::Client::from_env() };
+
+    let path = path.unwrap_or(Path::new(workspace::WORKSPACE_FILE));
+    let workspace = match Workspace::load(path) {
+        Ok(workspace) => workspace,
+        Err(err) => {
+            eprintln!("{err}");
+            panic!()
+        }
+    };
+
+    if let Some(member) = member {
+        if generate(args, member, Some(&workspace.common)) {
+            std::process::exit(STALE_EXIT_CODE);
+        }
+        return
+    }
+
+    let (order, dependencies) = match workspace.build_order()
+        .and_then(|order| Ok((order, workspace.dependencies()?))) {
+        Ok(order) => order,
+        Err(err) => {
+            eprintln!("{err}");
+            panic!()
+        }
+    };
+    let members = workspace.member_paths();
+
+    // we always hold one token ourselves, so `-j N` is N - 1 more.
+    let jobserver = jobserver.or_else(|| (jobs > 1).then(|| 
+        jobserver::Client::new(jobs - 1)
+            .expect("Failed to create a jobserver.")
+    ));
+
+    let stale = match jobserver {
+        Some(jobserver) => {
+            let exe = env::current_exe()
+                .expect("Failed to get the path of the xmva executable.");
+            let job = |member: &Path| {
+                let mut command = process::Command::new(&exe);
+                command.args(env::args_os().skip(1)).arg("--member").arg(member);
+                command
+            };
+            match build_parallel(&members, order, &dependencies, jobserver, job) {
+                Ok(stale) => stale,
+                Err(failed) => {
+                    for member in failed {
+                        eprintln!("Failed to generate workspace member {}", members[member].display());
+                    }
+                    panic!();
+                }
+            }
+        }
+        None => {
+            // generating moves into the directory of the config.
+            let current_dir = env::current_dir()
+                .expect("Failed to get the current directory.");
+            let mut stale = false;
+            for member in order {
+                log::info!("Building workspace member {}", members[member].display());
+                stale |= generate(args, &members[member], Some(&workspace.common));
+                env::set_current_dir(&current_dir)
+                    .unwrap_or_else(|_| panic!("Failed to change the current PWD back to {:?}", current_dir));
+            }
+            stale
+        }
+    };
+
+    if stale {
+        std::process::exit(STALE_EXIT_CODE);
+    }
+
+}
+
+/// How often [build_parallel] checks on its jobs.
+const JOB_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Generate the members in `order` each in its own process started by
+/// `job` (generating changes the current directory, so threads are out),
+/// starting a member as soon as everything it depends on is done and
+/// `jobserver` gives us a token for it. The first job runs on the token
+/// we implicitly hold.
+/// 
+/// Returns if a output is stale, see [generate], or the members that
+/// failed to generate.
+fn build_parallel(
+    members: &[PathBuf],
+    mut pending: Vec<usize>,
+    dependencies: &[Vec<usize>],
+    jobserver: jobserver::Client,
+    job: impl Fn(&Path) -> process::Command
+) -> Result<bool, Vec<usize>> {
+
+    let (sender, tokens) = mpsc::channel();
+    let helper = jobserver
+        .into_helper_thread(move |token| { let _ = sender.send(token); })
+        .expect("Failed to start the jobserver helper thread.");
+
+    let mut done = vec![false; members.len()];
+    let mut running: Vec<(usize, process::Child, Option<jobserver::Acquired>)> = vec![];
+    let mut acquired: Vec<jobserver::Acquired> = vec![];
+    let mut requested = 0;
+    let mut stale = false;
+    let mut failed: Vec<usize> = vec![];
+
+    while !running.is_empty() || (failed.is_empty() && !pending.is_empty()) {
+
+        // dropping the token of a finished job gives it back.
+        let mut i = 0;
+        while i < running.len() {
+            match running[i].1.try_wait() {
+                Ok(Some(status)) => {
+                    let (member, _, _) = running.swap_remove(i);
+                    done[member] = true;
+                    match status.code() {
+                        Some(0) => {}
+                        Some(STALE_EXIT_CODE) => stale = true,
+                        _ => failed.push(member)
+                    }
+                }
+                Ok(None) => i += 1,
+                Err(e) => {
+                    eprintln!("Failed to wait on the job of {}: {e}", members[running[i].0].display());
+                    panic!();
+                }
+            }
+        }
+
+        for token in tokens.try_iter() {
+            requested -= 1;
+            match token {
+                Ok(token) => acquired.push(token),
+                Err(e) => {
+                    eprintln!("Failed to get a token from the jobserver: {e}");
+                    panic!();
+                }
+            }
+        }
+
+        // a failed member stops us from starting new ones, the running
+        // ones are still waited on.
+        let ready = |pending: &[usize], done: &[bool]| pending
+            .iter()
+            .position(|member| dependencies[*member].iter().all(|dependency| done[*dependency]));
+        while failed.is_empty() && let Some(position) = ready(&pending, &done) {
+            let token = if running.is_empty() {
+                None
+            } else {
+                match acquired.pop() {
+                    Some(token) => Some(token),
+                    None => break
+                }
+            };
+            let member = pending.remove(position);
+            log::info!("Building workspace member {}", members[member].display());
+            let child = job(&members[member])
+                .spawn()
+                .unwrap_or_else(|e| panic!("Failed to start the job of {}: {e}", members[member].display()));
+            running.push((member, child, token));
+        }
+
+        let waiting = pending
+            .iter()
+            .filter(|member| dependencies[**member].iter().all(|dependency| done[*dependency]))
+            .count();
+        while failed.is_empty() && requested + acquired.len() < waiting {
+            helper.request_token();
+            requested += 1;
+        }
+
+        thread::sleep(JOB_POLL_INTERVAL);
+
+    }
+
+    if !failed.is_empty() {
+        return Err(failed)
+    }
+
+    Ok(stale)
+
+}
+
+/// Exit code of `--check` (and `verify`) when a output on disk is stale, so that CI can
+/// tell it apart from a broken config (which panics).
+pub const STALE_EXIT_CODE: i32 = 4;
+
+/// Longest repeat pattern preview printed by [list_generators].
+const PREVIEW_LENGTH: usize = 60;
+
+/// Print every generator (after lowering) with its name, repeats, output
+/// and a preview of the repeat pattern.
+fn list_generators(args: &Arguments) {
+
+    let mut config = match Config::load(&required_input(args)) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}"); 
+            panic!()
+        }
+    };
+
+    if let Err(err) = config.apply_overrides(args.repeats, args.prefix.clone()) {
+        fail(&err, err.location())
+    }
+
+    let main_output = config.common.output.clone().unwrap_or_default();
+
+    for (i, generator) in config.generator.iter().enumerate() {
+
+        let name = generator.name.clone().unwrap_or_else(|| "-".to_owned());
+        let output = generator.output.as_ref().unwrap_or(&main_output);
+        let mut preview = match lower::raw(&generator.repeat) {
+            Ok(repeat) => repeat.split_whitespace().collect::<Vec<&str>>().join(" "),
+            Err(err) => format!("<{err}>")
+        };
+        if preview.chars().count() > PREVIEW_LENGTH {
+            preview = preview.chars().take(PREVIEW_LENGTH - 3).collect::<String>() + "...";
+        }
+
+        // what the generator handles, not what the picker goes up to.
+        let range = generator.repeats.unwrap_or(RepeatRange { min: 0, max: None });
+        let repeats = format!("{}-{}", range.min, range.last(config.common.repeats));
+
+        println!("{i:>3}  {name:<16} repeats {repeats:<6} -> {:<24} {preview}",
+            output.display()
+        );
+
+    }
+
+}
+
+/// Path a config read from stdin pretends to be at, relative paths in it
+/// end up relative to the current directory.
+const STDIN_CONFIG: &str = "stdin.xmva.toml";
+
+/// Read a whole config in `format` from `stdin`, `path` is where it
+/// pretends to be.
+fn read_stdin(mut stdin: impl Read, format: StdinFormat, path: &Path) -> Result<Config, config::Error> {
+
+    let mut bytes = vec![];
+    stdin
+        .read_to_end(&mut bytes)
+        .map_err(|err| config::Error::io(config::Operation::ReadStdin, path, err))?;
+    let contents = config::decode(bytes, path)?;
+
+    match format {
+        StdinFormat::Toml => Config::parse(&contents, path),
+        StdinFormat::Json => Config::parse_json(&contents, path)
+    }
+
+}
+
+/// `--stop-after`, dump `config` as it is after `stage` next to its
+/// output. Never stale since nothing was checked.
+fn write_dump(config: &Config, stage: Stage, baked: Option<compiler::Baked>) -> bool {
+
+    let mut dump_path = config.common.output.clone().unwrap_or_default().into_os_string();
+    dump_path.push(".");
+    dump_path.push(stage.extension());
+    let dump_path = PathBuf::from(dump_path);
+
+    let dump = serde_json::to_string_pretty(&stage::dump(config, stage, baked))
+        .expect("Failed to serialize the stage dump.");
+    if let Err(e) = fs::write(&dump_path, dump) {
+        eprintln!("Failed to write the stage dump to {}: {e}", dump_path.display());
+        panic!();
+    }
+    log::info!("Stopped after {stage:?}, dump written to {}", dump_path.display());
+    false
+
+}
+
+/// Report a error of the config at `location` (as a annotation with
+/// `--message-format`) and stop.
+fn fail(err: &dyn std::fmt::Display, location: Location) -> ! {
+    if !diagnostic::annotate(diagnostic::Severity::Error, "xmva", &err.to_string(), Some(&location)) {
+        eprintln!("{err}");
+    }
+    panic!()
+}
+
+/// The default command, generate a header from the config at `input`
+/// with the `workspace` overrides (if it's a workspace member) applied
+/// before the command line ones.
+/// 
+/// Returns if a output is stale, only ever with `--check`.
+fn generate(args: &Arguments, input: &Path, workspace: Option<&CommonOverrides>) -> bool {
+
+    log::info!("Loaded arguments, input file is {:?}", input);
+    if let Some(output) = &args.output {
+        log::info!("Specified a external output file {:?}", output)
     }    
 
-    let config = match Config::load(args.input.as_path()) {
+    let mut timings = timings::Timings::new(args.timings);
+    lint::reset_denied();
+    let loaded = match args.stdin_format {
+        Some(format) => read_stdin(io::stdin(), format, input),
+        None => Config::load(input)
+    };
+    let mut config = match loaded {
         Ok(config) => {
             log::info!("Loaded config.");
             config
         },
+        Err(err) => fail(&err, err.location())
+    };
+    timings.phase("load", None);
+
+    if let Some(workspace) = workspace {
+        workspace.apply(&mut config);
+    }
+    if let Err(err) = config.apply_overrides(args.repeats, args.prefix.clone()) {
+        fail(&err, err.location())
+    }
+
+    // used configs are generated on their own, our output includes theirs.
+    for used in config.used.iter().filter(|used| !used.output.exists()) {
+        lint::emit(lint::Lint::MissingUsedOutput, &format!(
+            "{} (the output of {}) doesn't exist yet, generate it aswell.",
+            used.output.display(),
+            used.config.display()
+        ));
+    }
+
+    // the depfile path is relative to where we were called from,
+    // not to the config.
+    let depfile_path = args.depfile.as_ref().map(|depfile| 
+        path::absolute(depfile)
+            .expect("Failed to get absolute path from depfile.")
+    );
+
+    // relative to where we were called from aswell.
+    let resumed = args.resume_from.as_ref().map(|path| match stage::Dump::read(path) {
+        Ok(dump) => (path::absolute(path).unwrap_or_else(|_| path.clone()), dump),
         Err(err) => {
-            eprintln!("{err}"); 
+            eprintln!("{err}");
             panic!()
         }
-    };
+    });
+    let resumed_stage = resumed.as_ref().map(|(_, dump)| dump.stage);
+    if let (Some(stop), Some(resumed)) = (args.stop_after, resumed_stage)
+        && stop <= resumed {
+        eprintln!("Nothing to do, the dump is already past {stop:?} since it was dumped after {resumed:?}.");
+        panic!();
+    }
+    let done = |stage: Stage| resumed_stage.is_some_and(|resumed| resumed >= stage);
 
-    let output = args.input.as_path();
-    let canon_output = output.canonicalize()
-        .expect("Failed to get absolute path from output file.");
+    let output = input;
+    // there is no file behind a config from stdin.
+    let canon_output = match args.stdin_format {
+        Some(_) => path::absolute(output),
+        None => output.canonicalize()
+    }.expect("Failed to get absolute path from output file.");
     let current_dir = canon_output.parent();
 
-    env::set_current_dir(current_dir.clone().unwrap())
-        .expect(format!(
-            "Failed to change the current PWD to {:?}",
-            current_dir
-        ).as_str());
+    // `common.output` is relative to the config, made absolute while
+    // we still know where everything was relative to.
+    if let (Some(output), Some(current_dir)) = (config.common.output.as_mut(), current_dir) {
+        *output = current_dir.join(&*output);
+    }
 
-    match config.preprocess() {
-        Ok(_) => log::info!("Finished preprocessing."),
-        Err(err) => {
-            eprintln!("{err}"); 
-            panic!()
+    env::set_current_dir(current_dir.unwrap())
+        .unwrap_or_else(|_| panic!("Failed to change the current PWD to {:?}",
+            current_dir));
+
+    match &resumed {
+        Some((path, dump)) => match dump.restore(&config) {
+            Ok(_) => log::info!("Resuming after {:?} from {}.", dump.stage, path.display()),
+            Err(err) => {
+                eprintln!("{err}");
+                panic!()
+            }
+        }
+        None => match config.preprocess() {
+            Ok(_) => log::info!("Finished preprocessing."),
+            Err(err) => fail(&err, Location::file(input))
         }
     }
+    timings.phase("preprocess", None);
+
+    if args.explain_config {
+        print!("{}", explain::explain(&config));
+        return false
+    }
 
-    let output = match config.compile_and_assemble() {
+    if args.stop_after == Some(Stage::Preprocess) {
+        return write_dump(&config, Stage::Preprocess, None)
+    }
+
+    if !done(Stage::SurfaceCompile)
+        && let Err(err) = config.surface_compile() {
+        fail(&err, Location::file(input))
+    }
+    timings.phase("surface compile", None);
+    if args.stop_after == Some(Stage::SurfaceCompile) {
+        return write_dump(&config, Stage::SurfaceCompile, None)
+    }
+
+    let baked = match resumed.as_ref().and_then(|(_, dump)| dump.baked.clone()) {
+        Some(baked) => Ok(baked),
+        None => config.generate_repeats_with(|i, bytes|
+            timings.phase(format!("repeat generate (generator {i})"), Some(bytes))
+        )
+    };
+    let baked = match baked {
+        Ok(baked) => baked,
+        Err(err) => fail(&err, Location::file(input))
+    };
+    if args.stop_after == Some(Stage::RepeatGenerate) {
+        return write_dump(&config, Stage::RepeatGenerate, Some(baked))
+    }
+
+    let mut output = match config.assemble(&baked) {
         Ok(output) => {
             log::info!("Finished compiling and assembling.");
             output
         },
-        Err(err) => {
-            eprintln!("{err}");
-            panic!()
-        }
+        Err(err) => fail(&err, Location::file(input))
     };
+    timings.phase("assemble", Some(output.len()));
+
+    if args.minify {
+        let outputs = std::iter::once(&mut output.main)
+            .chain(output.routed.iter_mut().map(|(_, routed)| routed));
+        for mapped in outputs {
+            mapped.contents = minify::minify(&mapped.contents);
+        }
+        timings.phase("minify", Some(output.len()));
+    }
 
-    let output_path = &config.common.output.unwrap();
-    if let Err(e) = fs::write(output_path, &output) {
-        eprintln!("Failed to write output to {}: {e}", output_path.display());
+    let output_path = &config.common.output.clone().unwrap();
+
+    let outputs = std::iter::once((output_path, &output.main))
+        .chain(output.routed.iter().map(|(path, routed)| (path, routed)));
+    for (path, mapped) in outputs.filter(|(_, mapped)| mapped.contents.len() > lint::LARGE_OUTPUT_BYTES) {
+        lint::emit(lint::Lint::LargeOutput, &format!(
+            "{} is {} bytes, lower `common.repeats` if it doesn't need to be this big.",
+            path.display(),
+            mapped.contents.len()
+        ));
+    }
+    if let Some((message, location)) = oversized_output(&config, output_path, &output, args.max_output_bytes, input) {
+        fail(&message, location)
+    }
+
+    if lint::denied() > 0 {
+        eprintln!("Not generating {} since {} denied lint(s) were reported.",
+            output_path.display(),
+            lint::denied()
+        );
         panic!();
+    }
+
+    if args.check {
+        let mut stale = false;
+        let outputs = std::iter::once((output_path, &output.main))
+            .chain(output.routed.iter().map(|(path, routed)| (path, routed)));
+        for (path, mapped) in outputs {
+            match fs::read_to_string(path) {
+                Ok(existing) if existing == mapped.contents => {
+                    println!("{}: up to date", path.display())
+                }
+                Ok(_) => {
+                    stale = true;
+                    println!("{}: stale", path.display())
+                }
+                Err(_) => {
+                    stale = true;
+                    println!("{}: missing", path.display())
+                }
+            }
+        }
+        timings.phase("check", Some(output.len()));
+        report_timings(&timings);
+        return stale
+    }
+    // a typo in `common.output` shouldn't be able to eat a hand written file.
+    if !args.force {
+        let outputs = std::iter::once(output_path)
+            .chain(output.routed.iter().map(|(path, _)| path));
+        for path in outputs {
+            if let Ok(existing) = fs::read_to_string(path)
+                && !existing.lines().any(|line| line == compiler::SENTINEL) {
+                eprintln!("Refusing to overwrite {} since it wasn't generated by xmva (no `{}` line), use --force to overwrite it anyway.",
+                    path.display(),
+                    compiler::SENTINEL
+                );
+                panic!();
+            }
+        }
+    }
+
+    if let Err(e) = fs::write(output_path, &output.main.contents) {
+        let err = config::Error::io(config::Operation::WriteOutput, output_path, e)
+            .labeled(&config.metadata, "common.output");
+        fail(&err, err.location())
     } else {
         log::info!("Output written to {}", output_path.display());
     }
 
-}
\ No newline at end of file
+    for (routed_path, routed) in output.routed.iter() {
+        // the generator the routed output is set in.
+        let field = config.generator
+            .iter()
+            .position(|generator| generator.output.as_ref().is_some_and(|output| routed_path.ends_with(output)))
+            .map(|i| format!("generator.{i}.output"))
+            .unwrap_or_default();
+        if let Some(parent) = routed_path.parent()
+            && let Err(e) = fs::create_dir_all(parent) {
+            let err = config::Error::io(config::Operation::CreateDir, parent, e)
+                .labeled(&config.metadata, &field);
+            fail(&err, err.location())
+        }
+        if let Err(e) = fs::write(routed_path, &routed.contents) {
+            let err = config::Error::io(config::Operation::WriteOutput, routed_path, e)
+                .labeled(&config.metadata, &field);
+            fail(&err, err.location())
+        } else {
+            log::info!("Routed output written to {}", routed_path.display());
+        }
+    }
+    timings.phase("write", Some(output.len()));
+
+    if args.source_map {
+        let outputs = std::iter::once((output_path, &output.main))
+            .chain(output.routed.iter().map(|(path, routed)| (path, routed)));
+        for (path, mapped) in outputs {
+            let mut map_path = path.clone().into_os_string();
+            map_path.push(".map");
+            let map_path = PathBuf::from(map_path);
+            if let Err(e) = fs::write(&map_path, mapped.to_json()) {
+                eprintln!("Failed to write source map to {}: {e}", map_path.display());
+                panic!();
+            } else {
+                log::info!("Source map written to {}", map_path.display());
+            }
+        }
+    }
+
+    if args.ide_metadata {
+        let ide_path = ide::path(output_path);
+        let exported = ide::export(&config)
+            .map(|ide| serde_json::to_string_pretty(&ide).expect("Failed to serialize the IDE metadata."));
+        match exported.map(|json| fs::write(&ide_path, json)) {
+            Ok(Ok(_)) => log::info!("IDE metadata written to {}", ide_path.display()),
+            Ok(Err(e)) => {
+                eprintln!("Failed to write IDE metadata to {}: {e}", ide_path.display());
+                panic!();
+            }
+            Err(err) => fail(&err, Location::file(input))
+        }
+    }
+
+    if let Some(format) = args.trace_expansion {
+        let mut trace_path = output_path.clone().into_os_string();
+        trace_path.push(".");
+        trace_path.push(format.extension());
+        let trace_path = PathBuf::from(trace_path);
+        if let Err(e) = fs::write(&trace_path, trace::report(&config, format)) {
+            eprintln!("Failed to write expansion trace to {}: {e}", trace_path.display());
+            panic!();
+        } else {
+            log::info!("Expansion trace written to {}", trace_path.display());
+        }
+    }
+
+    if let Some(depfile_path) = depfile_path {
+        // the routed outputs get rebuilt by the same rule as the main one.
+        let targets: Vec<PathBuf> = verify::outputs(input, &config)
+            .iter()
+            .map(|output| path::absolute(output).expect("Failed to get absolute path from output file."))
+            .collect();
+        // the config itself and anything it pulls in from other files.
+        let mut dependencies = vec![];
+        if args.stdin_format.is_none() {
+            dependencies.push(canon_output.clone());
+        }
+        dependencies.extend(config.inputs());
+        dependencies.extend(resumed.map(|(path, _)| path));
+        if let Err(e) = depfile::write(&depfile_path, &targets, &dependencies) {
+            eprintln!("Failed to write depfile to {}: {e}", depfile_path.display());
+            panic!();
+        } else {
+            log::info!("Depfile written to {}", depfile_path.display());
+        }
+    }
+
+    report_timings(&timings);
+    false
+
+}
+
+/// The error for the first of the outputs that is over the limit of
+/// `--max-output-bytes` or `common.max_output_bytes`, [None] if there
+/// is no limit or everything fits.
+fn oversized_output(
+    config: &Config,
+    output_path: &Path,
+    output: &compiler::Assembled,
+    max_output_bytes: Option<usize>,
+    input: &Path
+) -> Option<(String, Location)> {
+
+    // `--max-output-bytes` wins over the config, only the config has a
+    // field to point at.
+    let (limit, field) = max_output_bytes
+        .map(|limit| (limit, None))
+        .or(config.common.max_output_bytes.map(|limit| (limit, Some("common.max_output_bytes"))))?;
+
+    let mut outputs = std::iter::once((output_path, &output.main))
+        .chain(output.routed.iter().map(|(path, routed)| (path.as_path(), routed)));
+    let (path, mapped) = outputs.find(|(_, mapped)| mapped.contents.len() > limit)?;
+
+    let mut message = format!(
+        "{} would be {} bytes which is over the limit of {limit}, nothing was written. Lower `common.repeats` or raise the limit.",
+        path.display(),
+        mapped.contents.len()
+    );
+    let location = match field {
+        Some(field) => {
+            if let Some(snippet) = config.metadata.label(field, "the limit") {
+                message.push('\n');
+                message.push_str(&snippet);
+            }
+            config.metadata.location(field)
+        }
+        None => Location::file(input)
+    };
+
+    Some((message, location))
+
+}
+
+/// `--timings`, on stderr so it doesn't end up in anything piped.
+fn report_timings(timings: &timings::Timings) {
+    if let Some(report) = timings.report() {
+        eprint!("{report}");
+    }
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn failing_member() {
+
+        let members: Vec<PathBuf> = ["a", "b", "c", "d"].iter().map(PathBuf::from).collect();
+        // `c` depends on the failing `b`, `d` on nothing.
+        let dependencies = vec![vec![], vec![0], vec![1], vec![]];
+        let started = std::cell::RefCell::new(vec![]);
+        let job = |member: &Path| {
+            started.borrow_mut().push(member.to_owned());
+            process::Command::new(if member == Path::new("b") { "false" } else { "true" })
+        };
+
+        let jobserver = jobserver::Client::new(2).unwrap();
+        let result = build_parallel(&members, vec![0, 1, 2, 3], &dependencies, jobserver, job);
+
+        assert_eq!(result, Err(vec![1]));
+        assert!(!started.borrow().contains(&PathBuf::from("c")));
+
+        let jobserver = jobserver::Client::new(2).unwrap();
+        let result = build_parallel(&members, vec![0, 1, 2, 3], &vec![vec![]; 4], jobserver, |_| process::Command::new("true"));
+        assert_eq!(result, Ok(false));
+
+    }
+
+    #[test]
+    fn stdin_configs() {
+
+        let toml = "[common]\nprefix = \"A_\"\nrepeats = 3\n\n[core]\nxmva = \"A\"\n\
+            args = [{key = \"n\", name = \"n\"}, {varadict = 1}]\n\n[[generator]]\nrepeat = \"$(0)$[,]\"\n";
+        let json = r#"{
+            "common": {"prefix": "A_", "repeats": 3},
+            "core": {"xmva": "A", "args": [{"key": "n", "name": "n"}, {"varadict": 1}]},
+            "generator": [{"repeat": "$(0)$[,]"}]
+        }"#;
+        let assemble = |contents: &str, format: StdinFormat| {
+            let config = read_stdin(contents.as_bytes(), format, Path::new(STDIN_CONFIG)).unwrap();
+            config.preprocess().unwrap();
+            config.compile_and_assemble().unwrap().main.contents
+        };
+
+        let from_toml = assemble(toml, StdinFormat::Toml);
+        assert!(from_toml.contains("#define A___ARGS__0_2(n, __0__, __1__) __0__,__1__\n"));
+        // the hash differs since the TOML is written out differently.
+        let without_hash = |assembled: String| assembled
+            .lines()
+            .filter(|line| !line.starts_with("/* xmva config hash"))
+            .collect::<Vec<&str>>()
+            .join("\n");
+        assert_eq!(without_hash(from_toml), without_hash(assemble(json, StdinFormat::Json)));
+
+        let err = read_stdin("{\"common\": ".as_bytes(), StdinFormat::Json, Path::new(STDIN_CONFIG)).unwrap_err();
+        assert!(err.to_string().contains("invalid JSON config"));
+
+    }
+
+    #[test]
+    fn output_size_limit() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 3\nmax_output_bytes = 100\n\n[core]\nxmva = \"A\"\n\
+            args = [{key = \"n\", name = \"n\"}, {varadict = 1}]\n\n[[generator]]\nrepeat = \"$(0)$[,]\"\n";
+        let input = Path::new("limit.xmva.toml");
+        let config = Config::parse(contents, input).unwrap();
+        config.preprocess().unwrap();
+        let output = config.compile_and_assemble().unwrap();
+        let oversized = |max_output_bytes| oversized_output(&config, Path::new("limit.h"), &output, max_output_bytes, input);
+
+        let (message, location) = oversized(None).unwrap();
+        assert!(message.starts_with(&format!(
+            "limit.h would be {} bytes which is over the limit of 100, nothing was written.",
+            output.main.contents.len()
+        )));
+        assert_eq!(location.line, Some(4));
+
+        // the command line wins, in both directions.
+        assert!(oversized(Some(output.main.contents.len())).is_none());
+        let (message, location) = oversized(Some(10)).unwrap();
+        assert!(message.contains("over the limit of 10,"));
+        assert_eq!(location, Location::file(input));
+
+    }
+
+    #[test]
+    fn clean_generated_only() {
+
+        let directory = std::env::temp_dir().join(format!("xmva-clean-{}", std::process::id()));
+        fs::create_dir_all(directory.join("include")).unwrap();
+        let path = directory.join("clean.xmva.toml");
+        let config = Config::parse(
+            "[common]\nprefix = \"A_\"\nrepeats = 3\noutput = \"include/a.h\"\n\n[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n\n\
+            [[generator]]\nrepeat = \"$(0)\"\n\n[[generator]]\nrepeat = \"$(0)\"\noutput = \"ladders.h\"\n\n\
+            [[generator]]\nrepeat = \"$(0)\"\noutput = \"missing.h\"\n",
+            &path
+        ).unwrap();
+        fs::write(directory.join("include/a.h"), format!("{}\n#define A\n", compiler::SENTINEL)).unwrap();
+        fs::write(directory.join("include/ladders.h"), "/* written by hand */\n").unwrap();
+
+        // the hand written file and the one that doesn't exist are left alone.
+        assert_eq!(clean_outputs(&path, &config).unwrap(), vec![directory.join("include/a.h")]);
+        assert!(!directory.join("include/a.h").exists());
+        assert!(directory.join("include/ladders.h").exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+
+    }
+
+}
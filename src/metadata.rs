@@ -0,0 +1,359 @@
+//! Everything we know about where a config came from, shared by the
+//! preprocessor, compiler and assembler so that diagnostics (and anything
+//! else that points back into the config) don't have to pass around
+//! paths and spans on their own.
+
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+use crate::diagnostic::Location;
+
+/// FNV-1a, the hash ends up in generated files so it has to stay the same
+/// across rust versions which [std::hash::DefaultHasher] doesn't promise.
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Hash of the config contents as a hex string.
+pub fn hash(contents: &str) -> String {
+    let hash = contents
+        .bytes()
+        .fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME));
+    format!("{hash:016x}")
+}
+
+/// Days since the unix epoch into a (year, month, day) date, the same
+/// one the build script uses for the build date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Seconds since the unix epoch as a UTC `YYYY-MM-DD HH:MM:SS` timestamp.
+pub fn format_timestamp(seconds: i64) -> String {
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let time = seconds.rem_euclid(86400);
+    format!("{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// The current time, or SOURCE_DATE_EPOCH for reproducible builds.
+pub fn timestamp() -> String {
+    let seconds = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<i64>().ok())
+        .unwrap_or_else(|| SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or(0)
+        );
+    format_timestamp(seconds)
+}
+
+/// The generator that is currently being preprocessed or compiled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratorContext {
+    pub index: usize,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    /// Path of the config as it was given to us.
+    pub config: PathBuf,
+    /// Absolute directory of the config, what paths written inside of
+    /// it are relative to no matter where we are called from.
+    pub directory: PathBuf,
+    /// See [hash].
+    pub hash: String,
+    pub version: &'static str,
+    /// Byte span of every field in the config by its dotted path, the
+    /// same path `xmva highlight` takes (`generator.0.repeat`).
+    pub spans: BTreeMap<String, Range<usize>>,
+    /// Byte offset of where every line starts, for turning spans into lines.
+    line_starts: Vec<usize>,
+    /// The config contents, for showing the line a span is on.
+    pub source: Arc<str>,
+    pub generator: Option<GeneratorContext>,
+}
+
+/// Walk the document and record the span of every value.
+fn index_spans(
+    prefix: &str,
+    item: &toml_edit::Item,
+    spans: &mut BTreeMap<String, Range<usize>>
+) {
+
+    let path = |key: &str| if prefix.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{prefix}.{key}")
+    };
+
+    if let Some(span) = item.span() {
+        spans.insert(prefix.to_owned(), span);
+    }
+
+    match item {
+        toml_edit::Item::Table(table) => {
+            for (key, item) in table.iter() {
+                index_spans(&path(key), item, spans);
+            }
+        }
+        toml_edit::Item::ArrayOfTables(array) => {
+            for (i, table) in array.iter().enumerate() {
+                if let Some(span) = table.span() {
+                    spans.insert(path(&i.to_string()), span);
+                }
+                for (key, item) in table.iter() {
+                    index_spans(&path(&format!("{i}.{key}")), item, spans);
+                }
+            }
+        }
+        toml_edit::Item::Value(value) => index_value_spans(prefix, value, spans),
+        toml_edit::Item::None => {}
+    }
+
+}
+
+fn index_value_spans(
+    prefix: &str,
+    value: &toml_edit::Value,
+    spans: &mut BTreeMap<String, Range<usize>>
+) {
+
+    if let Some(span) = value.span() {
+        spans.insert(prefix.to_owned(), span);
+    }
+
+    match value {
+        toml_edit::Value::Array(array) => {
+            for (i, value) in array.iter().enumerate() {
+                index_value_spans(&format!("{prefix}.{i}"), value, spans);
+            }
+        }
+        toml_edit::Value::InlineTable(table) => {
+            for (key, value) in table.iter() {
+                index_value_spans(&format!("{prefix}.{key}"), value, spans);
+            }
+        }
+        _ => {}
+    }
+
+}
+
+impl Metadata {
+
+    /// Metadata of the config at `path` with `contents`, the span index
+    /// is left empty if the contents aren't valid TOML (which the config
+    /// deserialization reports on its own).
+    pub fn new(path: &Path, contents: &str) -> Self {
+
+        let mut spans = BTreeMap::new();
+        if let Ok(document) = toml_edit::ImDocument::parse(contents) {
+            index_spans("", document.as_item(), &mut spans);
+        }
+        // the root spans the whole document.
+        spans.remove("");
+
+        let directory = std::path::absolute(path)
+            .ok()
+            .and_then(|path| path.parent().map(Path::to_owned))
+            .unwrap_or_default();
+
+        Self {
+            config: path.to_owned(),
+            directory,
+            hash: hash(contents),
+            version: env!("CARGO_PKG_VERSION"),
+            spans,
+            line_starts: std::iter::once(0)
+                .chain(contents.match_indices('\n').map(|(i, _)| i + 1))
+                .collect(),
+            source: Arc::from(contents),
+            generator: None
+        }
+
+    }
+
+    /// The same metadata but inside of generator `index`.
+    pub fn in_generator(&self, index: usize, name: Option<String>) -> Self {
+        Self {
+            generator: Some(GeneratorContext { index, name }),
+            ..self.clone()
+        }
+    }
+
+    /// Byte span of `field`, see [Metadata::spans].
+    pub fn span(&self, field: &str) -> Option<Range<usize>> {
+        self.spans.get(field).cloned()
+    }
+
+    /// If `field` is written as a TOML literal string, `'...'` or `'''...'''`.
+    pub fn literal(&self, field: &str) -> bool {
+        self.span(field).is_some_and(|span| self.source[span].starts_with('\''))
+    }
+
+    /// 1 based line of a byte offset.
+    pub fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|start| *start <= offset)
+    }
+
+    /// Where `field` starts, just the config if we don't know.
+    pub fn location(&self, field: &str) -> Location {
+        match self.span(field) {
+            Some(span) => self.location_of(span.start),
+            None => Location::file(&self.config)
+        }
+    }
+
+    /// Where the byte `offset` is.
+    pub fn location_of(&self, offset: usize) -> Location {
+        let line = self.line(offset);
+        let column = self.source
+            .get(self.line_starts[line - 1]..offset)
+            .map(|before| before.chars().count() + 1);
+        Location { file: self.config.clone(), line: Some(line), column }
+    }
+
+    /// The line `field` starts on with its value underlined and `label`
+    /// next to it, [None] if we don't know where `field` is.
+    /// 
+    /// ```text
+    ///   --> a.xmva.toml:2:10
+    ///    |
+    ///  2 | prefix = "A"
+    ///    |          ^^^ label
+    /// ```
+    pub fn label(&self, field: &str, label: &str) -> Option<String> {
+        self.label_span(self.span(field)?, label)
+    }
+
+    /// The same as [Metadata::label] but for any `span` of the config.
+    pub fn label_span(&self, span: Range<usize>, label: &str) -> Option<String> {
+
+        let line = self.line(span.start);
+        let line_start = self.line_starts[line - 1];
+        let line_end = self.line_starts
+            .get(line)
+            .map(|next| next - 1)
+            .unwrap_or(self.source.len());
+        let text = self.source.get(line_start..line_end)?.trim_end_matches('\r');
+
+        // only the part of the value on its first line is underlined.
+        let column = self.source.get(line_start..span.start)?.chars().count();
+        let width = self.source
+            .get(span.start..span.end.min(line_end))?
+            .chars()
+            .count()
+            .max(1);
+        let gutter = " ".repeat(line.to_string().len());
+
+        Some(format!("{gutter}--> {}:{line}:{}\n{gutter} |\n{line} | {text}\n{gutter} | {}{} {label}",
+            self.config.display(),
+            column + 1,
+            " ".repeat(column),
+            "^".repeat(width)
+        ))
+
+    }
+
+    /// Span of the first `text` inside of the value of `field`.
+    pub fn find(&self, field: &str, text: &str) -> Option<Range<usize>> {
+        let span = self.span(field)?;
+        let start = span.start + self.source.get(span)?.find(text)?;
+        Some(start..start + text.len())
+    }
+
+    /// Append where a error happened to its `message`.
+    pub fn annotate(&self, message: String) -> String {
+        let Some(GeneratorContext { index, name }) = &self.generator else {
+            return format!("{message}\n  --> in {}", self.config.display())
+        };
+        let location = match self.span(&format!("generator.{index}")) {
+            Some(span) => format!("{}:{}", self.config.display(), self.line(span.start)),
+            None => self.config.display().to_string()
+        };
+        match name {
+            Some(name) => format!("{message}\n  --> in generator {index} (`{name}`) at {location}"),
+            None => format!("{message}\n  --> in generator {index} at {location}")
+        }
+    }
+
+}
+
+/// The generator [Metadata::annotate] wrote into `message`, [None] if
+/// it happened outside of every generator.
+pub fn annotated_generator(message: &str) -> Option<usize> {
+    let (_, annotation) = message.rsplit_once("\n  --> in generator ")?;
+    annotation.split(' ').next()?.parse().ok()
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn field_spans() {
+
+        let contents = "[common]\nprefix = \"A_\"\n\n[[generator]]\nrepeat = \"$(0)\"\nkeys = [{key = \"k\"}]\n";
+        let metadata = Metadata::new(Path::new("a.xmva.toml"), contents);
+
+        let span = metadata.span("common.prefix").unwrap();
+        assert_eq!(&contents[span], "\"A_\"");
+        let span = metadata.span("generator.0.repeat").unwrap();
+        assert_eq!(&contents[span], "\"$(0)\"");
+        let span = metadata.span("generator.0.keys.0.key").unwrap();
+        assert_eq!(&contents[span], "\"k\"");
+
+        assert_eq!(
+            metadata.label("common.prefix", "here").unwrap(),
+            " --> a.xmva.toml:2:10\n  |\n2 | prefix = \"A_\"\n  |          ^^^^ here"
+        );
+
+        assert_eq!(
+            metadata.location("common.prefix"),
+            Location { file: PathBuf::from("a.xmva.toml"), line: Some(2), column: Some(10) }
+        );
+        assert_eq!(metadata.location("missing"), Location::file("a.xmva.toml"));
+
+        assert_eq!(metadata.line(0), 1);
+        assert_eq!(metadata.line(contents.find("[[generator]]").unwrap()), 4);
+        assert_eq!(
+            metadata.in_generator(0, None).annotate("oops".to_owned()),
+            "oops\n  --> in generator 0 at a.xmva.toml:4"
+        );
+        assert_eq!(annotated_generator(&metadata.in_generator(12, Some("n".to_owned())).annotate("oops".to_owned())), Some(12));
+        assert_eq!(annotated_generator(&metadata.annotate("oops".to_owned())), None);
+
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(1700000000), "2023-11-14 22:13:20 UTC");
+    }
+
+    #[test]
+    fn stable_hash() {
+        assert_eq!(hash(""), "cbf29ce484222325");
+        assert_eq!(hash("a"), "af63dc4c8601ec8c");
+    }
+
+}
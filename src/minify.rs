@@ -0,0 +1,146 @@
+//! `--minify`, for projects that treat the output as a pure build
+//! artifact. Runs over the assembled output and drops everything the
+//! preprocessor doesn't need:
+//!
+//! - comments, other than the [SENTINEL] and the config hash line since
+//!   overwriting and `xmva verify` still rely on them.
+//! - line continuations, every `#define` ends up on a single line.
+//! - runs of whitespace, blank lines and the spaces in between the
+//!   parameters of a `#define`.
+//!
+//! String and character literals are left untouched.
+
+use crate::compiler::{CONFIG_HASH_MARKER, SENTINEL};
+
+const DEFINE: &str = "#define ";
+
+/// Lines copied as is, comments or not.
+fn kept(line: &str) -> bool {
+    line == SENTINEL || line.trim_start().starts_with(&format!("/* {CONFIG_HASH_MARKER}"))
+}
+
+/// Remove comments and collapse whitespace outside of literals, lines
+/// passing [kept] are copied over.
+fn strip(contents: &str) -> String {
+
+    let mut stripped = String::with_capacity(contents.len());
+    let mut literal: Option<char> = None;
+    let mut rest = contents;
+
+    while let Some(ch) = rest.chars().next() {
+
+        if literal.is_none() && (stripped.is_empty() || stripped.ends_with('\n')) {
+            let line = rest.split_once('\n').map_or(rest, |(line, _)| line);
+            if kept(line) {
+                stripped.push_str(line);
+                stripped.push('\n');
+                rest = rest.get(line.len() + 1..).unwrap_or_default();
+                continue
+            }
+        }
+
+        rest = &rest[ch.len_utf8()..];
+        if let Some(quote) = literal {
+            stripped.push(ch);
+            if ch == '\\' && let Some(escaped) = rest.chars().next() {
+                stripped.push(escaped);
+                rest = &rest[escaped.len_utf8()..];
+            } else if ch == quote || ch == '\n' {
+                literal = None;
+            }
+            continue
+        }
+
+        match ch {
+            '"' | '\'' => {
+                literal = Some(ch);
+                stripped.push(ch);
+            }
+            '/' if rest.starts_with('*') => {
+                rest = rest[1..].split_once("*/").map_or("", |(_, after)| after);
+                if !stripped.ends_with([' ', '\n']) {
+                    stripped.push(' ');
+                }
+            }
+            '/' if rest.starts_with('/') => {
+                rest = rest.find('\n').map_or("", |end| &rest[end..]);
+            }
+            ' ' | '\t' | '\r' => if !stripped.ends_with([' ', '\n']) && !stripped.is_empty() {
+                stripped.push(' ');
+            }
+            _ => stripped.push(ch)
+        }
+
+    }
+
+    stripped
+
+}
+
+/// `#define NAME(a, b) body` into `#define NAME(a,b) body`.
+fn tighten_parameters(line: &str) -> String {
+    let Some(definition) = line.strip_prefix(DEFINE) else {
+        return line.to_owned()
+    };
+    let name_end = definition
+        .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+        .unwrap_or(definition.len());
+    if !definition[name_end..].starts_with('(') {
+        return line.to_owned()
+    }
+    let Some(close) = definition[name_end..].find(')').map(|close| name_end + close) else {
+        return line.to_owned()
+    };
+    format!("{DEFINE}{}{}{}",
+        &definition[..name_end],
+        definition[name_end..close].replace(' ', ""),
+        &definition[close..]
+    )
+}
+
+/// The minified `contents`.
+pub fn minify(contents: &str) -> String {
+    let spliced = contents.replace("\\\r\n", "").replace("\\\n", "");
+    strip(&spliced)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(tighten_parameters)
+        .fold(String::with_capacity(contents.len()), |mut minified, line| {
+            minified.push_str(&line);
+            minified.push('\n');
+            minified
+        })
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn minified() {
+
+        let contents = format!(concat!(
+            "{}\n",
+            "/* Generated by xmva, do not edit. */\n",
+            "/* {}abc */\n",
+            "\n",
+            "/* The main macro. */\n",
+            "#define X(a, b,  c) \\\n",
+            "    f(a,   b) /* inline */ \\\n",
+            "    g(\"keep  /* this */ \\\"  // too\", '  ') // trailing\n",
+            "\n",
+            "#define Y (a, b)\n",
+        ), SENTINEL, CONFIG_HASH_MARKER);
+
+        assert_eq!(minify(&contents), format!(concat!(
+            "{}\n",
+            "/* {}abc */\n",
+            "#define X(a,b,c) f(a, b) g(\"keep  /* this */ \\\"  // too\", '  ')\n",
+            "#define Y (a, b)\n",
+        ), SENTINEL, CONFIG_HASH_MARKER));
+
+    }
+
+}
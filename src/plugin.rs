@@ -0,0 +1,192 @@
+//! Scripted extension hooks, a config can point to a [Rhai](https://rhai.rs)
+//! script whose functions are then usable as:
+//!
+//! - name transforms, a function taking the name and returning the new
+//!   one, listed in the `transforms` of a tagged name. They run after
+//!   the tags were applied.
+//! - compiler token handlers, a function without parameters whose result
+//!   replaces `${function}` in a generator string when there is no named
+//!   argument with that key.
+//!
+//! Example
+//! -------
+//! ```toml
+//! [plugin]
+//! script = "transforms.rhai" # relative to the config.
+//!
+//! [[definition]]
+//! key  = "emsg"
+//! name = {tags = [], transforms = ["lower"], string = "ERROR"}
+//! ...
+//!
+//! [[generator]]
+//! preamble = "enum ${enum_name} {"
+//! ```
+//! ```rhai
+//! fn lower(name) { name.to_lower() }
+//! fn enum_name() { "ya_codes" }
+//! ```
+
+use std::{cell::RefCell, fs, path::{Path, PathBuf}};
+
+use rhai::{Engine, AST};
+
+/// Scripts stuck in a loop are stopped after this many operations.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    Load,
+    Compile,
+    MissingFunction,
+    Call,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub(crate) message: String
+}
+
+impl std::fmt::Display for Error {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Plugin encountered a error, [{:?}]: {}", self.kind, self.message)
+    }
+
+}
+
+impl std::error::Error for Error {}
+
+struct Script {
+    engine: Engine,
+    ast:    AST,
+    path:   PathBuf
+}
+
+impl Script {
+
+    fn defines(&self, function: &str, parameters: usize) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|defined| defined.name == function && defined.params.len() == parameters)
+    }
+
+    fn call(&self, function: &str, args: impl rhai::FuncArgs) -> Result<String, Error> {
+        self.engine
+            .call_fn::<rhai::Dynamic>(&mut rhai::Scope::new(), &self.ast, function, args)
+            .map(|result| result.to_string())
+            .map_err(|err| Error {
+                kind: ErrorKind::Call,
+                message: format!("calling `{function}` from {}: {err}", self.path.display())
+            })
+    }
+
+}
+
+thread_local! {
+    /// The script of the config being generated, per thread for the
+    /// same reason as the sigil overrides.
+    static SCRIPT: RefCell<Option<Script>> = const { RefCell::new(None) };
+}
+
+/// Compile the script at `path` and use it from now on, `None` unloads
+/// the current one.
+pub fn load(path: Option<&Path>) -> Result<(), Error> {
+
+    let Some(path) = path else {
+        SCRIPT.with(|script| *script.borrow_mut() = None);
+        return Ok(())
+    };
+
+    let source = fs::read_to_string(path).map_err(|err| Error {
+        kind: ErrorKind::Load,
+        message: format!("failed to read {}: {err}", path.display())
+    })?;
+
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    let ast = engine.compile(&source).map_err(|err| Error {
+        kind: ErrorKind::Compile,
+        message: format!("in {}: {err}", path.display())
+    })?;
+
+    log::debug!("Loaded plugin script {}.", path.display());
+    // absolute since we change directories before generating.
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_owned());
+    SCRIPT.with(|script| *script.borrow_mut() = Some(Script { engine, ast, path }));
+    Ok(())
+
+}
+
+/// Run `name` through every function in `transforms` in order.
+pub fn transform(transforms: &[String], name: &str) -> Result<String, Error> {
+
+    if transforms.is_empty() {
+        return Ok(name.to_owned())
+    }
+
+    SCRIPT.with(|script| {
+        let script = script.borrow();
+        let Some(script) = script.as_ref() else {
+            return Err(Error {
+                kind: ErrorKind::MissingFunction,
+                message: format!("`{name}` uses the transforms {transforms:?} without a [plugin] script")
+            })
+        };
+        let mut transformed = name.to_owned();
+        for function in transforms {
+            if !script.defines(function, 1) {
+                return Err(Error {
+                    kind: ErrorKind::MissingFunction,
+                    message: format!("{} has no transform `fn {function}(name)`", script.path.display())
+                })
+            }
+            transformed = script.call(function, (transformed,))?;
+        }
+        Ok(transformed)
+    })
+
+}
+
+/// The result of the token handler `function`, `None` if the script
+/// doesn't define one (or there is no script).
+pub fn handle(function: &str) -> Option<Result<String, Error>> {
+    SCRIPT.with(|script| {
+        let script = script.borrow();
+        let script = script.as_ref().filter(|script| script.defines(function, 0))?;
+        Some(script.call(function, ()))
+    })
+}
+
+/// The absolute path of the script being used, for the depfile.
+pub fn script() -> Option<PathBuf> {
+    SCRIPT.with(|script| script.borrow().as_ref().map(|script| script.path.clone()))
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn hooks() {
+
+        let path = std::env::temp_dir().join(format!("xmva-plugin-{}.rhai", std::process::id()));
+        fs::write(&path, "fn lower(name) { name.to_lower() }\nfn tail(name) { name + \"_T\" }\nfn codes() { \"ya_codes\" }\nfn spin() { loop {} }").unwrap();
+        load(Some(&path)).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(transform(&["lower".to_owned(), "tail".to_owned()], "ERROR").unwrap(), "error_T");
+        assert_eq!(transform(&["upper".to_owned()], "x").unwrap_err().kind, ErrorKind::MissingFunction);
+        assert_eq!(handle("codes").unwrap().unwrap(), "ya_codes");
+        assert!(handle("lower").is_none());
+        assert_eq!(handle("spin").unwrap().unwrap_err().kind, ErrorKind::Call);
+
+        load(None).unwrap();
+        assert!(handle("codes").is_none());
+        assert_eq!(transform(&["lower".to_owned()], "x").unwrap_err().kind, ErrorKind::MissingFunction);
+
+    }
+
+}
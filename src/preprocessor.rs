@@ -1,16 +1,45 @@
-use std::{collections::HashMap, mem::discriminant, sync::{Arc, Mutex, RwLock}};
+use std::{
+    borrow::Cow, cell::RefCell, collections::{BTreeSet, HashMap, HashSet}, fs, mem::discriminant,
+    path::{Path, PathBuf}, sync::{Arc, Mutex, RwLock}
+};
 
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
-use strum::EnumProperty;
 
 use crate::{
     config::{
-       Argument, CommonKeyable, Config, Name, StringWithTags
+       Argument, CommonKeyable, Config, Generator, Name, StringWithTags, Tag, KEY_SCOPE_SEPARATOR,
+       KEY_TAG_SEPARATOR
     }, 
+    lint::{self, Lint},
+    metadata::Metadata,
+    plugin,
     sigil::PreprocessorSigil
 };
 
+/// Builtin key only available inside of generator strings, the name of
+/// the generator or its index if it doesn't have one.
+pub const BUILTIN_GENERATOR_NAME: &str = "__GEN_NAME__";
+/// Builtin key only available inside of generator strings, the index
+/// of the generator (same one the generated macros are suffixed with).
+pub const BUILTIN_GENERATOR_INDEX: &str = "__GEN_INDEX__";
+/// Builtin key only available inside of generator strings, the
+/// [crate::config::Common::repeats].
+pub const BUILTIN_REPEATS: &str = "__REPEATS__";
+/// Builtin key only available inside of generator strings, the
+/// [crate::config::Argument::Varadict] group size.
+pub const BUILTIN_GROUP_SIZE: &str = "__GROUP_SIZE__";
+
+/// Keys starting with this include a file instead, `@{file:snippets/doc.h}`
+/// is replaced with the preprocessed contents of `snippets/doc.h`.
+pub const FILE_REFERENCE: &str = "file:";
+
+/// Main macro of a used config, `@{name.__XMVA__}`, see [crate::config::Config::uses].
+pub const BUILTIN_USED_XMVA: &str = "__XMVA__";
+
+/// Prefix of a used config, `@{name.__PREFIX__}`.
+pub const BUILTIN_USED_PREFIX: &str = "__PREFIX__";
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum ErrorKind {
     InvalidToken,
@@ -20,7 +49,11 @@ pub enum ErrorKind {
     NonExistantReference,
     MutualReferences,
     EmptyReference,
-    DuplicateKey
+    DuplicateKey,
+    Plugin,
+    Transform,
+    Include,
+    IncludeCycle
 }
 
 #[derive(Debug)]
@@ -86,19 +119,19 @@ pub trait Preprocess {
     /// Types from [Preprocessable] are not initialized (like [StringWithTags])
     /// and here they are meant to be initialized before they are processed
     /// into tokens.
-    fn into_preprocessor_tokens(
+    fn to_preprocessor_tokens(
         &self,
         keys: &CommonKeyable
-    ) -> Result<Vec<PreprocessorToken>, Error>;
+    ) -> Result<Vec<PreprocessorToken<'_>>, Error>;
 
 }
 
 impl Preprocess for String {
 
-    fn into_preprocessor_tokens(
+    fn to_preprocessor_tokens(
         &self,
         _: &CommonKeyable
-    ) -> Result<Vec<PreprocessorToken>, Error> {
+    ) -> Result<Vec<PreprocessorToken<'_>>, Error> {
         
         preprocessor_string_tokenizer(self)
 
@@ -108,20 +141,32 @@ impl Preprocess for String {
 
 impl Preprocess for Name {
 
-    fn into_preprocessor_tokens(
+    fn to_preprocessor_tokens(
         &self,
         keys: &CommonKeyable
-    ) -> Result<Vec<PreprocessorToken>, Error> {
+    ) -> Result<Vec<PreprocessorToken<'_>>, Error> {
 
         let s_w_tags = match self {
-            Self::Raw(s) => StringWithTags{tags: vec![], string: s.clone()},
+            Self::Raw(s) => StringWithTags{tags: vec![], string: s.clone(), transforms: vec![]},
             Self::Tagged(swt) => swt.clone()
         };
         
         // init
-        let s = &s_w_tags.apply_tags(keys);
+        let tagged = s_w_tags.apply_tags(keys).map_err(|err| Error {
+            kind: ErrorKind::Transform,
+            message: err.to_string()
+        })?;
+        let s = &plugin::transform(&s_w_tags.transforms, &tagged)
+            .map_err(|err| Error {
+                kind: ErrorKind::Plugin,
+                message: err.to_string()
+            })?;
 
-        preprocessor_string_tokenizer(s)
+        // the tagged string only lives in here.
+        Ok(preprocessor_string_tokenizer(s)?
+            .into_iter()
+            .map(PreprocessorToken::into_owned)
+            .collect())
         
     }
 
@@ -131,87 +176,185 @@ impl Preprocess for Name {
 /// a finished preprocessed string.
 /// `Raw` hold a raw string that has no special characteristics.
 /// `Key` holds a string that a name of a key. 
+/// 
+/// Both borrow from the tokenized string, `Raw` is only owned when it
+/// had embeds in it that had to be unescaped.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-pub enum PreprocessorToken {
-    Raw(String),
-    Key(String)
+pub enum PreprocessorToken<'a> {
+    Raw(Cow<'a, str>),
+    Key(Cow<'a, str>)
 }
 
-#[derive(Debug, Clone)]
+impl PreprocessorToken<'_> {
+
+    /// Turn the token back into the string it was tokenized from.
+    pub(crate) fn untokenize(&self) -> String {
+        let embed = PreprocessorSigil::TokenEmbed.sequence();
+        let start = PreprocessorSigil::TokenStart.sequence();
+        match self {
+            Self::Raw(value) => value
+                // first.
+                .replace(&embed, format!("{embed}{embed}").as_str())
+                .replace(&start, format!("{embed}{start}").as_str()),
+            Self::Key(value) => format!("{start}{}{value}{}",
+                PreprocessorSigil::KeyRefOpen.sequence(),
+                PreprocessorSigil::KeyRefClose.sequence()
+            )
+        }
+    }
+
+    /// The same token no longer borrowing the tokenized string.
+    pub(crate) fn into_owned(self) -> PreprocessorToken<'static> {
+        match self {
+            Self::Raw(value) => PreprocessorToken::Raw(Cow::Owned(value.into_owned())),
+            Self::Key(value) => PreprocessorToken::Key(Cow::Owned(value.into_owned()))
+        }
+    }
+
+}
+
+/// Byte offsets are into the string being tokenized, text is unescaped
+/// into the [String] once a embed shows up in it and borrowed otherwise.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum PreprocessorTokenizerState {
-    Copying(String),
-    CopyingKey(String),
+    Copying(usize, Option<String>),
+    CopyingKey(usize),
     SigilFound,
-    EmbedFound(String)
+    EmbedFound(String),
+    /// After a error while recovering, up to the next token start.
+    Skipping
+}
+
+/// Text from `start` to `end` of `s`, or the `unescaped` text if the
+/// tokenizer had to unescape it.
+pub(crate) fn tokenized_text(
+    s: &str,
+    start: usize,
+    end: usize,
+    unescaped: Option<String>
+) -> Cow<'_, str> {
+    match unescaped {
+        Some(unescaped) => Cow::Owned(unescaped),
+        None => Cow::Borrowed(&s[start..end])
+    }
 }
 
 /// Regular [PreprocessorToken] tokenizer, meant to be run on all [Preprocessable]s.
 /// This also includes the [crate::config::Generator::repeat] [Preprocessable]
 /// but it skips special sigils like [Sigil::CompilerSkipLastOpen]/[Sigil::CompilerSkipLastClose]
 /// and [Sigil::CompilerArgumentRefOpen]/[Sigil::CompilerArgumentRefClose].
-fn preprocessor_string_tokenizer(
+pub(crate) fn preprocessor_string_tokenizer(
+    s: &str
+) -> Result<Vec<PreprocessorToken<'_>>, Error> {
+    preprocessor_string_tokenizer_with(s, false).map(|(tokens, _)| tokens)
+}
+
+/// [preprocessor_string_tokenizer] that records errors and skips to the
+/// next token start instead of stopping, see [crate::compiler::CompilerToken::tokenize_recovering].
+pub(crate) fn preprocessor_string_tokenizer_recovering(
     s: &str
-) -> Result<Vec<PreprocessorToken>, Error> {
+) -> (Vec<PreprocessorToken<'_>>, Vec<Error>) {
+    preprocessor_string_tokenizer_with(s, true).unwrap_or_else(|err| (vec![], vec![err]))
+}
+
+fn preprocessor_string_tokenizer_with(
+    s: &str,
+    recover: bool
+) -> Result<(Vec<PreprocessorToken<'_>>, Vec<Error>), Error> {
 
     let mut parts: Vec<PreprocessorToken> = vec![];
+    let mut diagnostics: Vec<Error> = vec![];
     let mut state: PreprocessorTokenizerState 
-        = PreprocessorTokenizerState::Copying(String::new());
-    let mut prev_state = state.clone();
+        = PreprocessorTokenizerState::Copying(0, None);
+    let mut prev_state = discriminant(&state);
+
+    // see [crate::compiler::CompilerToken::tokenize_with].
+    macro_rules! fail {
+        ($err:expr) => {{
+            let err = $err;
+            if !recover {
+                return Err(err)
+            }
+            diagnostics.push(err);
+            state = PreprocessorTokenizerState::Skipping;
+            continue
+        }};
+    }
+    macro_rules! fail_at_end {
+        ($err:expr) => {{
+            let err = $err;
+            if !recover {
+                return Err(err)
+            }
+            diagnostics.push(err);
+        }};
+    }
 
-    for ch in s.chars() {
+    for (sigil, range) in PreprocessorSigil::lex(s) {
 
-        if discriminant(&prev_state) != discriminant(&state) {
+        if discriminant(&state) != prev_state {
             log::trace!(
                 "{}: {}",
-                format!("[preprocessor_string_tokenizer]").bold(),
-                format!("Curr state {:?}", prev_state).dimmed()
+                "[preprocessor_string_tokenizer]".to_string().bold(),
+                format!("Curr state {:?}", state).dimmed()
             );
+            prev_state = discriminant(&state);
         }
-        prev_state = state.clone();
+        let ch = &s[range.clone()];
 
         match state {
 
-            PreprocessorTokenizerState::Copying(ref mut buffer) => {
-                match PreprocessorSigil::from(ch) {
+            PreprocessorTokenizerState::Skipping => {
+                if sigil == PreprocessorSigil::TokenStart {
+                    state = PreprocessorTokenizerState::SigilFound;
+                }
+            }
+            PreprocessorTokenizerState::Copying(start, ref mut unescaped) => {
+                match sigil {
                     PreprocessorSigil::TokenStart => {
-                        if !buffer.is_empty() {
-                            parts.push(PreprocessorToken::Raw(buffer.clone()));
+                        let text = tokenized_text(s, start, range.start, unescaped.take());
+                        if !text.is_empty() {
+                            parts.push(PreprocessorToken::Raw(text));
                         }
                         state = PreprocessorTokenizerState::SigilFound;
                     }
                     PreprocessorSigil::TokenEmbed => {
-                        state = PreprocessorTokenizerState::EmbedFound(buffer.clone());
+                        state = PreprocessorTokenizerState::EmbedFound(
+                            tokenized_text(s, start, range.start, unescaped.take()).into_owned()
+                        );
                     }
                     PreprocessorSigil::KeyRefOpen |
                     PreprocessorSigil::KeyRefClose |
-                    PreprocessorSigil::Non(_) => buffer.push(ch)
+                    PreprocessorSigil::Non(_) => if let Some(unescaped) = unescaped {
+                        unescaped.push_str(ch)
+                    }
                 }
             }
             PreprocessorTokenizerState::EmbedFound(ref mut buffer) => {
-                match PreprocessorSigil::from(ch) {
+                match sigil {
                     PreprocessorSigil::TokenStart |
                     PreprocessorSigil::TokenEmbed => {
-                        buffer.push(ch);
+                        buffer.push_str(ch);
                     }
                     _ => {
-                        return Err(Error{
+                        fail!(Error{
                             kind: ErrorKind::IllegalSymbol,
                             message: format!(
                                 "Expected a {:?} symbol {:?} or {:?} symbol {:?} after '{ch}'",
                                     PreprocessorSigil::TokenStart,
-                                    PreprocessorSigil::TokenStart.get_str("ch"),
+                                    PreprocessorSigil::TokenStart.sequence(),
                                     PreprocessorSigil::TokenEmbed,
-                                    PreprocessorSigil::TokenEmbed.get_str("ch")
+                                    PreprocessorSigil::TokenEmbed.sequence()
                             )
                         })
                     }
                 }
-                state = PreprocessorTokenizerState::Copying(buffer.clone());
+                state = PreprocessorTokenizerState::Copying(range.end, Some(std::mem::take(buffer)));
             }
             PreprocessorTokenizerState::SigilFound => {
-                match PreprocessorSigil::from(ch) {  
+                match sigil {  
                     PreprocessorSigil::TokenStart => {
-                        return Err(Error{
+                        fail!(Error{
                             kind: ErrorKind::IllegalSymbol,
                             message: format!(
                                 "Duplicate symbol '{}' in '{}' twice or more in a row", ch, s
@@ -219,45 +362,45 @@ fn preprocessor_string_tokenizer(
                         })
                     }
                     PreprocessorSigil::KeyRefOpen => {
-                        state = PreprocessorTokenizerState::CopyingKey(String::new())
+                        state = PreprocessorTokenizerState::CopyingKey(range.end)
                     }
                     PreprocessorSigil::KeyRefClose |
                     PreprocessorSigil::TokenEmbed |
                     PreprocessorSigil::Non(_)=> {
-                        return Err(Error {
+                        fail!(Error {
                             kind: ErrorKind::IllegalSymbol,
                             message: format!(
                                 "Illegal character '{}' in '{}' after '{:?}' symbol '{:?}' ", 
-                                ch, s, PreprocessorSigil::TokenStart, PreprocessorSigil::TokenStart.get_str("ch")
+                                ch, s, PreprocessorSigil::TokenStart, PreprocessorSigil::TokenStart.sequence()
                             )
                         })
                     }
                 }
             }
-            PreprocessorTokenizerState::CopyingKey(ref mut buffer_key) => {
-                match PreprocessorSigil::from(ch) {
+            PreprocessorTokenizerState::CopyingKey(start) => {
+                match sigil {
                     PreprocessorSigil::KeyRefClose => {
-                        if buffer_key.is_empty() {
-                            return Err(Error {
+                        if start == range.start {
+                            fail!(Error {
                                 kind: ErrorKind::EmptyReference,
                                 message: format!(
                                     "Empty key reference `{}{}{}` inside of a preprocessable name `{s}`",
-                                    PreprocessorSigil::TokenStart.get_str("ch").unwrap(),
-                                    PreprocessorSigil::KeyRefOpen.get_str("ch").unwrap(),
-                                    PreprocessorSigil::KeyRefClose.get_str("ch").unwrap(),
+                                    PreprocessorSigil::TokenStart.sequence(),
+                                    PreprocessorSigil::KeyRefOpen.sequence(),
+                                    PreprocessorSigil::KeyRefClose.sequence(),
                                 )
                             })
                         }
-                        parts.push(PreprocessorToken::Key(buffer_key.clone()));
-                        state = PreprocessorTokenizerState::Copying(String::new());
+                        parts.push(PreprocessorToken::Key(Cow::Borrowed(&s[start..range.start])));
+                        state = PreprocessorTokenizerState::Copying(range.end, None);
                     }
-                    PreprocessorSigil::Non(ch) => buffer_key.push(ch),
+                    PreprocessorSigil::Non(_) => {}
                     _ => {
-                        return Err(Error {
+                        fail!(Error {
                             kind: ErrorKind::IllegalSymbol,
                             message: format!(
                                 "Illegal character '{}' in '{}', expected a '{:?}' symbol '{:?}'", 
-                                ch, s, PreprocessorSigil::KeyRefClose, PreprocessorSigil::KeyRefClose.get_str("ch")
+                                ch, s, PreprocessorSigil::KeyRefClose, PreprocessorSigil::KeyRefClose.sequence()
                             )
                         })
                     }
@@ -268,40 +411,42 @@ fn preprocessor_string_tokenizer(
 
     log::trace!(
         "{}: {}",
-        format!("[preprocessor_string_tokenizer]").bold(),
+        "[preprocessor_string_tokenizer]".to_string().bold(),
         format!("Last state {:?}", state).dimmed()
     );
 
     match state {
-        PreprocessorTokenizerState::Copying(buffer) => {
-            if !buffer.is_empty() {
-                parts.push(PreprocessorToken::Raw(buffer))
+        PreprocessorTokenizerState::Skipping => {}
+        PreprocessorTokenizerState::Copying(start, unescaped) => {
+            let text = tokenized_text(s, start, s.len(), unescaped);
+            if !text.is_empty() {
+                parts.push(PreprocessorToken::Raw(text))
             }
         }
         PreprocessorTokenizerState::EmbedFound(_) => {
-            return Err(Error{
+            fail_at_end!(Error{
                 kind: ErrorKind::IllegalSymbol,
                 message: format!(
                     "Expected a {:?} symbol {:?} or {:?} symbol {:?} after '{:?}'",
                         PreprocessorSigil::TokenStart,
-                        PreprocessorSigil::TokenStart.get_str("ch"),
+                        PreprocessorSigil::TokenStart.sequence(),
                         PreprocessorSigil::TokenEmbed,
-                        PreprocessorSigil::TokenEmbed.get_str("ch"),
-                        PreprocessorSigil::TokenStart.get_str("ch")
+                        PreprocessorSigil::TokenEmbed.sequence(),
+                        PreprocessorSigil::TokenStart.sequence()
                 )
             })
         }
         PreprocessorTokenizerState::SigilFound => {
-            return Err(Error {
+            fail_at_end!(Error {
                 kind: ErrorKind::InvalidToken,
                 message: format!(
                     "'{:?}' symbol '{:?}' found with no body to go along side it in '{}'", 
-                    PreprocessorSigil::TokenStart, PreprocessorSigil::TokenStart.get_str("ch"), s
+                    PreprocessorSigil::TokenStart, PreprocessorSigil::TokenStart.sequence(), s
                 )
             })
         }
         PreprocessorTokenizerState::CopyingKey(_) => {
-            return Err(Error {
+            fail_at_end!(Error {
                 kind: ErrorKind::InvalidToken,
                 message: format!(
                     "Unfinished `key reference` token in preprocessable '{}'", s)
@@ -309,7 +454,7 @@ fn preprocessor_string_tokenizer(
         }
     }
 
-    Ok(parts)
+    Ok((parts, diagnostics))
 
 }
 
@@ -336,12 +481,144 @@ pub enum AnyPreprocessable {
     String(PreprocessableString)
 }
 
+impl AnyPreprocessable {
+
+    /// The preprocessed value or [None] if it isn't preprocessed yet
+    /// (or the lock got poisoned).
+    pub fn preprocessed(&self) -> Option<String> {
+
+        match self {
+            AnyPreprocessable::Name(name) => {
+                match &*name.read().ok()? {
+                    Preprocessable::NotPreprocessed(_) => None,
+                    Preprocessable::Preprocessed(s) => Some(s.clone())
+                }
+            }
+            AnyPreprocessable::String(string) => {
+                match &*string.read().ok()? {
+                    Preprocessable::NotPreprocessed(_) => None,
+                    Preprocessable::Preprocessed(s) => Some(s.clone())
+                }
+            }
+        }
+
+    }
+
+
+    /// Take `value` as the preprocessed value, for resuming from a
+    /// [crate::stage::Dump].
+    pub fn set_preprocessed(&self, value: String) {
+        match self {
+            AnyPreprocessable::Name(name) => if let Ok(mut name) = name.write() {
+                *name = Preprocessable::Preprocessed(value)
+            }
+            AnyPreprocessable::String(string) => if let Ok(mut string) = string.write() {
+                *string = Preprocessable::Preprocessed(value)
+            }
+        }
+    }
+
+}
+
+/// The key of a [PreprocessorToken::Key] and the [Tag]s written after
+/// it, `@{key|TAG|...}`.
+pub(crate) fn split_reference(reference: &str) -> (&str, Vec<Tag>) {
+    let mut parts = reference.split(KEY_TAG_SEPARATOR);
+    let key = parts.next().unwrap_or_default();
+    (key, parts.map(Tag::parse).collect())
+}
+
+thread_local! {
+    /// Every file included with `@{file:...}` so far, for the depfile
+    /// and the config hash.
+    static INCLUDED: RefCell<BTreeSet<PathBuf>> = const { RefCell::new(BTreeSet::new()) };
+    /// Directory of the config being preprocessed, `@{file:...}` paths
+    /// are relative to it.
+    static INCLUDE_DIRECTORY: RefCell<PathBuf> = const { RefCell::new(PathBuf::new()) };
+}
+
+/// The absolute path of every file included with `@{file:...}`.
+pub fn included_files() -> Vec<PathBuf> {
+    INCLUDED.with(|included| included.borrow().iter().cloned().collect())
+}
+
+/// Forget every file included so far, once at the start of loading a config.
+pub fn reset_included() {
+    INCLUDED.with(|included| included.borrow_mut().clear());
+}
+
+/// Resolve `@{file:...}` paths against `directory` from now on.
+fn set_include_directory(directory: &Path) {
+    INCLUDE_DIRECTORY.with(|include_directory| *include_directory.borrow_mut() = directory.to_owned());
+}
+
+/// Contents of the file `path` (relative to the config, even when
+/// included from another file) preprocessed like any other string, so
+/// it can reference keys and include more files.
+/// 
+/// `including` holds the files being included right now along side
+/// the path they were written as, to catch a file including itself.
+fn include_file(
+    path: &str,
+    keys: &HashMap<String, AnyPreprocessable>,
+    common_keys: &CommonKeyable,
+    including: &mut Vec<(PathBuf, String)>
+) -> Result<Option<String>, Error> {
+
+    let canonical = INCLUDE_DIRECTORY
+        .with(|directory| directory.borrow().join(path))
+        .canonicalize()
+        .map_err(|err| Error {
+            kind: ErrorKind::Include,
+            message: format!("failed to include `{path}`: {err}")
+        })?;
+
+    if let Some(start) = including.iter().position(|(included, _)| *included == canonical) {
+        let cycle: Vec<&str> = including[start..]
+            .iter()
+            .map(|(_, written)| written.as_str())
+            .chain([path])
+            .collect();
+        return Err(Error {
+            kind: ErrorKind::IncludeCycle,
+            message: format!("`{path}` includes itself: {}", cycle.join(" -> "))
+        })
+    }
+
+    let contents = fs::read_to_string(&canonical).map_err(|err| Error {
+        kind: ErrorKind::Include,
+        message: format!("failed to include `{path}`: {err}")
+    })?;
+    INCLUDED.with(|included| included.borrow_mut().insert(canonical.clone()));
+
+    including.push((canonical, path.to_owned()));
+    let included = preprocessor_string_tokenizer(&contents)
+        .and_then(|tokens| assemble_tokens(tokens, keys, common_keys, including))
+        .map_err(|err| match err.kind {
+            ErrorKind::IncludeCycle => err,
+            kind => Error { kind, message: format!("in `{path}`: {}", err.message) }
+        });
+    including.pop();
+    included
+
+}
+
 /// Attempt to assemble a [Vec] of [PreprocessorToken].
 /// `keys` are a set of key name pairs from the [Config] and they are used for
 /// processing [PreprocessorToken::Key] tokens.
 pub fn preprocessor_token_assembly_attempt(
     tokens: Vec<PreprocessorToken>,
-    keys: &HashMap<String, AnyPreprocessable>
+    keys: &HashMap<String, AnyPreprocessable>,
+    common_keys: &CommonKeyable
+) -> Result<Option<String>, Error> {
+    assemble_tokens(tokens, keys, common_keys, &mut vec![])
+}
+
+fn assemble_tokens(
+    tokens: Vec<PreprocessorToken>,
+    keys: &HashMap<String, AnyPreprocessable>,
+    common_keys: &CommonKeyable,
+    including: &mut Vec<(PathBuf, String)>
 ) -> Result<Option<String>, Error> {
 
     let mut assembled_string = String::new();
@@ -350,14 +627,38 @@ pub fn preprocessor_token_assembly_attempt(
 
         match token {
             PreprocessorToken::Raw(s) => {
-                assembled_string.push_str(&s);
+                assembled_string.push_str(s);
             }
-            PreprocessorToken::Key(key) => {
+            PreprocessorToken::Key(reference) => {
+                let (key, tags) = split_reference(reference);
+                if let Some(path) = key.strip_prefix(FILE_REFERENCE) {
+                    let Some(included) = include_file(path, keys, common_keys, including)? else {
+                        return Ok(None)
+                    };
+                    assembled_string.push_str(&apply_reference_tags(&tags, included, common_keys)?);
+                    continue
+                }
                 let Some(preprocessable) = keys.get(key) else {
+                    let mut qualified: Vec<&String> = keys
+                        .keys()
+                        .filter(|k| k
+                            .split_once(KEY_SCOPE_SEPARATOR)
+                            .is_some_and(|(_, unqualified)| unqualified == key)
+                        )
+                        .collect();
+                    qualified.sort();
+                    let hint = match qualified.len() {
+                        0 => String::new(),
+                        1 => format!(", did you mean `{}`?", qualified[0]),
+                        _ => format!(
+                            ", it is ambiguous between {}, use one of them instead",
+                            qualified.iter().map(|k| format!("`{k}`")).collect::<Vec<String>>().join(", ")
+                        )
+                    };
                     return Err(Error { 
                         kind: ErrorKind::NonExistantReference, 
                         message: format!(
-                            "string was seperated into tokens: {:?}... but the token {:?} contains a key that doesn't exist",
+                            "string was seperated into tokens: {:?}... but the token {:?} contains a key that doesn't exist{hint}",
                             tokens, token
                         )
                     })
@@ -375,7 +676,7 @@ pub fn preprocessor_token_assembly_attempt(
                                 return Ok(None)
                             },
                             Preprocessable::Preprocessed(name) => {
-                                assembled_string.push_str(name.as_str());
+                                assembled_string.push_str(&apply_reference_tags(&tags, name.clone(), common_keys)?);
                             }
                         }
                     }
@@ -391,7 +692,7 @@ pub fn preprocessor_token_assembly_attempt(
                                 return Ok(None)
                             },
                             Preprocessable::Preprocessed(string) => {
-                                assembled_string.push_str(string);
+                                assembled_string.push_str(&apply_reference_tags(&tags, string.clone(), common_keys)?);
                             }
                         }
                     } 
@@ -405,7 +706,78 @@ pub fn preprocessor_token_assembly_attempt(
 
 }
 
-/// Preprocess key name pairs from `keys` and finialize them.
+/// [Tag::apply_to_reference] with a preprocessor error.
+fn apply_reference_tags(
+    tags: &[Tag],
+    value: String,
+    common_keys: &CommonKeyable
+) -> Result<String, Error> {
+    Tag::apply_to_reference(tags, value, common_keys).map_err(|err| Error {
+        kind: ErrorKind::Transform,
+        message: err.to_string()
+    })
+}
+
+/// Find a chain of key references among the `unresolved` keys that ends
+/// where it started, `a → b → c → a`.
+fn find_reference_cycle(
+    unresolved: &[(String, Name)],
+    common_keys: &CommonKeyable
+) -> Option<Vec<String>> {
+
+    let references: HashMap<&str, Vec<String>> = unresolved
+        .iter()
+        .map(|(key, name)| (
+            key.as_str(),
+            name.to_preprocessor_tokens(common_keys)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|token| match token {
+                    PreprocessorToken::Key(reference) => Some(split_reference(&reference).0.to_owned()),
+                    PreprocessorToken::Raw(_) => None
+                })
+                .collect()
+        ))
+        .collect();
+
+    let mut starts: Vec<&str> = references.keys().copied().collect();
+    // same cycle every time.
+    starts.sort();
+
+    for start in starts {
+        let mut path: Vec<&str> = vec![start];
+        let mut current = start;
+        // every key is unresolved because of one of its references, so
+        // follow the first unresolved one until a key repeats.
+        while let Some(next) = references.get(current)
+            .and_then(|refs| refs.iter().find(|r| references.contains_key(r.as_str()))) {
+            if let Some(position) = path.iter().position(|key| key == next) {
+                let mut cycle: Vec<String> = path[position..].iter().map(|key| key.to_string()).collect();
+                cycle.push(next.clone());
+                return Some(cycle)
+            }
+            path.push(next);
+            current = next;
+        }
+    }
+
+    None
+
+}
+
+/// Keys that are preprocessed together, the [KeyScope::own] keys are
+/// the ones this scope is responsible for and [KeyScope::keys] is what
+/// their key references are looked up in.
+/// 
+/// The global scope owns all global keys while every [Generator] scope
+/// owns only its local keys but looks them up in the global keys layered
+/// with its local ones.
+pub struct KeyScope<'a> {
+    pub keys: &'a HashMap<String, AnyPreprocessable>,
+    pub own:  Vec<String>
+}
+
+/// Preprocess key name pairs from every scope in `scopes` and finialize them.
 /// 
 /// Since the unpreprocessed key name pairs are stored in a [AnyPreprocessable] 
 /// they can be written to and the changes will be reflected in the [Config] they 
@@ -415,18 +787,23 @@ pub fn preprocessor_token_assembly_attempt(
 /// preprocessed all the key name pairs from `keys` and written the results 
 /// back into the [AnyPreprocessable].
 pub fn preprocess_key_name_pairs(
-    keys: &HashMap<String, AnyPreprocessable>,
+    scopes: &[KeyScope],
     common_keys: &CommonKeyable
 ) -> Result<(), Error> {
 
-    let mut left = keys.len();
+    let mut left: usize = scopes.iter().map(|scope| scope.own.len()).sum();
     
     while left != 0 {
 
         let now_left: Mutex<usize> = Mutex::new(0);
-        for (key, preprocessable) in keys.iter() {
+        for (keys, key) in scopes.iter().flat_map(|scope| scope.own.iter().map(|key| (scope.keys, key))) {
 
-            let tokens = match preprocessable {
+            let Some(preprocessable) = keys.get(key) else {
+                continue
+            };
+
+            // assembled while the lock is held since the tokens borrow from it.
+            let attempt = match preprocessable {
                 AnyPreprocessable::Name(name) => {
                     let name_kind = name.read()
                         .map_err(|err| Error {
@@ -435,7 +812,11 @@ pub fn preprocess_key_name_pairs(
                         })?;
                     match &*name_kind {
                         Preprocessable::NotPreprocessed(name) => {
-                            name.into_preprocessor_tokens(common_keys)?
+                            log::trace!("{}", 
+                                format!("Attempting to preprocess key `{key}` with name `{:?}`.", name)
+                                .dimmed()
+                            );
+                            preprocessor_token_assembly_attempt(name.to_preprocessor_tokens(common_keys)?, keys, common_keys)?
                         }
                         Preprocessable::Preprocessed(name) => {
                             log::trace!("{}", 
@@ -454,7 +835,11 @@ pub fn preprocess_key_name_pairs(
                         })?;
                     match &*s_kind {
                         Preprocessable::NotPreprocessed(s) => {
-                            s.into_preprocessor_tokens(common_keys)?
+                            log::trace!("{}", 
+                                format!("Attempting to preprocess key `{key}` with name `{:?}`.", s)
+                                .dimmed()
+                            );
+                            preprocessor_token_assembly_attempt(s.to_preprocessor_tokens(common_keys)?, keys, common_keys)?
                         }
                         Preprocessable::Preprocessed(s) => {
                             log::trace!("{}", 
@@ -467,17 +852,9 @@ pub fn preprocess_key_name_pairs(
                 }
             };
 
-            log::trace!("{}", 
-                format!("Attempting to preprocess key `{key}` with name `{:?}`.", preprocessable)
-                .dimmed()
-            );
-
-            let Some(preprocessed_string) = preprocessor_token_assembly_attempt(
-                tokens,
-                &keys
-            )? else {
+            let Some(preprocessed_string) = attempt else {
                 log::trace!("{}",
-                    format!("Key was not preprocessed successfully as it has dependencies that are not preprocessed themselves.")
+                    "Key was not preprocessed successfully as it has dependencies that are not preprocessed themselves.".to_string()
                     .truecolor(255, 165, 0).dimmed()
                 );
                 let mut guard = now_left.lock().unwrap();
@@ -512,10 +889,12 @@ pub fn preprocess_key_name_pairs(
 
         let guard_left= now_left.lock().unwrap();
 
-        if &*guard_left >= &left {
-            let key_names: Vec<(String, Name)> = keys
-                .clone()
-                .into_iter()
+        if *guard_left >= left {
+            let key_names: Vec<(String, Name)> = scopes
+                .iter()
+                .flat_map(|scope| scope.own.iter().filter_map(|key| {
+                    scope.keys.get(key).map(|v| (key.clone(), v.clone()))
+                }))
                 .filter_map(|(k, v)| {
                     match v {
                         AnyPreprocessable::Name(preprocessable_name) => {
@@ -534,6 +913,15 @@ pub fn preprocess_key_name_pairs(
                     }
                 })
                 .collect();
+            if let Some(cycle) = find_reference_cycle(&key_names, common_keys) {
+                return Err(Error {
+                    kind: ErrorKind::MutualReferences,
+                    message: format!(
+                        "keys reference each other in a cycle: {}",
+                        cycle.join(" → ")
+                    )
+                })
+            }
             return Err(Error {
                 kind: ErrorKind::MutualReferences,
                 message: format!(
@@ -551,10 +939,94 @@ pub fn preprocess_key_name_pairs(
     Ok(())
 }
 
+/// Every substitution made while preprocessing a string, starting with
+/// the string before any key was substituted.
+/// 
+/// The keys are the addresses of the [PreprocessableString]s (see
+/// [substitution_trace_key]) since the raw string is gone once the string
+/// is preprocessed.
+pub type SubstitutionTraces = Arc<RwLock<HashMap<usize, Vec<String>>>>;
+
+pub fn substitution_trace_key(s: &PreprocessableString) -> usize {
+    Arc::as_ptr(s) as usize
+}
+
+/// Every key referenced by `keys` and `strings` that aren't preprocessed
+/// yet, strings that don't tokenize are skipped since preprocessing
+/// reports them on its own.
+fn referenced_keys<'a>(
+    keys: impl Iterator<Item = &'a AnyPreprocessable>,
+    strings: &[PreprocessableString],
+    common_keys: &CommonKeyable
+) -> HashSet<String> {
+
+    let mut referenced: HashSet<String> = HashSet::new();
+    let mut reference = |tokens: Vec<PreprocessorToken>| referenced.extend(tokens
+        .into_iter()
+        .filter_map(|token| match token {
+            PreprocessorToken::Key(key) => Some(split_reference(&key).0.to_owned()),
+            PreprocessorToken::Raw(_) => None
+        })
+    );
+
+    for key in keys {
+        if let AnyPreprocessable::Name(name) = key
+            && let Ok(name) = name.read()
+            && let Preprocessable::NotPreprocessed(name) = &*name {
+            reference(name.to_preprocessor_tokens(common_keys).unwrap_or_default());
+        }
+    }
+    for string in strings {
+        if let Ok(string) = string.read()
+            && let Preprocessable::NotPreprocessed(string) = &*string {
+            reference(string.to_preprocessor_tokens(common_keys).unwrap_or_default());
+        }
+    }
+
+    referenced
+
+}
+
+/// Substitute the keys in `tokens` one by one and record the string
+/// after every substitution, [None] when there is nothing to substitute.
+fn substitution_trace(
+    tokens: &[PreprocessorToken],
+    keys: &HashMap<String, AnyPreprocessable>,
+    common_keys: &CommonKeyable
+) -> Option<Vec<String>> {
+
+    let render = |tokens: &[PreprocessorToken]| tokens
+        .iter()
+        .map(|token| match token {
+            PreprocessorToken::Raw(raw) => raw.to_string(),
+            PreprocessorToken::Key(_) => token.untokenize()
+        })
+        .collect::<String>();
+
+    let mut tokens = tokens.to_vec();
+    let mut trace = vec![render(&tokens)];
+
+    for i in 0..tokens.len() {
+        let PreprocessorToken::Key(key) = &tokens[i] else {
+            continue
+        };
+        let reference = tokens[i].untokenize();
+        let (key, tags) = split_reference(key);
+        let value = Tag::apply_to_reference(&tags, keys.get(key)?.preprocessed()?, common_keys).ok()?;
+        tokens[i] = PreprocessorToken::Raw(Cow::Owned(value));
+        trace.push(format!("after {reference}: {}", render(&tokens)));
+    }
+
+    (trace.len() > 1).then_some(trace)
+
+}
+
 pub fn preprocess_strings(
     preprocessable_strings: Vec<PreprocessableString>,
     keys: &HashMap<String, AnyPreprocessable>,
-    common_keys: &CommonKeyable
+    common_keys: &CommonKeyable,
+    traces: &SubstitutionTraces,
+    metadata: &Metadata
 ) -> Result<(), Error> {
     
     for ps in preprocessable_strings {
@@ -567,15 +1039,27 @@ pub fn preprocess_strings(
 
         let tokens = match &*ps_read {
             Preprocessable::NotPreprocessed(s) => {
-                s.into_preprocessor_tokens(common_keys)?
+                s.to_preprocessor_tokens(common_keys)?
             }
             Preprocessable::Preprocessed(_) => continue
         };
 
-        let preprocessed = match preprocessor_token_assembly_attempt(tokens, keys) {
+        if let Some(trace) = substitution_trace(&tokens, keys, common_keys) {
+            traces.write()
+                .map_err(|err| Error {
+                    kind: ErrorKind::PoisonedLock,
+                    message: err.to_string() 
+                })?
+                .insert(substitution_trace_key(&ps), trace);
+        }
+
+        let preprocessed = match preprocessor_token_assembly_attempt(tokens, keys, common_keys) {
             Ok(Some(s)) => s,
             Ok(None) => unreachable!(),
-            Err(err) => return Err(err)
+            Err(err) => return Err(Error { 
+                kind: err.kind, 
+                message: metadata.annotate(err.message) 
+            })
         };
 
         log::trace!("{}",
@@ -598,46 +1082,201 @@ pub fn preprocess_strings(
     Ok(())
 }
 
-    
+
+/// Insert a key into `keys` unless it already exists, `origin` describes
+/// where the key was defined so that both copies can be pointed at.
+/// Every string of [CommonKeyable] along side its key, the tables of
+/// [CommonKeyable::keys] are flattened into dotted keys.
+pub(crate) fn common_key_values(
+    keyable: &CommonKeyable
+) -> Result<Vec<(String, String)>, Error> {
+
+    fn flatten(key: String, value: serde_json::Value, values: &mut Vec<(String, String)>) {
+        match value {
+            serde_json::Value::String(s) => values.push((key, s)),
+            serde_json::Value::Object(table) => for (k, v) in table {
+                let key = match key.is_empty() {
+                    true => k,
+                    false => format!("{key}{KEY_SCOPE_SEPARATOR}{k}")
+                };
+                flatten(key, v, values);
+            }
+            _ => {}
+        }
+    }
+
+    let serde_json::Value::Object(common) = serde_json::to_value(keyable)
+        .map_err(|_| Error {
+            kind: ErrorKind::Serialization,
+            message: "Failed to serialize keyable common values.".to_owned() 
+        })? else {
+        return Err(Error {
+            kind: ErrorKind::Serialization,
+            message: "Failed to create object from serialized keyable common values.".to_owned() 
+        })
+    };
+
+    let mut values = vec![];
+    for (k, v) in common {
+        match (k.as_str(), v) {
+            // the keys table itself isn't part of the key names.
+            ("keys", keys) => flatten(String::new(), keys, &mut values),
+            (_, serde_json::Value::String(s)) => values.push((k, s)),
+            _ => {}
+        }
+    }
+    Ok(values)
+
+}
+
+fn insert_key(
+    keys: &mut HashMap<String, AnyPreprocessable>,
+    origins: &mut HashMap<String, String>,
+    key: String,
+    value: AnyPreprocessable,
+    origin: String
+) -> Result<(), Error> {
+
+    if let Some(first) = origins.get(&key) {
+        return Err(Error{
+            kind: ErrorKind::DuplicateKey,
+            message: format!(
+                "Key {key} must be unique, but it was first defined in {first} and redefined in {origin}."
+            )
+        })
+    }
+
+    origins.insert(key.clone(), origin);
+    keys.insert(key, value);
+
+    Ok(())
+
+}
+
+impl Generator {
+
+    /// Loads all preprocessable strings from the generator, these are
+    /// preprocessed with the generator's own keys layered on top of the
+    /// global ones.
+    fn load_preprocessable_strings(&self) -> Vec<PreprocessableString> {
+
+        let mut preprocessables: Vec<PreprocessableString> = vec![
+            self.preamble.clone(),
+            self.repeat.clone(),
+            self.postamble.clone()
+        ];
+        if let Some(condition) = &self.condition {
+            preprocessables.push(condition.clone());
+        }
+        preprocessables.push(self.fallbacks.unparity.clone());
+        preprocessables.push(self.fallbacks.empty.clone());
+        preprocessables.extend(self.fallbacks.unparity_remainder.values().cloned());
+
+        preprocessables
+
+    }
+
+    /// Layer the generator's own keys over the global `keys`, a local key
+    /// with the same name as a global one shadows it.
+    /// 
+    /// Builtin keys that describe the generator itself are layered in
+    /// aswell, see [BUILTIN_GENERATOR_NAME], [BUILTIN_GENERATOR_INDEX],
+    /// [BUILTIN_REPEATS] and [BUILTIN_GROUP_SIZE], so are the library
+    /// parameters from [Generator::with].
+    fn layer_key_name_pairs(
+        &self,
+        index: usize,
+        repeats: usize,
+        group_size: usize,
+        keys: &HashMap<String, AnyPreprocessable>
+    ) -> Result<HashMap<String, AnyPreprocessable>, Error> {
+
+        let mut layered = keys.clone();
+        let mut local: Vec<&str> = vec![];
+
+        let mut builtins = vec![
+            (BUILTIN_GENERATOR_NAME, self.name.clone().unwrap_or(index.to_string())),
+            (BUILTIN_GENERATOR_INDEX, index.to_string()),
+            (BUILTIN_REPEATS, repeats.to_string()),
+            (BUILTIN_GROUP_SIZE, group_size.to_string())
+        ];
+        builtins.extend(self.with.iter().map(|(param, value)| (param.as_str(), value.clone())));
+        for (key, value) in builtins.iter() {
+            layered.insert((*key).to_owned(), AnyPreprocessable::String(
+                Arc::new(RwLock::new(Preprocessable::Preprocessed(value.clone())))
+            ));
+        }
+
+        for (i, key) in self.keys.iter().flatten().enumerate() {
+            if let Some(first) = local.iter().position(|local| *local == key.key) {
+                return Err(Error{
+                    kind: ErrorKind::DuplicateKey,
+                    message: format!(
+                        "Key {} must be unique inside of generator {index}, but it was first defined in keys[{first}] and redefined in keys[{i}].",
+                        key.key
+                    )
+                })
+            }
+            if keys.contains_key(&key.key) || builtins.iter().any(|(builtin, _)| *builtin == key.key) {
+                lint::emit(Lint::ShadowedKey, &format!(
+                    "key `{}` of generator {index} shadows a global key with the same name.",
+                    key.key
+                ));
+            }
+            local.push(&key.key);
+            layered.insert(key.key.clone(), AnyPreprocessable::Name(key.name.clone()));
+        }
+
+        Ok(layered)
+
+    }
+
+}
+
 impl Config {
 
     /// Loads all preprocessable strings from the config that are not
-    /// key name pairs.
+    /// key name pairs (and not from a [Generator]).
     fn load_preprocessable_strings(&self) -> Vec<PreprocessableString> {
 
         let mut preprocessables: Vec<PreprocessableString> = vec![];
 
-        if let Some(preamble) = &self.preamble {
-            if let Some(raw) = &preamble.raw {
+        preprocessables.extend(self.includes.system.iter().cloned());
+        preprocessables.extend(self.includes.local.iter().cloned());
+
+        if let Some(preamble) = &self.preamble
+            && let Some(raw) = &preamble.raw {
+                preprocessables.push(raw.clone());
+            }
+
+        if let Some(postamble) = &self.postamble
+            && let Some(raw) = &postamble.raw {
                 preprocessables.push(raw.clone());
             }
-        }
 
         if let Some(definitions) = &self.definition {
             for def in definitions {
                 preprocessables.push(def.expansion.clone());
+                if let Some(condition) = &def.condition {
+                    preprocessables.push(condition.clone());
+                }
             }
         }
 
         preprocessables.push(self.core.xmva.clone());
+        if let Some(list) = &self.core.list {
+            preprocessables.push(list.clone());
+        }
 
         for arg in self.core.args.iter() {
             match arg {
-                &Argument::Named(ref named) => {
+                Argument::Named(named) => {
                     preprocessables.push(named.name.clone())
                 }
                 &Argument::Varadict { varadict: _ } => ()
             }
         }
 
-        for generator in &self.generator {
-            preprocessables.push(generator.preamble.clone());
-            preprocessables.push(generator.repeat.clone());
-            preprocessables.push(generator.postamble.clone());
-            preprocessables.push(generator.fallbacks.unparity.clone());
-            preprocessables.push(generator.fallbacks.empty.clone());
-        }
-
         preprocessables
 
     }
@@ -657,74 +1296,76 @@ impl Config {
     /// Also worthy of noting, the value of the [HashMap] is [AnyPreprocessable]
     /// which holds a [Arc]<[RwLock]<>> of the name data, meaning that any change
     /// done within the [RwLock] is reflected on the config itself.
-    fn load_preprocessable_key_name_pairs(&self) -> Result<HashMap<String, AnyPreprocessable>, Error> {
+    pub(crate) fn load_preprocessable_key_name_pairs(&self) -> Result<HashMap<String, AnyPreprocessable>, Error> {
         let mut keys: HashMap<String, AnyPreprocessable> = HashMap::new();
 
         // Vrijednosti iz CommonKeyable mogu se pojaviti kao ključevi unutar
         // imena.
-        let common_keys: Vec<(String, serde_json::Value)>  = serde_json::to_value(&self.common.keyable)
-            .map_err(|_| Error {
-                kind: ErrorKind::Serialization,
-                message: "Failed to serialize keyable common values.".to_owned() 
-            })?
-            .as_object()
-            .ok_or_else(|| Error {
-                kind: ErrorKind::Serialization,
-                message: "Failed to create object from serialized keyable common values.".to_owned() 
-            })?
-            .into_iter()
-            .map(|(k, v)| (k.to_owned(), v.to_owned()))
-            .collect();
+        let common_keys = common_key_values(&self.common.keyable)?;
+
+        // where every key was defined, for duplicate key errors.
+        let mut origins: HashMap<String, String> = HashMap::new();
+
+        for (k, s) in common_keys {
+            // Common varijable su uvijek čiste od kljuceva unutar sebe
+            // te ih mozemo odma staviti kao preprocesirane.
+            insert_key(&mut keys, &mut origins, 
+                k.clone(),
+                AnyPreprocessable::String(
+                    Arc::new(RwLock::new(Preprocessable::Preprocessed(s)))
+                ),
+                format!("[common] {k}")
+            )?;
+        }
 
-        for (k, v) in common_keys {
-            if let serde_json::Value::String(s) = v {
-                if keys.contains_key(&k) {
-                    return Err(Error{
-                        kind: ErrorKind::DuplicateKey,
-                        message: format!(
-                            "Common key {k} must be unique, but multiple keys with the same name were found."
-                        )
-                    })
+        let ambles = [("preamble", self.preamble.as_ref()), ("postamble", self.postamble.as_ref())];
+        for (section, amble) in ambles {
+            if let Some(amble) = amble 
+                && let Some(amble_keys) = amble.keys.as_ref() {
+                for (i, key) in amble_keys.iter().enumerate() {
+                    insert_key(&mut keys, &mut origins,
+                        key.key.clone(),
+                        AnyPreprocessable::Name(key.name.clone()),
+                        format!("[{section}] keys[{i}]")
+                    )?;
                 }
-                // Common varijable su uvijek čiste od kljuceva unutar sebe
-                // te ih mozemo odma staviti kao preprocesirane.
-                keys.insert(k, 
-                    AnyPreprocessable::String(
-                        Arc::new(RwLock::new(Preprocessable::Preprocessed(s)))
-                    )
-                );
             }
         }
 
-        if let Some(preamble) = self.preamble.as_ref() {
-            if let Some(preamble_keys) = preamble.keys.as_ref() {
-                for key in preamble_keys {
-                    if keys.contains_key(&key.key) {
-                        return Err(Error{
-                            kind: ErrorKind::DuplicateKey,
-                            message: format!(
-                                "Key {} must be unique, but multiple keys with the same name were found.",
-                                key.key
-                            )
-                        })
-                    }
-                    keys.insert(key.key.clone(), AnyPreprocessable::Name(key.name.clone()));
-                }
+        if let Some(definitions) = self.definition.as_ref() {
+            for (i, definition) in definitions.iter().enumerate() {
+                insert_key(&mut keys, &mut origins,
+                    definition.key.clone(),
+                    AnyPreprocessable::Name(definition.name.clone()),
+                    format!("[[definition]] {i}")
+                )?;
             }
         }
 
-        if let Some(definitions) = self.definition.as_ref() {
-            for definition in definitions {
-                if keys.contains_key(&definition.key) {
-                    return Err(Error{
-                        kind: ErrorKind::DuplicateKey,
-                        message: format!(
-                            "Key {} must be unique, but multiple keys with the same name were found.",
-                        definition.key
-                        )
-                    })
-                }
-                keys.insert(definition.key.clone(),AnyPreprocessable::Name(definition.name.clone()));
+        for (i, used) in self.used.iter().enumerate() {
+            let builtins = [(BUILTIN_USED_XMVA, &used.xmva), (BUILTIN_USED_PREFIX, &used.prefix)];
+            for (builtin, value) in builtins {
+                insert_key(&mut keys, &mut origins,
+                    format!("{}{KEY_SCOPE_SEPARATOR}{builtin}", used.alias),
+                    AnyPreprocessable::String(
+                        Arc::new(RwLock::new(Preprocessable::Preprocessed(value.clone())))
+                    ),
+                    format!("uses[{i}]")
+                )?;
+            }
+        }
+
+        // keys of named generators are visible everywhere when qualified.
+        for (i, generator) in self.generator.iter().enumerate() {
+            let Some(scope) = &generator.name else {
+                continue
+            };
+            for (j, key) in generator.keys.iter().flatten().enumerate() {
+                insert_key(&mut keys, &mut origins,
+                    format!("{scope}{KEY_SCOPE_SEPARATOR}{}", key.key),
+                    AnyPreprocessable::Name(key.name.clone()),
+                    format!("[[generator]] {i} keys[{j}]")
+                )?;
             }
         }
 
@@ -733,9 +1374,52 @@ impl Config {
     }
     
 
+    /// Emit [Lint::UnusedKey] for every key of the preamble, postamble or
+    /// a generator that no string or key name references, has to run
+    /// before preprocessing since that replaces the references.
+    fn lint_unused_keys(
+        &self,
+        keys: &HashMap<String, AnyPreprocessable>,
+        layered_keys: &[HashMap<String, AnyPreprocessable>]
+    ) {
+
+        let mut strings = self.load_preprocessable_strings();
+        for generator in self.generator.iter() {
+            strings.extend(generator.load_preprocessable_strings());
+        }
+        let referenced = referenced_keys(
+            keys.values().chain(layered_keys.iter().flat_map(|layered| layered.values())),
+            &strings,
+            &self.common.keyable
+        );
+
+        let ambles = [("[preamble]", self.preamble.as_ref()), ("[postamble]", self.postamble.as_ref())];
+        for (section, amble) in ambles {
+            for key in amble.and_then(|amble| amble.keys.as_ref()).into_iter().flatten() {
+                if !referenced.contains(&key.key) {
+                    lint::emit(Lint::UnusedKey, &format!("key `{}` of {section} is never used.", key.key));
+                }
+            }
+        }
+
+        for (i, generator) in self.generator.iter().enumerate() {
+            for key in generator.keys.iter().flatten() {
+                let qualified = generator.name
+                    .as_ref()
+                    .map(|name| format!("{name}{KEY_SCOPE_SEPARATOR}{}", key.key));
+                if !referenced.contains(&key.key) 
+                    && qualified.is_none_or(|qualified| !referenced.contains(&qualified)) {
+                    lint::emit(Lint::UnusedKey, &format!("key `{}` of generator {i} is never used.", key.key));
+                }
+            }
+        }
+
+    }
+
     pub fn preprocess(&self) -> Result<(), Error> {
 
         log::debug!("Starting to preprocess the config.");
+        set_include_directory(&self.metadata.directory);
 
         log::debug!("Loading key name pairs...");
         let keys = self.load_preprocessable_key_name_pairs()?;
@@ -743,8 +1427,44 @@ impl Config {
             format!("Loaded keys: {:#?}", keys).dimmed()
         );
         
+        log::debug!("Layering generator keys...");
+        let layered_keys = self.generator
+            .iter()
+            .enumerate()
+            .map(|(i, generator)| generator.layer_key_name_pairs(
+                i, 
+                self.common.repeats, 
+                self.core.varadict().unwrap_or(0), 
+                &keys
+            ))
+            .collect::<Result<Vec<HashMap<String, AnyPreprocessable>>, Error>>()?;
+
+        // qualified generator keys are owned by their generators scope.
+        let qualified: Vec<String> = self.generator
+            .iter()
+            .filter_map(|generator| generator.name.as_ref().map(|name| (name, generator)))
+            .flat_map(|(name, generator)| generator.keys
+                .iter()
+                .flatten()
+                .map(move |key| format!("{name}{KEY_SCOPE_SEPARATOR}{}", key.key))
+            )
+            .collect();
+
+        // sorted so that every run resolves (and reports) keys in the same order.
+        let mut own: Vec<String> = keys.keys().filter(|key| !qualified.contains(key)).cloned().collect();
+        own.sort();
+        let mut scopes = vec![KeyScope { keys: &keys, own }];
+        for (generator, layered) in self.generator.iter().zip(layered_keys.iter()) {
+            scopes.push(KeyScope {
+                keys: layered,
+                own: generator.keys.iter().flatten().map(|key| key.key.clone()).collect()
+            });
+        }
+        
+        self.lint_unused_keys(&keys, &layered_keys);
+
         log::debug!("Preprocessing key name pairs...");
-        preprocess_key_name_pairs(&keys, &self.common.keyable)?;
+        preprocess_key_name_pairs(&scopes, &self.common.keyable)?;
 
         log::debug!("Loading all preprocessable strings...");
         let preprocessable_strings = self.load_preprocessable_strings();
@@ -753,9 +1473,30 @@ impl Config {
         );
 
         log::debug!("Preprocessing strings...");
-        preprocess_strings(preprocessable_strings, &keys, &self.common.keyable)?;
+        preprocess_strings(
+            preprocessable_strings, 
+            &keys, 
+            &self.common.keyable, 
+            &self.substitutions,
+            &self.metadata
+        )?;
+
+        log::debug!("Preprocessing generators...");
+        for (i, (generator, layered)) in self.generator.iter().zip(layered_keys.iter()).enumerate() {
+            preprocess_strings(
+                generator.load_preprocessable_strings(), 
+                layered, 
+                &self.common.keyable, 
+                &self.substitutions,
+                &self.metadata.in_generator(i, generator.name.clone())
+            )?;
+        }
 
-        return Ok(())
+        lint::names(self);
+        lint::compiler_tokens(self);
+        lint::repeats(self);
+
+        Ok(())
 
     }
 
@@ -780,8 +1521,8 @@ mod tests {
                 "hello world@{prefix}"
             ).unwrap(),
             vec![
-                PreprocessorToken::Raw("hello world".to_owned()),
-                PreprocessorToken::Key("prefix".to_owned())
+                PreprocessorToken::Raw("hello world".into()),
+                PreprocessorToken::Key("prefix".into())
             ]
         );
 
@@ -796,13 +1537,13 @@ mod tests {
                 "@{#$%\"\"!23O1''???ŠSĆDsl😍💕😳****}\\@{destroyer}\\\\@{beyonce}#$%\"\"!23O1''???ŠSĆDsl😍💕😳****@{prefix}@{dufus}\\\\"
             ).unwrap(),
             vec![
-                PreprocessorToken::Key("#$%\"\"!23O1''???ŠSĆDsl😍💕😳****".to_owned()),
-                PreprocessorToken::Raw("@{destroyer}\\".to_owned()),
-                PreprocessorToken::Key("beyonce".to_owned()),
-                PreprocessorToken::Raw("#$%\"\"!23O1''???ŠSĆDsl😍💕😳****".to_owned()),
-                PreprocessorToken::Key("prefix".to_owned()),
-                PreprocessorToken::Key("dufus".to_owned()),
-                PreprocessorToken::Raw("\\".to_owned()),
+                PreprocessorToken::Key("#$%\"\"!23O1''???ŠSĆDsl😍💕😳****".into()),
+                PreprocessorToken::Raw("@{destroyer}\\".into()),
+                PreprocessorToken::Key("beyonce".into()),
+                PreprocessorToken::Raw("#$%\"\"!23O1''???ŠSĆDsl😍💕😳****".into()),
+                PreprocessorToken::Key("prefix".into()),
+                PreprocessorToken::Key("dufus".into()),
+                PreprocessorToken::Raw("\\".into()),
             ]
         );
     
@@ -821,7 +1562,7 @@ mod tests {
                 "\\@ \\\\\\\\"
             ).unwrap(),
             vec![
-                PreprocessorToken::Raw("@ \\\\".to_owned())
+                PreprocessorToken::Raw("@ \\\\".into())
             ]
         );
 
@@ -845,6 +1586,46 @@ mod tests {
 
     }
 
+    #[test]
+    fn tokenize_and_untokenize() {
+
+        let s = "\\@ @{prefix}\\\\ ${named} @{😳}";
+
+        assert_eq!(
+            s.to_owned(),
+            preprocessor_string_tokenizer(s)
+                .unwrap()
+                .into_iter()
+                .map(|x| x.untokenize())
+                .collect::<Vec<String>>()
+                .join("")
+        );
+
+    }
+
+    #[test]
+    fn tokenize_and_untokenize_custom_embed() {
+
+        crate::sigil::set_overrides(
+            HashMap::from([(PreprocessorSigil::TokenEmbed, "^".to_owned())]), 
+            HashMap::new()
+        ).unwrap();
+
+        let s = "^@ \\n @{prefix}^^ \"C:\\path\" @{😳}";
+        let tokens = preprocessor_string_tokenizer(s).unwrap();
+
+        assert_eq!(tokens[0], PreprocessorToken::Raw("@ \\n ".into()));
+        assert_eq!(
+            s.to_owned(),
+            tokens
+                .into_iter()
+                .map(|x| x.untokenize())
+                .collect::<Vec<String>>()
+                .join("")
+        );
+
+    }
+
     #[test]
     fn tokenizer_check_no_empty_raws() {
 
@@ -855,9 +1636,9 @@ mod tests {
                 "@{hello}@{hi}@{byebye}"
             ).unwrap(),
             vec![
-                PreprocessorToken::Key("hello".to_owned()),
-                PreprocessorToken::Key("hi".to_owned()),
-                PreprocessorToken::Key("byebye".to_owned()),
+                PreprocessorToken::Key("hello".into()),
+                PreprocessorToken::Key("hi".into()),
+                PreprocessorToken::Key("byebye".into()),
             ]
         );
 
@@ -902,5 +1683,226 @@ mod tests {
 
     }
 
+    #[test]
+    fn reference_cycle() {
+
+        let common_keys = CommonKeyable {
+            prefix: "P_".to_owned(),
+            suffix: String::new(),
+            namespace: String::new(),
+            separator: String::new(),
+            keys: Default::default()
+        };
+        let unresolved = vec![
+            ("d".to_owned(), Name::Raw("@{a}".to_owned())),
+            ("a".to_owned(), Name::Raw("@{b}".to_owned())),
+            ("b".to_owned(), Name::Raw("x@{a}".to_owned())),
+        ];
+
+        assert_eq!(
+            find_reference_cycle(&unresolved, &common_keys),
+            Some(vec!["a".to_owned(), "b".to_owned(), "a".to_owned()])
+        );
+        assert_eq!(find_reference_cycle(&unresolved[..1], &common_keys), None);
+
+    }
+
+    #[test]
+    fn reference_tags() {
+
+        let common_keys: CommonKeyable = toml::from_str(
+            "prefix = \"P_\"\nsuffix = \"_T\"\nnamespace = \"ns\"\nseparator = \"__\"\n"
+        ).unwrap();
+        let tagged = StringWithTags { tags: vec![Tag::Lower], string: "ERROR".to_owned(), transforms: vec![] };
+        assert_eq!(tagged.apply_tags(&common_keys).unwrap(), "ns__p_error_t");
 
-}
\ No newline at end of file
+        let (key, tags) = split_reference("err|NO_PREFIX|NO_SUFFIX|LOWER");
+        assert_eq!(key, "err");
+        assert_eq!(tags, vec![Tag::NoPrefix, Tag::NoSuffix, Tag::Lower]);
+        assert_eq!(Tag::apply_to_reference(&tags, "ns__P_ERROR_T".to_owned(), &common_keys).unwrap(), "ns__error");
+
+        let (_, tags) = split_reference("err|NO_NAMESPACE|UPPER");
+        assert_eq!(Tag::apply_to_reference(&tags, "ns__P_ERROR_T".to_owned(), &common_keys).unwrap(), "P_ERROR_T");
+        // already gone.
+        assert_eq!(Tag::apply_to_reference(&[Tag::NoPrefix], "ERROR".to_owned(), &common_keys).unwrap(), "ERROR");
+        assert_eq!(split_reference("err|missing").1, vec![Tag::Chain("missing".to_owned())]);
+
+    }
+
+    #[test]
+    fn include_cycle() {
+
+        let directory = std::env::temp_dir().join(format!("xmva-include-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let a = directory.join("a.h").display().to_string();
+        let b = directory.join("b.h").display().to_string();
+        let c = directory.join("c.h").display().to_string();
+        fs::write(&a, format!("a @{{file:{b}}}")).unwrap();
+        fs::write(&b, format!("b @{{file:{a}}}")).unwrap();
+        fs::write(&c, "c @{prefix}").unwrap();
+
+        let common_keys: CommonKeyable = toml::from_str("prefix = \"P_\"").unwrap();
+        let keys = HashMap::from([(
+            "prefix".to_owned(),
+            AnyPreprocessable::String(Arc::new(RwLock::new(Preprocessable::Preprocessed("P_".to_owned()))))
+        )]);
+        let assemble = |s: &str| preprocessor_token_assembly_attempt(
+            preprocessor_string_tokenizer(s).unwrap(), &keys, &common_keys
+        );
+
+        assert_eq!(assemble(&format!("<@{{file:{c}|LOWER}}>")).unwrap().unwrap(), "<c p_>");
+        assert!(included_files().contains(&PathBuf::from(&c).canonicalize().unwrap()));
+        let err = assemble(&format!("@{{file:{a}}}")).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::IncludeCycle);
+        assert!(err.message.ends_with(&format!("{a} -> {b} -> {a}")));
+        assert_eq!(assemble("@{file:missing.h}").unwrap_err().kind, ErrorKind::Include);
+
+        fs::remove_dir_all(&directory).unwrap();
+
+    }
+
+    #[test]
+    fn nested_common_keys() {
+
+        let common: crate::config::Common = toml::from_str(
+            "prefix = \"P_\"\nrepeats = 2\n[keys]\nlicense = \"MIT\"\n[keys.paths.c]\ninclude_dir = \"inc\"\n"
+        ).unwrap();
+        let values = common_key_values(&common.keyable).unwrap();
+
+        assert!(values.contains(&("license".to_owned(), "MIT".to_owned())));
+        assert!(values.contains(&("paths.c.include_dir".to_owned(), "inc".to_owned())));
+        assert!(values.contains(&("prefix".to_owned(), "P_".to_owned())));
+        assert!(!values.iter().any(|(key, _)| key.starts_with("keys")));
+
+    }
+
+    /// The repeat of every generator of the config `contents` after
+    /// preprocessing.
+    #[allow(dead_code)]
+    fn preprocessed_repeats(contents: &str) -> Result<Vec<String>, Error> {
+        let config = Config::parse(
+            &format!("[common]\nprefix = \"P_\"\nrepeats = 2\n[core]\nxmva = \"X\"\nargs = [{{varadict = 1}}]\n{contents}"),
+            Path::new("keys.xmva.toml")
+        ).unwrap();
+        config.preprocess()?;
+        Ok(config.generator
+            .iter()
+            .map(|generator| AnyPreprocessable::String(generator.repeat.clone()).preprocessed().unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn generator_keys() {
+
+        let repeats = preprocessed_repeats(concat!(
+            "[preamble]\nkeys = [{key = \"err\", name = \"GLOBAL\"}]\n",
+            "[[generator]]\nkeys = [{key = \"err\", name = \"LOCAL\"}, {key = \"only\", name = \"@{err}_ONLY\"}]\nrepeat = \"@{err} @{only}\"\n",
+            "[[generator]]\nrepeat = \"@{err}\"\n"
+        )).unwrap();
+        assert_eq!(repeats, vec!["P_LOCAL P_P_LOCAL_ONLY", "P_GLOBAL"]);
+
+        // local keys stay inside of their generator.
+        let err = preprocessed_repeats(concat!(
+            "[[generator]]\nkeys = [{key = \"only\", name = \"ONLY\"}]\nrepeat = \"@{only}\"\n",
+            "[[generator]]\nrepeat = \"@{only}\"\n"
+        )).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NonExistantReference);
+
+        let err = preprocessed_repeats(
+            "[[generator]]\nkeys = [{key = \"a\", name = \"A\"}, {key = \"a\", name = \"B\"}]\nrepeat = \"@{a}\"\n"
+        ).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::DuplicateKey);
+        assert!(err.message.contains("Key a must be unique inside of generator 0"));
+
+    }
+
+    #[test]
+    fn qualified_keys() {
+
+        let named = concat!(
+            "[[generator]]\nname = \"a\"\nkeys = [{key = \"k\", name = \"A\"}]\nrepeat = \"@{k}\"\n",
+            "[[generator]]\nname = \"b\"\nkeys = [{key = \"k\", name = \"B\"}]\nrepeat = \"@{a.k}\"\n"
+        );
+
+        let repeats = preprocessed_repeats(&format!("{named}[[generator]]\nrepeat = \"@{{a.k}} @{{b.k}}\"\n")).unwrap();
+        assert_eq!(repeats, vec!["P_A", "P_A", "P_A P_B"]);
+
+        let err = preprocessed_repeats(&format!("{named}[[generator]]\nrepeat = \"@{{k}}\"\n")).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NonExistantReference);
+        assert!(err.message.contains("it is ambiguous between `a.k`, `b.k`, use one of them instead"));
+
+        let err = preprocessed_repeats(concat!(
+            "[[generator]]\nname = \"a\"\nkeys = [{key = \"k\", name = \"A\"}]\nrepeat = \"$(0)\"\n",
+            "[[generator]]\nrepeat = \"@{k}\"\n"
+        )).unwrap_err();
+        assert!(err.message.contains("doesn't exist, did you mean `a.k`?"));
+
+    }
+
+    #[test]
+    fn duplicate_key_origins() {
+
+        let err = preprocessed_repeats(concat!(
+            "[preamble]\nkeys = [{key = \"a\", name = \"A\"}, {key = \"err\", name = \"ERR\"}]\n",
+            "[[definition]]\nkey = \"err\"\nname = \"ERR\"\nexpansion = \"1\"\n",
+            "[[generator]]\nrepeat = \"$(0)\"\n"
+        )).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::DuplicateKey);
+        assert!(err.message.starts_with(
+            "Key err must be unique, but it was first defined in [preamble] keys[1] and redefined in [[definition]] 0."
+        ));
+
+        let err = preprocessed_repeats(concat!(
+            "[[generator]]\nkeys = [{key = \"a\", name = \"A\"}, {key = \"b\", name = \"B\"}, {key = \"a\", name = \"C\"}]\n",
+            "repeat = \"$(0)\"\n"
+        )).unwrap_err();
+        assert!(err.message.contains("first defined in keys[0] and redefined in keys[2]."));
+
+    }
+
+    #[test]
+    fn builtin_generator_keys() {
+
+        let repeats = preprocessed_repeats(concat!(
+            "[[generator]]\nname = \"first\"\nrepeat = \"@{__GEN_NAME__} @{__GEN_INDEX__} @{__REPEATS__}\"\n",
+            "[[generator]]\nrepeat = \"@{__GEN_NAME__} @{__GEN_INDEX__} @{__REPEATS__}\"\n"
+        )).unwrap();
+        // the index stands in for a missing name.
+        assert_eq!(repeats, vec!["first 0 2", "1 1 2"]);
+
+        // a generator key with the same name wins, but not quietly.
+        let (repeats, emitted) = lint::collect(|| preprocessed_repeats(
+            "[[generator]]\nkeys = [{key = \"__GEN_INDEX__\", name = \"INDEX\"}]\nrepeat = \"@{__GEN_INDEX__}\"\n"
+        ));
+        assert_eq!(repeats.unwrap(), vec!["P_INDEX"]);
+        assert_eq!(emitted.iter().map(|emitted| emitted.lint).collect::<Vec<Lint>>(), vec![Lint::ShadowedKey]);
+
+        // only generator strings know which generator they are in.
+        let config = Config::parse(
+            "[common]\nprefix = \"P_\"\nrepeats = 2\n[core]\nxmva = \"X\"\nargs = [{varadict = 1}]\n[preamble]\nraw = \"@{__GEN_NAME__}\"\n[[generator]]\nrepeat = \"$(0)\"\n",
+            Path::new("keys.xmva.toml")
+        ).unwrap();
+        assert_eq!(config.preprocess().unwrap_err().kind, ErrorKind::NonExistantReference);
+
+    }
+
+    #[test]
+    fn keyable_common_fields() {
+
+        let config = Config::parse(concat!(
+            "[common]\nprefix = \"P_\"\nsuffix = \"_T\"\nnamespace = \"lib\"\nseparator = \"_\"\nrepeats = 2\n",
+            "[core]\nxmva = \"X\"\nargs = [{varadict = 1}]\n",
+            "[[generator]]\nkeys = [{key = \"err\", name = \"ERR\"}]\n",
+            "repeat = \"@{err} @{err|NO_SUFFIX} @{err|NO_NAMESPACE} @{err|NO_PREFIX|NO_SUFFIX|NO_NAMESPACE} @{namespace}@{separator}@{suffix}\"\n"
+        ), Path::new("keys.xmva.toml")).unwrap();
+        config.preprocess().unwrap();
+
+        // the namespace goes in front of the prefix.
+        assert_eq!(
+            AnyPreprocessable::String(config.generator[0].repeat.clone()).preprocessed().unwrap(),
+            "lib_P_ERR_T lib_P_ERR P_ERR_T ERR lib__T"
+        );
+
+    }
+
+}
@@ -0,0 +1,172 @@
+//! `xmva preview`, what one generator expands into for the first few
+//! argument groups. The config is generated with only that generator and
+//! just enough repeats, then the invocations are expanded with the
+//! [crate::expand] simulator, so iterating on a `repeat` string doesn't
+//! mean reading through a whole ladder.
+//!
+//! Example
+//! -------
+//! ```sh
+//! $ xmva -i ya_ecgen.xmva.toml preview --generator 1 --groups 2
+//! YA_ECGEN(lowercase_name, UPPERCASE_NAME, arg0_1, arg1_1)
+//!     const char *ya_lowercase_name_conversion_table[] = {[YA_UPPERCASE_NAME_arg0_1] = arg1_1};
+//!
+//! YA_ECGEN(lowercase_name, UPPERCASE_NAME, arg0_1, arg1_1, arg0_2, arg1_2)
+//!     const char *ya_lowercase_name_conversion_table[] = {[YA_UPPERCASE_NAME_arg0_1] = arg1_1, ...};
+//! ```
+//!
+//! Named arguments are passed as their own names and the varadict ones
+//! are named after the [crate::config::Group] members (or `arg0_`,
+//! `arg1_`...) with the group they are in, `value1` or `arg0_1`.
+
+use crate::{
+    compiler::read_preprocessed,
+    config::{Argument, Config},
+    expand::Macros
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownGenerator,
+    Preprocess,
+    Compile,
+    Expand,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub(crate) message: String
+}
+
+impl std::fmt::Display for Error {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Preview encountered a error, [{:?}]: {}", self.kind, self.message)
+    }
+
+}
+
+impl std::error::Error for Error {}
+
+/// The index of the generator called `generator`, or with `generator`
+/// as its index.
+fn find_generator(config: &Config, generator: &str) -> Result<usize, Error> {
+
+    if let Some(i) = config.generator.iter().position(|candidate| candidate.name.as_deref() == Some(generator)) {
+        return Ok(i)
+    }
+    if let Ok(i) = generator.parse::<usize>()
+        && i < config.generator.len() {
+        return Ok(i)
+    }
+
+    let names: Vec<String> = config.generator
+        .iter()
+        .enumerate()
+        .map(|(i, candidate)| candidate.name.clone().unwrap_or_else(|| i.to_string()))
+        .collect();
+    Err(Error {
+        kind: ErrorKind::UnknownGenerator,
+        message: format!("no generator `{generator}`, the config has {}", names.join(", "))
+    })
+
+}
+
+/// The expansion of `generator` (a name or index) for 1 up to `groups`
+/// argument groups, one invocation after the other.
+pub fn preview(mut config: Config, generator: &str, groups: usize) -> Result<String, Error> {
+
+    let index = find_generator(&config, generator)?;
+    let group = config.core.varadict().unwrap_or(1).max(1);
+    config.common.repeats = groups * group + 1;
+
+    // preprocessed with every generator so errors point at the right one.
+    config.preprocess().map_err(|err| Error {
+        kind: ErrorKind::Preprocess,
+        message: err.to_string()
+    })?;
+    let mut selected = config.generator.swap_remove(index);
+    selected.output = None;
+    config.generator = vec![selected];
+
+    let compile_error = |err: crate::compiler::Error| Error {
+        kind: ErrorKind::Compile,
+        message: err.to_string()
+    };
+    let header = config.compile_and_assemble().map_err(compile_error)?.main.contents;
+    let macros = Macros::from_header(&header);
+
+    let mut named: Vec<String> = vec![];
+    for arg in config.core.args.iter() {
+        if let Argument::Named(argument) = arg {
+            named.push(read_preprocessed(&argument.name).map_err(compile_error)?);
+        }
+    }
+    let members: Vec<String> = match &config.core.group {
+        Some(members) => members.names().into_iter().map(str::to_owned).collect(),
+        None => (0..group).map(|i| format!("arg{i}_")).collect()
+    };
+    let xmva = read_preprocessed(&config.core.xmva).map_err(compile_error)?;
+
+    let mut previews: Vec<String> = vec![];
+    for count in 1..=groups {
+        let arguments: Vec<String> = named
+            .iter()
+            .cloned()
+            .chain((1..=count).flat_map(|n| members.iter().map(move |member| format!("{member}{n}"))))
+            .collect();
+        let invocation = format!("{xmva}({})", arguments.join(", "));
+        let expanded = macros.expand(&invocation).map_err(|err| Error {
+            kind: ErrorKind::Expand,
+            message: format!("{invocation}: {err}")
+        })?;
+        previews.push(format!("{invocation}\n    {expanded}\n"));
+    }
+    Ok(previews.join("\n"))
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn previewed() {
+
+        let config = || Config::parse(concat!(
+            "[common]\n",
+            "prefix = \"P_\"\n",
+            "repeats = 30\n",
+            "[core]\n",
+            "xmva = \"COLOR\"\n",
+            "args = [{key = \"name\", name = \"name\"}, {varadict = 2}]\n",
+            "group = {members = [\"value\", \"string\"]}\n",
+            "[[generator]]\n",
+            "name = \"enum\"\n",
+            "preamble = \"enum ${name} {\"\n",
+            "repeat = \"$(value)$[, ]\"\n",
+            "postamble = \"};\"\n",
+            "[[generator]]\n",
+            "name = \"strings\"\n",
+            "output = \"strings.h\"\n",
+            "repeat = \"$(string)$[ ]\"\n",
+        ), std::path::Path::new("preview.xmva.toml")).unwrap();
+
+        assert_eq!(preview(config(), "strings", 2).unwrap(), concat!(
+            "COLOR(name, value1, string1)\n",
+            "    string1\n",
+            "\n",
+            "COLOR(name, value1, string1, value2, string2)\n",
+            "    string1 string2\n",
+        ));
+        assert_eq!(preview(config(), "0", 1).unwrap(), "COLOR(name, value1, string1)\n    enum name {value1};\n");
+
+        let unknown = preview(config(), "missing", 1).unwrap_err();
+        assert_eq!(unknown.kind, ErrorKind::UnknownGenerator);
+        assert!(unknown.message.ends_with("the config has enum, strings"));
+
+    }
+
+}
@@ -0,0 +1,91 @@
+//! `xmva self-test`, a quick sanity check after installing us or playing
+//! around with the sigils. Generates [EXAMPLE] inside of a temporary
+//! directory and expands [INVOCATION] against the header with the
+//! [crate::expand] simulator, which has to give [EXPECTED].
+
+use std::{env, fs, path::Path, process};
+
+use colored::Colorize;
+
+use crate::{config::Config, expand::Macros};
+
+/// The example config from the repository.
+pub const EXAMPLE: &str = include_str!("../example.xmva.toml");
+
+pub const INVOCATION: &str = "YA_ECGEN(vector, VECTOR, EMPTY, \"Vector was empty.\", FULL, \"Vector was full.\")";
+
+pub const EXPECTED: &str = concat!(
+    "enum ya_vector_error_codes {YA_VECTOR_EMPTY,YA_VECTOR_FULL}; ",
+    "const char *ya_vector_conversion_table[] = {[YA_VECTOR_EMPTY] = \"Vector was empty.\", [YA_VECTOR_FULL] = \"Vector was full.\"};"
+);
+
+fn step(name: &str, result: Result<(), String>) -> bool {
+    match result {
+        Ok(()) => {
+            println!("{name} ... {}", "ok".green());
+            true
+        }
+        Err(err) => {
+            println!("{name} ... {}\n{err}", "FAILED".red().bold());
+            false
+        }
+    }
+}
+
+/// Run the self test, printing every step as it goes.
+///
+/// Returns if it passed, the temporary directory is left behind when
+/// it didn't so the generated header can be looked at.
+pub fn run() -> bool {
+
+    let directory = env::temp_dir().join(format!("xmva-self-test-{}", process::id()));
+    let config_path = directory.join("example.xmva.toml");
+
+    let mut header = String::new();
+    let passed = step("write the example config", fs::create_dir_all(&directory)
+            .and_then(|_| fs::write(&config_path, EXAMPLE))
+            .map_err(|err| err.to_string())
+        )
+        && step("generate the example config", generate(&config_path).map(|generated| header = generated))
+        && step("expand a sample invocation", expand(&header));
+
+    if passed {
+        let _ = fs::remove_dir_all(&directory);
+        println!("self test {}", "passed".green().bold());
+    } else {
+        println!("self test {}, see {}", "failed".red().bold(), directory.display());
+    }
+    passed
+
+}
+
+/// Generate the config at `path` the same way the default command does,
+/// returning the main output.
+fn generate(path: &Path) -> Result<String, String> {
+
+    let config = Config::load(path).map_err(|err| err.to_string())?;
+
+    // relative paths in the config are relative to it.
+    if let Some(directory) = path.parent() {
+        env::set_current_dir(directory).map_err(|err| err.to_string())?;
+    }
+
+    config.preprocess().map_err(|err| err.to_string())?;
+    let output = config.compile_and_assemble().map_err(|err| err.to_string())?;
+
+    let output_path = config.common.output.clone().unwrap_or_default();
+    fs::write(&output_path, &output.main.contents)
+        .map_err(|err| format!("Failed to write output to {}: {err}", output_path.display()))?;
+    fs::read_to_string(&output_path).map_err(|err| err.to_string())
+
+}
+
+fn expand(header: &str) -> Result<(), String> {
+    let expanded = Macros::from_header(header)
+        .expand(INVOCATION)
+        .map_err(|err| err.to_string())?;
+    if expanded != EXPECTED {
+        return Err(format!("{INVOCATION}\nexpanded into\n{expanded}\ninstead of\n{EXPECTED}"))
+    }
+    Ok(())
+}
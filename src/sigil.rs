@@ -1,9 +1,5 @@
-use std::{
-    collections::HashMap, 
-    process::exit
-};
+use std::{cell::RefCell, collections::HashMap, hash::Hash, ops::Range};
 
-use lazy_static::lazy_static;
 use strum::{
     EnumIter, EnumProperty, EnumString, IntoEnumIterator
 };
@@ -51,70 +47,251 @@ pub enum CompilerSigil {
     #[strum(props(ch = "]"))]
     SkipLastClose,
 
+    #[strum(props(ch = "<"))]
+    LeftoverArgumentRefOpen,
+    #[strum(props(ch = ">"))]
+    LeftoverArgumentRefClose,
+
+}
+
+thread_local! {
+    /// Sigils the config remapped, anything not in here uses its `ch`.
+    /// Per thread so that tests with different sigils don't step on each other.
+    static PREPROCESSOR_SIGIL_OVERRIDES: RefCell<HashMap<PreprocessorSigil, String>> = 
+        RefCell::new(HashMap::new());
+    static COMPILER_SIGIL_OVERRIDES: RefCell<HashMap<CompilerSigil, String>> = 
+        RefCell::new(HashMap::new());
 }
 
-lazy_static! {
-    static ref PREPROCESSOR_SIGIL_CONVERSION_TABLE: HashMap<char, PreprocessorSigil> = {
-        let mut table: HashMap<char, PreprocessorSigil> = HashMap::new();
-        for sigil in PreprocessorSigil::iter() {
-            let Some(s) = sigil.get_str("ch") else {
-                continue;
-            };
-            if s.len() != 1 {
-                eprintln!("PREPROCESSOR_SIGIL_CONVERSION_TABLE: property 'ch' had a string with .len() != 1");
-                exit(1);
-            }
-            let ch = s.chars().nth(0).unwrap();
-            if let Some(existing) = table.get(&ch) {
-                eprintln!(
-                    "PREPROCESSOR_SIGIL_CONVERSION_TABLE: duplicate entry for '{}': {:?} and {:?}",
-                    ch, existing, sigil
-                );
-                exit(1);
-            }
-            table.insert(ch, sigil);
+/// Sigil tables of both tokenizers that can't be used together.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Collision {
+    Empty {tokenizer: &'static str, sigil: String},
+    /// Two sigils of the same tokenizer with the same sequence.
+    Intra {tokenizer: &'static str, sequence: String, first: String, second: String},
+    /// A preprocessor sigil overlaps the compiler token start, since the
+    /// preprocessor runs first it would eat the compiler tokens.
+    Inter {preprocessor: String, compiler: String, sequence: String}
+}
+
+impl Collision {
+
+    /// The sigil the user most likely remapped wrong.
+    pub fn sigil(&self) -> (&'static str, &str) {
+        match self {
+            Self::Empty { tokenizer, sigil } => (tokenizer, sigil),
+            Self::Intra { tokenizer, second, .. } => (tokenizer, second),
+            Self::Inter { compiler, .. } => ("compiler", compiler)
         }
-        table
-    };
+    }
+
+}
 
-    static ref COMPILER_SIGIL_CONVERSION_TABLE: HashMap<char, CompilerSigil> = {
-        let mut table: HashMap<char, CompilerSigil> = HashMap::new();
-        for sigil in CompilerSigil::iter() {
-            let Some(s) = sigil.get_str("ch") else {
-                continue;
-            };
-            if s.len() != 1 {
-                eprintln!("COMPILER_SIGIL_CONVERSION_TABLE: property 'ch' had a string with .len() != 1");
-                exit(1);
-            }
-            let ch = s.chars().nth(0).unwrap();
-            if let Some(existing) = table.get(&ch) {
-                eprintln!(
-                    "COMPILER_SIGIL_CONVERSION_TABLE: duplicate entry for '{}': {:?} and {:?}",
-                    ch, existing, sigil
-                );
-                exit(1);
-            }
-            table.insert(ch, sigil);
+impl std::fmt::Display for Collision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty { tokenizer, sigil } => 
+                write!(f, "{tokenizer} sigil {sigil} can't be empty"),
+            Self::Intra { tokenizer, sequence, first, second } => 
+                write!(f, "{tokenizer} sigils {first} and {second} are both `{sequence}`"),
+            Self::Inter { preprocessor, compiler, sequence } => write!(f, 
+                "the preprocessor {preprocessor} and the compiler {compiler} overlap on `{sequence}`"
+            )
         }
-        table
-    };
+    }
 }
 
-impl From<char> for PreprocessorSigil {
-    fn from(value: char) -> Self {
-        if let Some(sigil) = PREPROCESSOR_SIGIL_CONVERSION_TABLE.get(&value) {
-            return sigil.to_owned();
+/// Build the sigil table of a tokenizer with `overrides` applied, sorted
+/// from the longest to the shortest sequence so that lexing always takes
+/// the longest match.
+fn table<T>(tokenizer: &'static str, overrides: &HashMap<T, String>) -> Result<Vec<(String, T)>, Collision>
+where T: IntoEnumIterator + EnumProperty + Hash + Eq + Copy + std::fmt::Debug
+{
+    let mut table: Vec<(String, T)> = vec![];
+    for sigil in T::iter() {
+        let sequence = match (overrides.get(&sigil), sigil.get_str("ch")) {
+            (Some(sequence), _) => sequence.clone(),
+            (None, Some(ch)) => ch.to_owned(),
+            (None, None) => continue
+        };
+        if sequence.is_empty() {
+            return Err(Collision::Empty { tokenizer, sigil: format!("{:?}", sigil) })
+        }
+        if let Some((_, existing)) = table.iter().find(|(existing, _)| *existing == sequence) {
+            return Err(Collision::Intra { 
+                tokenizer, 
+                sequence, 
+                first: format!("{:?}", existing), 
+                second: format!("{:?}", sigil) 
+            })
         }
-        PreprocessorSigil::Non(value)
+        table.push((sequence, sigil));
     }
+    table.sort_by_key(|(s, _)| std::cmp::Reverse(s.len()));
+    Ok(table)
 }
 
-impl From<char> for CompilerSigil {
-    fn from(value: char) -> Self {
-        if let Some(sigil) = COMPILER_SIGIL_CONVERSION_TABLE.get(&value) {
-            return sigil.to_owned();
+/// Check that the sigils with the given overrides can be used, this is
+/// the only place collisions are reported, tokenizers assume valid tables.
+pub fn validate(
+    preprocessor: &HashMap<PreprocessorSigil, String>,
+    compiler: &HashMap<CompilerSigil, String>
+) -> Result<(), Collision> {
+
+    let preprocessor_table = table("preprocessor", preprocessor)?;
+    let compiler_table = table("compiler", compiler)?;
+
+    let Some((compiler_start, _)) = compiler_table
+        .iter()
+        .find(|(_, sigil)| *sigil == CompilerSigil::TokenStart) else {
+        return Ok(())
+    };
+
+    // the embed of both is allowed to be the same, the preprocessor
+    // keeps embeds it doesn't understand.
+    for (sequence, sigil) in preprocessor_table.iter() {
+        if matches!(sigil, PreprocessorSigil::TokenStart | PreprocessorSigil::TokenEmbed)
+            && (sequence.starts_with(compiler_start) || compiler_start.starts_with(sequence)) {
+            return Err(Collision::Inter { 
+                preprocessor: format!("{:?}", sigil), 
+                compiler: format!("{:?}", CompilerSigil::TokenStart), 
+                sequence: sequence.clone() 
+            })
         }
-        CompilerSigil::Non(value)
     }
-}
\ No newline at end of file
+
+    Ok(())
+
+}
+
+/// Validate and then use the given overrides for all tokenizing from now on.
+pub fn set_overrides(
+    preprocessor: HashMap<PreprocessorSigil, String>,
+    compiler: HashMap<CompilerSigil, String>
+) -> Result<(), Collision> {
+
+    validate(&preprocessor, &compiler)?;
+
+    PREPROCESSOR_SIGIL_OVERRIDES.with(|overrides| *overrides.borrow_mut() = preprocessor);
+    COMPILER_SIGIL_OVERRIDES.with(|overrides| *overrides.borrow_mut() = compiler);
+
+    Ok(())
+
+}
+
+/// Split `s` into sigils along side the byte range of `s` they matched,
+/// sigils can be sequences of multiple characters and the longest one
+/// wins. Everything that isn't a sigil is returned one character at a
+/// time through `non`.
+fn lex<T: Copy>(
+    s: &str,
+    table: &[(String, T)],
+    non: fn(char) -> T
+) -> Vec<(T, Range<usize>)> {
+
+    let mut sigils: Vec<(T, Range<usize>)> = vec![];
+    let mut at = 0;
+
+    while let Some(ch) = s[at..].chars().next() {
+        let rest = &s[at..];
+        let (sigil, len) = table
+            .iter()
+            .find(|(sequence, _)| rest.starts_with(sequence.as_str()))
+            .map(|(sequence, sigil)| (*sigil, sequence.len()))
+            .unwrap_or((non(ch), ch.len_utf8()));
+        sigils.push((sigil, at..at + len));
+        at += len;
+    }
+
+    sigils
+
+}
+
+impl PreprocessorSigil {
+
+    /// The sequence of characters this sigil is written as.
+    pub fn sequence(&self) -> String {
+        PREPROCESSOR_SIGIL_OVERRIDES
+            .with(|overrides| overrides.borrow().get(self).cloned())
+            .unwrap_or_else(|| self.get_str("ch").unwrap_or_default().to_owned())
+    }
+
+    pub fn lex(s: &str) -> Vec<(Self, Range<usize>)> {
+        // validated when the overrides were set.
+        let table = PREPROCESSOR_SIGIL_OVERRIDES
+            .with(|overrides| table("preprocessor", &overrides.borrow()))
+            .unwrap_or_default();
+        lex(s, &table, Self::Non)
+    }
+
+}
+
+impl CompilerSigil {
+
+    /// The sequence of characters this sigil is written as.
+    pub fn sequence(&self) -> String {
+        COMPILER_SIGIL_OVERRIDES
+            .with(|overrides| overrides.borrow().get(self).cloned())
+            .unwrap_or_else(|| self.get_str("ch").unwrap_or_default().to_owned())
+    }
+
+    pub fn lex(s: &str) -> Vec<(Self, Range<usize>)> {
+        // validated when the overrides were set.
+        let table = COMPILER_SIGIL_OVERRIDES
+            .with(|overrides| table("compiler", &overrides.borrow()))
+            .unwrap_or_default();
+        lex(s, &table, Self::Non)
+    }
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn lex_longest_sequence() {
+
+        let table = [
+            ("${{".to_owned(), CompilerSigil::NamedArgumentRefOpen),
+            ("$".to_owned(), CompilerSigil::TokenStart)
+        ];
+
+        assert_eq!(
+            lex("a${{b$😳", &table, CompilerSigil::Non),
+            vec![
+                (CompilerSigil::Non('a'), 0..1),
+                (CompilerSigil::NamedArgumentRefOpen, 1..4),
+                (CompilerSigil::Non('b'), 4..5),
+                (CompilerSigil::TokenStart, 5..6),
+                (CompilerSigil::Non('😳'), 6..10),
+            ]
+        );
+
+    }
+
+    #[test]
+    fn collisions() {
+
+        assert_eq!(validate(&HashMap::new(), &HashMap::new()), Ok(()));
+        assert!(matches!(
+            validate(&HashMap::from([(PreprocessorSigil::TokenStart, "$".to_owned())]), &HashMap::new()),
+            Err(Collision::Inter { .. })
+        ));
+        assert!(matches!(
+            validate(&HashMap::new(), &HashMap::from([(CompilerSigil::TokenStart, ".".to_owned())])),
+            Err(Collision::Intra { .. })
+        ));
+        assert!(matches!(
+            validate(&HashMap::new(), &HashMap::from([(CompilerSigil::TokenStart, String::new())])),
+            Err(Collision::Empty { .. })
+        ));
+        assert!(matches!(
+            validate(&HashMap::from([(PreprocessorSigil::TokenEmbed, "$".to_owned())]), &HashMap::new()),
+            Err(Collision::Inter { .. })
+        ));
+
+    }
+
+}
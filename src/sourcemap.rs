@@ -0,0 +1,197 @@
+//! Source maps for generated files, every section the assembler writes
+//! remembers which lines of the output it ended up on and which part of
+//! the config produced it.
+//!
+//! Written next to the output as `name.h.map` with `--source-map`.
+//!
+//! Every `__ARGS__N` step of a ladder is also mapped on its own with
+//! the generator it belongs to and the `repeat` (or fallback) it expands,
+//! so a editor can jump from a ladder line to the string that made it.
+
+use std::{ops::Range, path::PathBuf};
+
+use serde::Serialize;
+
+use crate::metadata::Metadata;
+
+/// The part of the pipeline that produced a section.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Include,
+    Preamble,
+    Definition,
+    Repeat,
+    Generator,
+    Main,
+    List,
+    Postamble,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    /// First and last line (1 based, inclusive) in the output.
+    pub lines: (usize, usize),
+    pub config: PathBuf,
+    /// Dotted path of the field, the same path `xmva highlight` takes.
+    pub field: String,
+    /// Byte span of the field in the config.
+    pub span: Option<Range<usize>>,
+    pub stage: Stage,
+    /// The generator a ladder step came from, only on the mappings of
+    /// single `#define`s (see [SourceMapped::push_defines]).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generator: Option<usize>,
+    /// The name of the `#define`, along side [Mapping::generator].
+    #[serde(rename = "macro", skip_serializing_if = "Option::is_none")]
+    pub macro_name: Option<String>,
+}
+
+/// A output that is being assembled along side its mappings.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMapped {
+    pub contents: String,
+    pub mappings: Vec<Mapping>,
+}
+
+impl SourceMapped {
+
+    /// Append something that isn't mapped to any part of the config.
+    pub fn push(&mut self, s: &str) {
+        self.contents.push_str(s);
+    }
+
+    /// Append a section produced by `field`.
+    pub fn push_mapped(
+        &mut self,
+        s: &str,
+        metadata: &Metadata,
+        field: &str,
+        stage: Stage
+    ) {
+
+        let content = s.trim_end_matches('\n');
+        if content.is_empty() {
+            self.push(s);
+            return
+        }
+
+        let start = self.contents.matches('\n').count() + 1;
+        let end = start + content.matches('\n').count();
+        self.mappings.push(Mapping {
+            lines: (start, end),
+            config: metadata.config.clone(),
+            field: field.to_owned(),
+            span: metadata.span(field),
+            stage,
+            generator: None,
+            macro_name: None
+        });
+        self.push(s);
+
+    }
+
+    /// [SourceMapped::push_mapped] a section of `#define`s produced by
+    /// `generator`, every define named in `fields` also gets a mapping of
+    /// its own to the field it came from. Tooling takes the narrowest
+    /// mapping of a line, the whole section is still mapped to `field`.
+    pub fn push_defines(
+        &mut self,
+        s: &str,
+        metadata: &Metadata,
+        field: &str,
+        stage: Stage,
+        generator: usize,
+        fields: &[(String, String)]
+    ) {
+
+        let first_line = self.contents.matches('\n').count() + 1;
+        self.push_mapped(s, metadata, field, stage);
+
+        let lines: Vec<&str> = s.lines().collect();
+        let mut i = 0;
+        while i < lines.len() {
+            let start = i;
+            // a define goes on for as long as its lines are continued.
+            while lines[i].trim_end().ends_with('\\') && i + 1 < lines.len() {
+                i += 1;
+            }
+            let name = lines[start]
+                .strip_prefix("#define ")
+                .map(|define| define.split(['(', ' ']).next().unwrap_or_default());
+            if let Some((name, field)) = name.and_then(|name| fields.iter().find(|(candidate, _)| candidate == name)) {
+                self.mappings.push(Mapping {
+                    lines: (first_line + start, first_line + i),
+                    config: metadata.config.clone(),
+                    field: field.clone(),
+                    span: metadata.span(field),
+                    stage,
+                    generator: Some(generator),
+                    macro_name: Some(name.clone())
+                });
+            }
+            i += 1;
+        }
+
+    }
+
+    /// Append another [SourceMapped], moving its mappings below what we
+    /// already have.
+    pub fn append(&mut self, other: SourceMapped) {
+        let offset = self.contents.matches('\n').count();
+        self.mappings.extend(other.mappings.into_iter().map(|mapping| Mapping {
+            lines: (mapping.lines.0 + offset, mapping.lines.1 + offset),
+            ..mapping
+        }));
+        self.push(&other.contents);
+    }
+
+    /// The `.map` file contents.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.mappings)
+            .unwrap_or_else(|_| "[]".to_owned())
+    }
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn line_ranges() {
+
+        let metadata = Metadata::default();
+        let mut mapped = SourceMapped::default();
+        mapped.push("// banner\n");
+        mapped.push_mapped("#define A 1\n#define B 2\n", &metadata, "preamble.raw", Stage::Preamble);
+
+        let mut other = SourceMapped::default();
+        other.push_mapped("#define C 3", &metadata, "postamble.raw", Stage::Postamble);
+        mapped.push("\n");
+        mapped.append(other);
+
+        assert_eq!(mapped.mappings[0].lines, (2, 3));
+        assert_eq!(mapped.mappings[1].lines, (5, 5));
+        assert_eq!(mapped.contents.lines().nth(4), Some("#define C 3"));
+
+        mapped.push("\n");
+        mapped.push_defines(
+            "/* ladder */\n#define A_0() empty\n#define A_1(a) \\\n    a\n",
+            &metadata,
+            "generator.0",
+            Stage::Repeat,
+            0,
+            &[("A_0".to_owned(), "generator.0.fallbacks.empty".to_owned()), ("A_1".to_owned(), "generator.0.repeat".to_owned())]
+        );
+        assert_eq!(mapped.mappings[2].lines, (6, 9));
+        assert_eq!(mapped.mappings[3].lines, (7, 7));
+        assert_eq!(mapped.mappings[3].field, "generator.0.fallbacks.empty");
+        assert_eq!(mapped.mappings[4].lines, (8, 9));
+        assert_eq!(mapped.mappings[4].macro_name.as_deref(), Some("A_1"));
+        assert_eq!(mapped.mappings[4].generator, Some(0));
+
+    }
+
+}
@@ -0,0 +1,214 @@
+//! `--stop-after`, stops generating after one of the stages and dumps
+//! what the stage left behind as JSON next to the output instead of
+//! writing it (`<output>.<stage>.json`). For debugging and for tools
+//! that only need part of the work.
+//!
+//! - `preprocess`, every string the compiler reads with the keys
+//!   substituted.
+//! - `surface-compile`, the same strings after the named arguments
+//!   were put into the generator strings.
+//! - `repeat-generate`, the strings along side the baked repeat ladders
+//!   and generator macros.
+//!
+//! `--resume-from` takes a dump and continues after its stage, with the
+//! config it was dumped from loaded again for everything else. Resuming
+//! after `repeat-generate` only assembles, so `[output]` options like
+//! the `layout` or `comments` can be changed in between but the ones the
+//! ladders are baked with (`wrap` and `indent`) can't.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compiler::Baked,
+    config::Config,
+    preprocessor::AnyPreprocessable,
+    trace
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    Read,
+    Parse,
+    Mismatch,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub(crate) message: String
+}
+
+impl std::fmt::Display for Error {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Stage dump encountered a error, [{:?}]: {}", self.kind, self.message)
+    }
+
+}
+
+impl std::error::Error for Error {}
+
+/// In the order they run.
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stage {
+    Preprocess,
+    SurfaceCompile,
+    RepeatGenerate,
+}
+
+impl Stage {
+
+    /// Appended to the output path for the dump path.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Preprocess => "preprocess.json",
+            Self::SurfaceCompile => "surface-compile.json",
+            Self::RepeatGenerate => "repeat-generate.json"
+        }
+    }
+
+}
+
+/// What is left after a [Stage].
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Dump {
+    pub stage: Stage,
+    /// Version of xmva that dumped it.
+    pub version: String,
+    /// Every string the compiler reads by its dotted path.
+    pub strings: BTreeMap<String, String>,
+    /// Only after [Stage::RepeatGenerate].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baked: Option<Baked>,
+}
+
+/// Every string the compiler reads along side its dotted path, the
+/// same paths `--trace-expansion` uses.
+pub(crate) fn strings(config: &Config) -> Vec<(String, AnyPreprocessable)> {
+    let mut strings: Vec<(String, AnyPreprocessable)> = trace::named_strings(config)
+        .into_iter()
+        .map(|(field, string)| (field, AnyPreprocessable::String(string)))
+        .collect();
+    for (i, definition) in config.definition.iter().flatten().enumerate() {
+        strings.push((format!("definition.{i}.name"), AnyPreprocessable::Name(definition.name.clone())));
+    }
+    strings
+}
+
+/// Dump `config` as it is after `stage`.
+pub fn dump(config: &Config, stage: Stage, baked: Option<Baked>) -> Dump {
+    Dump {
+        stage,
+        version: config.metadata.version.to_owned(),
+        strings: strings(config)
+            .into_iter()
+            .filter_map(|(field, string)| Some((field, string.preprocessed()?)))
+            .collect(),
+        baked
+    }
+}
+
+impl Dump {
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path).map_err(|err| Error {
+            kind: ErrorKind::Read,
+            message: format!("failed to read {}: {err}", path.display())
+        })?;
+        serde_json::from_str(&contents).map_err(|err| Error {
+            kind: ErrorKind::Parse,
+            message: format!("{} isn't a stage dump: {err}", path.display())
+        })
+    }
+
+    /// Put the dumped strings back into `config` in place of preprocessing
+    /// (and surface compiling), the config has to be the one it was
+    /// dumped from.
+    pub fn restore(&self, config: &Config) -> Result<(), Error> {
+
+        let mismatch = |message: String| Error {
+            kind: ErrorKind::Mismatch,
+            message: format!("{message}, dump the config again")
+        };
+
+        if self.version != config.metadata.version {
+            return Err(mismatch(format!("dumped by xmva {} but this is xmva {}", self.version, config.metadata.version)))
+        }
+        let strings = strings(config);
+        if let Some(extra) = self.strings.keys().find(|field| !strings.iter().any(|(path, _)| path == *field)) {
+            return Err(mismatch(format!("the config has no `{extra}` that the dump has")))
+        }
+        if let Some(baked) = &self.baked
+            && (baked.generators.len() != config.generator.len() || baked.repeats.len() != config.generator.len()) {
+            return Err(mismatch(format!("the dump has {} generators but the config has {}", baked.generators.len(), config.generator.len())))
+        }
+
+        for (field, string) in strings {
+            let Some(value) = self.strings.get(&field) else {
+                return Err(mismatch(format!("the dump is missing `{field}`")))
+            };
+            string.set_preprocessed(value.clone());
+        }
+        Ok(())
+
+    }
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn dumps() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 3\n\n[core]\nxmva = \"@{prefix}X\"\n\
+            args = [{key = \"n\", name = \"n\"}, {varadict = 1}]\n\n[[generator]]\nrepeat = \"${n}$(0)\"\n\n\
+            [[definition]]\nkey = \"d\"\nname = \"D\"\nexpansion = \"1\"\n";
+        let config = Config::parse(contents, std::path::Path::new("dump.xmva.toml")).unwrap();
+        config.preprocess().unwrap();
+
+        let preprocessed = dump(&config, Stage::Preprocess, None);
+        assert_eq!(preprocessed.strings["core.xmva"], "A_X");
+        assert_eq!(preprocessed.strings["definition.0.name"], "A_D");
+        assert_eq!(preprocessed.strings["generator.0.repeat"], "${n}$(0)");
+
+        config.surface_compile().unwrap();
+        let baked = config.generate_repeats().unwrap();
+        let json = serde_json::to_string(&dump(&config, Stage::RepeatGenerate, Some(baked))).unwrap();
+        let generated: Dump = serde_json::from_str(&json).unwrap();
+        assert_eq!(generated.stage, Stage::RepeatGenerate);
+        assert_eq!(generated.strings["generator.0.repeat"], "n$(0)");
+        assert_eq!(generated.baked.unwrap().generators.len(), 1);
+        assert!(json.contains("\"stage\":\"repeat-generate\""));
+
+    }
+
+    #[test]
+    fn restores() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 3\n\n[core]\nxmva = \"@{prefix}X\"\n\
+            args = [{varadict = 1}]\n\n[[generator]]\nrepeat = \"$(0)\"\n";
+        let path = std::path::Path::new("restore.xmva.toml");
+        let config = Config::parse(contents, path).unwrap();
+        config.preprocess().unwrap();
+        let mut dumped = dump(&config, Stage::Preprocess, None);
+        let expected = config.compile_and_assemble().unwrap().main.contents;
+
+        let resumed = Config::parse(contents, path).unwrap();
+        dumped.restore(&resumed).unwrap();
+        assert_eq!(resumed.compile_and_assemble().unwrap().main.contents, expected);
+
+        let other = Config::parse(&format!("{contents}\n[[generator]]\nrepeat = \"$(0)\"\n"), path).unwrap();
+        assert_eq!(dumped.restore(&other).unwrap_err().kind, ErrorKind::Mismatch);
+        dumped.version = "0.0.0".to_owned();
+        assert_eq!(dumped.restore(&resumed).unwrap_err().kind, ErrorKind::Mismatch);
+
+    }
+
+}
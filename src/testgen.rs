@@ -0,0 +1,265 @@
+//! `xmva emit-tests`, a C test file for the host project exercising the
+//! generated `xmva` with every [crate::config::Invocation] of the config,
+//! so the header gets a regression test without writing one by hand.
+//!
+//! Every invocation gets a test function of its own and is expanded
+//! inside of it, so invocations can declare the same names. When the
+//! config has a `core.list` the argument groups it sees are counted
+//! with it and compared against the invocation, both at compile time
+//! and with the assertions of the framework.
+//!
+//! Example
+//! -------
+//! ```toml
+//! [example]
+//! args = ["vector", "VECTOR", "EMPTY", "\"Vector was empty.\""]
+//!
+//! [[test]]
+//! name = "full"
+//! args = ["vector", "VECTOR", "EMPTY", "\"Empty.\"", "FULL", "\"Full.\""]
+//! ```
+//! ```sh
+//! xmva -i ya_ecgen.xmva.toml emit-tests --framework cmocka > test_ya_ecgen.c
+//! ```
+
+use clap::ValueEnum;
+
+use crate::{
+    compiler::{read_preprocessed, SENTINEL},
+    config::{Argument, Config, Invocation}
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    NoInvocations,
+    Preprocess,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub(crate) message: String
+}
+
+impl std::fmt::Display for Error {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Test generation encountered a error, [{:?}]: {}", self.kind, self.message)
+    }
+
+}
+
+impl std::error::Error for Error {}
+
+/// The unit test framework the test file is written for.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Framework {
+    #[default]
+    Unity,
+    Cmocka
+}
+
+impl Framework {
+
+    fn includes(&self) -> &'static [&'static str] {
+        match self {
+            Self::Unity => &["unity.h"],
+            // cmocka wants these before it.
+            Self::Cmocka => &["stdarg.h", "stddef.h", "stdint.h", "setjmp.h", "cmocka.h"]
+        }
+    }
+
+    fn fixtures(&self) -> &'static str {
+        match self {
+            Self::Unity => "void setUp(void) {}\nvoid tearDown(void) {}\n\n",
+            Self::Cmocka => ""
+        }
+    }
+
+    fn signature(&self, name: &str) -> String {
+        match self {
+            Self::Unity => format!("static void {name}(void) {{\n"),
+            Self::Cmocka => format!("static void {name}(void **state) {{\n    (void) state;\n")
+        }
+    }
+
+    fn assert_equal(&self, expected: usize, actual: &str) -> String {
+        match self {
+            Self::Unity => format!("TEST_ASSERT_EQUAL_INT({expected}, {actual});"),
+            Self::Cmocka => format!("assert_int_equal({actual}, {expected});")
+        }
+    }
+
+    fn runner(&self, tests: &[String]) -> String {
+        let mut runner = String::from("int main(void) {\n");
+        match self {
+            Self::Unity => {
+                runner.push_str("    UNITY_BEGIN();\n");
+                for test in tests {
+                    runner.push_str(&format!("    RUN_TEST({test});\n"));
+                }
+                runner.push_str("    return UNITY_END();\n");
+            }
+            Self::Cmocka => {
+                runner.push_str("    const struct CMUnitTest tests[] = {\n");
+                for test in tests {
+                    runner.push_str(&format!("        cmocka_unit_test({test}),\n"));
+                }
+                runner.push_str("    };\n    return cmocka_run_group_tests(tests, NULL, NULL);\n");
+            }
+        }
+        runner.push_str("}\n");
+        runner
+    }
+
+}
+
+/// A C identifier out of the name of a invocation.
+fn test_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|ch| if ch.is_ascii_alphanumeric() { ch } else { '_' })
+        .collect();
+    format!("test_{name}")
+}
+
+/// The test file for the invocations of `config`, which has to be
+/// preprocessed already. `header` is what gets included.
+pub fn emit(config: &Config, header: &str, framework: Framework) -> Result<String, Error> {
+
+    let preprocessed = |name| read_preprocessed(name).map_err(|err| Error {
+        kind: ErrorKind::Preprocess,
+        message: err.to_string()
+    });
+
+    let invocations: Vec<(String, &Invocation)> = config.example
+        .iter()
+        .map(|example| (example.name.clone().unwrap_or("example".to_owned()), example))
+        .chain(config.test.iter().enumerate().map(|(i, test)| (test.name.clone().unwrap_or(i.to_string()), test)))
+        .collect();
+    if invocations.is_empty() {
+        return Err(Error {
+            kind: ErrorKind::NoInvocations,
+            message: "there is nothing to test without a [example] or [[test]] invocation".to_owned()
+        })
+    }
+
+    let xmva = preprocessed(&config.core.xmva)?;
+    let list = config.core.list.as_ref().map(preprocessed).transpose()?;
+    let counter = format!("{}TEST_COUNT", config.common.keyable.prefix);
+    let named = config.core.args
+        .iter()
+        .filter(|arg| matches!(arg, Argument::Named(_)))
+        .count();
+    let varadict = config.core.varadict();
+
+    let mut file = format!("{SENTINEL}\n/* Generated by xmva from the [example] and [[test]] invocations of {xmva}. */\n\n");
+    for include in framework.includes() {
+        file.push_str(&format!("#include <{include}>\n"));
+    }
+    file.push_str(&format!("#include \"{header}\"\n\n"));
+    if list.is_some() {
+        file.push_str(&format!("#define {counter}(...) + 1\n\n"));
+    }
+    file.push_str(framework.fixtures());
+
+    let mut tests: Vec<String> = Vec::new();
+    for (name, invocation) in invocations {
+
+        let mut test = test_name(&name);
+        if tests.contains(&test) {
+            test = format!("{test}_{}", tests.len());
+        }
+
+        file.push_str(&format!("/* {name} */\n"));
+        file.push_str(&framework.signature(&test));
+        file.push_str(&format!("    {xmva}({});\n", invocation.args.join(", ")));
+
+        // invocations without whole groups are there to hit a fallback.
+        let groups = varadict
+            .filter(|varadict| *varadict > 0)
+            .zip(invocation.args.get(named..))
+            .filter(|(varadict, args)| args.len() % varadict == 0);
+        if let (Some(list), Some((varadict, args))) = (&list, groups) {
+            let groups = args.len() / varadict;
+            let count = match args.is_empty() {
+                true => format!("(0 {list}({counter}))"),
+                false => format!("(0 {list}({counter}, {}))", args.join(", "))
+            };
+            file.push_str(&format!(
+                "    _Static_assert({count} == {groups}, \"{name}: {list} has to see {groups} argument groups.\");\n"
+            ));
+            file.push_str(&format!("    {}\n", framework.assert_equal(groups, &count)));
+        }
+
+        file.push_str("}\n\n");
+        tests.push(test);
+
+    }
+
+    file.push_str(&framework.runner(&tests));
+    Ok(file)
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn emitted() {
+
+        let config = Config::parse(concat!(
+            "[common]\n",
+            "prefix = \"C_\"\n",
+            "repeats = 8\n",
+            "[core]\n",
+            "xmva = \"COLOR\"\n",
+            "list = \"COLOR_LIST\"\n",
+            "args = [{key = \"name\", name = \"name\"}, {varadict = 2}]\n",
+            "[[generator]]\n",
+            "repeat = \"$(0) = $(1)$[, ]\"\n",
+            "fallbacks = {unparity = \"\", empty = \"\"}\n",
+            "[example]\n",
+            "args = [\"color\", \"RED\", \"1\"]\n",
+            "[[test]]\n",
+            "name = \"no groups\"\n",
+            "args = [\"color\"]\n",
+            "[[test]]\n",
+            "args = [\"color\", \"RED\"]\n",
+        ), std::path::Path::new("color.xmva.toml")).unwrap();
+        config.preprocess().unwrap();
+
+        let unity = emit(&config, "color.h", Framework::Unity).unwrap();
+        assert!(unity.contains("#include <unity.h>\n#include \"color.h\"\n"));
+        assert!(unity.contains("#define C_TEST_COUNT(...) + 1\n"));
+        assert!(unity.contains(concat!(
+            "static void test_example(void) {\n",
+            "    COLOR(color, RED, 1);\n",
+            "    _Static_assert((0 COLOR_LIST(C_TEST_COUNT, RED, 1)) == 1, \"example: COLOR_LIST has to see 1 argument groups.\");\n",
+            "    TEST_ASSERT_EQUAL_INT(1, (0 COLOR_LIST(C_TEST_COUNT, RED, 1)));\n",
+            "}\n"
+        )));
+        assert!(unity.contains("static void test_no_groups(void) {\n    COLOR(color);\n    _Static_assert((0 COLOR_LIST(C_TEST_COUNT)) == 0"));
+        // a unparity is left to the header.
+        assert!(unity.contains("static void test_1(void) {\n    COLOR(color, RED);\n}\n"));
+        assert!(unity.contains("RUN_TEST(test_example);\n    RUN_TEST(test_no_groups);\n    RUN_TEST(test_1);\n"));
+
+        let cmocka = emit(&config, "color.h", Framework::Cmocka).unwrap();
+        assert!(cmocka.contains("#include <setjmp.h>\n#include <cmocka.h>\n"));
+        assert!(cmocka.contains("static void test_example(void **state) {\n    (void) state;\n"));
+        assert!(cmocka.contains("assert_int_equal((0 COLOR_LIST(C_TEST_COUNT, RED, 1)), 1);"));
+        assert!(cmocka.contains("cmocka_unit_test(test_1),\n"));
+        assert!(!cmocka.contains("setUp"));
+
+        let untested = Config::parse(
+            "[common]\nprefix = \"X_\"\nrepeats = 2\n[core]\nxmva = \"X\"\nargs = [{varadict = 1}]\n",
+            std::path::Path::new("x.xmva.toml")
+        ).unwrap();
+        untested.preprocess().unwrap();
+        assert_eq!(emit(&untested, "x.h", Framework::Unity).unwrap_err().kind, ErrorKind::NoInvocations);
+
+    }
+
+}
@@ -0,0 +1,104 @@
+//! `--timings`, how long every phase of generating took and how many
+//! bytes it produced, printed to stderr once the outputs are written.
+//!
+//! ```text
+//! phase                                   time        bytes
+//! load                                 1.204ms            -
+//! preprocess                           0.310ms            -
+//! surface compile                      0.095ms            -
+//! repeat generate (generator 0)      412.870ms      8391204
+//! ...
+//! total                              498.114ms
+//! ```
+
+use std::time::{Duration, Instant};
+
+struct Phase {
+    name:     String,
+    duration: Duration,
+    bytes:    Option<usize>,
+}
+
+/// Phases recorded one after the other, every phase took from where the
+/// last one ended. Does nothing when it isn't enabled.
+pub struct Timings {
+    enabled: bool,
+    start:   Instant,
+    last:    Instant,
+    phases:  Vec<Phase>,
+}
+
+impl Timings {
+
+    pub fn new(enabled: bool) -> Self {
+        let now = Instant::now();
+        Self { enabled, start: now, last: now, phases: vec![] }
+    }
+
+    /// End the phase `name` here, having produced `bytes` of output.
+    pub fn phase(&mut self, name: impl Into<String>, bytes: Option<usize>) {
+        if !self.enabled {
+            return
+        }
+        let now = Instant::now();
+        self.phases.push(Phase { name: name.into(), duration: now - self.last, bytes });
+        self.last = now;
+    }
+
+    /// The table of every phase so far, [None] when not enabled.
+    pub fn report(&self) -> Option<String> {
+
+        if !self.enabled {
+            return None
+        }
+
+        let width = self.phases
+            .iter()
+            .map(|phase| phase.name.len())
+            .max()
+            .unwrap_or(0)
+            .max("phase".len());
+        let milliseconds = |duration: Duration| format!("{:.3}ms", duration.as_secs_f64() * 1000.0);
+
+        let mut report = format!("{:<width$} {:>12} {:>12}\n", "phase", "time", "bytes");
+        for phase in self.phases.iter() {
+            report.push_str(&format!("{:<width$} {:>12} {:>12}\n",
+                phase.name,
+                milliseconds(phase.duration),
+                phase.bytes.map_or("-".to_owned(), |bytes| bytes.to_string())
+            ));
+        }
+        report.push_str(&format!("{:<width$} {:>12}\n", "total", milliseconds(self.last - self.start)));
+        Some(report)
+
+    }
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn reported() {
+
+        let mut disabled = Timings::new(false);
+        disabled.phase("load", None);
+        assert!(disabled.report().is_none());
+
+        let mut timings = Timings::new(true);
+        timings.phase("load", None);
+        timings.phase("repeat generate (generator 0)", Some(1234));
+        let report = timings.report().unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("phase                         "));
+        assert!(lines[1].starts_with("load ") && lines[1].ends_with("ms            -"));
+        assert!(lines[2].starts_with("repeat generate (generator 0) ") && lines[2].ends_with("ms         1234"));
+        assert!(lines[3].starts_with("total ") && lines[3].ends_with("ms"));
+
+    }
+
+}
@@ -0,0 +1,145 @@
+//! `--trace-expansion`, a report of every substitution made while
+//! preprocessing and surface compiling, field by field.
+//!
+//! The traces themselves are recorded into the [Config] during
+//! preprocessing ([Config::substitutions]) and surface compiling
+//! ([Config::surface_compilations]), this only puts a name on every
+//! string and writes them out.
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    config::{Argument, Config},
+    preprocessor::{substitution_trace_key, PreprocessableString}
+};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    Text,
+    Json
+}
+
+impl TraceFormat {
+
+    /// Appended to the output path for the report path.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Text => "trace.txt",
+            Self::Json => "trace.json"
+        }
+    }
+
+}
+
+/// How a single field of the config got to its final value.
+#[derive(Serialize, Debug)]
+pub struct Expansion {
+    /// Dotted path of the field, same as in `highlight`.
+    pub field: String,
+    /// The string before anything was substituted followed by the
+    /// string after every substitution.
+    pub steps: Vec<String>
+}
+
+/// Every string of the config that gets preprocessed along side its
+/// dotted path.
+pub(crate) fn named_strings(config: &Config) -> Vec<(String, PreprocessableString)> {
+
+    let mut strings: Vec<(String, PreprocessableString)> = vec![];
+
+    for (i, include) in config.includes.system.iter().enumerate() {
+        strings.push((format!("includes.system.{i}"), include.clone()));
+    }
+    for (i, include) in config.includes.local.iter().enumerate() {
+        strings.push((format!("includes.local.{i}"), include.clone()));
+    }
+    if let Some(raw) = config.preamble.as_ref().and_then(|preamble| preamble.raw.as_ref()) {
+        strings.push(("preamble.raw".to_owned(), raw.clone()));
+    }
+    for (i, definition) in config.definition.iter().flatten().enumerate() {
+        strings.push((format!("definition.{i}.expansion"), definition.expansion.clone()));
+        if let Some(condition) = &definition.condition {
+            strings.push((format!("definition.{i}.condition"), condition.clone()));
+        }
+    }
+    strings.push(("core.xmva".to_owned(), config.core.xmva.clone()));
+    if let Some(list) = &config.core.list {
+        strings.push(("core.list".to_owned(), list.clone()));
+    }
+    for (i, arg) in config.core.args.iter().enumerate() {
+        if let Argument::Named(named) = arg {
+            strings.push((format!("core.args.{i}.name"), named.name.clone()));
+        }
+    }
+    for (i, generator) in config.generator.iter().enumerate() {
+        strings.push((format!("generator.{i}.preamble"), generator.preamble.clone()));
+        strings.push((format!("generator.{i}.repeat"), generator.repeat.clone()));
+        strings.push((format!("generator.{i}.postamble"), generator.postamble.clone()));
+        if let Some(condition) = &generator.condition {
+            strings.push((format!("generator.{i}.condition"), condition.clone()));
+        }
+        strings.push((format!("generator.{i}.fallbacks.unparity"), generator.fallbacks.unparity.clone()));
+        strings.push((format!("generator.{i}.fallbacks.empty"), generator.fallbacks.empty.clone()));
+        for (remainder, unparity) in generator.fallbacks.unparity_remainder.iter() {
+            strings.push((format!("generator.{i}.fallbacks.unparity_remainder.{remainder}"), unparity.clone()));
+        }
+    }
+    if let Some(raw) = config.postamble.as_ref().and_then(|postamble| postamble.raw.as_ref()) {
+        strings.push(("postamble.raw".to_owned(), raw.clone()));
+    }
+
+    strings
+
+}
+
+/// Every field that had at least one substitution, in config order.
+pub fn expansions(config: &Config) -> Vec<Expansion> {
+
+    let substitutions = config.substitutions.read().ok();
+    let surface_compilations = config.surface_compilations.read().ok();
+
+    named_strings(config)
+        .into_iter()
+        .filter_map(|(field, string)| {
+            let key = substitution_trace_key(&string);
+            let mut steps: Vec<String> = substitutions
+                .as_ref()
+                .and_then(|traces| traces.get(&key))
+                .cloned()
+                .unwrap_or_default();
+            if let Some(compilation) = surface_compilations.as_ref().and_then(|traces| traces.get(&key)) {
+                // the string before surface compiling is already the
+                // last step of preprocessing.
+                let skip = if steps.is_empty() { 0 } else { 1 };
+                steps.extend(compilation.iter().skip(skip).cloned());
+            }
+            (!steps.is_empty()).then_some(Expansion { field, steps })
+        })
+        .collect()
+
+}
+
+/// Render the report of a compiled config.
+pub fn report(config: &Config, format: TraceFormat) -> String {
+
+    let expansions = expansions(config);
+
+    match format {
+        TraceFormat::Text => expansions
+            .iter()
+            .map(|expansion| format!("{}\n{}\n",
+                expansion.field,
+                expansion.steps
+                    .iter()
+                    .map(|step| format!("    {step}"))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            ))
+            .collect::<Vec<String>>()
+            .join("\n"),
+        TraceFormat::Json => serde_json::to_string_pretty(&expansions)
+            .unwrap_or_else(|_| "[]".to_owned())
+    }
+
+}
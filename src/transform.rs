@@ -0,0 +1,186 @@
+//! Transform chains, named lists of steps a `name` goes through when
+//! it is tagged with the name of the chain. They run after the built in
+//! [crate::config::Tag]s (prefix, namespace and suffix) were applied.
+//!
+//! Example
+//! -------
+//! ```toml
+//! [transforms]
+//! short_lower = [
+//!     {replace = {from = "ERROR", to = "ERR"}},
+//!     {case = "lower"},
+//!     {truncate = 16},
+//!     {prefix = "ya_"},
+//! ]
+//!
+//! [[definition]]
+//! key  = "emsg"
+//! name = {tags = ["NO_PREFIX", "short_lower"], string = "ERROR_MESSAGE"}
+//! ```
+//!
+//! The steps run before the name is preprocessed, so a `prefix` or
+//! `suffix` can reference keys.
+
+use std::{cell::RefCell, collections::BTreeMap};
+
+use serde::Deserialize;
+
+use crate::plugin;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownChain,
+    Plugin,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub(crate) message: String
+}
+
+impl std::fmt::Display for Error {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Transform encountered a error, [{:?}]: {}", self.kind, self.message)
+    }
+
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Case {
+    Lower,
+    Upper,
+    /// `ErrorCode` and `ERROR_CODE` into `error_code`.
+    Snake,
+    /// `ErrorCode` and `error_code` into `ERROR_CODE`.
+    ScreamingSnake,
+}
+
+impl Case {
+
+    fn apply(&self, name: &str) -> String {
+        match self {
+            Self::Lower => name.to_lowercase(),
+            Self::Upper => name.to_uppercase(),
+            Self::Snake => snake(name),
+            Self::ScreamingSnake => snake(name).to_uppercase()
+        }
+    }
+
+}
+
+/// Split camel case words with a `_`, names without lowercase letters
+/// are already split.
+fn snake(name: &str) -> String {
+    if !name.chars().any(char::is_lowercase) {
+        return name.to_lowercase()
+    }
+    let mut snake = String::with_capacity(name.len());
+    let mut previous: Option<char> = None;
+    for ch in name.chars() {
+        if ch.is_uppercase() && previous.is_some_and(|previous| previous.is_lowercase() || previous.is_ascii_digit()) {
+            snake.push('_');
+        }
+        snake.extend(ch.to_lowercase());
+        previous = Some(ch);
+    }
+    snake
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Replace {
+    pub from: String,
+    pub to:   String,
+}
+
+/// A single step of a chain.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    Prefix(String),
+    Suffix(String),
+    Case(Case),
+    /// Replace every occurrence.
+    Replace(Replace),
+    /// Keep at most this many characters.
+    Truncate(usize),
+    /// A transform function of the [crate::plugin] script.
+    Script(String),
+}
+
+impl Step {
+
+    fn apply(&self, name: String) -> Result<String, Error> {
+        Ok(match self {
+            Self::Prefix(prefix) => format!("{prefix}{name}"),
+            Self::Suffix(suffix) => format!("{name}{suffix}"),
+            Self::Case(case) => case.apply(&name),
+            Self::Replace(Replace { from, .. }) if from.is_empty() => name,
+            Self::Replace(Replace { from, to }) => name.replace(from, to),
+            Self::Truncate(length) => name.chars().take(*length).collect(),
+            Self::Script(function) => plugin::transform(std::slice::from_ref(function), &name)
+                .map_err(|err| Error {
+                    kind: ErrorKind::Plugin,
+                    message: err.to_string()
+                })?
+        })
+    }
+
+}
+
+pub type Chains = BTreeMap<String, Vec<Step>>;
+
+thread_local! {
+    /// The chains of the config being generated, per thread for the
+    /// same reason as the sigil overrides.
+    static CHAINS: RefCell<Chains> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Use `chains` for every name from now on.
+pub fn set_chains(chains: Chains) {
+    CHAINS.with(|current| *current.borrow_mut() = chains);
+}
+
+/// Run `name` through the chain called `chain`.
+pub fn apply(chain: &str, name: String) -> Result<String, Error> {
+    CHAINS.with(|chains| {
+        let chains = chains.borrow();
+        let Some(steps) = chains.get(chain) else {
+            return Err(Error {
+                kind: ErrorKind::UnknownChain,
+                message: format!("`{name}` is tagged with `{chain}` which is neither a tag nor a chain in [transforms], the chains are {:?}",
+                    chains.keys().collect::<Vec<_>>()
+                )
+            })
+        };
+        steps.iter().try_fold(name, |name, step| step.apply(name))
+    })
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn chains() {
+
+        let chains: Chains = toml::from_str(concat!(
+            "short = [{replace = {from = \"ERROR\", to = \"ERR\"}}, {case = \"lower\"}, {truncate = 8}, {prefix = \"ya_\"}]\n",
+            "snake = [{case = \"screaming_snake\"}, {suffix = \"_T\"}]\n"
+        )).unwrap();
+        set_chains(chains);
+
+        assert_eq!(apply("short", "ERROR_MESSAGE".to_owned()).unwrap(), "ya_err_mess");
+        assert_eq!(apply("snake", "ErrorCode2Big".to_owned()).unwrap(), "ERROR_CODE2_BIG_T");
+        assert_eq!(apply("missing", "X".to_owned()).unwrap_err().kind, ErrorKind::UnknownChain);
+
+        assert!(toml::from_str::<Chains>("bad = [{case = \"kebab\"}]").is_err());
+
+    }
+
+}
@@ -0,0 +1,148 @@
+//! `xmva verify`, checks that every output was generated from the config
+//! as it is right now by comparing the config hash in the output banner
+//! against a freshly computed one. The hash covers the files the config
+//! pulls in aswell, see [Config::inputs].
+//!
+//! Unlike `--check` nothing is compiled, so this also works for configs
+//! that currently fail to compile and is a lot faster in CI.
+
+use std::path::{Path, PathBuf};
+
+use crate::{compiler::CONFIG_HASH_MARKER, config::{self, Config}};
+
+/// What we found in a single output.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Freshness {
+    Fresh,
+    /// The output was generated from a config with a different hash.
+    Stale(String),
+    /// The output has no hash in its banner.
+    Unhashed,
+    Missing,
+}
+
+impl std::fmt::Display for Freshness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fresh => write!(f, "up to date"),
+            Self::Stale(hash) => write!(f, "stale, generated from a config with hash {hash}"),
+            Self::Unhashed => write!(f, "stale, no config hash in the banner"),
+            Self::Missing => write!(f, "missing")
+        }
+    }
+}
+
+/// Compare the hash inside of `output` to `hash`.
+pub fn freshness(output: Option<&str>, hash: &str) -> Freshness {
+
+    let Some(output) = output else {
+        return Freshness::Missing
+    };
+
+    // the hash is part of the banner so it is always near the top.
+    let embedded = output
+        .lines()
+        .take_while(|line| line.starts_with("/*"))
+        .find_map(|line| line
+            .split_once(CONFIG_HASH_MARKER)
+            .map(|(_, rest)| rest.trim_end_matches("*/").trim().to_owned())
+        );
+
+    match embedded {
+        Some(embedded) if embedded == hash => Freshness::Fresh,
+        Some(embedded) => Freshness::Stale(embedded),
+        None => Freshness::Unhashed
+    }
+
+}
+
+/// Every file generating the config at `path` writes, the main output
+/// first and then the routed ones. Relative to where we were called from.
+pub fn outputs(path: &Path, config: &Config) -> Vec<PathBuf> {
+
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let main_output = config.common.output.clone().unwrap_or_default();
+    let main_output_dir = main_output.parent().unwrap_or(Path::new(""));
+
+    let mut outputs = vec![directory.join(&main_output)];
+    for routed in config.generator.iter().filter_map(|generator| generator.output.as_ref()) {
+        let routed = directory.join(main_output_dir).join(routed);
+        if !outputs.contains(&routed) {
+            outputs.push(routed);
+        }
+    }
+
+    outputs
+
+}
+
+/// Every output of the config at `path` along side its freshness, paths
+/// are relative to the config just like they are when generating.
+pub fn verify(path: &Path) -> Result<Vec<(PathBuf, Freshness)>, String> {
+
+    let contents = config::read(path).map_err(|err| err.to_string())?;
+    let config = Config::parse(&contents, path).map_err(|err| err.to_string())?;
+    // only to find the included files, a config that doesn't preprocess
+    // anymore is hashed with the ones found until then.
+    if let Err(err) = config.preprocess() {
+        log::info!("Failed to preprocess {}: {err}", path.display());
+    }
+    // hashed the same way as when generating.
+    let hash = config.hash();
+
+    Ok(outputs(path, &config)
+        .into_iter()
+        .map(|output| {
+            let freshness = freshness(std::fs::read_to_string(&output).ok().as_deref(), &hash);
+            (output, freshness)
+        })
+        .collect())
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn embedded_hash() {
+
+        let output = format!("/* Generated by xmva */\n/* {CONFIG_HASH_MARKER}abc */\n#define A\n");
+
+        assert_eq!(freshness(Some(&output), "abc"), Freshness::Fresh);
+        assert_eq!(freshness(Some(&output), "abd"), Freshness::Stale("abc".to_owned()));
+        assert_eq!(freshness(Some("#define A\n"), "abc"), Freshness::Unhashed);
+        assert_eq!(freshness(None, "abc"), Freshness::Missing);
+
+    }
+
+    #[test]
+    fn hashed_inputs() {
+
+        let directory = std::env::temp_dir().join(format!("xmva-verify-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("main.xmva.toml");
+        std::fs::write(directory.join("doc.h"), "/* doc */").unwrap();
+        std::fs::write(
+            &path,
+            "[common]\nprefix = \"A_\"\nrepeats = 3\noutput = \"main.h\"\n\n[core]\nxmva = \"A\"\nargs = [{varadict = 1}]\n\n[[generator]]\nrepeat = \"@{file:doc.h} $(0)\"\n"
+        ).unwrap();
+
+        // included relative to the config, not to where the tests run.
+        let config = Config::load(&path).unwrap();
+        config.preprocess().unwrap();
+        let output = config.compile_and_assemble().unwrap().main.contents;
+        assert!(output.contains("/* doc */"));
+        std::fs::write(directory.join("main.h"), output).unwrap();
+        assert_eq!(verify(&path).unwrap(), vec![(directory.join("main.h"), Freshness::Fresh)]);
+
+        // the config didn't change, the file it includes did.
+        std::fs::write(directory.join("doc.h"), "/* edited */").unwrap();
+        assert!(matches!(verify(&path).unwrap()[0].1, Freshness::Stale(_)));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+    }
+
+}
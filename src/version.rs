@@ -0,0 +1,31 @@
+//! Version report for `--version`, with `--verbose` it is a JSON object
+//! with everything needed to pin down the exact generator that produced
+//! a header.
+
+use serde_json::json;
+
+use crate::config::CONFIG_SCHEMA_VERSIONS;
+
+/// The version report, plain `xmva <version>` or the whole build
+/// information as JSON when `verbose`.
+pub fn report(verbose: bool) -> String {
+
+    if !verbose {
+        return format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+    }
+
+    let features: Vec<&str> = env!("XMVA_FEATURES")
+        .split(',')
+        .filter(|feature| !feature.is_empty())
+        .collect();
+
+    json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_hash": env!("XMVA_GIT_HASH"),
+        "build_date": env!("XMVA_BUILD_DATE"),
+        "features": features,
+        "config_schema_versions": CONFIG_SCHEMA_VERSIONS
+    }).to_string()
+
+}
@@ -0,0 +1,308 @@
+//! Workspaces, a `xmva-workspace.toml` listing configs that get
+//! generated together by `xmva build`.
+//!
+//! Example
+//! -------
+//! ```toml
+//! members = ["errors.xmva.toml", "net/codes.xmva.toml"]
+//!
+//! # applied to every member, the command line still wins.
+//! [common]
+//! repeats = 64
+//! ```
+//!
+//! Members are built in dependency order, a member that `uses` another
+//! one (or `#include`s its output) is built after it. With `-j` members
+//! that don't depend on each other are generated at the same time, under
+//! `make` the jobserver from `MAKEFLAGS` decides how many instead.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::config::{self, Config};
+
+/// Looked up in the current directory when `xmva build` isn't given one.
+pub const WORKSPACE_FILE: &str = "xmva-workspace.toml";
+
+/// The parts of a member config that tell us what it depends on,
+/// read on their own so that a member which doesn't parse yet (because
+/// its dependency wasn't generated) can still be ordered.
+#[derive(Deserialize, Debug, Default)]
+struct Dependencies {
+    #[serde(default)]
+    uses: Vec<PathBuf>,
+    #[serde(default)]
+    common: DependenciesCommon,
+    #[serde(default)]
+    includes: DependenciesIncludes,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct DependenciesCommon {
+    output: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct DependenciesIncludes {
+    #[serde(default)]
+    local: Vec<String>,
+}
+
+/// A path that compares equal to the same file reached another way.
+fn identity(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_owned())
+}
+
+/// [crate::config::Common] values every member of the workspace shares.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct CommonOverrides {
+    pub repeats:   Option<usize>,
+    pub prefix:    Option<String>,
+    pub suffix:    Option<String>,
+    pub namespace: Option<String>,
+    pub separator: Option<String>,
+}
+
+impl CommonOverrides {
+
+    /// Override the values of `config` with the ones that were given, they
+    /// are checked along side the command line ones by the
+    /// [Config::apply_overrides] that has to follow.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(repeats) = self.repeats {
+            config.set_repeats(repeats, "`common.repeats` of the workspace".to_owned());
+        }
+        if let Some(prefix) = &self.prefix {
+            config.set_prefix(prefix.clone(), "`common.prefix` of the workspace".to_owned());
+        }
+        let keyable = &mut config.common.keyable;
+        if let Some(suffix) = &self.suffix {
+            keyable.suffix = suffix.clone();
+        }
+        if let Some(namespace) = &self.namespace {
+            keyable.namespace = namespace.clone();
+        }
+        if let Some(separator) = &self.separator {
+            keyable.separator = separator.clone();
+        }
+    }
+
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Workspace {
+    /// Configs relative to the workspace file, each one is generated
+    /// into its own outputs just like it would be on its own.
+    pub members: Vec<PathBuf>,
+    #[serde(default)]
+    pub common: CommonOverrides,
+    /// Where the workspace file is, members are relative to it.
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+impl Workspace {
+
+    pub fn load(path: &Path) -> Result<Self, config::Error> {
+
+        let contents = config::read(path)?;
+
+        let contents = config::strip_bom(&contents);
+        let mut workspace: Self = toml::from_str(contents)
+            .map_err(|toml_err| config::toml_error(toml_err, contents, path))?;
+        let root = path.parent()
+            .filter(|root| !root.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        workspace.root = std::path::absolute(root)
+            .map_err(|fs_err| config::Error::io(config::Operation::ResolvePath, path, fs_err))?;
+
+        Ok(workspace)
+
+    }
+
+    /// Absolute paths of every member, generating changes the current
+    /// directory so relative ones wouldn't survive the first member.
+    pub fn member_paths(&self) -> Vec<PathBuf> {
+        self.members
+            .iter()
+            .map(|member| self.root.join(config::normalize_separators(member)))
+            .collect()
+    }
+
+    /// The members every member depends on, as indices into
+    /// [Workspace::member_paths].
+    pub fn dependencies(&self) -> Result<Vec<Vec<usize>>, config::Error> {
+
+        let members = self.member_paths();
+        let identities: Vec<PathBuf> = members.iter().map(|member| identity(member)).collect();
+
+        // what every member depends on, as indices into `members`.
+        let mut dependencies: Vec<Vec<usize>> = vec![];
+        let mut outputs: Vec<PathBuf> = vec![];
+        let mut includes: Vec<Vec<PathBuf>> = vec![];
+        for member in members.iter() {
+
+            let contents = config::read(member)?;
+            let contents = config::strip_bom(&contents);
+            let parsed: Dependencies = toml::from_str(contents)
+                .map_err(|toml_err| config::toml_error(toml_err, contents, member))?;
+
+            let directory = member.parent().unwrap_or(Path::new(""));
+            let output = directory.join(parsed.common.output.as_deref().map_or(member.clone(), config::normalize_separators));
+            let output_directory = output.parent().unwrap_or(Path::new("")).to_owned();
+
+            dependencies.push(parsed.uses
+                .iter()
+                .filter_map(|uses| identities.iter().position(|other| *other == identity(&directory.join(config::normalize_separators(uses)))))
+                .collect());
+            includes.push(parsed.includes.local
+                .iter()
+                .map(|include| identity(&output_directory.join(include)))
+                .collect());
+            outputs.push(identity(&output));
+
+        }
+        for (i, includes) in includes.iter().enumerate() {
+            for (j, output) in outputs.iter().enumerate() {
+                if i != j && includes.contains(output) && !dependencies[i].contains(&j) {
+                    dependencies[i].push(j);
+                }
+            }
+        }
+
+        Ok(dependencies)
+
+    }
+
+    /// Indices into [Workspace::member_paths] ordered so that every member
+    /// comes after the members it depends on, otherwise in the order they
+    /// were listed.
+    pub fn build_order(&self) -> Result<Vec<usize>, config::Error> {
+
+        let members = self.member_paths();
+        let dependencies = self.dependencies()?;
+
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        enum Mark { Unvisited, Visiting, Done }
+
+        fn visit(
+            i: usize,
+            members: &[PathBuf],
+            dependencies: &[Vec<usize>],
+            marks: &mut [Mark],
+            order: &mut Vec<usize>
+        ) -> Result<(), config::Error> {
+            marks[i] = Mark::Visiting;
+            for j in dependencies[i].iter().copied() {
+                match marks[j] {
+                    Mark::Done => {}
+                    Mark::Visiting => return Err(config::Error::Invalid {
+                        file: members[i].clone(),
+                        message: format!(
+                            "workspace members {} and {} depend on each other",
+                            members[i].display(),
+                            members[j].display()
+                        )
+                    }),
+                    Mark::Unvisited => visit(j, members, dependencies, marks, order)?
+                }
+            }
+            marks[i] = Mark::Done;
+            order.push(i);
+            Ok(())
+        }
+
+        let mut marks = vec![Mark::Unvisited; members.len()];
+        let mut order = vec![];
+        for i in 0..members.len() {
+            if marks[i] == Mark::Unvisited {
+                visit(i, &members, &dependencies, &mut marks, &mut order)?;
+            }
+        }
+
+        Ok(order)
+
+    }
+
+}
+
+mod tests {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn member_paths() {
+
+        let mut workspace: Workspace = toml::from_str(
+            "members = [\"a.xmva.toml\", \"sub/b.xmva.toml\"]\n[common]\nrepeats = 8\n"
+        ).unwrap();
+        workspace.root = PathBuf::from("/ws");
+
+        assert_eq!(workspace.common.repeats, Some(8));
+        assert_eq!(
+            workspace.member_paths(),
+            vec![PathBuf::from("/ws/a.xmva.toml"), PathBuf::from("/ws/sub/b.xmva.toml")]
+        );
+
+    }
+
+    #[test]
+    fn checked_overrides() {
+
+        let contents = "[common]\nprefix = \"A_\"\nrepeats = 5\n\n[core]\nxmva = \"A\"\n\
+            args = [{varadict = 2}]\n\n[[generator]]\nrepeat = \"$(0)\"\n";
+        let overrides: CommonOverrides = toml::from_str("repeats = 2\nprefix = \"\"\n").unwrap();
+
+        let mut config = Config::parse(contents, Path::new("member.xmva.toml")).unwrap();
+        overrides.apply(&mut config);
+        let err = config.apply_overrides(None, None).unwrap_err().to_string();
+        assert!(err.ends_with("not even one group of 2, set by `common.repeats` of the workspace"));
+
+        // only the final values are checked, the command line wins.
+        let mut config = Config::parse(contents, Path::new("member.xmva.toml")).unwrap();
+        overrides.apply(&mut config);
+        assert!(config.apply_overrides(Some(9), Some("B_".to_owned())).is_ok());
+
+    }
+
+    #[test]
+    fn dependency_order() {
+
+        let directory = std::env::temp_dir().join(format!("xmva-workspace-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let write = |name: &str, contents: &str| std::fs::write(directory.join(name), contents).unwrap();
+
+        // a diamond, `c` depends on `a` through including its output.
+        write("a.xmva.toml", "[common]\noutput = \"a.h\"\n");
+        write("b.xmva.toml", "uses = [\"a.xmva.toml\"]\n");
+        write("c.xmva.toml", "[includes]\nlocal = [\"a.h\"]\n");
+        write("d.xmva.toml", "uses = [\"b.xmva.toml\", \"c.xmva.toml\"]\n");
+        let workspace = |members: &str| Workspace {
+            members: members.split(' ').map(PathBuf::from).collect(),
+            common: Default::default(),
+            root: directory.clone()
+        };
+
+        let diamond = workspace("d.xmva.toml b.xmva.toml c.xmva.toml a.xmva.toml");
+        assert_eq!(diamond.dependencies().unwrap(), vec![vec![1, 2], vec![3], vec![3], vec![]]);
+        assert_eq!(diamond.build_order().unwrap(), vec![3, 1, 2, 0]);
+
+        write("x.xmva.toml", "uses = [\"y.xmva.toml\"]\n");
+        write("y.xmva.toml", "uses = [\"x.xmva.toml\"]\n");
+        let err = workspace("a.xmva.toml x.xmva.toml y.xmva.toml").build_order().unwrap_err().to_string();
+        assert!(err.contains(&format!(
+            "workspace members {} and {} depend on each other",
+            directory.join("y.xmva.toml").display(),
+            directory.join("x.xmva.toml").display()
+        )), "{err}");
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+    }
+
+}